@@ -0,0 +1,65 @@
+//! Lookups by chunk type on a 10,000-chunk PNG, through the type index against a scan of
+//! every chunk, the way `chunk_by_type` used to work.
+//!
+//! cargo bench --bench chunk_index
+
+use std::hint::black_box;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use pngme::chunk::Chunk;
+use pngme::chunk_type::ChunkType;
+use pngme::png::Png;
+
+const IDAT_CHUNKS: usize = 10_000;
+const ROUNDS: u32 = 1_000;
+
+fn synthetic_png() -> Png {
+    let mut chunks = vec![Chunk::new(ChunkType::IHDR, vec![0; 13])];
+    chunks.extend((0..IDAT_CHUNKS).map(|i| Chunk::new(ChunkType::IDAT, (i as u32).to_be_bytes().to_vec())));
+    chunks.push(Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec()));
+    chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+    Png::from_chunks(chunks).unwrap()
+}
+
+fn time(rounds: u32, mut f: impl FnMut()) -> Duration {
+    let started = Instant::now();
+    for _ in 0..rounds {
+        f();
+    }
+    started.elapsed() / rounds
+}
+
+fn report(name: &str, scan: Duration, indexed: Duration) {
+    let speedup = scan.as_secs_f64() / indexed.as_secs_f64().max(f64::MIN_POSITIVE);
+    println!("{:<28} scan {:>12?}  indexed {:>12?}  {:>8.0}x", name, scan, indexed, speedup);
+}
+
+fn main() {
+    let png = synthetic_png();
+    let rust = ChunkType::from_str("ruSt").unwrap();
+
+    let scan = time(ROUNDS, || {
+        black_box(png.chunks().iter().find(|c| *c.chunk_type() == rust));
+    });
+    let indexed = time(ROUNDS, || {
+        black_box(png.chunk_by_type(black_box("ruSt")));
+    });
+    report("chunk_by_type(ruSt)", scan, indexed);
+
+    let scan = time(ROUNDS, || {
+        black_box(png.chunks().iter().filter(|c| *c.chunk_type() == ChunkType::IEND).count());
+    });
+    let indexed = time(ROUNDS, || {
+        black_box(png.chunks_by_type(black_box("IEND")).len());
+    });
+    report("chunks_by_type(IEND)", scan, indexed);
+
+    let scan = time(ROUNDS, || {
+        black_box(png.chunks().iter().position(|c| *c.chunk_type() == ChunkType::IEND));
+    });
+    let indexed = time(ROUNDS, || {
+        black_box(png.structure().iend);
+    });
+    report("structure().iend", scan, indexed);
+}
@@ -0,0 +1,27 @@
+//! Hide a message in a PNG file.
+//!
+//! cargo run --example embed_message -- <input.png> <output.png> <chunk type> <message>
+
+use std::env;
+use std::fs;
+use std::str::FromStr;
+
+use pngme::chunk_type::ChunkType;
+use pngme::encode::{encode_message, EncodeOptions};
+use pngme::png::Png;
+
+pub fn run(input: &str, output: &str, chunk_type: &str, message: &str) -> pngme::Result<()> {
+    let mut png = Png::from_file(input)?;
+    let opts = EncodeOptions::new(ChunkType::from_str(chunk_type)?);
+    encode_message(&mut png, message.as_bytes(), &opts)?;
+    fs::write(output, png.as_bytes())?;
+    Ok(())
+}
+
+fn main() -> pngme::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [input, output, chunk_type, message] => run(input, output, chunk_type, message),
+        _ => Err("usage: embed_message <input.png> <output.png> <chunk type> <message>".into()),
+    }
+}
@@ -0,0 +1,42 @@
+//! Write the data of every private ancillary chunk in a PNG to its own file, without the
+//! message frame when pngme wrote the chunk.
+//!
+//! cargo run --example extract_all -- <input.png> <output dir>
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pngme::framing;
+use pngme::png::Png;
+
+pub fn run(input: &str, out_dir: &Path) -> pngme::Result<Vec<PathBuf>> {
+    let png = Png::from_file(input)?;
+    fs::create_dir_all(out_dir)?;
+
+    let mut written = Vec::new();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type();
+        if chunk_type.is_critical() || chunk_type.is_public() {
+            continue;
+        }
+        let path = out_dir.join(format!("{}-{}.bin", index, chunk_type));
+        let data = framing::open(chunk.data()).map_or(chunk.data(), |frame| frame.body);
+        fs::write(&path, data)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+fn main() -> pngme::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [input, out_dir] => {
+            for path in run(input, Path::new(out_dir))? {
+                println!("{}", path.display());
+            }
+            Ok(())
+        },
+        _ => Err("usage: extract_all <input.png> <output dir>".into()),
+    }
+}
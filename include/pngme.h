@@ -0,0 +1,51 @@
+/* C API for pngme, built with the `ffi` feature; see src/ffi.rs. */
+
+#ifndef PNGME_H
+#define PNGME_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define PME_OK 0
+#define PME_NULL_ARGUMENT -1
+#define PME_INVALID_ARGUMENT -2
+#define PME_FAILED -3
+#define PME_NOT_FOUND -4
+#define PME_OUT_OF_MEMORY -5
+#define PME_PANIC -6
+#define PME_KEY_REQUIRED -7
+
+/* Hides `msg` in a `chunk_type` chunk of the PNG in `png`; the new file is returned in `*out`
+ * and must be released with pme_free. */
+int32_t pme_encode(const uint8_t *png, size_t len, const char *chunk_type,
+                   const uint8_t *msg, size_t msg_len, uint8_t **out, size_t *out_len);
+
+/* Reads the message in the first `chunk_type` chunk into `*out`, which must be released with
+ * pme_free. Returns PME_NOT_FOUND when there is no such chunk, PME_KEY_REQUIRED when the message
+ * needs a password or HMAC key, and PME_FAILED when the chunk does not start with the frame
+ * pme_encode writes. */
+int32_t pme_decode(const uint8_t *png, size_t len, const char *chunk_type,
+                   uint8_t **out, size_t *out_len);
+
+/* As pme_decode, with the keys to open the message: `password` decrypts, `hmac_key` checks an
+ * HMAC integrity frame and `verify_key`, a public key in PEM form, checks the signature. Each
+ * may be NULL for none; with `hmac_key` or `verify_key` given, a message without that layer
+ * fails with PME_FAILED. */
+int32_t pme_decode_with_keys(const uint8_t *png, size_t len, const char *chunk_type,
+                             const char *password, const char *hmac_key, const char *verify_key,
+                             uint8_t **out, size_t *out_len);
+
+void pme_free(uint8_t *ptr);
+
+/* The error of the last failed call on this thread, or NULL; valid until the next call. */
+const char *pme_last_error_message(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PNGME_H */
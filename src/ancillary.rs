@@ -0,0 +1,650 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Result;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMsgError;
+use crate::png::Png;
+
+const METERS_PER_INCH: f64 = 0.0254;
+
+// Ancillary chunks that change how the pixels render, so a scrub keeps them unless asked not to.
+pub const RENDERING_CHUNKS: [&str; 1] = ["tRNS"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysicalUnit {
+    Unknown,
+    Meter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    pub x_pixels_per_unit: u32,
+    pub y_pixels_per_unit: u32,
+    pub unit: PhysicalUnit,
+}
+
+impl PhysicalDimensions {
+    pub fn from_dpi(x_dpi: f64, y_dpi: f64) -> PhysicalDimensions {
+        PhysicalDimensions {
+            x_pixels_per_unit: (x_dpi / METERS_PER_INCH).round() as u32,
+            y_pixels_per_unit: (y_dpi / METERS_PER_INCH).round() as u32,
+            unit: PhysicalUnit::Meter,
+        }
+    }
+
+    // Without a unit pHYs only gives the pixel aspect ratio, so there is no DPI.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        match self.unit {
+            PhysicalUnit::Meter => Some((
+                self.x_pixels_per_unit as f64 * METERS_PER_INCH,
+                self.y_pixels_per_unit as f64 * METERS_PER_INCH,
+            )),
+            PhysicalUnit::Unknown => None,
+        }
+    }
+
+    fn parse(data: &[u8]) -> std::result::Result<PhysicalDimensions, PngMsgError> {
+        let data: &[u8; 9] = data.try_into().map_err(|_| PngMsgError::InvalidChunkLayout {
+            chunk_type: "pHYs",
+            layout: "pixels per unit on X and Y (4 bytes each), then the unit (1 byte)",
+            len: data.len(),
+        })?;
+        let unit = match data[8] {
+            0 => PhysicalUnit::Unknown,
+            1 => PhysicalUnit::Meter,
+            value => return Err(PngMsgError::UnknownFieldValue { field: "pHYs unit specifier", value }),
+        };
+        Ok(PhysicalDimensions {
+            x_pixels_per_unit: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            y_pixels_per_unit: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            unit,
+        })
+    }
+
+    fn to_data(self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(9);
+        data.extend_from_slice(&self.x_pixels_per_unit.to_be_bytes());
+        data.extend_from_slice(&self.y_pixels_per_unit.to_be_bytes());
+        data.push(match self.unit {
+            PhysicalUnit::Unknown => 0,
+            PhysicalUnit::Meter => 1,
+        });
+        data
+    }
+}
+
+// "2835x2835 px/m", or "2x1 (aspect ratio only)" when pHYs gives no unit.
+impl fmt::Display for PhysicalDimensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.x_pixels_per_unit, self.y_pixels_per_unit)?;
+        match self.unit {
+            PhysicalUnit::Meter => write!(f, " px/m"),
+            PhysicalUnit::Unknown => write!(f, " (aspect ratio only)"),
+        }
+    }
+}
+
+// The sRGB chunk's one byte: how colors outside the output gamut are mapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+impl RenderingIntent {
+    fn parse(data: &[u8]) -> std::result::Result<RenderingIntent, PngMsgError> {
+        match data {
+            [0] => Ok(RenderingIntent::Perceptual),
+            [1] => Ok(RenderingIntent::RelativeColorimetric),
+            [2] => Ok(RenderingIntent::Saturation),
+            [3] => Ok(RenderingIntent::AbsoluteColorimetric),
+            &[value] => Err(PngMsgError::UnknownFieldValue { field: "sRGB rendering intent", value }),
+            _ => Err(PngMsgError::InvalidChunkLayout { chunk_type: "sRGB", layout: "the rendering intent (1 byte)", len: data.len() }),
+        }
+    }
+}
+
+impl fmt::Display for RenderingIntent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RenderingIntent::Perceptual => "perceptual",
+            RenderingIntent::RelativeColorimetric => "relative colorimetric",
+            RenderingIntent::Saturation => "saturation",
+            RenderingIntent::AbsoluteColorimetric => "absolute colorimetric",
+        })
+    }
+}
+
+fn expect_type(chunk: &Chunk, expected: &'static str, chunk_type: ChunkType) -> std::result::Result<(), PngMsgError> {
+    match chunk.chunk_type() == &chunk_type {
+        true => Ok(()),
+        false => Err(PngMsgError::WrongChunkType { expected, found: *chunk.chunk_type() }),
+    }
+}
+
+impl Chunk {
+    pub fn as_phys(&self) -> std::result::Result<PhysicalDimensions, PngMsgError> {
+        expect_type(self, "pHYs", ChunkType::PHYS)?;
+        PhysicalDimensions::parse(self.data())
+    }
+
+    // The stored value is the gamma times 100000, so 45455 reads as 0.45455.
+    pub fn as_gama(&self) -> std::result::Result<f64, PngMsgError> {
+        expect_type(self, "gAMA", ChunkType::GAMA)?;
+        let data: [u8; 4] = self.data().try_into().map_err(|_| PngMsgError::InvalidChunkLayout {
+            chunk_type: "gAMA",
+            layout: "the gamma times 100000 (4 bytes)",
+            len: self.data().len(),
+        })?;
+        Ok(u32::from_be_bytes(data) as f64 / 100_000.0)
+    }
+
+    pub fn as_srgb(&self) -> std::result::Result<RenderingIntent, PngMsgError> {
+        expect_type(self, "sRGB", ChunkType::SRGB)?;
+        RenderingIntent::parse(self.data())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    PaletteIndex(u8),
+    Gray(u16),
+    Rgb(u16, u16, u16),
+}
+
+impl Background {
+    fn parse(data: &[u8], color_type: u8) -> Result<Background> {
+        let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+        match (color_type, data.len()) {
+            (3, 1) => Ok(Background::PaletteIndex(data[0])),
+            (0 | 4, 2) => Ok(Background::Gray(sample(0))),
+            (2 | 6, 6) => Ok(Background::Rgb(sample(0), sample(2), sample(4))),
+            (_, len) => Err(format!("bKGD chunk of {} bytes does not match color type {}", len, color_type).into()),
+        }
+    }
+
+    fn to_data(self, color_type: u8) -> Result<Vec<u8>> {
+        match (self, color_type) {
+            (Background::PaletteIndex(index), 3) => Ok(vec![index]),
+            (Background::Gray(gray), 0 | 4) => Ok(gray.to_be_bytes().to_vec()),
+            (Background::Rgb(r, g, b), 2 | 6) => Ok([r, g, b].iter().flat_map(|s| s.to_be_bytes()).collect()),
+            (background, _) => Err(format!("{:?} background is not valid for color type {}", background, color_type).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignificantBits {
+    Gray(u8),
+    Rgb(u8, u8, u8),
+    GrayAlpha(u8, u8),
+    Rgba(u8, u8, u8, u8),
+}
+
+impl SignificantBits {
+    fn parse(data: &[u8], color_type: u8) -> Result<SignificantBits> {
+        match (color_type, data) {
+            (0, &[gray]) => Ok(SignificantBits::Gray(gray)),
+            (2 | 3, &[r, g, b]) => Ok(SignificantBits::Rgb(r, g, b)),
+            (4, &[gray, alpha]) => Ok(SignificantBits::GrayAlpha(gray, alpha)),
+            (6, &[r, g, b, a]) => Ok(SignificantBits::Rgba(r, g, b, a)),
+            (_, data) => Err(format!("sBIT chunk of {} bytes does not match color type {}", data.len(), color_type).into()),
+        }
+    }
+
+    fn to_data(self, color_type: u8) -> Result<Vec<u8>> {
+        match (self, color_type) {
+            (SignificantBits::Gray(gray), 0) => Ok(vec![gray]),
+            (SignificantBits::Rgb(r, g, b), 2 | 3) => Ok(vec![r, g, b]),
+            (SignificantBits::GrayAlpha(gray, alpha), 4) => Ok(vec![gray, alpha]),
+            (SignificantBits::Rgba(r, g, b, a), 6) => Ok(vec![r, g, b, a]),
+            (bits, _) => Err(format!("{:?} is not valid for color type {}", bits, color_type).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextField {
+    pub value: String,
+    pub chunk_index: usize,
+}
+
+// The keywords exiftool and pngcrush write; the first chunk carrying a keyword wins.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WellKnownText {
+    pub comment: Option<TextField>,
+    pub description: Option<TextField>,
+    pub software: Option<TextField>,
+}
+
+impl WellKnownText {
+    pub fn fields(&self) -> impl Iterator<Item = (&'static str, &TextField)> {
+        [("Comment", &self.comment), ("Description", &self.description), ("Software", &self.software)]
+            .into_iter()
+            .filter_map(|(keyword, field)| field.as_ref().map(|field| (keyword, field)))
+    }
+
+    fn slot(&mut self, keyword: &str) -> Option<&mut Option<TextField>> {
+        match keyword {
+            "Comment" => Some(&mut self.comment),
+            "Description" => Some(&mut self.description),
+            "Software" => Some(&mut self.software),
+            _ => None,
+        }
+    }
+}
+
+// tEXt is Latin-1 and iTXt is UTF-8; compressed iTXt only reads with the `compression` feature.
+fn parse_text(chunk: &Chunk) -> Option<(String, String)> {
+    match &chunk.chunk_type().bytes() {
+        b"tEXt" => chunk.as_text().ok(),
+        b"iTXt" => chunk.as_itxt().ok().map(|text| (text.keyword, text.text)),
+        _ => None,
+    }
+}
+
+const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XmpSource {
+    // The standard form: an uncompressed iTXt chunk keyed `XML:com.adobe.xmp`.
+    Itxt { chunk_index: usize },
+    // Private tXMP chunks holding the packet as plain data.
+    Private { chunk_index: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmpPacket {
+    pub raw_xml: String,
+    pub source: XmpSource,
+}
+
+fn is_xmp_chunk(chunk: &Chunk) -> bool {
+    match &chunk.chunk_type().bytes() {
+        b"tXMP" | b"zXMP" => true,
+        b"iTXt" => chunk.data().starts_with(XMP_KEYWORD.as_bytes())
+            && chunk.data().get(XMP_KEYWORD.len()) == Some(&0),
+        _ => false,
+    }
+}
+
+// Loose on purpose: writers disagree on the xpacket wrapper, so either marker is enough.
+fn looks_like_xmp(xml: &str) -> bool {
+    xml.contains("<?xpacket") || xml.contains("<x:xmpmeta")
+}
+
+impl Png {
+    pub fn well_known_text(&self) -> WellKnownText {
+        let mut found = WellKnownText::default();
+        for (chunk_index, chunk) in self.chunks().iter().enumerate() {
+            let Some((keyword, value)) = parse_text(chunk) else { continue };
+            if let Some(slot @ None) = found.slot(&keyword) {
+                *slot = Some(TextField { value, chunk_index });
+            }
+        }
+        found
+    }
+
+    // zXMP packets are skipped, and compressed iTXt ones like compressed text.
+    pub fn xmp(&self) -> Option<XmpPacket> {
+        self.chunks().iter().enumerate().find_map(|(chunk_index, chunk)| {
+            if !is_xmp_chunk(chunk) {
+                return None;
+            }
+            let (raw_xml, source) = match &chunk.chunk_type().bytes() {
+                b"iTXt" => (parse_text(chunk)?.1, XmpSource::Itxt { chunk_index }),
+                b"tXMP" => (String::from_utf8(chunk.data().to_vec()).ok()?, XmpSource::Private { chunk_index }),
+                _ => return None,
+            };
+            looks_like_xmp(&raw_xml).then_some(XmpPacket { raw_xml, source })
+        })
+    }
+
+    // Writes the standard iTXt form where the first existing packet was and drops every other
+    // variant, so readers never see two packets.
+    pub fn set_xmp(&mut self, xml: &str) -> Result<()> {
+        let chunk = Chunk::new_itxt(XMP_KEYWORD, "", "", xml, false)?;
+
+        if self.remove_xmp_except(Some(chunk.clone()))? == 0 {
+            let index = self.chunks().iter()
+                .position(|c| matches!(&c.chunk_type().bytes(), b"IDAT" | b"IEND"))
+                .unwrap_or(self.chunks().len());
+            self.insert_chunk(index, chunk);
+        }
+        Ok(())
+    }
+
+    // Returns how many XMP chunks were removed.
+    pub fn remove_xmp(&mut self) -> Result<usize> {
+        self.remove_xmp_except(None)
+    }
+
+    fn remove_xmp_except(&mut self, replacement: Option<Chunk>) -> Result<usize> {
+        let indices: Vec<usize> = self.chunks().iter().enumerate()
+            .filter(|(_, c)| is_xmp_chunk(c))
+            .map(|(index, _)| index)
+            .collect();
+        if indices.is_empty() {
+            return Ok(0);
+        }
+        self.replace_chunk_run(&indices, replacement.into_iter().collect())?;
+        Ok(indices.len())
+    }
+
+    // Removes every ancillary chunk not in `keep` and returns them in file order. Nothing in the
+    // library is kept by default; see `RENDERING_CHUNKS` for what a scrub usually wants to keep.
+    pub fn strip_ancillary(&mut self, keep: &[ChunkType]) -> Vec<Chunk> {
+        let indices: Vec<usize> = self.chunks().iter().enumerate()
+            .filter(|(_, c)| !c.chunk_type().is_critical() && !keep.contains(c.chunk_type()))
+            .map(|(index, _)| index)
+            .collect();
+        if indices.is_empty() {
+            return Vec::new();
+        }
+        self.replace_chunk_run(&indices, Vec::new()).expect("indices come from the chunk list")
+    }
+
+    pub fn physical_dimensions(&self) -> Result<Option<PhysicalDimensions>> {
+        Ok(self.chunk_by_type("pHYs").map(Chunk::as_phys).transpose()?)
+    }
+
+    pub fn set_physical_dimensions(&mut self, dims: PhysicalDimensions) -> Result<()> {
+        self.put_before("pHYs", dims.to_data(), &["IDAT"])
+    }
+
+    pub fn background(&self) -> Result<Option<Background>> {
+        self.chunk_by_type("bKGD")
+            .map(|c| Background::parse(c.data(), self.color_type()?))
+            .transpose()
+    }
+
+    pub fn set_background(&mut self, background: Background) -> Result<()> {
+        let data = background.to_data(self.color_type()?)?;
+        self.put_before("bKGD", data, &["IDAT"])
+    }
+
+    pub fn significant_bits(&self) -> Result<Option<SignificantBits>> {
+        self.chunk_by_type("sBIT")
+            .map(|c| SignificantBits::parse(c.data(), self.color_type()?))
+            .transpose()
+    }
+
+    pub fn set_significant_bits(&mut self, bits: SignificantBits) -> Result<()> {
+        let data = bits.to_data(self.color_type()?)?;
+        self.put_before("sBIT", data, &["PLTE", "IDAT"])
+    }
+
+    fn color_type(&self) -> Result<u8> {
+        match self.chunk_by_type("IHDR") {
+            Some(ihdr) if ihdr.data().len() == 13 => Ok(ihdr.data()[9]),
+            Some(_) => Err("IHDR chunk must be 13 bytes".into()),
+            None => Err("PNG has no IHDR chunk".into()),
+        }
+    }
+
+    // Replaces an existing chunk in place, otherwise inserts ahead of the first chunk that
+    // must follow it (falling back to just before IEND).
+    fn put_before(&mut self, chunk_type: &str, data: Vec<u8>, successors: &[&str]) -> Result<()> {
+        let chunk = Chunk::new(ChunkType::from_str(chunk_type)?, data);
+        let position = |png: &Png, types: &[&str]| {
+            png.chunks().iter().position(|c| types.contains(&c.chunk_type().to_string().as_str()))
+        };
+
+        if let Some(index) = position(self, &[chunk_type]) {
+            self.remove_chunk(chunk_type)?;
+            self.insert_chunk(index, chunk);
+            return Ok(());
+        }
+        let index = position(self, successors)
+            .or_else(|| position(self, &["IEND"]))
+            .unwrap_or(self.chunks().len());
+        self.insert_chunk(index, chunk);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn ihdr(color_type: u8, bit_depth: u8) -> Chunk {
+        chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, bit_depth, color_type, 0, 0, 0])
+    }
+
+    fn testing_png(color_type: u8, extra: Vec<Chunk>) -> Png {
+        let mut chunks = vec![ihdr(color_type, 8)];
+        if color_type == 3 {
+            chunks.push(chunk("PLTE", &[0, 0, 0]));
+        }
+        chunks.extend(extra);
+        chunks.push(chunk("IDAT", &[]));
+        chunks.push(chunk("IEND", &[]));
+        Png::from_chunks(chunks).unwrap()
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|c| c.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_well_known_text() {
+        let png = testing_png(2, vec![
+            chunk("tEXt", b"Comment\0caf\xe9"),
+            chunk("iTXt", "Description\0\0\0en\0\0d\u{e9}j\u{e0} vu".as_bytes()),
+            chunk("iTXt", b"Software\0\x01\0\0\0x\x9c"),
+            chunk("tEXt", b"Comment\0second"),
+            chunk("tEXt", b"Author\0someone"),
+        ]);
+        let text = png.well_known_text();
+
+        assert_eq!(text.comment, Some(TextField { value: "caf\u{e9}".to_string(), chunk_index: 1 }));
+        assert_eq!(text.description.unwrap().value, "d\u{e9}j\u{e0} vu");
+        assert_eq!(text.software, None);
+        assert_eq!(testing_png(2, vec![]).well_known_text(), WellKnownText::default());
+    }
+
+    const PACKET: &str = "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+        <x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF/></x:xmpmeta><?xpacket end=\"w\"?>";
+
+    fn adobe_xmp(xml: &str) -> Chunk {
+        let mut data = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+        data.extend_from_slice(xml.as_bytes());
+        Chunk::new(ChunkType::from_str("iTXt").unwrap(), data)
+    }
+
+    #[test]
+    fn test_xmp_detection() {
+        let png = testing_png(2, vec![chunk("tEXt", b"Comment\0hi"), adobe_xmp(PACKET)]);
+        assert_eq!(png.xmp(), Some(XmpPacket { raw_xml: PACKET.to_string(), source: XmpSource::Itxt { chunk_index: 2 } }));
+
+        let png = testing_png(2, vec![chunk("tXMP", PACKET.as_bytes())]);
+        assert_eq!(png.xmp().unwrap().source, XmpSource::Private { chunk_index: 1 });
+
+        assert_eq!(testing_png(2, vec![adobe_xmp("not xmp at all")]).xmp(), None);
+        assert_eq!(testing_png(2, vec![]).xmp(), None);
+    }
+
+    #[test]
+    fn test_set_xmp_deduplicates() {
+        let mut png = testing_png(2, vec![chunk("tXMP", PACKET.as_bytes()), chunk("zXMP", b"\x78\x9c"), adobe_xmp(PACKET)]);
+        let updated = PACKET.replace("<rdf:RDF/>", "<rdf:RDF></rdf:RDF>");
+        png.set_xmp(&updated).unwrap();
+
+        assert_eq!(types(&png), vec!["IHDR", "iTXt", "IDAT", "IEND"]);
+        assert_eq!(png.xmp(), Some(XmpPacket { raw_xml: updated, source: XmpSource::Itxt { chunk_index: 1 } }));
+        assert_eq!(png.chunks()[1], adobe_xmp(&png.xmp().unwrap().raw_xml));
+
+        let mut fresh = testing_png(2, vec![]);
+        fresh.set_xmp(PACKET).unwrap();
+        assert_eq!(types(&fresh), vec!["IHDR", "iTXt", "IDAT", "IEND"]);
+        assert_eq!(fresh.remove_xmp().unwrap(), 1);
+        assert_eq!(fresh.xmp(), None);
+    }
+
+    #[test]
+    fn test_phys_in_meters() {
+        let png = testing_png(2, vec![chunk("pHYs", &[0, 0, 0x0e, 0xc4, 0, 0, 0x0e, 0xc4, 1])]);
+        let dims = png.physical_dimensions().unwrap().unwrap();
+
+        assert_eq!(dims.x_pixels_per_unit, 3780);
+        assert_eq!(dims.unit, PhysicalUnit::Meter);
+        let (x_dpi, y_dpi) = dims.dpi().unwrap();
+        assert!((x_dpi - 96.012).abs() < 0.001);
+        assert!((y_dpi - 96.012).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_phys_unspecified_unit_has_no_dpi() {
+        let png = testing_png(2, vec![chunk("pHYs", &[0, 0, 0, 2, 0, 0, 0, 1, 0])]);
+        let dims = png.physical_dimensions().unwrap().unwrap();
+
+        assert_eq!(dims.unit, PhysicalUnit::Unknown);
+        assert_eq!((dims.x_pixels_per_unit, dims.y_pixels_per_unit), (2, 1));
+        assert!(dims.dpi().is_none());
+    }
+
+    #[test]
+    fn test_phys_rejects_bad_length() {
+        let png = testing_png(2, vec![chunk("pHYs", &[0, 0, 0, 2, 0, 0, 0, 1])]);
+        assert!(png.physical_dimensions().is_err());
+    }
+
+    // As written by GIMP and libpng: 72 DPI, gamma 1/2.2 and perceptual sRGB.
+    const PHYS_72_DPI: [u8; 9] = [0x00, 0x00, 0x0b, 0x13, 0x00, 0x00, 0x0b, 0x13, 0x01];
+    const GAMA_45455: [u8; 4] = [0x00, 0x00, 0xb1, 0x8f];
+    const SRGB_PERCEPTUAL: [u8; 1] = [0x00];
+
+    #[test]
+    fn test_typed_accessors_read_real_payloads() {
+        let phys = chunk("pHYs", &PHYS_72_DPI).as_phys().unwrap();
+        assert_eq!(phys, PhysicalDimensions { x_pixels_per_unit: 2835, y_pixels_per_unit: 2835, unit: PhysicalUnit::Meter });
+        assert_eq!(phys.to_string(), "2835x2835 px/m");
+        assert_eq!(chunk("pHYs", &[0, 0, 0, 2, 0, 0, 0, 1, 0]).as_phys().unwrap().to_string(), "2x1 (aspect ratio only)");
+
+        assert_eq!(chunk("gAMA", &GAMA_45455).as_gama().unwrap(), 0.45455);
+        assert_eq!(chunk("sRGB", &SRGB_PERCEPTUAL).as_srgb().unwrap(), RenderingIntent::Perceptual);
+        assert_eq!(chunk("sRGB", &[3]).as_srgb().unwrap().to_string(), "absolute colorimetric");
+    }
+
+    #[test]
+    fn test_typed_accessors_reject_wrong_type_and_layout() {
+        assert!(matches!(
+            chunk("gAMA", &GAMA_45455).as_phys(),
+            Err(PngMsgError::WrongChunkType { expected: "pHYs", .. })
+        ));
+        assert!(matches!(chunk("sRGB", &SRGB_PERCEPTUAL).as_gama(), Err(PngMsgError::WrongChunkType { expected: "gAMA", .. })));
+        assert!(matches!(chunk("pHYs", &PHYS_72_DPI).as_srgb(), Err(PngMsgError::WrongChunkType { expected: "sRGB", .. })));
+
+        assert_eq!(
+            chunk("pHYs", &PHYS_72_DPI[..8]).as_phys().unwrap_err().to_string(),
+            "pHYs chunk of 8 bytes does not match its layout: pixels per unit on X and Y (4 bytes each), then the unit (1 byte)"
+        );
+        assert_eq!(
+            chunk("gAMA", &[0, 0xb1, 0x8f]).as_gama().unwrap_err().to_string(),
+            "gAMA chunk of 3 bytes does not match its layout: the gamma times 100000 (4 bytes)"
+        );
+        assert!(matches!(chunk("sRGB", &[0, 0]).as_srgb(), Err(PngMsgError::InvalidChunkLayout { chunk_type: "sRGB", len: 2, .. })));
+        assert_eq!(chunk("sRGB", &[4]).as_srgb().unwrap_err().to_string(), "Unknown sRGB rendering intent 4");
+        assert_eq!(chunk("pHYs", &[0, 0, 0, 1, 0, 0, 0, 1, 2]).as_phys().unwrap_err().to_string(), "Unknown pHYs unit specifier 2");
+    }
+
+    #[test]
+    fn test_missing_chunks_are_none() {
+        let png = testing_png(2, vec![]);
+        assert_eq!(png.physical_dimensions().unwrap(), None);
+        assert_eq!(png.background().unwrap(), None);
+        assert_eq!(png.significant_bits().unwrap(), None);
+    }
+
+    #[test]
+    fn test_strip_ancillary() {
+        let mut png = testing_png(3, vec![
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("tRNS", &[0]),
+            chunk("tEXt", b"Comment\0hi"),
+            chunk("ruSt", b"secret"),
+        ]);
+        let keep = [ChunkType::from_str("gAMA").unwrap(), ChunkType::from_str(RENDERING_CHUNKS[0]).unwrap()];
+
+        let removed: Vec<String> = png.strip_ancillary(&keep).iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(removed, ["tEXt", "ruSt"]);
+        let left: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(left, ["IHDR", "PLTE", "gAMA", "tRNS", "IDAT", "IEND"]);
+        assert!(png.strip_ancillary(&keep).is_empty());
+
+        assert_eq!(png.strip_ancillary(&[]).len(), 2);
+        assert_eq!(png.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_set_phys_inserts_before_idat() {
+        let mut png = testing_png(2, vec![]);
+        png.set_physical_dimensions(PhysicalDimensions::from_dpi(300.0, 300.0)).unwrap();
+
+        assert_eq!(types(&png), vec!["IHDR", "pHYs", "IDAT", "IEND"]);
+        let dims = png.physical_dimensions().unwrap().unwrap();
+        assert_eq!(dims.x_pixels_per_unit, 11811);
+
+        png.set_physical_dimensions(PhysicalDimensions::from_dpi(72.0, 72.0)).unwrap();
+        assert_eq!(types(&png), vec!["IHDR", "pHYs", "IDAT", "IEND"]);
+        assert_eq!(png.physical_dimensions().unwrap().unwrap().x_pixels_per_unit, 2835);
+    }
+
+    #[test]
+    fn test_background_per_color_type() {
+        let png = testing_png(3, vec![chunk("bKGD", &[7])]);
+        assert_eq!(png.background().unwrap(), Some(Background::PaletteIndex(7)));
+
+        let png = testing_png(4, vec![chunk("bKGD", &[0x01, 0x02])]);
+        assert_eq!(png.background().unwrap(), Some(Background::Gray(0x0102)));
+
+        let png = testing_png(6, vec![chunk("bKGD", &[0, 1, 0, 2, 0, 3])]);
+        assert_eq!(png.background().unwrap(), Some(Background::Rgb(1, 2, 3)));
+
+        let png = testing_png(2, vec![chunk("bKGD", &[0, 1])]);
+        assert!(png.background().is_err());
+    }
+
+    #[test]
+    fn test_set_background_after_palette() {
+        let mut png = testing_png(3, vec![]);
+        png.set_background(Background::PaletteIndex(2)).unwrap();
+
+        assert_eq!(types(&png), vec!["IHDR", "PLTE", "bKGD", "IDAT", "IEND"]);
+        assert_eq!(png.background().unwrap(), Some(Background::PaletteIndex(2)));
+        assert!(png.set_background(Background::Gray(0)).is_err());
+    }
+
+    #[test]
+    fn test_significant_bits_per_color_type() {
+        let png = testing_png(0, vec![chunk("sBIT", &[5])]);
+        assert_eq!(png.significant_bits().unwrap(), Some(SignificantBits::Gray(5)));
+
+        let png = testing_png(3, vec![chunk("sBIT", &[5, 6, 5])]);
+        assert_eq!(png.significant_bits().unwrap(), Some(SignificantBits::Rgb(5, 6, 5)));
+
+        let png = testing_png(4, vec![chunk("sBIT", &[8, 1])]);
+        assert_eq!(png.significant_bits().unwrap(), Some(SignificantBits::GrayAlpha(8, 1)));
+
+        let png = testing_png(6, vec![chunk("sBIT", &[8, 8, 8])]);
+        assert!(png.significant_bits().is_err());
+    }
+
+    #[test]
+    fn test_set_significant_bits_before_palette() {
+        let mut png = testing_png(3, vec![]);
+        png.set_significant_bits(SignificantBits::Rgb(5, 6, 5)).unwrap();
+
+        assert_eq!(types(&png), vec!["IHDR", "sBIT", "PLTE", "IDAT", "IEND"]);
+        assert!(png.set_significant_bits(SignificantBits::Gray(1)).is_err());
+    }
+}
@@ -1,16 +1,744 @@
-use clap::{Parser, Subcommand};
+use std::ffi::{OsStr, OsString};
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use clap::{Arg, ArgMatches, Command, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap::parser::ValueSource;
+use clap::builder::TypedValueParser;
+use clap::error::{Error, ErrorKind};
+use pngme::chunk_type::ChunkType;
+use pngme::limits::Limits;
+
+// Only where the chunk type is the last positional; for `encode` the message follows it.
+pub const CHUNK_TYPE_ENV: &str = "PNG_MSG_CHUNK_TYPE";
+// For encode --input-format and decode --format alike, so a shell that works in base64 or hex
+// sets it once.
+pub const FORMAT_ENV: &str = "PNG_MSG_FORMAT";
+
+// Secrets are safer here or in a file than on the command line, where other users can see them
+// in the process list and they end up in shell history.
+pub const PASSWORD_ENV: &str = "PNG_MSG_PASSWORD";
+pub const PASSWORD_FILE_ENV: &str = "PNG_MSG_PASSWORD_FILE";
+pub const HMAC_KEY_ENV: &str = "PNG_MSG_HMAC_KEY";
+pub const HMAC_KEY_FILE_ENV: &str = "PNG_MSG_HMAC_KEY_FILE";
+
+// As an input path, read stdin; as an output path, write stdout.
+pub const STDIO: &str = "-";
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
 pub struct Args {
    #[command(subcommand)]
-    pub command: Commands
+    pub command: Commands,
+    /// Language for messages (also read from PNG_MSG_LANG)
+    #[arg(long, global = true)]
+    pub lang: Option<String>,
+    /// Size and count limits preset; the --max-* flags override single limits
+    #[arg(long, global = true, value_enum, default_value_t = LimitsPreset::Default)]
+    pub limits: LimitsPreset,
+    #[arg(long, global = true, value_name = "BYTES")]
+    pub max_input_bytes: Option<u64>,
+    /// Largest chunk read, checked before its data is [default: 64 MiB]. Raising it lets a
+    /// file make the parser allocate that much; do so at your own risk, for files you trust
+    #[arg(long, global = true, value_name = "BYTES", alias = "max-chunk-size")]
+    pub max_chunk_bytes: Option<u32>,
+    #[arg(long, global = true, value_name = "COUNT")]
+    pub max_chunks: Option<usize>,
+    #[arg(long, global = true, value_name = "BYTES")]
+    pub max_message_bytes: Option<usize>,
+    /// Write binary output to stdout (path `-`) even when it is a terminal
+    #[arg(long, global = true)]
+    pub force_stdout: bool,
+    /// Keep the file a command overwrites as FILE.bak
+    #[arg(long, global = true)]
+    pub backup: bool,
+}
+
+impl Args {
+    // Use these instead of `parse` and `try_parse_from`; see `Commands::resolve`.
+    pub fn parse_resolved() -> Args {
+        Args::try_parse_resolved(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    pub fn try_parse_resolved<I, T>(argv: I) -> Result<Args, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Args::command().try_get_matches_from(argv)?;
+        let mut args = Args::from_arg_matches(&matches)?;
+        args.command.resolve()?;
+        args.command.resolve_format(&matches)?;
+        Ok(args)
+    }
+
+    pub fn limits(&self) -> Limits {
+        let preset = match self.limits {
+            LimitsPreset::Strict => Limits::strict(),
+            LimitsPreset::Default => Limits::default(),
+            LimitsPreset::Unlimited => Limits::unlimited(),
+        };
+        Limits {
+            max_input_bytes: self.max_input_bytes.or(preset.max_input_bytes),
+            max_chunk_bytes: self.max_chunk_bytes.or(preset.max_chunk_bytes),
+            max_chunks: self.max_chunks.or(preset.max_chunks),
+            max_message_bytes: self.max_message_bytes.or(preset.max_message_bytes),
+        }
+    }
+
+    pub fn writes_to_terminal(&self) -> bool {
+        !self.force_stdout && self.command.output_path() == Some(STDIO) && io::stdout().is_terminal()
+    }
+}
+
+impl Commands {
+    // clap fills positionals in order, so with --random-type, which leaves out CHUNK_TYPE, the
+    // value typed there is MESSAGE and the one after it OUTPUT. This moves them into place, or
+    // else parses CHUNK_TYPE.
+    fn resolve(&mut self) -> Result<(), Error> {
+        let Commands::Encode { chunk_type, chunk_type_slot, message, output, input_file, random_type, .. } = self else {
+            return Ok(());
+        };
+        let mut cmd = Args::command();
+        let encode = cmd.find_subcommand_mut("encode").expect("encode is a subcommand");
+        let Some(_) = random_type else {
+            let slot = chunk_type_slot.as_deref().expect("CHUNK_TYPE is required without --random-type");
+            let arg = encode.get_arguments().find(|arg| arg.get_id() == "chunk_type").cloned();
+            *chunk_type = Some(ChunkTypeParser.parse_ref(encode, arg.as_ref(), OsStr::new(slot))?);
+            return Ok(());
+        };
+        let extra = match input_file {
+            Some(_) => message.as_ref(),
+            None => output.as_ref(),
+        };
+        if let Some(extra) = extra {
+            let reason = format!("unexpected argument '{}' found; --random-type takes no CHUNK_TYPE", extra);
+            return Err(encode.error(ErrorKind::UnknownArgument, reason));
+        }
+        if chunk_type_slot.is_none() && input_file.is_none() {
+            return Err(encode.error(ErrorKind::MissingRequiredArgument, "MESSAGE is required unless --input-file is given"));
+        }
+        *output = message.take();
+        *message = chunk_type_slot.take();
+        Ok(())
+    }
+
+    // --json and --raw print the message their own way, so --format cannot go with them. A
+    // format from FORMAT_ENV, set for every decode, gives way to them instead.
+    fn resolve_format(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        let Commands::Decode { format, json, raw, .. } = self else {
+            return Ok(());
+        };
+        if !*json && !*raw {
+            return Ok(());
+        }
+        if matches.subcommand_matches("decode").and_then(|m| m.value_source("format")) != Some(ValueSource::CommandLine) {
+            *format = TextFormat::Plain;
+            return Ok(());
+        }
+        let mut cmd = Args::command();
+        let decode = cmd.find_subcommand_mut("decode").expect("decode is a subcommand");
+        let flag = if *json { "--json" } else { "--raw" };
+        Err(decode.error(ErrorKind::ArgumentConflict, format!("the argument '{}' cannot be used with '--format <FORMAT>'", flag)))
+    }
+
+    // Where the command writes a file, with in-place rewrites resolved to the input path.
+    pub fn output_path(&self) -> Option<&str> {
+        match self {
+            // With --input-file, MESSAGE holds the output path; see `commands::encode_payload`.
+            Commands::Encode { path, message, output, input_file: Some(_), .. } =>
+                Some(message.as_deref().or(output.as_deref()).unwrap_or(path)),
+            Commands::Encode { path, output, .. }
+            | Commands::Strip { path, output, .. }
+            | Commands::Repair { path, output, .. }
+            | Commands::Pipeline { path, output, .. } => Some(output.as_deref().unwrap_or(path)),
+            Commands::Remove { path, .. } => Some(path),
+            Commands::Decode { output_file, .. } => output_file.as_deref(),
+            _ => None,
+        }
+    }
 }
 #[derive(Subcommand)]
 pub enum Commands {
-    Encode {path: String, chunk_type: String, message: String},
-    Decode {path: String, chunk_type: String},
-    Remove {path: String, chunk_type: String},
-    Print {path: String}
+    Encode {
+        /// The carrier PNG, or a directory to encode into every PNG in
+        path: String,
+        // Set by `Commands::resolve`, from CHUNK_TYPE; `None` with --random-type.
+        #[arg(skip)]
+        chunk_type: Option<ChunkType>,
+        /// Left out with --random-type
+        #[arg(id = "chunk_type", value_name = "CHUNK_TYPE", required_unless_present = "random_type")]
+        chunk_type_slot: Option<String>,
+        #[arg(required_unless_present_any = ["input_file", "random_type"])]
+        message: Option<String>,
+        /// Where to write the result; the input file is rewritten when left out
+        output: Option<String>,
+        /// Hide the bytes of this file instead of MESSAGE; any output path then goes where MESSAGE would
+        #[arg(long, value_name = "PATH", conflicts_with = "output")]
+        input_file: Option<String>,
+        /// How MESSAGE (or the --input-file contents) is written; base64 and hex are decoded first
+        #[arg(long, value_enum, value_name = "FORMAT", env = FORMAT_ENV, default_value_t = TextFormat::Plain)]
+        input_format: TextFormat,
+        #[arg(long)]
+        record_provenance: bool,
+        /// Store the message as a tEXt keyword (e.g. Comment) that exiftool can read
+        #[arg(long, visible_alias = "text")]
+        keyword: Option<String>,
+        /// Encrypt the message with this passphrase (needs the `encrypt` feature). The least safe
+        /// way to give it: others can see it in the process list. Prefer the environment or
+        /// --password-file
+        #[arg(long, env = PASSWORD_ENV, hide_env_values = true, conflicts_with = "keyword")]
+        password: Option<String>,
+        /// Read the passphrase from the first line of this file
+        #[arg(long, value_name = "PATH", env = PASSWORD_FILE_ENV, conflicts_with_all = ["keyword", "password"])]
+        password_file: Option<String>,
+        /// Append a SHA-256 of the stored message so decode can tell if it was changed
+        #[arg(long, conflicts_with = "keyword")]
+        digest: bool,
+        /// Append an HMAC-SHA256 keyed by this passphrase instead of a plain digest. The least
+        /// safe way to give the key: others can see it in the process list. Prefer the
+        /// environment or --hmac-key-file
+        #[arg(long, env = HMAC_KEY_ENV, hide_env_values = true, conflicts_with_all = ["keyword", "digest"])]
+        hmac_key: Option<String>,
+        /// Read the HMAC key from the first line of this file
+        #[arg(long, value_name = "PATH", env = HMAC_KEY_FILE_ENV, conflicts_with_all = ["keyword", "digest", "hmac_key"])]
+        hmac_key_file: Option<String>,
+        /// Sign the message with this Ed25519 private key, as written by `keygen` (needs the `sign` feature)
+        #[arg(long, value_name = "PATH", conflicts_with = "keyword")]
+        sign_key: Option<String>,
+        /// Record the current UTC time in the tIME chunk, replacing any earlier one
+        #[arg(long)]
+        timestamp: bool,
+        /// Split the message over as many chunks as it takes to keep each chunk's data, headers
+        /// included, to at most this many bytes
+        #[arg(long, value_name = "BYTES", conflicts_with = "keyword")]
+        split_size: Option<usize>,
+        #[arg(long, value_name = "PATH")]
+        manifest: Option<String>,
+        #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "table")]
+        report_size: Option<ReportFormat>,
+        /// Encode even when the carrier is not a well-formed PNG, or into a critical chunk type
+        /// (which corrupts the image) or a standard one (which viewers interpret)
+        #[arg(long)]
+        force: bool,
+        /// Hide the message in the pixels' low bits instead of a chunk; the chunk type is ignored
+        /// (needs the `stego` feature)
+        #[arg(long, conflicts_with_all = ["keyword", "record_provenance", "manifest", "report_size", "digest", "hmac_key", "hmac_key_file", "sign_key", "timestamp", "split_size", "random_type"])]
+        lsb: bool,
+        /// Store the message in a new random private chunk type, printed for decoding later, in
+        /// place of CHUNK_TYPE; --random-type=X starts it with X (needs the `random-type` feature)
+        #[arg(long, value_name = "LETTER", num_args = 0..=1, require_equals = true, default_missing_value = "", conflicts_with = "keyword")]
+        random_type: Option<String>,
+        #[command(flatten)]
+        batch: Box<BatchArgs>,
+    },
+    Decode {
+        path: String,
+        #[arg(value_parser = ChunkTypeParser, env = CHUNK_TYPE_ENV)]
+        chunk_type: ChunkType,
+        /// Print the message and where it came from as JSON
+        #[arg(long)]
+        json: bool,
+        /// Print every chunk of the type as a message, with its chunk index
+        #[arg(long, conflicts_with = "json")]
+        all: bool,
+        /// Decrypt a message stored with `encode --password`. The least safe way to give the
+        /// passphrase: others can see it in the process list. Prefer the environment or
+        /// --password-file
+        #[arg(long, env = PASSWORD_ENV, hide_env_values = true)]
+        password: Option<String>,
+        /// Read the passphrase from the first line of this file
+        #[arg(long, value_name = "PATH", env = PASSWORD_FILE_ENV, conflicts_with = "password")]
+        password_file: Option<String>,
+        /// Check a message stored with `encode --hmac-key`; refuses messages without a valid HMAC.
+        /// The least safe way to give the key: others can see it in the process list. Prefer the
+        /// environment or --hmac-key-file
+        #[arg(long, env = HMAC_KEY_ENV, hide_env_values = true)]
+        hmac_key: Option<String>,
+        /// Read the HMAC key from the first line of this file
+        #[arg(long, value_name = "PATH", env = HMAC_KEY_FILE_ENV, conflicts_with = "hmac_key")]
+        hmac_key_file: Option<String>,
+        /// Check a message signed with `encode --sign-key` against this public key; refuses
+        /// unsigned messages (needs the `sign` feature)
+        #[arg(long, value_name = "PATH")]
+        verify_key: Option<String>,
+        /// Write the message to this file as is, text or not, instead of printing it
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["json", "all"])]
+        output_file: Option<String>,
+        /// Print the message bytes unchanged, even binary ones to a terminal
+        #[arg(long, conflicts_with_all = ["json", "all", "output_file"])]
+        raw: bool,
+        /// Give the message as base64 or hex instead of as it is stored; not with --json or --raw
+        #[arg(long, value_enum, value_name = "FORMAT", env = FORMAT_ENV, default_value_t = TextFormat::Plain)]
+        format: TextFormat,
+        /// Read a message stored with `encode --lsb`; the chunk type is ignored
+        #[arg(long, conflicts_with_all = ["json", "all", "hmac_key", "hmac_key_file", "verify_key"])]
+        lsb: bool,
+        /// Take the chunk data as stored, without the frame encode writes: for messages from
+        /// before framing, keyword messages and chunks written by other tools
+        #[arg(long, conflicts_with = "lsb")]
+        unframed: bool,
+    },
+    Remove {
+        /// A PNG file, or a directory to remove the chunk from every PNG in
+        path: String,
+        #[arg(value_parser = ChunkTypeParser, env = CHUNK_TYPE_ENV)]
+        chunk_type: ChunkType,
+        /// Remove every chunk of the type, not just the first; critical types are refused
+        #[arg(long)]
+        all: bool,
+        #[arg(long)]
+        record_provenance: bool,
+        #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "table")]
+        report_size: Option<ReportFormat>,
+        #[command(flatten)]
+        batch: BatchArgs,
+    },
+    /// Remove every ancillary chunk, so the file carries no hidden data or metadata
+    Strip {
+        /// A PNG file, or a directory to strip every PNG in
+        path: String,
+        /// Where to write the result; the input file is rewritten when left out
+        output: Option<String>,
+        /// Ancillary chunk types to keep, e.g. gAMA,sRGB
+        #[arg(long, value_parser = ChunkTypeParser, value_delimiter = ',')]
+        keep: Vec<ChunkType>,
+        /// Also remove tRNS, which changes how transparent pixels render
+        #[arg(long)]
+        strip_trns: bool,
+        /// Add a record of the strip to the provenance chunk, which is then kept
+        #[arg(long)]
+        record_provenance: bool,
+        #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "table")]
+        report_size: Option<ReportFormat>,
+        #[command(flatten)]
+        batch: BatchArgs,
+    },
+    /// Recompute wrong chunk CRCs and lengths
+    Repair {
+        path: String,
+        /// Where to write the result; the input file is rewritten when left out
+        output: Option<String>,
+        #[arg(long)]
+        record_provenance: bool,
+        #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "table")]
+        report_size: Option<ReportFormat>,
+    },
+    /// Strip, encode and repair with one read and one write of the file
+    Pipeline {
+        path: String,
+        /// Where to write the result; the input file is rewritten when left out
+        output: Option<String>,
+        /// The operations to apply, in order, e.g. strip,encode,repair
+        #[arg(long, value_enum, value_delimiter = ',', required = true)]
+        ops: Vec<PipelineOp>,
+        /// The chunk type encode writes
+        #[arg(long, value_parser = ChunkTypeParser, env = CHUNK_TYPE_ENV, required_if_eq("ops", "encode"))]
+        chunk_type: Option<ChunkType>,
+        /// The message encode hides
+        #[arg(long, required_if_eq("ops", "encode"))]
+        message: Option<String>,
+        /// Ancillary chunk types strip keeps, e.g. gAMA,sRGB
+        #[arg(long, value_parser = ChunkTypeParser, value_delimiter = ',')]
+        keep: Vec<ChunkType>,
+        /// Also have strip remove tRNS, which changes how transparent pixels render
+        #[arg(long)]
+        strip_trns: bool,
+        #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "table")]
+        report_size: Option<ReportFormat>,
+    },
+    /// Show how much message data the file can take
+    Capacity {
+        path: String,
+        /// Largest acceptable file size; shows how many message bytes still fit under it
+        #[arg(long, value_name = "BYTES")]
+        max_size: Option<u64>,
+    },
+    /// Write a new Ed25519 key pair for `encode --sign-key` and `decode --verify-key`
+    Keygen {
+        /// Where the private key goes; an existing file is never overwritten
+        private_key: String,
+        public_key: String,
+    },
+    Print {
+        path: String,
+        #[arg(long)]
+        provenance: bool,
+        /// Show a hex dump of each chunk's data
+        #[arg(long, conflicts_with = "provenance")]
+        hex: bool,
+        /// Print the chunk listing as JSON; data over 1 KiB is given only as a SHA-256
+        #[arg(long, conflicts_with_all = ["provenance", "hex"])]
+        json: bool,
+        /// Bytes of each chunk to dump
+        #[arg(long, requires = "hex", value_name = "BYTES", default_value_t = 256)]
+        limit: usize,
+    },
+    /// Show where the file's bytes go: image data, ancillary chunks and overhead, by chunk type
+    Stats {
+        path: String,
+        /// Print the statistics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the chunks added, removed, changed or moved from PATH to OTHER
+    Diff {
+        path: String,
+        other: String,
+        /// Print the differences as JSON
+        #[arg(long)]
+        json: bool,
+        /// Color the +/-/~ lines; `auto` does when stdout is a terminal and NO_COLOR is unset
+        #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto, conflicts_with = "json")]
+        color: ColorChoice,
+    },
+    /// Flag chunks that may hide data: unregistered or repeated private types, large text,
+    /// chunks after IEND and high-entropy data. One line per finding; exits with 1 when there
+    /// is any
+    Scan {
+        /// A PNG, or a directory whose PNGs are all scanned
+        path: String,
+        /// With a directory PATH, also scan the files in its subdirectories
+        #[arg(long)]
+        recursive: bool,
+        /// With a directory PATH, scan the files whose names match this, e.g. 'shot-*.png'
+        /// [default: names ending in .png, in any case]
+        #[arg(long, value_name = "PATTERN")]
+        glob: Option<String>,
+        /// Also report where the rules in this file match
+        #[cfg(feature = "rules")]
+        #[arg(long, value_name = "FILE")]
+        rules: Option<String>,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LimitsPreset {
+    Strict,
+    Default,
+    Unlimited,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TextFormat {
+    Plain,
+    Base64,
+    Hex,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PipelineOp {
+    Strip,
+    Encode,
+    Repair,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Table,
+    Json,
+}
+
+// For a directory PATH; each file is changed on its own and a failure does not stop the rest.
+#[derive(clap::Args, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BatchArgs {
+    /// With a directory PATH, also process the files in its subdirectories
+    #[arg(long)]
+    pub recursive: bool,
+    /// With a directory PATH, process the files whose names match this, e.g. 'shot-*.png'
+    /// [default: names ending in .png, in any case]
+    #[arg(long, value_name = "PATTERN")]
+    pub glob: Option<String>,
+    /// With a directory PATH, write the results here, in the same layout, instead of rewriting
+    /// the files
+    #[arg(long, value_name = "DIR")]
+    pub output_dir: Option<String>,
+}
+
+impl BatchArgs {
+    pub fn is_set(&self) -> bool {
+        *self != BatchArgs::default()
+    }
+}
+
+// Names the offending byte and offers a look-alike fix, e.g. "ru5t" -> "ruSt".
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkTypeParser;
+
+impl TypedValueParser for ChunkTypeParser {
+    type Value = ChunkType;
+
+    fn parse_ref(&self, cmd: &Command, arg: Option<&Arg>, value: &OsStr) -> Result<ChunkType, Error> {
+        let value = value.to_string_lossy();
+        ChunkType::from_str(&value).map_err(|reason| {
+            let name = arg.map_or_else(|| "...".to_string(), |a| a.get_id().as_str().to_uppercase());
+            let mut message = format!("invalid value '{}' for '<{}>': {}\n", value, name, reason);
+            if let Some(suggestion) = ChunkType::suggest(&value) {
+                message.push_str(&format!("\n  tip: did you mean '{}'?\n", suggestion));
+            }
+            Error::raw(ErrorKind::ValueValidation, message).with_cmd(cmd)
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(argv: &[&str]) -> String {
+        match Args::try_parse_resolved(argv) {
+            Ok(_) => panic!("{:?} should not parse", argv),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_chunk_type_is_parsed() {
+        let args = Args::try_parse_resolved(["pngme", "decode", "a.png", "ruSt"]).unwrap();
+        match args.command {
+            Commands::Decode { chunk_type, .. } => assert_eq!(chunk_type.to_string(), "ruSt"),
+            _ => panic!("expected decode"),
+        }
+    }
+
+    #[test]
+    fn test_encode_output_is_optional() {
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "ruSt", "hi", "b.png"]).unwrap();
+        assert!(matches!(args.command, Commands::Encode { output: Some(ref output), .. } if output == "b.png"));
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "ruSt", "hi"]).unwrap();
+        assert!(matches!(args.command, Commands::Encode { output: None, .. }));
+    }
+
+    #[test]
+    fn test_encode_input_file_takes_message_slot() {
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "ruSt", "--input-file", "key.bin", "b.png"]).unwrap();
+        match args.command {
+            Commands::Encode { message, input_file, output, .. } => {
+                assert_eq!((message.as_deref(), input_file.as_deref(), output), (Some("b.png"), Some("key.bin"), None));
+            }
+            _ => panic!("expected encode"),
+        }
+        assert!(parse_error(&["pngme", "encode", "a.png", "ruSt"]).contains("required"));
+        let error = parse_error(&["pngme", "encode", "a.png", "ruSt", "hi", "b.png", "--input-file", "key.bin"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+    }
+
+    #[test]
+    fn test_text_formats() {
+        let args = Args::try_parse_resolved(["pngme", "decode", "a.png", "ruSt", "--format", "base64"]).unwrap();
+        assert!(matches!(args.command, Commands::Decode { format: TextFormat::Base64, .. }));
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "ruSt", "00ff", "--input-format", "hex"]).unwrap();
+        assert!(matches!(args.command, Commands::Encode { input_format: TextFormat::Hex, .. }));
+        let error = parse_error(&["pngme", "decode", "a.png", "ruSt", "--format", "hex", "--raw"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+    }
+
+    #[test]
+    fn test_decode_output_file_and_raw() {
+        let args = Args::try_parse_resolved(["pngme", "decode", "a.png", "ruSt", "--output-file", "key.bin"]).unwrap();
+        assert!(matches!(args.command, Commands::Decode { output_file: Some(ref p), raw: false, .. } if p == "key.bin"));
+        let error = parse_error(&["pngme", "decode", "a.png", "ruSt", "--raw", "--output-file", "key.bin"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+    }
+
+    #[test]
+    fn test_decode_unframed() {
+        let args = Args::try_parse_resolved(["pngme", "decode", "a.png", "tEXt", "--unframed"]).unwrap();
+        assert!(matches!(args.command, Commands::Decode { unframed: true, .. }));
+        let error = parse_error(&["pngme", "decode", "a.png", "ruSt", "--unframed", "--lsb"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+    }
+
+    #[test]
+    fn test_pipeline_ops() {
+        let args = Args::try_parse_resolved([
+            "pngme", "pipeline", "a.png", "b.png", "--ops", "strip,encode,repair", "--chunk-type", "ruSt", "--message", "hi",
+        ]).unwrap();
+        match args.command {
+            Commands::Pipeline { ref ops, chunk_type, ref message, .. } => {
+                assert_eq!(ops, &[PipelineOp::Strip, PipelineOp::Encode, PipelineOp::Repair]);
+                assert_eq!(chunk_type.map(|c| c.to_string()).as_deref(), Some("ruSt"));
+                assert_eq!(message.as_deref(), Some("hi"));
+            }
+            _ => panic!("expected pipeline"),
+        }
+        assert_eq!(args.command.output_path(), Some("b.png"));
+
+        assert!(Args::try_parse_resolved(["pngme", "pipeline", "a.png", "--ops", "strip,repair"]).is_ok());
+        let error = Args::try_parse_resolved(["pngme", "pipeline", "a.png", "--ops", "strip,encode", "--chunk-type", "ruSt"]).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_scan_directory_flags() {
+        let args = Args::try_parse_resolved(["pngme", "scan", "shots", "--recursive", "--glob", "*.png"]).unwrap();
+        assert!(matches!(args.command, Commands::Scan { recursive: true, glob: Some(ref g), .. } if g == "*.png"));
+        assert_eq!(args.command.output_path(), None);
+    }
+
+    #[test]
+    fn test_output_path_resolves_stdio() {
+        let output = |argv: &[&str]| Args::try_parse_resolved(argv).unwrap().command.output_path().map(str::to_string);
+        assert_eq!(output(&["pngme", "encode", "-", "ruSt", "hi", "-"]).as_deref(), Some(STDIO));
+        assert_eq!(output(&["pngme", "encode", "-", "ruSt", "hi"]).as_deref(), Some(STDIO));
+        assert_eq!(output(&["pngme", "encode", "a.png", "ruSt", "--input-file", "-", "b.png"]).as_deref(), Some("b.png"));
+        assert_eq!(output(&["pngme", "strip", "-", "out.png"]).as_deref(), Some("out.png"));
+        assert_eq!(output(&["pngme", "remove", "-", "ruSt"]).as_deref(), Some(STDIO));
+        assert_eq!(output(&["pngme", "decode", "-", "ruSt"]), None);
+        assert_eq!(output(&["pngme", "print", "-"]), None);
+    }
+
+    #[test]
+    fn test_password_conflicts_with_keyword() {
+        let args = Args::try_parse_resolved(["pngme", "decode", "a.png", "ruSt", "--password", "pw"]).unwrap();
+        assert!(matches!(args.command, Commands::Decode { password: Some(ref p), .. } if p == "pw"));
+        let error = parse_error(&["pngme", "encode", "a.png", "tEXt", "hi", "--keyword", "Comment", "--password", "pw"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+    }
+
+    #[test]
+    fn test_password_file() {
+        let args = Args::try_parse_resolved(["pngme", "decode", "a.png", "ruSt", "--password-file", "pw.txt"]).unwrap();
+        assert!(matches!(args.command, Commands::Decode { password: None, password_file: Some(ref p), .. } if p == "pw.txt"));
+        let error = parse_error(&["pngme", "encode", "a.png", "ruSt", "hi", "--password", "pw", "--password-file", "pw.txt"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+    }
+
+    #[test]
+    fn test_hmac_key_file() {
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "ruSt", "hi", "--hmac-key-file", "key.txt"]).unwrap();
+        assert!(matches!(args.command, Commands::Encode { hmac_key: None, hmac_key_file: Some(ref p), .. } if p == "key.txt"));
+        let error = parse_error(&["pngme", "decode", "a.png", "ruSt", "--hmac-key", "k", "--hmac-key-file", "key.txt"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+        let error = parse_error(&["pngme", "encode", "a.png", "ruSt", "hi", "--digest", "--hmac-key-file", "key.txt"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+    }
+
+    #[test]
+    fn test_sign_and_verify_keys() {
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "ruSt", "hi", "--sign-key", "id.pem"]).unwrap();
+        assert!(matches!(args.command, Commands::Encode { sign_key: Some(ref p), .. } if p == "id.pem"));
+        let args = Args::try_parse_resolved(["pngme", "decode", "a.png", "ruSt", "--verify-key", "id.pub.pem"]).unwrap();
+        assert!(matches!(args.command, Commands::Decode { verify_key: Some(ref p), .. } if p == "id.pub.pem"));
+        let error = parse_error(&["pngme", "encode", "a.png", "ruSt", "hi", "--sign-key", "id.pem", "--lsb"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+        let args = Args::try_parse_resolved(["pngme", "keygen", "id.pem", "id.pub.pem"]).unwrap();
+        assert!(matches!(args.command, Commands::Keygen { ref private_key, .. } if private_key == "id.pem"));
+        assert_eq!(args.command.output_path(), None);
+    }
+
+    #[test]
+    fn test_encode_timestamp() {
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "ruSt", "hi", "--timestamp"]).unwrap();
+        assert!(matches!(args.command, Commands::Encode { timestamp: true, .. }));
+        let error = parse_error(&["pngme", "encode", "a.png", "ruSt", "hi", "--timestamp", "--lsb"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+    }
+
+    #[test]
+    fn test_encode_split_size() {
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "ruSt", "hi", "--split-size", "64"]).unwrap();
+        assert!(matches!(args.command, Commands::Encode { split_size: Some(64), .. }));
+        assert!(parse_error(&["pngme", "encode", "a.png", "ruSt", "hi", "--split-size", "big"]).contains("invalid value"));
+        let error = parse_error(&["pngme", "encode", "a.png", "tEXt", "hi", "--keyword", "Comment", "--split-size", "64"]);
+        assert!(error.contains("cannot be used with"), "{}", error);
+    }
+
+    #[test]
+    fn test_strip_keep_list() {
+        let args = Args::try_parse_resolved(["pngme", "strip", "--keep", "gAMA,sRGB", "a.png", "b.png"]).unwrap();
+        match args.command {
+            Commands::Strip { keep, output, strip_trns, .. } => {
+                assert_eq!(keep.iter().map(ToString::to_string).collect::<Vec<_>>(), ["gAMA", "sRGB"]);
+                assert_eq!(output.as_deref(), Some("b.png"));
+                assert!(!strip_trns);
+            }
+            _ => panic!("expected strip"),
+        }
+    }
+
+    #[test]
+    fn test_batch_flags() {
+        let args = Args::try_parse_resolved(["pngme", "strip", "shots", "--recursive", "--glob", "shot-*.png", "--output-dir", "clean"]).unwrap();
+        let expected = BatchArgs { recursive: true, glob: Some("shot-*.png".into()), output_dir: Some("clean".into()) };
+        assert!(matches!(args.command, Commands::Strip { ref batch, .. } if *batch == expected));
+
+        let args = Args::try_parse_resolved(["pngme", "remove", "shots", "ruSt"]).unwrap();
+        assert!(matches!(args.command, Commands::Remove { ref batch, .. } if !batch.is_set()));
+        assert!(parse_error(&["pngme", "decode", "shots", "ruSt", "--recursive"]).contains("unexpected argument"));
+    }
+
+    #[test]
+    fn test_diff_color() {
+        let args = Args::try_parse_resolved(["pngme", "diff", "a.png", "b.png"]).unwrap();
+        assert!(matches!(args.command, Commands::Diff { color: ColorChoice::Auto, json: false, .. }));
+        let args = Args::try_parse_resolved(["pngme", "diff", "a.png", "b.png", "--color", "never"]).unwrap();
+        assert!(matches!(args.command, Commands::Diff { color: ColorChoice::Never, .. }));
+        assert!(!ColorChoice::Never.enabled() && ColorChoice::Always.enabled());
+        assert!(parse_error(&["pngme", "diff", "a.png", "b.png", "--json", "--color", "always"]).contains("cannot be used with"));
+    }
+
+    #[test]
+    fn test_limits_preset_and_overrides() {
+        let args = Args::try_parse_resolved(["pngme", "print", "a.png"]).unwrap();
+        assert_eq!(args.limits(), Limits::default());
+
+        let args = Args::try_parse_resolved(["pngme", "print", "a.png", "--limits", "strict", "--max-chunks", "5"]).unwrap();
+        assert_eq!(args.limits(), Limits { max_chunks: Some(5), ..Limits::strict() });
+
+        let args = Args::try_parse_resolved(["pngme", "print", "a.png", "--max-chunk-size", "1000"]).unwrap();
+        assert_eq!(args.limits().max_chunk_bytes, Some(1000));
+    }
+
+    #[test]
+    fn test_random_type_moves_positionals_up() {
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "--random-type", "hi", "b.png"]).unwrap();
+        match args.command {
+            Commands::Encode { chunk_type, message, output, random_type, .. } => {
+                assert_eq!(chunk_type, None);
+                assert_eq!((message.as_deref(), output.as_deref()), (Some("hi"), Some("b.png")));
+                assert_eq!(random_type.as_deref(), Some(""));
+            }
+            _ => panic!("expected encode"),
+        }
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "--random-type=q", "--input-file", "key.bin", "b.png"]).unwrap();
+        assert_eq!(args.command.output_path(), Some("b.png"));
+        assert!(matches!(args.command, Commands::Encode { random_type: Some(ref prefix), .. } if prefix == "q"));
+
+        let args = Args::try_parse_resolved(["pngme", "encode", "a.png", "ruSt", "hi"]).unwrap();
+        assert!(matches!(args.command, Commands::Encode { chunk_type: Some(t), .. } if t == ChunkType::from_str("ruSt").unwrap()));
+
+        assert!(parse_error(&["pngme", "encode", "a.png", "--random-type", "hi", "b.png", "c.png"]).contains("unexpected argument 'c.png'"));
+        assert!(parse_error(&["pngme", "encode", "a.png", "--random-type"]).contains("MESSAGE is required"));
+        assert!(parse_error(&["pngme", "encode", "a.png", "--random-type", "--keyword", "Comment", "hi"]).contains("cannot be used with"));
+    }
+
+    #[test]
+    fn test_chunk_type_error_names_byte_and_suggests() {
+        let error = parse_error(&["pngme", "encode", "a.png", "ru5t", "hi"]);
+        assert!(error.contains("invalid value 'ru5t' for '<CHUNK_TYPE>': byte 3 ('5') is not an ASCII letter"), "{}", error);
+        assert!(error.contains("tip: did you mean 'ruSt'?"), "{}", error);
+
+        let error = parse_error(&["pngme", "remove", "a.png", "ruStt"]);
+        assert!(error.contains("expected 4 bytes, got 5"), "{}", error);
+        assert!(!error.contains("tip:"), "{}", error);
+    }
 }
@@ -0,0 +1,188 @@
+// Replaces files without ever leaving them half-written. The new contents go to a temp file in
+// the same directory, so the final rename stays on one filesystem, and only a complete file is
+// renamed over the original. A failed write leaves the original exactly as it was.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const BACKUP_EXTENSION: &str = "bak";
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Deletes the temp file when dropped, unless it was renamed into place.
+struct TempFile {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+// `write` gets the temp file; whatever it returns, `path` either has all of it or is unchanged.
+// An existing file's permissions carry over to the replacement.
+pub fn replace_file<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let mut temp = TempFile {
+        path: dir.join(format!(
+            ".{}.{}-{}.tmp", name.to_string_lossy(), std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        )),
+        persisted: false,
+    };
+
+    let file = File::options().write(true).create_new(true).open(&temp.path)?;
+    let mut out = BufWriter::new(file);
+    write(&mut out)?;
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    drop(file);
+
+    match fs::metadata(path) {
+        Ok(metadata) => fs::set_permissions(&temp.path, metadata.permissions())?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    rename_over(&temp.path, path)?;
+    temp.persisted = true;
+    Ok(())
+}
+
+// "file.png" -> "file.png.bak"
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(BACKUP_EXTENSION);
+    PathBuf::from(name)
+}
+
+// Keeps the current contents of `path` as its backup, replacing an older backup. A hard link
+// costs nothing and survives `replace_file`, which swaps in a new file rather than writing into
+// this one; filesystems without links get a copy.
+pub fn backup_file(path: &Path) -> io::Result<PathBuf> {
+    let backup = backup_path(path);
+    match fs::remove_file(&backup) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    if fs::hard_link(path, &backup).is_err() {
+        fs::copy(path, &backup)?;
+    }
+    Ok(backup)
+}
+
+#[cfg(not(windows))]
+fn rename_over(from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to)
+}
+
+// MoveFileEx (behind `fs::rename`) replaces an existing file, but not a read-only one, which a
+// POSIX rename does not care about. The replacement already carries the read-only flag.
+#[cfg(windows)]
+fn rename_over(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            let original = fs::metadata(to)?.permissions();
+            if !original.readonly() {
+                return Err(e);
+            }
+            let mut writable = original.clone();
+            writable.set_readonly(false);
+            fs::set_permissions(to, writable)?;
+            fs::rename(from, to).inspect_err(|_| {
+                let _ = fs::set_permissions(to, original);
+            })
+        }
+        result => result,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pngme-atomic-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_replace_and_create() {
+        let dir = scratch_dir("replace");
+        let path = dir.join("a.png");
+        replace_file(&path, |w| w.write_all(b"first")).unwrap();
+        replace_file(&path, |w| w.write_all(b"second")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(entries(&dir), ["a.png"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_write_leaves_original_untouched() {
+        let dir = scratch_dir("failure");
+        let path = dir.join("a.png");
+        fs::write(&path, b"original contents").unwrap();
+
+        // Fails after part of the new file is already out, as a full disk would.
+        let error = replace_file(&path, |w| {
+            w.write_all(&[0xab; 100_000])?;
+            Err(io::Error::other("disk full"))
+        }).unwrap_err();
+        assert_eq!(error.to_string(), "disk full");
+        assert_eq!(fs::read(&path).unwrap(), b"original contents");
+        assert_eq!(entries(&dir), ["a.png"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions_carry_over() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = scratch_dir("permissions");
+        let path = dir.join("a.png");
+        fs::write(&path, b"original").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        replace_file(&path, |w| w.write_all(b"new")).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_backup_keeps_original() {
+        let dir = scratch_dir("backup");
+        let path = dir.join("a.png");
+        fs::write(dir.join("a.png.bak"), b"old backup").unwrap();
+        fs::write(&path, b"original").unwrap();
+
+        assert_eq!(backup_file(&path).unwrap(), dir.join("a.png.bak"));
+        replace_file(&path, |w| w.write_all(b"edited")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"edited");
+        assert_eq!(fs::read(dir.join("a.png.bak")).unwrap(), b"original");
+        assert!(backup_file(&dir.join("missing.png")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+// Applies one change to many files. Each file is read, parsed, changed and written on its own,
+// so a failure is recorded against that file and the rest still go through.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Result};
+use crate::atomic::{backup_file, replace_file};
+use crate::limits::Limits;
+use crate::png::{ParseOptions, Png, SerializePolicy};
+
+// Where `process_files` writes each result.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Destination {
+    #[default]
+    InPlace,
+    // A file's path under `root` is recreated under `dir`; a file outside `root` goes straight
+    // into `dir`.
+    Mirror { root: PathBuf, dir: PathBuf },
+}
+
+impl Destination {
+    pub fn path_for(&self, file: &Path) -> PathBuf {
+        match self {
+            Destination::InPlace => file.to_path_buf(),
+            Destination::Mirror { root, dir } => match file.strip_prefix(root) {
+                Ok(relative) => dir.join(relative),
+                Err(_) => dir.join(file.file_name().unwrap_or(file.as_os_str())),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchOptions {
+    pub destination: Destination,
+    pub limits: Limits,
+    // Keep a file about to be overwritten as FILE.bak.
+    pub backup: bool,
+}
+
+#[derive(Debug)]
+pub struct BatchFailure {
+    pub path: PathBuf,
+    pub error: Error,
+}
+
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    // The paths written, in the order their inputs were given.
+    pub written: Vec<PathBuf>,
+    pub failed: Vec<BatchFailure>,
+}
+
+impl BatchReport {
+    pub fn total(&self) -> usize {
+        self.written.len() + self.failed.len()
+    }
+}
+
+// Runs `op` on every file and writes the ones it succeeds on; nothing is written for a file
+// that fails to read, parse, change or serialize.
+pub fn process_files<F>(files: &[PathBuf], opts: &BatchOptions, mut op: F) -> BatchReport
+where
+    F: FnMut(&mut Png) -> Result<()>,
+{
+    let mut report = BatchReport::default();
+    for file in files {
+        match process_file(file, opts, &mut op) {
+            Ok(written) => report.written.push(written),
+            Err(error) => report.failed.push(BatchFailure { path: file.clone(), error }),
+        }
+    }
+    report
+}
+
+fn process_file<F>(file: &Path, opts: &BatchOptions, op: &mut F) -> Result<PathBuf>
+where
+    F: FnMut(&mut Png) -> Result<()>,
+{
+    let bytes = fs::read(file)?;
+    let options = ParseOptions { limits: opts.limits, ..Default::default() };
+    let (mut png, _) = Png::parse_with(&bytes, &options)?;
+    op(&mut png)?;
+    let output = png.serialize(SerializePolicy::Strict)?;
+
+    let target = opts.destination.path_for(file);
+    if let (Destination::Mirror { .. }, Some(parent)) = (&opts.destination, target.parent()) {
+        fs::create_dir_all(parent)?;
+    }
+    if opts.backup && target.exists() {
+        backup_file(&target)?;
+    }
+    replace_file(&target, |out| out.write_all(&output))?;
+    Ok(target)
+}
+
+// The files in `dir` whose names match `glob`, or that end in .png (in any case) when there is
+// no pattern, sorted by path. Symlinked directories are not followed, so a link cannot loop.
+pub fn collect_files(dir: &Path, recursive: bool, glob: Option<&str>) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let (path, file_type) = (entry.path(), entry.file_type()?);
+            if file_type.is_dir() {
+                if recursive {
+                    pending.push(path);
+                }
+                continue;
+            }
+            if !(file_type.is_file() || file_type.is_symlink() && path.is_file()) {
+                continue;
+            }
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let wanted = match glob {
+                Some(pattern) => glob_match(pattern, &name),
+                None => path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")),
+            };
+            if wanted {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// `*` matches any run of characters and `?` any one; everything else matches itself.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // The last `*` seen, and the name position it currently stands in for up to.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match star {
+                // Let the `*` take one more character and try again from there.
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pngme-batch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn carrier() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IDAT, vec![1, 2, 3]),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap().as_bytes()
+    }
+
+    fn names(root: &Path, files: &[PathBuf]) -> Vec<String> {
+        files.iter().map(|f| f.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/")).collect()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.png", "shot.png"));
+        assert!(glob_match("*.png", ".png"));
+        assert!(!glob_match("*.png", "shot.PNG"));
+        assert!(glob_match("shot-??.png", "shot-01.png"));
+        assert!(!glob_match("shot-??.png", "shot-1.png"));
+        assert!(glob_match("*-*-final*", "a-b-c-final.png"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("a*b", "a-b-c"));
+        assert!(glob_match("größe*", "größe.png"));
+    }
+
+    #[test]
+    fn test_collect_files() {
+        let dir = scratch_dir("collect");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in ["b.png", "a.PNG", "notes.txt", "sub/c.png", "sub/shot-1.png"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        assert_eq!(names(&dir, &collect_files(&dir, false, None).unwrap()), ["a.PNG", "b.png"]);
+        assert_eq!(names(&dir, &collect_files(&dir, true, None).unwrap()), ["a.PNG", "b.png", "sub/c.png", "sub/shot-1.png"]);
+        assert_eq!(names(&dir, &collect_files(&dir, true, Some("shot-*")).unwrap()), ["sub/shot-1.png"]);
+        assert_eq!(names(&dir, &collect_files(&dir, false, Some("*.txt")).unwrap()), ["notes.txt"]);
+        assert!(collect_files(&dir.join("missing"), false, None).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_failures_do_not_stop_the_batch() {
+        let dir = scratch_dir("in-place");
+        fs::write(dir.join("a.png"), carrier()).unwrap();
+        fs::write(dir.join("broken.png"), b"not a png").unwrap();
+        fs::write(dir.join("c.png"), carrier()).unwrap();
+        let files = collect_files(&dir, false, None).unwrap();
+
+        let report = process_files(&files, &BatchOptions::default(), |png| {
+            png.insert_before_iend(Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hi".to_vec()));
+            Ok(())
+        });
+        assert_eq!((report.total(), report.written.len()), (3, 2));
+        assert_eq!(names(&dir, &[report.failed[0].path.clone()]), ["broken.png"]);
+        assert!(report.failed[0].error.to_string().starts_with("Not a PNG file"));
+        for name in ["a.png", "c.png"] {
+            let png = Png::from_file(dir.join(name)).unwrap();
+            assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"hi");
+        }
+        assert_eq!(fs::read(dir.join("broken.png")).unwrap(), b"not a png");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_mirror_into_output_dir() {
+        let dir = scratch_dir("mirror");
+        fs::create_dir_all(dir.join("in/sub")).unwrap();
+        fs::write(dir.join("in/a.png"), carrier()).unwrap();
+        fs::write(dir.join("in/sub/b.png"), carrier()).unwrap();
+        let files = collect_files(&dir.join("in"), true, None).unwrap();
+
+        let opts = BatchOptions {
+            destination: Destination::Mirror { root: dir.join("in"), dir: dir.join("out") },
+            ..Default::default()
+        };
+        let report = process_files(&files, &opts, |png| png.remove_chunk("IDAT").map(|_| ()));
+        assert!(report.failed.is_empty());
+        assert_eq!(names(&dir, &report.written), ["out/a.png", "out/sub/b.png"]);
+        assert!(Png::from_file(dir.join("out/sub/b.png")).unwrap().chunk_by_type("IDAT").is_none());
+        assert_eq!(fs::read(dir.join("in/sub/b.png")).unwrap(), carrier());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_op_errors_leave_the_file_alone() {
+        let dir = scratch_dir("op-error");
+        fs::write(dir.join("a.png"), carrier()).unwrap();
+        let opts = BatchOptions { backup: true, ..Default::default() };
+
+        let report = process_files(&[dir.join("a.png")], &opts, |png| png.remove_chunk("ruSt").map(|_| ()));
+        assert_eq!(report.failed.len(), 1);
+        assert!(!dir.join("a.png.bak").exists());
+
+        let report = process_files(&[dir.join("a.png")], &opts, |png| png.remove_chunk("IDAT").map(|_| ()));
+        assert_eq!(report.written.len(), 1);
+        assert_eq!(fs::read(dir.join("a.png.bak")).unwrap(), carrier());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
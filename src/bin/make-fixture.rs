@@ -0,0 +1,34 @@
+// Builds a test PNG from a fixture spec:
+//   cargo run --features fixture --bin make-fixture -- --spec tests/fixtures/specs/three_idats.toml --out three_idats.png
+use std::fs;
+use std::io::Write;
+use clap::Parser;
+use pngme::fixture::FixtureSpec;
+
+#[derive(Parser, Debug)]
+#[command(about = "Build a deterministic test PNG from a fixture spec")]
+struct Args {
+    #[arg(long)]
+    spec: String,
+    // Written to stdout when omitted.
+    #[arg(long)]
+    out: Option<String>,
+}
+
+fn run(args: &Args) -> pngme::Result<()> {
+    let spec: FixtureSpec = fs::read_to_string(&args.spec)?.parse()?;
+    let bytes = spec.build()?;
+    match &args.out {
+        Some(out) => fs::write(out, bytes)?,
+        None => std::io::stdout().write_all(&bytes)?,
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(e) = run(&args) {
+        eprintln!("error: {}: {}", args.spec, e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::chunk::{SerializedSize, CHUNK_OVERHEAD, MAX_CHUNK_LENGTH};
+use crate::framing;
+use crate::png::Png;
+use crate::split::PIECE_HEADER_LEN;
+
+// Every chunk a message is written to starts with the message frame.
+const FRAME_LEN: u64 = framing::HEADER_LEN as u64;
+
+// What the file holds now and how much a message can grow it; all sizes are bytes on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityReport {
+    pub file_size: SerializedSize,
+    pub ancillary_count: usize,
+    pub ancillary_bytes: SerializedSize,
+    // The most one unsplit chunk can carry after the message frame.
+    pub max_chunk_payload: u32,
+    // The same for each piece of a split message, after the frame and its piece header.
+    pub max_piece_payload: u32,
+}
+
+impl CapacityReport {
+    // Message bytes that fit before the file passes `max_file_size`: one chunk while the payload
+    // fits in one, otherwise as many full pieces as fit plus whatever the rest can carry. Just
+    // past one chunk the piece headers cost more than the extra room, so one full chunk wins.
+    pub fn room_for(&self, max_file_size: u64) -> u64 {
+        let budget = max_file_size.saturating_sub(self.file_size.get());
+        let single = budget.saturating_sub(CHUNK_OVERHEAD + FRAME_LEN);
+        if single <= self.max_chunk_payload as u64 {
+            return single;
+        }
+        let piece_size = CHUNK_OVERHEAD + MAX_CHUNK_LENGTH as u64;
+        let full_pieces = budget / piece_size;
+        let last = (budget % piece_size).saturating_sub(CHUNK_OVERHEAD + FRAME_LEN + PIECE_HEADER_LEN as u64);
+        (full_pieces * self.max_piece_payload as u64 + last).max(self.max_chunk_payload as u64)
+    }
+}
+
+impl Png {
+    pub fn capacity_report(&self) -> CapacityReport {
+        let ancillary: Vec<SerializedSize> = self.chunks().iter()
+            .filter(|c| !c.chunk_type().is_critical())
+            .map(|c| c.serialized_size())
+            .collect();
+        CapacityReport {
+            file_size: self.total_size(),
+            ancillary_count: ancillary.len(),
+            ancillary_bytes: ancillary.into_iter().sum(),
+            max_chunk_payload: MAX_CHUNK_LENGTH - FRAME_LEN as u32,
+            max_piece_payload: MAX_CHUNK_LENGTH - FRAME_LEN as u32 - PIECE_HEADER_LEN as u32,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, len: usize) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; len])
+    }
+
+    fn report() -> CapacityReport {
+        Png::from_chunks(vec![
+            chunk("IHDR", 13),
+            chunk("tEXt", 20),
+            chunk("IDAT", 100),
+            chunk("ruSt", 8),
+            chunk("IEND", 0),
+        ]).unwrap().capacity_report()
+    }
+
+    #[test]
+    fn test_counts_ancillary_chunks() {
+        let report = report();
+        assert_eq!(report.file_size.get(), 8 + 25 + 32 + 112 + 20 + 12);
+        assert_eq!((report.ancillary_count, report.ancillary_bytes.get()), (2, 52));
+        assert_eq!(report.max_chunk_payload, 0x7FFF_FFFF - 6);
+    }
+
+    #[test]
+    fn test_room_for_one_chunk() {
+        let report = report();
+        let size = report.file_size.get();
+        assert_eq!(report.room_for(size + 1018), 1000);
+        assert_eq!(report.room_for(size + 18), 0);
+        assert_eq!(report.room_for(size + 5), 0);
+        assert_eq!(report.room_for(size / 2), 0);
+    }
+
+    #[test]
+    fn test_room_for_split_message() {
+        let report = report();
+        let piece = CHUNK_OVERHEAD + MAX_CHUNK_LENGTH as u64;
+        let size = report.file_size.get();
+        assert_eq!(report.room_for(size + piece), report.max_chunk_payload as u64);
+        assert_eq!(report.room_for(size + piece + 30), report.max_chunk_payload as u64);
+        // A second chunk means both need piece headers.
+        assert_eq!(report.room_for(size + piece + 1000), report.max_piece_payload as u64 + 970);
+        assert_eq!(report.room_for(size + 3 * piece), 3 * report.max_piece_payload as u64);
+    }
+}
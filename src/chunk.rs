@@ -1,92 +1,644 @@
-use crc::Crc;
 use std::convert::TryFrom;
 use std::fmt;
-use crate::Error;
+use std::io::{self, Write};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Deref};
+use std::sync::Arc;
+use crate::error::PngMsgError;
 use crate::chunk_type::ChunkType;
+use crate::crc32::crc_of;
+use crate::text_codec::{base64_encode, hex_encode};
+use crate::limits::Limits;
 // use std::array::TryFromSliceError;
 
 
-const CRC: Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+// Where a chunk starts in a file: its byte offset (the signature included), its zero-based
+// index, and its type if that much could be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkPosition {
+    pub offset: u64,
+    pub index: usize,
+    pub chunk_type: Option<ChunkType>,
+}
+
+impl ChunkPosition {
+    // `bytes` starts at the chunk; the type is only kept when it is a valid one.
+    pub(crate) fn of(offset: u64, index: usize, bytes: &[u8]) -> ChunkPosition {
+        let chunk_type = bytes.get(4..8)
+            .and_then(|type_bytes| ChunkType::try_from(<[u8; 4]>::try_from(type_bytes).unwrap()).ok());
+        ChunkPosition { offset, index, chunk_type }
+    }
+}
+
+// "chunk #7 (tEXt) at offset 0x1a2b3c"
+impl fmt::Display for ChunkPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.chunk_type {
+            Some(chunk_type) => write!(f, "chunk #{} ({}) at offset {:#x}", self.index, chunk_type, self.offset),
+            None => write!(f, "chunk #{} at offset {:#x}", self.index, self.offset),
+        }
+    }
+}
+
+// `position` is None for a chunk parsed on its own, outside a file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CrcMismatch {
+    pub stored: u32,
+    pub computed: u32,
+    pub position: Option<ChunkPosition>,
+}
+
+impl fmt::Display for CrcMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(position) = &self.position {
+            write!(f, "{}: ", position)?;
+        }
+        write!(f, "CRC mismatch (expected {:08x}, found {:08x})", self.computed, self.stored)
+    }
+}
+
+impl std::error::Error for CrcMismatch {}
+
+// Which part of a chunk a too-short slice stops in, so callers can tell a cut-off tail from a
+// bad length field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkParseError {
+    MissingLength { got: usize },
+    MissingChunkType { got: usize },
+    TruncatedData { declared: u32, available: usize },
+    MissingCrc { got: usize },
+    TrailingBytes { extra: usize },
+}
+
+impl fmt::Display for ChunkParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkParseError::MissingLength { got } => write!(f, "length field cut off after {} of 4 bytes", got),
+            ChunkParseError::MissingChunkType { got } => write!(f, "chunk type cut off after {} of 4 bytes", got),
+            ChunkParseError::TruncatedData { declared, available } =>
+                write!(f, "data cut off after {} of {} bytes", available, declared),
+            ChunkParseError::MissingCrc { got } => write!(f, "CRC cut off after {} of 4 bytes", got),
+            ChunkParseError::TrailingBytes { extra } => write!(f, "{} bytes after the CRC", extra),
+        }
+    }
+}
+
+impl std::error::Error for ChunkParseError {}
+
+// The spec caps the length field at 2^31 - 1 so it can be read as a signed 32-bit integer.
+pub const MAX_CHUNK_LENGTH: u32 = 0x7FFF_FFFF;
+
+// Length, type and CRC fields around the data of every serialized chunk.
+pub const CHUNK_OVERHEAD: u64 = 12;
+
+// The spec meaning of a chunk's length field: the number of data bytes, nothing else.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChunkLength(pub u32);
+
+impl ChunkLength {
+    pub fn get(self) -> u32 {
+        self.0
+    }
+
+    pub fn serialized_size(self) -> SerializedSize {
+        SerializedSize(self.0 as u64 + CHUNK_OVERHEAD)
+    }
+}
+
+impl fmt::Display for ChunkLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+// Bytes on disk: data plus the 12 bytes of framing, or a sum of such sizes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SerializedSize(pub u64);
+
+impl SerializedSize {
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    pub fn delta(self, after: SerializedSize) -> i64 {
+        after.0 as i64 - self.0 as i64
+    }
+}
+
+impl fmt::Display for SerializedSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
 
-#[derive(Debug)]
+impl Add for SerializedSize {
+    type Output = SerializedSize;
+
+    fn add(self, other: SerializedSize) -> SerializedSize {
+        SerializedSize(self.0 + other.0)
+    }
+}
+
+impl AddAssign for SerializedSize {
+    fn add_assign(&mut self, other: SerializedSize) {
+        self.0 += other.0;
+    }
+}
+
+impl Sum for SerializedSize {
+    fn sum<I: Iterator<Item = SerializedSize>>(iter: I) -> SerializedSize {
+        iter.fold(SerializedSize::default(), Add::add)
+    }
+}
+
+/// One PNG chunk: a type, its data, and the length and CRC fields stored with them.
+///
+/// The constructors here take finished data or a single text layout; anything more (text in a
+/// chosen layout, compression, data from a reader, a size cap) is assembled with
+/// [`ChunkBuilder`](crate::chunk_builder::ChunkBuilder).
+///
+/// ```
+/// use pngme::chunk::Chunk;
+///
+/// let chunk = Chunk::new("ruSt".parse().unwrap(), b"hidden".to_vec());
+/// assert_eq!(chunk.chunk_type(), "ruSt");
+/// assert_eq!(chunk.data(), b"hidden");
+/// assert_eq!(Chunk::try_from(&chunk.as_bytes()[..]).unwrap(), chunk);
+/// ```
+#[derive(Clone)]
 pub struct Chunk {
-    
+
     length: u32,
     chunk_type: ChunkType,
-    data: Vec<u8>,
+    // Shared between clones; mutation goes through `map_data` or `ChunkMut`, which copy on write.
+    data: Arc<Vec<u8>>,
     crc: u32,
 
 }
 
 impl Chunk {
 
+    /// Builds a chunk around `data`, filling in the length and CRC.
+    ///
+    /// Panics when `data` is longer than [`MAX_CHUNK_LENGTH`]; use [`Chunk::try_new`] when the
+    /// size is not known to be small.
+    ///
+    /// ```
+    /// use pngme::chunk::Chunk;
+    /// use pngme::chunk_type::ChunkType;
+    ///
+    /// let chunk = Chunk::new(ChunkType::TEXT, b"Comment\0hi".to_vec());
+    /// assert_eq!(chunk.length().get(), 10);
+    /// assert!(chunk.is_consistent());
+    /// ```
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Self {
-        Chunk {
-            length: data.len() as u32,
-            crc: CRC.checksum(&chunk_type.bytes().iter().cloned()
-                                          .chain(data.iter().cloned())
-                                          .collect::<Vec<u8>>()),
+        Chunk::try_new(chunk_type, data).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like [`Chunk::new`], but data over [`MAX_CHUNK_LENGTH`] bytes is an error.
+    ///
+    /// ```
+    /// # use pngme::chunk::Chunk;
+    /// let chunk = Chunk::try_new("ruSt".parse().unwrap(), b"payload".to_vec()).unwrap();
+    /// assert_eq!(chunk.length().get(), 7);
+    /// ```
+    pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Self, PngMsgError> {
+        let length = checked_length(data.len())?;
+        Ok(Chunk {
+            length,
+            crc: Chunk::compute_crc(&chunk_type, &data),
             chunk_type,
-            data,
-        }
+            data: Arc::new(data),
+        })
     }
 
-    pub fn length(&self) -> u32 {
-        self.length
+    fn compute_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+        crc_of(&[&chunk_type.bytes(), data])
     }
 
+    /// The length field, which counts data bytes only.
+    ///
+    /// ```
+    /// # use pngme::chunk::Chunk;
+    /// let chunk = Chunk::new("ruSt".parse().unwrap(), vec![0; 42]);
+    /// assert_eq!(chunk.length().get(), 42);
+    /// assert_eq!(chunk.serialized_size().get(), 54);
+    /// ```
+    pub fn length(&self) -> ChunkLength {
+        ChunkLength(self.length)
+    }
+
+    // Based on the data actually held, so it stays right even if the length field is stale.
+    pub fn serialized_size(&self) -> SerializedSize {
+        SerializedSize(self.data.len() as u64 + CHUNK_OVERHEAD)
+    }
+
+    /// ```
+    /// # use pngme::chunk::Chunk;
+    /// let chunk = Chunk::new("ruSt".parse().unwrap(), Vec::new());
+    /// assert!(chunk.chunk_type().is_safe_to_copy());
+    /// ```
     pub fn chunk_type(&self) -> &ChunkType {
         &self.chunk_type
     }
 
+    /// ```
+    /// # use pngme::chunk::Chunk;
+    /// let chunk = Chunk::new("ruSt".parse().unwrap(), b"payload".to_vec());
+    /// assert_eq!(chunk.data(), b"payload");
+    /// ```
     pub fn data(&self) -> &[u8] {
         &self.data
     }
 
+    /// Takes the data out of the chunk; it is only copied when a clone still shares it.
+    ///
+    /// ```
+    /// # use pngme::chunk::Chunk;
+    /// let chunk = Chunk::new("ruSt".parse().unwrap(), b"payload".to_vec());
+    /// assert_eq!(chunk.into_data(), b"payload".to_vec());
+    /// ```
+    pub fn into_data(self) -> Vec<u8> {
+        Arc::try_unwrap(self.data).unwrap_or_else(|shared| (*shared).clone())
+    }
+
+    /// The CRC as stored, which differs from `computed_crc()` for a leniently parsed bad chunk.
+    ///
+    /// ```
+    /// # use pngme::chunk::Chunk;
+    /// let chunk = Chunk::new("IEND".parse().unwrap(), Vec::new());
+    /// assert_eq!(chunk.crc(), 0xAE42_6082);
+    /// ```
     pub fn crc(&self) -> u32 {
         self.crc
     }
 
-    pub fn data_as_string(&self) -> Result<String, Error> {
+    pub fn map_data<F: FnOnce(&mut Vec<u8>)>(&mut self, f: F) {
+        let data = Arc::make_mut(&mut self.data);
+        f(data);
+        self.length = checked_length(data.len()).unwrap_or_else(|e| panic!("{}", e));
+        self.crc = Chunk::compute_crc(&self.chunk_type, data);
+    }
+
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.length = checked_length(data.len()).unwrap_or_else(|e| panic!("{}", e));
+        self.crc = Chunk::compute_crc(&self.chunk_type, &data);
+        self.data = Arc::new(data);
+    }
+
+    // tEXt layout: a 1-79 character Latin-1 keyword, a NUL, then Latin-1 text without NULs.
+    pub fn new_text(keyword: &str, text: &str) -> Result<Chunk, PngMsgError> {
+        let mut data = keyword_field(keyword)?;
+        push_latin1(&mut data, text)?;
+        Chunk::try_new(ChunkType::TEXT, data)
+    }
+
+    // Splits on the first NUL; the keyword is not re-validated so odd files still read.
+    pub fn as_text(&self) -> Result<(String, String), PngMsgError> {
+        let (keyword, text) = self.keyword_and_rest("tEXt")?;
+        Ok((latin1(keyword), latin1(text)))
+    }
+
+    // zTXt layout: the tEXt keyword and NUL, compression method 0, then the zlib-deflated text.
+    #[cfg(feature = "compression")]
+    pub fn new_ztxt(keyword: &str, text: &str) -> Result<Chunk, PngMsgError> {
+        let mut data = keyword_field(keyword)?;
+        let mut plain = Vec::with_capacity(text.len());
+        push_latin1(&mut plain, text)?;
+        data.push(0);
+        data.extend_from_slice(&deflate(&plain)?);
+        Chunk::try_new(ChunkType::ZTXT, data)
+    }
+
+    #[cfg(feature = "compression")]
+    pub fn as_ztxt(&self) -> Result<(String, String), PngMsgError> {
+        let (keyword, rest) = self.keyword_and_rest("zTXt")?;
+        let (&method, stream) = rest.split_first().ok_or(PngMsgError::UnknownCompressionMethod(None))?;
+        if method != 0 {
+            return Err(PngMsgError::UnknownCompressionMethod(Some(method)));
+        }
+        let text = inflate(stream)?;
+        Ok((latin1(keyword), latin1(&text)))
+    }
+
+    // iTXt layout: keyword, NUL, compression flag, method 0, language tag, NUL, translated keyword,
+    // NUL, then UTF-8 text, deflated when `compressed` is set.
+    pub fn new_itxt(keyword: &str, language: &str, translated_keyword: &str, text: &str, compressed: bool)
+        -> Result<Chunk, PngMsgError> {
+        if !language.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+            return Err(PngMsgError::InvalidLanguageTag(language.to_string()));
+        }
+        if translated_keyword.contains('\0') {
+            return Err(PngMsgError::InvalidTextChar('\0'));
+        }
+        let mut data = keyword_field(keyword)?;
+        data.extend_from_slice(&[compressed as u8, 0]);
+        for field in [language, translated_keyword] {
+            data.extend_from_slice(field.as_bytes());
+            data.push(0);
+        }
+        if compressed {
+            data.extend_from_slice(&deflate(text.as_bytes())?);
+        } else {
+            data.extend_from_slice(text.as_bytes());
+        }
+        Chunk::try_new(ChunkType::ITXT, data)
+    }
+
+    pub fn as_itxt(&self) -> Result<InternationalText, PngMsgError> {
+        let (keyword, rest) = self.keyword_and_rest("iTXt")?;
+        let (compressed, rest) = match rest {
+            [flag @ (0 | 1), 0, rest @ ..] => (*flag == 1, rest),
+            [0 | 1, method, ..] => return Err(PngMsgError::UnknownCompressionMethod(Some(*method))),
+            [0 | 1] => return Err(PngMsgError::UnknownCompressionMethod(None)),
+            [flag, ..] => return Err(PngMsgError::InvalidCompressionFlag(*flag)),
+            [] => return Err(PngMsgError::MissingTextField("compression flag")),
+        };
+        let mut fields = rest.splitn(3, |&b| b == 0);
+        let language = fields.next().unwrap_or_default();
+        let translated_keyword = fields.next().ok_or(PngMsgError::MissingTextField("language tag"))?;
+        let text = fields.next().ok_or(PngMsgError::MissingTextField("translated keyword"))?;
+        let text = if compressed { inflate(text)? } else { text.to_vec() };
+
+        Ok(InternationalText {
+            keyword: latin1(keyword),
+            compressed,
+            language: String::from_utf8(language.to_vec())?,
+            translated_keyword: String::from_utf8(translated_keyword.to_vec())?,
+            text: String::from_utf8(text)?,
+        })
+    }
+
+    fn keyword_and_rest(&self, expected: &'static str) -> Result<(&[u8], &[u8]), PngMsgError> {
+        if self.chunk_type.bytes() != expected.as_bytes() {
+            return Err(PngMsgError::WrongChunkType { expected, found: self.chunk_type });
+        }
+        let nul = self.data.iter().position(|&b| b == 0).ok_or(PngMsgError::MissingKeywordSeparator)?;
+        Ok((&self.data[..nul], &self.data[nul + 1..]))
+    }
+
+    /// The data in the classic dump layout: offset, 16 hex bytes, then the bytes as ASCII with
+    /// `.` for anything unprintable. Only the first `limit` bytes are shown.
+    ///
+    /// ```
+    /// # use pngme::chunk::Chunk;
+    /// let chunk = Chunk::new("ruSt".parse().unwrap(), b"Hi\0".to_vec());
+    /// assert_eq!(chunk.hex_dump(64), "0  48 69 00                                          |Hi.|\n");
+    /// ```
+    pub fn hex_dump(&self, limit: usize) -> String {
+        let shown = &self.data[..self.data.len().min(limit)];
+        // Offsets are padded to the width of the chunk's size, so every line of one dump lines up.
+        let width = format!("{:x}", self.data.len()).len();
+        let mut out = String::new();
+        for (line, bytes) in shown.chunks(HEX_DUMP_WIDTH).enumerate() {
+            out.push_str(&format!("{:0width$x} ", line * HEX_DUMP_WIDTH, width = width));
+            for i in 0..HEX_DUMP_WIDTH {
+                out.push_str(if i == HEX_DUMP_WIDTH / 2 { "  " } else { " " });
+                match bytes.get(i) {
+                    Some(byte) => out.push_str(&format!("{:02x}", byte)),
+                    None => out.push_str("  "),
+                }
+            }
+            out.push_str("  |");
+            out.extend(bytes.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+            out.push_str("|\n");
+        }
+        if self.data.len() > shown.len() {
+            out.push_str(&format!("... {} more bytes\n", self.data.len() - shown.len()));
+        }
+        out
+    }
+
+    pub fn data_as_string(&self) -> Result<String, PngMsgError> {
         Ok(String::from_utf8(self.data().to_vec())?)
     }
 
+    // For data that is not text; see `text_codec` for the way back.
+    pub fn data_as_base64(&self) -> String {
+        base64_encode(self.data())
+    }
+
+    pub fn data_as_hex(&self) -> String {
+        hex_encode(self.data())
+    }
+
+    // Reads the chunk at the front of `bytes`, which may hold more after it, and returns it with
+    // the number of bytes it took up.
+    pub fn parse_prefix(bytes: &[u8]) -> Result<(Chunk, usize), PngMsgError> {
+        let (chunk, consumed) = Chunk::parse_prefix_lenient(bytes)?;
+        let computed = chunk.computed_crc();
+        if chunk.crc != computed {
+            return Err(CrcMismatch { stored: chunk.crc, computed, position: None }.into());
+        }
+        Ok((chunk, consumed))
+    }
+
+    // Like `parse_prefix`, but a wrong CRC is kept as read instead of rejected, so `crc()` and
+    // `computed_crc()` differ until `repair_crc`. Every other problem is still an error.
+    pub fn parse_prefix_lenient(bytes: &[u8]) -> Result<(Chunk, usize), PngMsgError> {
+        let field = |at: usize| -> Option<[u8; 4]> { bytes.get(at..at + 4)?.try_into().ok() };
+
+        let length = field(0).map(u32::from_be_bytes)
+            .ok_or(ChunkParseError::MissingLength { got: bytes.len() })?;
+        checked_length(length as usize)?;
+        let type_bytes = field(4).ok_or(ChunkParseError::MissingChunkType { got: bytes.len() - 4 })?;
+        let chunk_type = ChunkType::try_from(type_bytes)?;
+
+        let data_end = 8 + length as usize;
+        let data_bytes = bytes.get(8..data_end)
+            .ok_or(ChunkParseError::TruncatedData { declared: length, available: bytes.len() - 8 })?;
+        let crc_from_slice = field(data_end).map(u32::from_be_bytes)
+            .ok_or(ChunkParseError::MissingCrc { got: bytes.len() - data_end })?;
+
+        let mut chunk = Chunk::new(chunk_type, data_bytes.to_vec());
+        chunk.crc = crc_from_slice;
+        Ok((chunk, data_end + 4))
+    }
+
+    pub fn try_from_lenient(bytes: &[u8]) -> Result<Chunk, PngMsgError> {
+        let (chunk, consumed) = Chunk::parse_prefix_lenient(bytes)?;
+        if bytes.len() > consumed {
+            return Err(ChunkParseError::TrailingBytes { extra: bytes.len() - consumed }.into());
+        }
+        Ok(chunk)
+    }
+
+    pub fn split_at(&self, offset: usize) -> Result<(Chunk, Chunk), PngMsgError> {
+        if offset > self.data.len() {
+            return Err(PngMsgError::SplitPastEnd { offset, len: self.data.len() });
+        }
+        let (head, tail) = self.data.split_at(offset);
+
+        Ok((
+            Chunk::new(self.chunk_type, head.to_vec()),
+            Chunk::new(self.chunk_type, tail.to_vec()),
+        ))
+    }
+
+    pub fn concat(&self, other: &Chunk) -> Result<Chunk, PngMsgError> {
+        if self.chunk_type != other.chunk_type {
+            return Err(PngMsgError::ChunkTypesDiffer {
+                first: self.chunk_type,
+                second: other.chunk_type,
+            });
+        }
+        checked_length(self.data.len() + other.data.len())?;
+
+        let mut data = Vec::with_capacity(self.data.len() + other.data.len());
+        data.extend_from_slice(&self.data);
+        data.extend_from_slice(&other.data);
+
+        Ok(Chunk::new(self.chunk_type, data))
+    }
+
+    pub fn computed_crc(&self) -> u32 {
+        Chunk::compute_crc(&self.chunk_type, &self.data)
+    }
+
+    // Recomputes the length and CRC from the data; true when either was wrong.
+    pub fn repair_crc(&mut self) -> bool {
+        if self.is_consistent() {
+            return false;
+        }
+        self.length = self.data.len() as u32;
+        self.crc = self.computed_crc();
+        true
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.length as usize == self.data.len() && self.crc == self.computed_crc()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_raw_fields_for_test(&mut self, length: u32, crc: u32) {
+        self.length = length;
+        self.crc = crc;
+    }
+
+    // Returns the number of bytes written, always `serialized_size()`. A wrong CRC can only be one
+    // `parse_prefix_lenient` kept as read, and is written back as is.
+    pub fn write_to<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<usize> {
+        debug_assert!(self.length as usize == self.data.len(), "{} chunk has a stale length", self.chunk_type);
+        w.write_all(&self.length.to_be_bytes())?;
+        w.write_all(&self.chunk_type.bytes())?;
+        w.write_all(&self.data)?;
+        w.write_all(&self.crc.to_be_bytes())?;
+        Ok(self.data.len() + CHUNK_OVERHEAD as usize)
+    }
+
+    /// The chunk as it appears in a file: length, type, data, then CRC.
+    ///
+    /// ```
+    /// # use pngme::chunk::Chunk;
+    /// let chunk = Chunk::new("IEND".parse().unwrap(), Vec::new());
+    /// assert_eq!(chunk.as_bytes(), [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+    /// ```
     pub fn as_bytes(&self) -> Vec<u8> {
-        self.length().to_be_bytes().iter().cloned()
-            .chain(self.chunk_type().bytes().iter().cloned())
-            .chain(self.data().iter().cloned())
-            .chain(self.crc().to_be_bytes().iter().cloned())
-            .collect()
+        let mut bytes = Vec::with_capacity(self.data.len() + CHUNK_OVERHEAD as usize);
+        self.write_to(&mut bytes).expect("writing to a Vec cannot fail");
+        bytes
     }
 }
 
 impl TryFrom<&[u8]> for Chunk {
-    type Error = Error; 
+    type Error = PngMsgError;
 
+    // Holds the declared length to the default `Limits`; `Png::parse_with` takes its own.
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if let Some(length) = bytes.get(..4) {
+            let length = checked_length(u32::from_be_bytes(length.try_into().unwrap()) as usize)?;
+            Limits::default().check_chunk(length)?;
+        }
+        let (chunk, consumed) = Chunk::parse_prefix(bytes)?;
+        if bytes.len() > consumed {
+            return Err(ChunkParseError::TrailingBytes { extra: bytes.len() - consumed }.into());
+        }
+        Ok(chunk)
+    }
+}
 
-        let data_len = bytes.len();
-        let mut iter = bytes.iter().cloned();
-        let first_four_bytes: [u8; 4] = iter.by_ref().take(4).collect::<Vec<u8>>().try_into().unwrap();
-        let length = u32::from_be_bytes(first_four_bytes);
-
-        let second_four_bytes: Vec<u8> = iter.by_ref().take(4).collect();
-        let chunk_type = ChunkType::try_from(TryInto::<[u8; 4]>::try_into(second_four_bytes.as_slice()).unwrap()).unwrap();
+// A chunk lent out by `Png::iter_mut`. Data changed through `data_mut` gets its length and CRC
+// recomputed when the guard drops, panicking like `map_data` if it grew past the length limit.
+pub struct ChunkMut<'a> {
+    chunk: &'a mut Chunk,
+    changed: bool,
+}
 
-        let data_bytes: Vec<u8> = iter.by_ref().take(data_len - 12).collect();
+impl<'a> ChunkMut<'a> {
+    pub(crate) fn new(chunk: &'a mut Chunk) -> ChunkMut<'a> {
+        ChunkMut { chunk, changed: false }
+    }
 
-        let crc_byte: [u8; 4] = iter.collect::<Vec<u8>>().try_into().unwrap();
-        let crc_from_slice = u32::from_be_bytes(crc_byte);
+    pub fn data_mut(&mut self) -> &mut Vec<u8> {
+        self.changed = true;
+        Arc::make_mut(&mut self.chunk.data)
+    }
+}
 
-        let chunk = Chunk::new(chunk_type, data_bytes);
+impl Deref for ChunkMut<'_> {
+    type Target = Chunk;
 
+    fn deref(&self) -> &Chunk {
+        self.chunk
+    }
+}
 
-        if chunk.crc != crc_from_slice {
-            return Err("Wrong crc".into());
+impl Drop for ChunkMut<'_> {
+    fn drop(&mut self) {
+        if self.changed {
+            let chunk = &mut *self.chunk;
+            chunk.length = checked_length(chunk.data.len()).unwrap_or_else(|e| panic!("{}", e));
+            chunk.crc = Chunk::compute_crc(&chunk.chunk_type, &chunk.data);
         }
+    }
+}
 
-        Ok(chunk)
+pub(crate) fn checked_length(len: usize) -> Result<u32, PngMsgError> {
+    match u32::try_from(len) {
+        Ok(length) if length <= MAX_CHUNK_LENGTH => Ok(length),
+        _ => Err(PngMsgError::DataTooLarge { len }),
+    }
+}
+
+// Chunks are equal when their type and data are; the length and CRC follow from those unless
+// the chunk was read leniently, and a bad CRC should not make two copies of a message differ.
+impl PartialEq for Chunk {
+    fn eq(&self, other: &Chunk) -> bool {
+        self.chunk_type == other.chunk_type && self.data == other.data
+    }
+}
+
+impl Eq for Chunk {}
 
+const HEX_DUMP_WIDTH: usize = 16;
+
+// Only the start of the data is shown, so debugging a multi-megabyte IDAT stays readable.
+const DEBUG_PREVIEW_BYTES: usize = 16;
+
+struct HexPreview<'a>(&'a [u8]);
+
+impl fmt::Debug for HexPreview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shown = &self.0[..self.0.len().min(DEBUG_PREVIEW_BYTES)];
+        f.write_str("\"")?;
+        for (i, byte) in shown.iter().enumerate() {
+            write!(f, "{}{:02x}", if i == 0 { "" } else { " " }, byte)?;
+        }
+        if self.0.len() > shown.len() {
+            write!(f, " ... ({} more bytes)", self.0.len() - shown.len())?;
+        }
+        f.write_str("\"")
+    }
+}
+
+impl fmt::Debug for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chunk")
+            .field("chunk_type", &self.chunk_type.name())
+            .field("length", &self.length)
+            .field("crc", &format_args!("{:#010x}", self.crc))
+            .field("data", &HexPreview(&self.data))
+            .finish()
     }
 }
 
@@ -96,13 +648,90 @@ impl fmt::Display for Chunk {
     }
 }
 
+// The `text` of an iTXt chunk and the fields in front of it; the compression method is always 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalText {
+    pub keyword: String,
+    pub compressed: bool,
+    pub language: String,
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+// Far above any real text chunk, but keeps a tiny zTXt or iTXt from inflating without bound.
+#[cfg(feature = "compression")]
+const MAX_INFLATED_TEXT: usize = 64 * 1024 * 1024;
+
+#[cfg(feature = "compression")]
+pub(crate) fn deflate(bytes: &[u8]) -> Result<Vec<u8>, PngMsgError> {
+    Ok(crate::zlib::compress(bytes))
+}
+
+#[cfg(not(feature = "compression"))]
+pub(crate) fn deflate(_: &[u8]) -> Result<Vec<u8>, PngMsgError> {
+    Err(PngMsgError::CompressionUnavailable)
+}
+
+#[cfg(feature = "compression")]
+fn inflate(stream: &[u8]) -> Result<Vec<u8>, PngMsgError> {
+    crate::zlib::decompress(stream, MAX_INFLATED_TEXT)
+}
+
+#[cfg(not(feature = "compression"))]
+fn inflate(_: &[u8]) -> Result<Vec<u8>, PngMsgError> {
+    Err(PngMsgError::CompressionUnavailable)
+}
+
+// A valid tEXt/zTXt/iTXt keyword followed by its NUL separator.
+pub(crate) fn keyword_field(keyword: &str) -> Result<Vec<u8>, PngMsgError> {
+    let printable = |c: char| matches!(c as u32, 0x20..=0x7e | 0xa1..=0xff);
+    if keyword.is_empty() || keyword.chars().count() > 79 || !keyword.chars().all(printable)
+        || keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(PngMsgError::InvalidKeyword(keyword.to_string()));
+    }
+    let mut field: Vec<u8> = keyword.chars().map(|c| c as u8).collect();
+    field.push(0);
+    Ok(field)
+}
+
+pub(crate) fn push_latin1(out: &mut Vec<u8>, text: &str) -> Result<(), PngMsgError> {
+    for c in text.chars() {
+        match u8::try_from(c as u32) {
+            Ok(byte) if byte != 0 => out.push(byte),
+            _ => return Err(PngMsgError::InvalidTextChar(c)),
+        }
+    }
+    Ok(())
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::chunk_type::ChunkType;
+    use crate::chunk_type::{ChunkType, ChunkTypeError};
     use std::str::FromStr;
 
+    fn parse_error(bytes: &[u8]) -> Option<ChunkParseError> {
+        match Chunk::try_from(bytes) {
+            Err(PngMsgError::TruncatedChunk(e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_try_from_holds_length_to_default_limit() {
+        let mut bytes = vec![0x10, 0, 0, 0];
+        bytes.extend_from_slice(b"ruSt");
+        assert!(matches!(
+            Chunk::try_from(bytes.as_slice()),
+            Err(PngMsgError::LimitExceeded(e)) if e.limit == "max_chunk_bytes" && e.actual == 1 << 28
+        ));
+    }
+
     fn testing_chunk() -> Chunk {
         let data_length: u32 = 42;
         let chunk_type = "RuSt".as_bytes();
@@ -126,14 +755,14 @@ mod tests {
         let chunk_type = ChunkType::from_str("RuSt").unwrap();
         let data = "This is where your secret message will be!".as_bytes().to_vec();
         let chunk = Chunk::new(chunk_type, data);
-        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.length(), ChunkLength(42));
         assert_eq!(chunk.crc(), 2882656334);
     }
 
     #[test]
     fn test_chunk_length() {
         let chunk = testing_chunk();
-        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.length(), ChunkLength(42));
     }
 
     #[test]
@@ -150,6 +779,14 @@ mod tests {
         assert_eq!(chunk_string, expected_chunk_string);
     }
 
+    #[test]
+    fn test_chunk_base64_and_hex() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![0x00, 0xff, 0xc0, 0xaf]);
+        assert!(chunk.data_as_string().is_err());
+        assert_eq!(chunk.data_as_base64(), "AP/Arw==");
+        assert_eq!(chunk.data_as_hex(), "00ffc0af");
+    }
+
     #[test]
     fn test_chunk_crc() {
         let chunk = testing_chunk();
@@ -177,7 +814,7 @@ mod tests {
         let chunk_string = chunk.data_as_string().unwrap();
         let expected_chunk_string = String::from("This is where your secret message will be!");
 
-        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.length(), ChunkLength(42));
         assert_eq!(chunk.chunk_type().to_string(), String::from("RuSt"));
         assert_eq!(chunk_string, expected_chunk_string);
         assert_eq!(chunk.crc(), 2882656334);
@@ -202,6 +839,377 @@ mod tests {
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
         assert!(chunk.is_err());
+        let err = chunk.unwrap_err();
+        assert!(matches!(
+            err,
+            PngMsgError::CrcMismatch(CrcMismatch { stored: 2882656333, computed: 2882656334, position: None })
+        ));
+
+        let mut lenient = Chunk::try_from_lenient(chunk_data.as_ref()).unwrap();
+        assert_eq!((lenient.crc(), lenient.computed_crc()), (2882656333, 2882656334));
+        assert!(lenient.repair_crc());
+        assert_eq!(lenient, testing_chunk());
+        assert!(!lenient.repair_crc());
+
+        let mut bad_type = chunk_data.clone();
+        bad_type[5] = b'5';
+        assert!(matches!(Chunk::try_from_lenient(&bad_type), Err(PngMsgError::InvalidChunkType(_))));
+    }
+
+    #[test]
+    fn test_every_truncation_point() {
+        let whole = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"8 bytes!".to_vec()).as_bytes();
+        assert_eq!(whole.len(), 20);
+
+        for len in 0..=20 {
+            let expected = match len {
+                0..=3 => Some(ChunkParseError::MissingLength { got: len }),
+                4..=7 => Some(ChunkParseError::MissingChunkType { got: len - 4 }),
+                8..=15 => Some(ChunkParseError::TruncatedData { declared: 8, available: len - 8 }),
+                16..=19 => Some(ChunkParseError::MissingCrc { got: len - 16 }),
+                _ => None,
+            };
+            let result = Chunk::try_from(&whole[..len]);
+            match expected {
+                Some(expected) => assert_eq!(parse_error(&whole[..len]), Some(expected), "length {}", len),
+                None => assert!(result.is_ok(), "length {}", len),
+            }
+        }
+
+        let mut longer = whole.clone();
+        longer.extend_from_slice(b"xy");
+        assert_eq!(parse_error(&longer), Some(ChunkParseError::TrailingBytes { extra: 2 }));
+    }
+
+    #[test]
+    fn test_random_input_never_panics() {
+        let mut seed: u32 = 0x9e37_79b9;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+        for _ in 0..2000 {
+            let len = (next() % 64) as usize;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            // Small declared lengths, so some inputs get as far as the type and CRC checks.
+            if len >= 4 && next() % 2 == 0 {
+                bytes[..4].copy_from_slice(&(next() % 48).to_be_bytes());
+            }
+            assert!(Chunk::try_from(bytes.as_slice()).is_err() || bytes.len() >= 12);
+        }
+
+        let mut bad_type = testing_chunk().as_bytes();
+        bad_type[5] = b'5';
+        assert!(matches!(Chunk::try_from(bad_type.as_slice()), Err(PngMsgError::InvalidChunkType(ChunkTypeError::NotAsciiLetter { position: 2, .. }))));
+        assert!(Chunk::try_from(&[0xff, 0xff, 0xff, 0xff, b'r', b'u', b'S', b't'][..]).is_err());
+    }
+
+    #[test]
+    fn test_parse_prefix_walks_a_buffer() {
+        let first = testing_chunk();
+        let second = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"next".to_vec());
+        let mut bytes = first.as_bytes();
+        bytes.extend(second.as_bytes());
+
+        let (chunk, consumed) = Chunk::parse_prefix(&bytes).unwrap();
+        assert_eq!((chunk, consumed), (first, 54));
+        assert_eq!(Chunk::parse_prefix(&bytes[consumed..]).unwrap(), (second, 16));
+        assert_eq!(parse_error(&bytes), Some(ChunkParseError::TrailingBytes { extra: 16 }));
+    }
+
+    #[test]
+    fn test_write_to_matches_as_bytes() {
+        let chunk = testing_chunk();
+        let mut out = b"prefix".to_vec();
+        assert_eq!(chunk.write_to(&mut out).unwrap(), 54);
+        assert_eq!(&out[6..], chunk.as_bytes().as_slice());
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let chunk = Chunk::new_text("Comment", "caf\u{e9}").unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(chunk.data(), b"Comment\0caf\xe9");
+        assert_eq!(chunk.as_text().unwrap(), ("Comment".to_string(), "caf\u{e9}".to_string()));
+
+        let with_nul = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Note\0a\0b".to_vec());
+        assert_eq!(with_nul.as_text().unwrap().1, "a\0b");
+    }
+
+    #[test]
+    fn test_text_errors() {
+        assert!(matches!(Chunk::new_text("Comment", "\u{263a}"), Err(PngMsgError::InvalidTextChar('\u{263a}'))));
+        assert!(matches!(Chunk::new_text("Comment", "a\0b"), Err(PngMsgError::InvalidTextChar('\0'))));
+        for keyword in ["", " Comment", "Two  spaces", &"k".repeat(80)] {
+            assert!(matches!(Chunk::new_text(keyword, "x"), Err(PngMsgError::InvalidKeyword(_))), "{:?}", keyword);
+        }
+        assert!(Chunk::new_text(&"k".repeat(79), "x").is_ok());
+
+        let no_nul = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Comment".to_vec());
+        assert!(matches!(no_nul.as_text(), Err(PngMsgError::MissingKeywordSeparator)));
+        assert!(matches!(testing_chunk().as_text(), Err(PngMsgError::WrongChunkType { expected: "tEXt", .. })));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_ztxt_round_trip() {
+        let long = "caf\u{e9} ".repeat(1000);
+        for text in ["", "x", "\u{ff}\u{a1}!", long.as_str()] {
+            let chunk = Chunk::new_ztxt("Comment", text).unwrap();
+            assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+            assert!(chunk.data().starts_with(b"Comment\0\0"));
+            assert_eq!(chunk.as_ztxt().unwrap(), ("Comment".to_string(), text.to_string()));
+        }
+        assert!(Chunk::new_ztxt("Comment", &long).unwrap().data().len() < 100);
+        assert!(matches!(Chunk::new_ztxt("", "x"), Err(PngMsgError::InvalidKeyword(_))));
+        assert!(matches!(Chunk::new_ztxt("Comment", "\u{263a}"), Err(PngMsgError::InvalidTextChar(_))));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_ztxt_errors() {
+        let chunk = Chunk::new_ztxt("Comment", &"some text ".repeat(50)).unwrap();
+        let mut data = chunk.data().to_vec();
+        let last = data.len() - 5;
+        data[last] ^= 0xff;
+        let ztxt = ChunkType::from_str("zTXt").unwrap();
+        let corrupt = Chunk::new(ztxt, data.clone());
+        assert!(matches!(corrupt.as_ztxt(), Err(PngMsgError::InvalidCompressedData(_))));
+
+        data.truncate(20);
+        assert!(Chunk::new(ztxt, data).as_ztxt().is_err());
+        let method = Chunk::new(ztxt, b"Comment\0\x01\x78\x01".to_vec());
+        assert!(matches!(method.as_ztxt(), Err(PngMsgError::UnknownCompressionMethod(Some(1)))));
+        let no_method = Chunk::new(ztxt, b"Comment\0".to_vec());
+        assert!(matches!(no_method.as_ztxt(), Err(PngMsgError::UnknownCompressionMethod(None))));
+        assert!(matches!(testing_chunk().as_ztxt(), Err(PngMsgError::WrongChunkType { expected: "zTXt", .. })));
+    }
+
+    #[test]
+    fn test_itxt_round_trip() {
+        let chunk = Chunk::new_itxt("Title", "ja", "\u{984c}\u{540d}", "\u{3053}\u{3093}\u{306b}\u{3061}\u{306f}", false).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "iTXt");
+        assert!(chunk.data().starts_with(b"Title\0\0\0ja\0"));
+        assert_eq!(chunk.as_itxt().unwrap(), InternationalText {
+            keyword: "Title".to_string(),
+            compressed: false,
+            language: "ja".to_string(),
+            translated_keyword: "\u{984c}\u{540d}".to_string(),
+            text: "\u{3053}\u{3093}\u{306b}\u{3061}\u{306f}".to_string(),
+        });
+
+        let bare = Chunk::new_itxt("Comment", "", "", "", false).unwrap();
+        assert_eq!(bare.data(), b"Comment\0\0\0\0\0");
+        let text = bare.as_itxt().unwrap();
+        assert_eq!((text.language.as_str(), text.translated_keyword.as_str(), text.text.as_str()), ("", "", ""));
+
+        let with_nul = Chunk::new(ChunkType::from_str("iTXt").unwrap(), b"Note\0\0\0en-GB\0\0a\0b".to_vec());
+        assert_eq!(with_nul.as_itxt().unwrap().text, "a\0b");
+    }
+
+    #[test]
+    fn test_itxt_errors() {
+        assert!(matches!(Chunk::new_itxt("Title", "en_GB", "", "x", false), Err(PngMsgError::InvalidLanguageTag(_))));
+        assert!(matches!(Chunk::new_itxt("Title", "en", "a\0b", "x", false), Err(PngMsgError::InvalidTextChar('\0'))));
+        assert!(matches!(Chunk::new_itxt(" Title", "en", "", "x", false), Err(PngMsgError::InvalidKeyword(_))));
+
+        let itxt = |data: &[u8]| Chunk::new(ChunkType::from_str("iTXt").unwrap(), data.to_vec()).as_itxt();
+        assert!(matches!(itxt(b"Title"), Err(PngMsgError::MissingKeywordSeparator)));
+        assert!(matches!(itxt(b"Title\0"), Err(PngMsgError::MissingTextField("compression flag"))));
+        assert!(matches!(itxt(b"Title\0\0"), Err(PngMsgError::UnknownCompressionMethod(None))));
+        assert!(matches!(itxt(b"Title\0\x02\0\0\0"), Err(PngMsgError::InvalidCompressionFlag(2))));
+        assert!(matches!(itxt(b"Title\0\0\x08\0\0"), Err(PngMsgError::UnknownCompressionMethod(Some(8)))));
+        assert!(matches!(itxt(b"Title\0\0\0en"), Err(PngMsgError::MissingTextField("language tag"))));
+        assert!(matches!(itxt(b"Title\0\0\0en\0Titel"), Err(PngMsgError::MissingTextField("translated keyword"))));
+        assert!(matches!(itxt(b"Title\0\0\0en\0\0\xff"), Err(PngMsgError::Utf8(_))));
+        assert!(matches!(testing_chunk().as_itxt(), Err(PngMsgError::WrongChunkType { expected: "iTXt", .. })));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compressed_itxt() {
+        let text = "\u{3053}\u{3093}\u{306b}\u{3061}\u{306f} ".repeat(200);
+        let chunk = Chunk::new_itxt("Comment", "ja", "", &text, true).unwrap();
+        assert!(chunk.data().starts_with(b"Comment\0\x01\0ja\0\0"));
+        assert!(chunk.data().len() < text.len() / 10);
+        let parsed = chunk.as_itxt().unwrap();
+        assert!(parsed.compressed);
+        assert_eq!(parsed.text, text);
+
+        let mut data = chunk.data().to_vec();
+        data.truncate(data.len() - 3);
+        let cut = Chunk::new(ChunkType::from_str("iTXt").unwrap(), data);
+        assert!(matches!(cut.as_itxt(), Err(PngMsgError::InvalidCompressedData(_))));
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn test_compressed_itxt_needs_feature() {
+        assert!(matches!(Chunk::new_itxt("Comment", "", "", "x", true), Err(PngMsgError::CompressionUnavailable)));
+        let chunk = Chunk::new(ChunkType::from_str("iTXt").unwrap(), b"Comment\0\x01\0\0\0\x78\x9c".to_vec());
+        assert!(matches!(chunk.as_itxt(), Err(PngMsgError::CompressionUnavailable)));
+    }
+
+    #[test]
+    fn test_split_at() {
+        let chunk = testing_chunk();
+        let (head, tail) = chunk.split_at(8).unwrap();
+
+        assert_eq!(head.data(), b"This is ");
+        assert_eq!(tail.data(), b"where your secret message will be!");
+        assert_eq!(head.length(), ChunkLength(8));
+        assert_eq!(tail.length(), ChunkLength(34));
+        assert_eq!(head.chunk_type(), chunk.chunk_type());
+        assert_eq!(Chunk::try_from(head.as_bytes().as_ref()).unwrap(), head);
+        assert_eq!(Chunk::try_from(tail.as_bytes().as_ref()).unwrap(), tail);
+    }
+
+    #[test]
+    fn test_length_vs_serialized_size() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.length(), ChunkLength(42));
+        assert_eq!(chunk.serialized_size(), SerializedSize(54));
+        assert_eq!(chunk.length().serialized_size(), chunk.serialized_size());
+        assert_eq!(chunk.serialized_size().get(), chunk.as_bytes().len() as u64);
+    }
+
+    #[test]
+    fn test_split_at_bounds() {
+        let chunk = testing_chunk();
+
+        let (head, tail) = chunk.split_at(0).unwrap();
+        assert_eq!(head.length(), ChunkLength(0));
+        assert_eq!(tail, chunk);
+
+        let (head, tail) = chunk.split_at(42).unwrap();
+        assert_eq!(head, chunk);
+        assert_eq!(tail.length(), ChunkLength(0));
+
+        assert!(matches!(chunk.split_at(43), Err(PngMsgError::SplitPastEnd { offset: 43, len: 42 })));
+    }
+
+    #[test]
+    fn test_concat() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let first = Chunk::new(chunk_type, b"This is where your ".to_vec());
+        let second = Chunk::new(chunk_type, b"secret message will be!".to_vec());
+
+        assert_eq!(first.concat(&second).unwrap(), testing_chunk());
+    }
+
+    #[test]
+    fn test_length_over_spec_limit() {
+        assert_eq!(checked_length(MAX_CHUNK_LENGTH as usize).unwrap(), MAX_CHUNK_LENGTH);
+        for len in [MAX_CHUNK_LENGTH as usize + 1, u32::MAX as usize + 5] {
+            assert!(matches!(checked_length(len), Err(PngMsgError::DataTooLarge { len: l }) if l == len));
+        }
+        assert_eq!(
+            checked_length(1 << 31).unwrap_err().to_string(),
+            "Chunk data of 2147483648 bytes is over the PNG limit of 2147483647 bytes"
+        );
+
+        // Rejected from the length field alone, before the (missing) data is looked at.
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[..4].copy_from_slice(&0x8000_0000u32.to_be_bytes());
+        assert!(matches!(Chunk::try_from(bytes.as_ref()), Err(PngMsgError::DataTooLarge { len: 0x8000_0000 })));
+    }
+
+    #[test]
+    fn test_concat_type_mismatch() {
+        let first = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"abc".to_vec());
+        let second = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"def".to_vec());
+
+        assert!(matches!(first.concat(&second), Err(PngMsgError::ChunkTypesDiffer { .. })));
+    }
+
+    #[test]
+    fn test_split_then_concat_round_trips() {
+        // Small xorshift so the property check stays deterministic without extra deps.
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        for _ in 0..256 {
+            let len = (next() % 512) as usize;
+            let data: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            let chunk = Chunk::new(chunk_type, data);
+            let offset = (next() as usize) % (len + 1);
+
+            let (head, tail) = chunk.split_at(offset).unwrap();
+            assert_eq!(head.length().get() as usize, offset);
+            assert_eq!(head.concat(&tail).unwrap(), chunk);
+        }
+    }
+
+    #[test]
+    fn test_clone_shares_data() {
+        let chunk = testing_chunk();
+        let copy = chunk.clone();
+        assert!(Arc::ptr_eq(&chunk.data, &copy.data));
+    }
+
+    #[test]
+    fn test_map_data_copies_on_write() {
+        let original = testing_chunk();
+        let mut edited = original.clone();
+        edited.map_data(|data| data.extend_from_slice(b" Or not."));
+
+        assert!(!Arc::ptr_eq(&original.data, &edited.data));
+        assert_eq!(original.data_as_string().unwrap(), "This is where your secret message will be!");
+        assert_eq!(original.length(), ChunkLength(42));
+        assert_eq!(edited.data_as_string().unwrap(), "This is where your secret message will be! Or not.");
+        assert_eq!(edited.length(), ChunkLength(50));
+        assert!(edited.is_consistent());
+    }
+
+    #[test]
+    fn test_set_data() {
+        let mut chunk = testing_chunk();
+        let copy = chunk.clone();
+        chunk.set_data(b"short".to_vec());
+
+        assert_eq!(chunk.length(), ChunkLength(5));
+        assert!(chunk.is_consistent());
+        assert_eq!(copy, testing_chunk());
+    }
+
+    #[test]
+    fn test_new_chunk_is_consistent() {
+        assert!(testing_chunk().is_consistent());
+    }
+
+    #[test]
+    fn test_stale_crc_is_inconsistent() {
+        let mut chunk = testing_chunk();
+        chunk.set_raw_fields_for_test(42, 1);
+        assert!(!chunk.is_consistent());
+        assert_eq!(chunk.computed_crc(), 2882656334);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "stale length")]
+    fn test_as_bytes_panics_on_stale_length_in_debug() {
+        let mut chunk = testing_chunk();
+        chunk.set_raw_fields_for_test(41, 2882656334);
+        chunk.as_bytes();
+    }
+
+    #[test]
+    fn test_bad_crc_is_written_as_read() {
+        let mut bytes = testing_chunk().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        let chunk = Chunk::try_from_lenient(&bytes).unwrap();
+        assert!(!chunk.is_consistent());
+        assert_eq!(chunk.as_bytes(), bytes);
     }
 
     #[test]
@@ -224,6 +1232,54 @@ mod tests {
         
         let _chunk_string = format!("{}", chunk);
     }
-}
 
+    #[test]
+    fn test_debug_shows_fields_and_truncates_data() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Hi".to_vec());
+        assert_eq!(format!("{:?}", chunk), format!(
+            "Chunk {{ chunk_type: \"ruSt\", length: 2, crc: {:#010x}, data: \"48 69\" }}", chunk.crc()));
+
+        let big = Chunk::new(ChunkType::IDAT, vec![0xab; 5_000_000]);
+        let debug = format!("{:?}", big);
+        assert!(debug.ends_with(&format!("data: \"{} ... (4999984 more bytes)\" }}", ["ab"; 16].join(" "))), "{}", debug);
+    }
+
+    #[test]
+    fn test_eq_compares_type_and_data_only() {
+        let chunk = testing_chunk();
+        let mut bad_crc = chunk.clone();
+        bad_crc.set_raw_fields_for_test(42, 1);
+        assert_eq!(chunk, bad_crc);
+        assert_ne!(chunk, Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"other".to_vec()));
+        assert_ne!(chunk, Chunk::new(ChunkType::from_str("ruSt").unwrap(), chunk.data().to_vec()));
+    }
+
+    #[test]
+    fn test_into_data_with_and_without_a_shared_clone() {
+        let chunk = testing_chunk();
+        let clone = chunk.clone();
+        assert_eq!(chunk.into_data(), clone.data());
+        assert_eq!(clone.into_data(), b"This is where your secret message will be!");
+    }
+
+    #[test]
+    fn test_hex_dump_layout() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Hello, hex dump!\x00\x7f\xffend".to_vec());
+        assert_eq!(chunk.hex_dump(1024), concat!(
+            "00  48 65 6c 6c 6f 2c 20 68  65 78 20 64 75 6d 70 21  |Hello, hex dump!|\n",
+            "10  00 7f ff 65 6e 64                                 |...end|\n",
+        ));
+        assert_eq!(Chunk::new(ChunkType::IEND, Vec::new()).hex_dump(16), "");
+    }
 
+    #[test]
+    fn test_hex_dump_limit() {
+        let chunk = Chunk::new(ChunkType::IDAT, vec![b'a'; 5_000_000]);
+        let dump = chunk.hex_dump(20);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("000000  61 61"));
+        assert!(lines[1].starts_with("000010  61 61 61 61   "), "{}", lines[1]);
+        assert_eq!(lines[2], "... 4999980 more bytes");
+    }
+}
@@ -0,0 +1,266 @@
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::chunk::{deflate, keyword_field, push_latin1, Chunk, MAX_CHUNK_LENGTH};
+use crate::chunk_type::ChunkType;
+use crate::error::PngMsgError;
+
+enum Source {
+    Data(Vec<u8>),
+    Text { keyword: String, value: String },
+    Reader(Vec<u8>),
+}
+
+impl Source {
+    fn name(&self) -> &'static str {
+        match self {
+            Source::Data(_) => "data",
+            Source::Text { .. } => "text",
+            Source::Reader(_) => "from_reader",
+        }
+    }
+}
+
+/// Assembles a chunk step by step, for layouts where [`Chunk::new`] and the text constructors
+/// would mean doing the framing by hand.
+///
+/// Apart from the chunk type, nothing is checked until [`build`](ChunkBuilder::build), which
+/// reports every problem at once in a single [`PngMsgError::ChunkBuild`].
+///
+/// ```
+/// use pngme::chunk_builder::ChunkBuilder;
+/// use pngme::error::PngMsgError;
+///
+/// let chunk = ChunkBuilder::new("iTXt")?.text("Comment", "grüße").max_len(1 << 20).build()?;
+/// assert_eq!(chunk.as_itxt()?.text, "grüße");
+///
+/// // An empty keyword, and tEXt cannot be compressed: both are reported.
+/// let error = ChunkBuilder::new("tEXt")?.text("", "hi").compressed(true).build().unwrap_err();
+/// assert!(matches!(error, PngMsgError::ChunkBuild(ref problems) if problems.len() == 2));
+/// # Ok::<(), pngme::error::PngMsgError>(())
+/// ```
+pub struct ChunkBuilder {
+    chunk_type: ChunkType,
+    sources: Vec<Source>,
+    compressed: Option<bool>,
+    max_len: usize,
+    // Found before `build`, e.g. a reader that ran dry.
+    problems: Vec<PngMsgError>,
+}
+
+impl ChunkBuilder {
+    /// Starts a chunk of the given type; the type string is the one thing checked up front.
+    pub fn new(chunk_type: &str) -> Result<ChunkBuilder, PngMsgError> {
+        Ok(ChunkBuilder {
+            chunk_type: ChunkType::from_str(chunk_type).map_err(PngMsgError::InvalidChunkType)?,
+            sources: Vec::new(),
+            compressed: None,
+            max_len: MAX_CHUNK_LENGTH as usize,
+            problems: Vec::new(),
+        })
+    }
+
+    /// Uses `bytes` as the chunk data, deflated first when [`compressed`](ChunkBuilder::compressed).
+    pub fn data(mut self, bytes: impl Into<Vec<u8>>) -> ChunkBuilder {
+        self.sources.push(Source::Data(bytes.into()));
+        self
+    }
+
+    /// A keyword and text in the layout of the chunk type, which must be tEXt, zTXt or iTXt.
+    pub fn text(mut self, keyword: &str, value: &str) -> ChunkBuilder {
+        self.sources.push(Source::Text { keyword: keyword.to_string(), value: value.to_string() });
+        self
+    }
+
+    /// Reads exactly `len` bytes of chunk data; a reader that ends early is reported by `build`.
+    pub fn from_reader<R: Read>(mut self, reader: R, len: usize) -> ChunkBuilder {
+        if len > MAX_CHUNK_LENGTH as usize {
+            self.problems.push(PngMsgError::DataTooLarge { len });
+            return self;
+        }
+        let mut bytes = Vec::new();
+        match reader.take(len as u64).read_to_end(&mut bytes) {
+            Ok(read) if read < len => self.problems.push(PngMsgError::ShortRead { expected: len, read }),
+            Ok(_) => {}
+            Err(e) => self.problems.push(PngMsgError::Io(e)),
+        }
+        self.sources.push(Source::Reader(bytes));
+        self
+    }
+
+    /// Deflates the data with zlib (needs the `compression` feature). For text, picks between
+    /// the compressed and plain iTXt layouts; zTXt is always compressed and tEXt never is.
+    pub fn compressed(mut self, compressed: bool) -> ChunkBuilder {
+        self.compressed = Some(compressed);
+        self
+    }
+
+    /// Refuses to build a chunk with more than `max_len` data bytes; never above the PNG limit.
+    pub fn max_len(mut self, max_len: usize) -> ChunkBuilder {
+        self.max_len = max_len.min(MAX_CHUNK_LENGTH as usize);
+        self
+    }
+
+    pub fn build(self) -> Result<Chunk, PngMsgError> {
+        let ChunkBuilder { chunk_type, mut sources, compressed, max_len, mut problems } = self;
+        if sources.len() > 1 {
+            let names: Vec<&str> = sources.iter().map(Source::name).collect();
+            problems.push(PngMsgError::ConflictingChunkOptions(
+                format!("only one of data, text and from_reader can be given, not {}", names.join(" and "))
+            ));
+        }
+
+        let data = match sources.drain(..).next() {
+            Some(Source::Text { keyword, value }) => text_data(&chunk_type, &keyword, &value, compressed, &mut problems),
+            Some(Source::Data(bytes) | Source::Reader(bytes)) => raw_data(&chunk_type, bytes, compressed, &mut problems),
+            None => raw_data(&chunk_type, Vec::new(), compressed, &mut problems),
+        };
+        if let Some(data) = data.as_ref().filter(|data| data.len() > max_len) {
+            problems.push(PngMsgError::ChunkOverCap { len: data.len(), max: max_len });
+        }
+
+        match data {
+            Some(data) if problems.is_empty() => Chunk::try_new(chunk_type, data),
+            _ => Err(PngMsgError::ChunkBuild(problems)),
+        }
+    }
+}
+
+fn is_text_type(chunk_type: &ChunkType) -> bool {
+    matches!(&chunk_type.bytes(), b"tEXt" | b"zTXt" | b"iTXt")
+}
+
+// Compressed raw data in a text chunk would have no keyword in front of it.
+fn raw_data(chunk_type: &ChunkType, bytes: Vec<u8>, compressed: Option<bool>, problems: &mut Vec<PngMsgError>)
+    -> Option<Vec<u8>> {
+    if compressed != Some(true) {
+        return Some(bytes);
+    }
+    if is_text_type(chunk_type) {
+        problems.push(PngMsgError::ConflictingChunkOptions(
+            format!("compressed data would not be a valid {} chunk, use text", chunk_type)
+        ));
+        return None;
+    }
+    deflate(&bytes).map_err(|e| problems.push(e)).ok()
+}
+
+// Every field is checked even after one fails, so the error lists them all.
+fn text_data(chunk_type: &ChunkType, keyword: &str, value: &str, compressed: Option<bool>, problems: &mut Vec<PngMsgError>)
+    -> Option<Vec<u8>> {
+    let before = problems.len();
+    let keyword = keyword_field(keyword).map_err(|e| problems.push(e)).ok();
+    let conflict = |reason: &str| PngMsgError::ConflictingChunkOptions(reason.to_string());
+    let body = match (&chunk_type.bytes(), compressed) {
+        (latin1_type @ (b"tEXt" | b"zTXt"), compressed) => {
+            let mut text = Vec::with_capacity(value.len());
+            let text = push_latin1(&mut text, value).map_err(|e| problems.push(e)).ok().map(|_| text);
+            match (latin1_type, compressed) {
+                (b"tEXt", Some(true)) => {
+                    problems.push(conflict("a tEXt chunk cannot be compressed, use zTXt or iTXt"));
+                    None
+                },
+                (b"zTXt", Some(false)) => {
+                    problems.push(conflict("a zTXt chunk is always compressed, use tEXt or iTXt"));
+                    None
+                },
+                // Compression method 0 comes before the stream.
+                (b"zTXt", _) => text.and_then(|text| deflate(&text).map_err(|e| problems.push(e)).ok())
+                    .map(|stream| [&[0][..], &stream].concat()),
+                _ => text,
+            }
+        },
+        (b"iTXt", compressed) => {
+            // Compression flag and method, then empty language tag and translated keyword.
+            let compressed = compressed.unwrap_or(false);
+            let text = match compressed {
+                true => deflate(value.as_bytes()).map_err(|e| problems.push(e)).ok(),
+                false => Some(value.as_bytes().to_vec()),
+            };
+            text.map(|text| [&[compressed as u8, 0, 0, 0][..], &text].concat())
+        },
+        _ => {
+            problems.push(PngMsgError::ConflictingChunkOptions(
+                format!("text needs a tEXt, zTXt or iTXt chunk, not {}", chunk_type)
+            ));
+            None
+        },
+    };
+    match (keyword, body) {
+        (Some(keyword), Some(body)) if problems.len() == before => Some([keyword, body].concat()),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(builder: ChunkBuilder) -> Vec<String> {
+        match builder.build() {
+            Err(PngMsgError::ChunkBuild(problems)) => problems.iter().map(ToString::to_string).collect(),
+            other => panic!("expected a build error, got {:?}", other.map(|c| c.to_string())),
+        }
+    }
+
+    #[test]
+    fn test_data_and_reader() {
+        let chunk = ChunkBuilder::new("ruSt").unwrap().data(b"hidden".to_vec()).build().unwrap();
+        assert_eq!(chunk, Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec()));
+
+        let chunk = ChunkBuilder::new("ruSt").unwrap().from_reader(&b"hidden and more"[..], 6).build().unwrap();
+        assert_eq!(chunk.data(), b"hidden");
+        assert_eq!(ChunkBuilder::new("IEND").unwrap().build().unwrap().length().get(), 0);
+    }
+
+    #[test]
+    fn test_text_layouts_match_constructors() {
+        let text = ChunkBuilder::new("tEXt").unwrap().text("Comment", "hi").build().unwrap();
+        assert_eq!(text, Chunk::new_text("Comment", "hi").unwrap());
+        let itxt = ChunkBuilder::new("iTXt").unwrap().text("Title", "grüße").build().unwrap();
+        assert_eq!(itxt, Chunk::new_itxt("Title", "", "", "grüße", false).unwrap());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compressed_layouts() {
+        let ztxt = ChunkBuilder::new("zTXt").unwrap().text("Comment", "hi hi hi hi").build().unwrap();
+        assert_eq!(ztxt.as_ztxt().unwrap(), ("Comment".to_string(), "hi hi hi hi".to_string()));
+        let itxt = ChunkBuilder::new("iTXt").unwrap().text("Title", "grüße").compressed(true).build().unwrap();
+        assert_eq!(itxt.as_itxt().unwrap().text, "grüße");
+        let raw = ChunkBuilder::new("ruSt").unwrap().data(vec![7; 1000]).compressed(true).build().unwrap();
+        assert_eq!(crate::zlib::decompress(raw.data(), 1000).unwrap(), vec![7; 1000]);
+    }
+
+    #[test]
+    fn test_every_problem_is_reported() {
+        let builder = ChunkBuilder::new("ruSt").unwrap()
+            .data(vec![1; 10])
+            .from_reader(&b"short"[..], 8)
+            .max_len(4);
+        assert_eq!(problems(builder), [
+            "Reader ended after 5 of 8 bytes",
+            "Conflicting chunk options: only one of data, text and from_reader can be given, not data and from_reader",
+            "Chunk data of 10 bytes is over the cap of 4 bytes",
+        ]);
+
+        let builder = ChunkBuilder::new("zTXt").unwrap().text("  bad", "\0").compressed(false);
+        assert_eq!(problems(builder), [
+            "\"  bad\" is not a valid tEXt keyword",
+            "'\\0' cannot be stored in a Latin-1 tEXt chunk",
+            "Conflicting chunk options: a zTXt chunk is always compressed, use tEXt or iTXt",
+        ]);
+        assert_eq!(problems(ChunkBuilder::new("ruSt").unwrap().text("Comment", "hi")), [
+            "Conflicting chunk options: text needs a tEXt, zTXt or iTXt chunk, not ruSt",
+        ]);
+        assert_eq!(problems(ChunkBuilder::new("tEXt").unwrap().data(b"x".to_vec()).compressed(true)), [
+            "Conflicting chunk options: compressed data would not be a valid tEXt chunk, use text",
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_type_fails_up_front() {
+        assert!(matches!(ChunkBuilder::new("ru5t"), Err(PngMsgError::InvalidChunkType(_))));
+    }
+}
@@ -2,17 +2,36 @@ use std::fmt::{self, Display};
 use std::convert::TryFrom;
 use std::str::FromStr;
 
-#[derive(PartialEq, Clone, Eq, Debug)]
+use crate::error::PngMsgError;
+
+// Ordered and hashed by the raw bytes, so uppercase types sort before lowercase ones.
+#[derive(PartialEq, Clone, Copy, Eq, Hash, PartialOrd, Ord, Debug)]
 pub struct ChunkType {
     bytes: [u8; 4],
 }
 
 impl ChunkType {
-    
+    pub const IHDR: ChunkType = ChunkType { bytes: *b"IHDR" };
+    pub const PLTE: ChunkType = ChunkType { bytes: *b"PLTE" };
+    pub const IDAT: ChunkType = ChunkType { bytes: *b"IDAT" };
+    pub const IEND: ChunkType = ChunkType { bytes: *b"IEND" };
+    pub const TEXT: ChunkType = ChunkType { bytes: *b"tEXt" };
+    pub const ZTXT: ChunkType = ChunkType { bytes: *b"zTXt" };
+    pub const ITXT: ChunkType = ChunkType { bytes: *b"iTXt" };
+    pub const TIME: ChunkType = ChunkType { bytes: *b"tIME" };
+    pub const PHYS: ChunkType = ChunkType { bytes: *b"pHYs" };
+    pub const GAMA: ChunkType = ChunkType { bytes: *b"gAMA" };
+    pub const SRGB: ChunkType = ChunkType { bytes: *b"sRGB" };
+
     pub fn bytes(&self) -> [u8; 4] {
         self.bytes.to_owned()
     }
 
+    // Every constructor checks the bytes are ASCII letters, so this cannot fail.
+    pub fn name(&self) -> &str {
+        std::str::from_utf8(&self.bytes).expect("chunk type bytes are ASCII letters")
+    }
+
     pub fn is_critical(&self) -> bool {
         let first_byte: u8 = self.bytes[0];
         let fifth_bit = (first_byte >> 5) & 1;
@@ -24,70 +43,290 @@ impl ChunkType {
         let second_byte: u8 = self.bytes()[1];
         let fifth_bit = (second_byte >> 5) & 1;
 
-        return fifth_bit == 0;
+        fifth_bit == 0
     }
 
     pub fn is_reserved_bit_valid(&self) -> bool {
         let third_byte: u8 = self.bytes()[2];
         let fifth_bit = (third_byte >> 5) & 1;
 
-        return fifth_bit == 0;
+        fifth_bit == 0
     }
 
     pub fn is_safe_to_copy(&self) -> bool {
         let fourth_byte: u8 = self.bytes()[3];
         let fifth_bit = (fourth_byte >> 5) & 1;
 
-        return fifth_bit == 1;
+        fifth_bit == 1
+    }
+
+    // The same letters with the first two lowercased: ancillary and private, so never standard.
+    pub fn to_private_ancillary(&self) -> ChunkType {
+        let [first, second, third, fourth] = self.bytes;
+        ChunkType { bytes: [first.to_ascii_lowercase(), second.to_ascii_lowercase(), third, fourth] }
     }
     
+    // What the spec asks of a chunk type today: four ASCII letters and an uppercase third byte.
+    // Readers that should accept future uses of the reserved bit check `is_reserved_bit_valid`
+    // on its own instead.
     pub fn is_valid(&self) -> bool {
-        self.is_reserved_bit_valid()
+        self.bytes.iter().all(|byte| ChunkType::is_valid_byte(*byte)) && self.is_reserved_bit_valid()
     }
 
     pub fn is_valid_byte(byte: u8) -> bool {
         byte.is_ascii_alphabetic()
     }
+
+    #[inline]
+    fn key(&self) -> u32 {
+        u32::from_be_bytes(self.bytes)
+    }
+
+    #[inline]
+    pub fn category(&self) -> Option<ChunkCategory> {
+        STANDARD_CHUNKS.binary_search_by_key(&self.key(), |(bytes, _)| u32::from_be_bytes(*bytes))
+            .ok()
+            .map(|index| STANDARD_CHUNKS[index].1)
+    }
+
+    // Registered in the PNG specification (third edition, including APNG and HDR metadata).
+    #[inline]
+    pub fn is_standard(&self) -> bool {
+        self.category().is_some()
+    }
 }
 
-impl FromStr for ChunkType {
-    type Err = &'static str;
-    
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let bytes = s.as_bytes();
-        if bytes.len() != 4 {
-            return Err("input str length is not 4".into());
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChunkCategory {
+    Critical,
+    Transparency,
+    ColorSpace,
+    Text,
+    Time,
+    Miscellaneous,
+    Animation,
+}
+
+// Sorted by the big-endian u32 value of the type bytes, i.e. plain byte order, so lookups can
+// binary search without allocating.
+const STANDARD_CHUNKS: [([u8; 4], ChunkCategory); 25] = [
+    (*b"IDAT", ChunkCategory::Critical),
+    (*b"IEND", ChunkCategory::Critical),
+    (*b"IHDR", ChunkCategory::Critical),
+    (*b"PLTE", ChunkCategory::Critical),
+    (*b"acTL", ChunkCategory::Animation),
+    (*b"bKGD", ChunkCategory::Miscellaneous),
+    (*b"cHRM", ChunkCategory::ColorSpace),
+    (*b"cICP", ChunkCategory::ColorSpace),
+    (*b"cLLI", ChunkCategory::ColorSpace),
+    (*b"eXIf", ChunkCategory::Miscellaneous),
+    (*b"fcTL", ChunkCategory::Animation),
+    (*b"fdAT", ChunkCategory::Animation),
+    (*b"gAMA", ChunkCategory::ColorSpace),
+    (*b"hIST", ChunkCategory::Miscellaneous),
+    (*b"iCCP", ChunkCategory::ColorSpace),
+    (*b"iTXt", ChunkCategory::Text),
+    (*b"mDCV", ChunkCategory::ColorSpace),
+    (*b"pHYs", ChunkCategory::Miscellaneous),
+    (*b"sBIT", ChunkCategory::ColorSpace),
+    (*b"sPLT", ChunkCategory::Miscellaneous),
+    (*b"sRGB", ChunkCategory::ColorSpace),
+    (*b"tEXt", ChunkCategory::Text),
+    (*b"tIME", ChunkCategory::Time),
+    (*b"tRNS", ChunkCategory::Transparency),
+    (*b"zTXt", ChunkCategory::Text),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkTypeError {
+    WrongLength { len: usize },
+    // `position` counts from 1, the way people read "byte 3".
+    NotAsciiLetter { position: usize, found: u8 },
+}
+
+impl Display for ChunkTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkTypeError::WrongLength { len } => write!(f, "expected 4 bytes, got {}", len),
+            ChunkTypeError::NotAsciiLetter { position, found } if found.is_ascii_graphic() || *found == b' ' =>
+                write!(f, "byte {} ('{}') is not an ASCII letter", position, *found as char),
+            ChunkTypeError::NotAsciiLetter { position, found } =>
+                write!(f, "byte {} (0x{:02x}) is not an ASCII letter", position, found),
         }
-        let mut arr = [0; 4];
-        arr.copy_from_slice(bytes);
+    }
+}
+
+impl std::error::Error for ChunkTypeError {}
 
-        if s.chars().any(|c| !c.is_ascii_alphabetic()) {
-            return Err("input str has non alphabetic ascii character(s)".into());
+impl ChunkType {
+    // Swaps look-alike digits for letters ("ru5t" -> "ruSt") when that is the only problem.
+    pub fn suggest(input: &str) -> Option<ChunkType> {
+        let fixed: String = input.chars()
+            .map(|c| match c {
+                '0' => 'O', '1' => 'I', '2' => 'Z', '3' => 'E', '4' => 'A',
+                '5' => 'S', '6' => 'G', '7' => 'T', '8' => 'B', '9' => 'g',
+                c => c,
+            })
+            .collect();
+        if fixed == input {
+            return None;
         }
+        ChunkType::from_str(&fixed).ok()
+    }
+}
 
-        Ok(ChunkType { bytes: arr })
+impl ChunkType {
+    // Takes the letters in any case; the flags decide the case of bytes 1, 2 and 4, and byte 3
+    // is made uppercase as the reserved bit requires.
+    pub fn new(name: &str, ancillary: bool, private: bool, safe_to_copy: bool) -> Result<ChunkType, ChunkTypeError> {
+        Ok(ChunkType::from_str(name)?
+            .with_ancillary(ancillary)
+            .with_private(private)
+            .with_reserved_valid()
+            .with_safe_to_copy(safe_to_copy))
+    }
+
+    // Each property is the case bit (0x20) of one byte: set means lowercase.
+    fn with_case_bit(&self, index: usize, set: bool) -> ChunkType {
+        let mut bytes = self.bytes;
+        bytes[index] = if set { bytes[index].to_ascii_lowercase() } else { bytes[index].to_ascii_uppercase() };
+        ChunkType { bytes }
+    }
+
+    pub fn with_ancillary(&self, ancillary: bool) -> ChunkType {
+        self.with_case_bit(0, ancillary)
+    }
+
+    pub fn with_private(&self, private: bool) -> ChunkType {
+        self.with_case_bit(1, private)
+    }
+
+    pub fn with_reserved_valid(&self) -> ChunkType {
+        self.with_case_bit(2, false)
+    }
+
+    pub fn with_safe_to_copy(&self, safe_to_copy: bool) -> ChunkType {
+        self.with_case_bit(3, safe_to_copy)
+    }
+}
+
+impl ChunkType {
+    // Four random letters as an ancillary, private, safe-to-copy type, so hidden messages do not
+    // all sit in the tutorial's ruSt. Registered types are all public, so this never picks one.
+    pub fn random_private() -> Result<ChunkType, PngMsgError> {
+        let [first, ..] = random_letters::<1>()?;
+        ChunkType::random_private_with_prefix(first as char)
+    }
+
+    // The first letter is lowercased, since an uppercase one would make the type critical.
+    pub fn random_private_with_prefix(prefix: char) -> Result<ChunkType, PngMsgError> {
+        if !prefix.is_ascii_alphabetic() {
+            let found = prefix.to_string().as_bytes()[0];
+            return Err(ChunkTypeError::NotAsciiLetter { position: 1, found }.into());
+        }
+        let [second, third, fourth] = random_letters::<3>()?;
+        let chunk_type = ChunkType { bytes: [prefix as u8, second, third, fourth] }
+            .with_ancillary(true)
+            .with_private(true)
+            .with_reserved_valid()
+            .with_safe_to_copy(true);
+        debug_assert!(!chunk_type.is_standard());
+        Ok(chunk_type)
+    }
+}
+
+// Lowercase letters, each equally likely: bytes from 234 = 9 * 26 up are thrown away.
+#[cfg(feature = "random-type")]
+fn random_letters<const N: usize>() -> Result<[u8; N], PngMsgError> {
+    let mut letters = [0; N];
+    let mut filled = 0;
+    let mut pool = [0; 16];
+    while filled < N {
+        crate::random::random_bytes(&mut pool)?;
+        for byte in pool.into_iter().filter(|&byte| byte < 234).take(N - filled) {
+            letters[filled] = b'a' + byte % 26;
+            filled += 1;
+        }
+    }
+    Ok(letters)
+}
+
+#[cfg(not(feature = "random-type"))]
+fn random_letters<const N: usize>() -> Result<[u8; N], PngMsgError> {
+    Err(PngMsgError::RandomTypeUnavailable)
+}
+
+impl FromStr for ChunkType {
+    type Err = ChunkTypeError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes: [u8; 4] = s.as_bytes().try_into()
+            .map_err(|_| ChunkTypeError::WrongLength { len: s.len() })?;
+        ChunkType::try_from(bytes)
     }
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = &'static str;
+    type Error = ChunkTypeError;
 
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
-        if value.iter().any(|byte| !byte.is_ascii_alphabetic()) {
-            return Err("Value is not valid ascii alphabet");
-        } 
+        if let Some(index) = value.iter().position(|byte| !ChunkType::is_valid_byte(*byte)) {
+            return Err(ChunkTypeError::NotAsciiLetter { position: index + 1, found: value[index] });
+        }
         Ok(ChunkType { bytes: value })
     }
 }
 
+// Lets lookups such as `Png::chunks_by_type` take "ruSt" and a `ChunkType` alike.
+impl TryFrom<&str> for ChunkType {
+    type Error = ChunkTypeError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        ChunkType::from_str(value)
+    }
+}
+
+impl From<&ChunkType> for ChunkType {
+    fn from(value: &ChunkType) -> Self {
+        *value
+    }
+}
+
+impl AsRef<[u8]> for ChunkType {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl From<ChunkType> for [u8; 4] {
+    fn from(value: ChunkType) -> Self {
+        value.bytes
+    }
+}
+
+// `str` so filters can say `chunk.chunk_type() == "ruSt"`, `&str` for an owned ChunkType.
+impl PartialEq<str> for ChunkType {
+    fn eq(&self, other: &str) -> bool {
+        self.bytes == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for ChunkType {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl PartialEq<[u8; 4]> for ChunkType {
+    fn eq(&self, other: &[u8; 4]) -> bool {
+        &self.bytes == other
+    }
+}
+
 impl Display for ChunkType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}",
-               String::from_utf8(self.bytes().to_vec())
-                   .expect("chunk_type should be valid utf-8")
-                   .to_string()
-                )
-        }
+        f.pad(self.name())
+    }
 }
 
 
@@ -97,6 +336,58 @@ mod tests {
     use std::convert::TryFrom;
     use std::str::FromStr;
 
+    #[test]
+    fn test_standard_table_is_sorted() {
+        let keys: Vec<u32> = STANDARD_CHUNKS.iter().map(|(bytes, _)| u32::from_be_bytes(*bytes)).collect();
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_category_lookup() {
+        for (bytes, category) in STANDARD_CHUNKS {
+            let chunk_type = ChunkType::try_from(bytes).unwrap();
+            assert_eq!(chunk_type.category(), Some(category));
+            assert!(chunk_type.is_standard());
+        }
+        assert_eq!(ChunkType::from_str("tEXt").unwrap().category(), Some(ChunkCategory::Text));
+        assert!(!ChunkType::from_str("ruSt").unwrap().is_standard());
+        assert!(!ChunkType::from_str("idat").unwrap().is_standard());
+    }
+
+    #[test]
+    fn test_constants_and_name() {
+        let constants = [
+            ChunkType::IHDR, ChunkType::PLTE, ChunkType::IDAT, ChunkType::IEND, ChunkType::TEXT,
+            ChunkType::ZTXT, ChunkType::ITXT, ChunkType::TIME, ChunkType::PHYS, ChunkType::GAMA,
+        ];
+        for chunk_type in &constants {
+            assert_eq!(&ChunkType::from_str(chunk_type.name()).unwrap(), chunk_type);
+            assert!(chunk_type.is_standard(), "{}", chunk_type);
+        }
+        assert_eq!(ChunkType::TEXT.name(), "tEXt");
+        assert!(ChunkType::IEND.is_critical() && !ChunkType::GAMA.is_critical());
+        assert_eq!(format!("{:<6}|", ChunkType::IDAT), "IDAT  |");
+    }
+
+    #[test]
+    fn test_property_builders() {
+        let base = ChunkType::from_str("RUST").unwrap();
+        for flag in [false, true] {
+            assert_eq!(base.with_ancillary(flag).is_critical(), !flag);
+            assert_eq!(base.with_private(flag).is_public(), !flag);
+            assert_eq!(base.with_safe_to_copy(flag).is_safe_to_copy(), flag);
+        }
+        let odd = ChunkType::from_str("rusT").unwrap();
+        assert!(!odd.is_reserved_bit_valid());
+        assert_eq!(odd.with_reserved_valid().to_string(), "ruST");
+        assert_eq!(base.with_ancillary(true).with_private(true).to_string(), "ruST");
+
+        assert_eq!(ChunkType::new("rust", true, false, true).unwrap().to_string(), "rUSt");
+        assert_eq!(ChunkType::new("RuSt", false, true, false).unwrap().to_string(), "RuST");
+        assert_eq!(ChunkType::new("ru5t", true, true, true), Err(ChunkTypeError::NotAsciiLetter { position: 3, found: b'5' }));
+        assert!(ChunkType::new("rst", true, true, true).is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_from_bytes() {
         let expected = [82, 117, 83, 116];
@@ -160,6 +451,48 @@ mod tests {
         assert!(!chunk.is_safe_to_copy());
     }
 
+    #[cfg(feature = "random-type")]
+    #[test]
+    fn test_random_private_types_satisfy_every_predicate() {
+        let mut seen = std::collections::HashSet::new();
+        for i in 0..1000 {
+            let chunk_type = match i % 2 {
+                0 => ChunkType::random_private().unwrap(),
+                _ => ChunkType::random_private_with_prefix('Q').unwrap(),
+            };
+            assert!(!chunk_type.is_critical(), "{}", chunk_type);
+            assert!(!chunk_type.is_public(), "{}", chunk_type);
+            assert!(chunk_type.is_reserved_bit_valid(), "{}", chunk_type);
+            assert!(chunk_type.is_safe_to_copy(), "{}", chunk_type);
+            assert!(chunk_type.is_valid() && !chunk_type.is_standard(), "{}", chunk_type);
+            if i % 2 == 1 {
+                assert_eq!(chunk_type.bytes()[0], b'q');
+            }
+            seen.insert(chunk_type);
+        }
+        // 26^4 possible types; a handful of repeats is fine, a stuck generator is not.
+        assert!(seen.len() > 900);
+        assert!(matches!(
+            ChunkType::random_private_with_prefix('5'),
+            Err(PngMsgError::InvalidChunkType(ChunkTypeError::NotAsciiLetter { position: 1, found: b'5' }))
+        ));
+    }
+
+    #[cfg(not(feature = "random-type"))]
+    #[test]
+    fn test_random_private_needs_feature() {
+        assert!(matches!(ChunkType::random_private(), Err(PngMsgError::RandomTypeUnavailable)));
+    }
+
+    #[test]
+    pub fn test_to_private_ancillary() {
+        for (chunk_type, expected) in [("IDAT", "idAT"), ("sRGB", "srGB"), ("ruSt", "ruSt")] {
+            let private = ChunkType::from_str(chunk_type).unwrap().to_private_ancillary();
+            assert_eq!(private.to_string(), expected);
+            assert!(!private.is_critical() && !private.is_public() && !private.is_standard() && private.is_valid());
+        }
+    }
+
     #[test]
     pub fn test_valid_chunk_is_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -179,6 +512,31 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_is_valid_checks_every_byte() {
+        // Only reachable by building the struct directly; the public constructors refuse these.
+        for bytes in [*b"1uSt", *b"RuS ", [0, b'u', b'S', b't'], *b"R\xffSt"] {
+            let chunk = ChunkType { bytes };
+            assert!(chunk.is_reserved_bit_valid(), "{:?}", bytes);
+            assert!(!chunk.is_valid(), "{:?}", bytes);
+            assert!(ChunkType::try_from(bytes).is_err());
+        }
+    }
+
+    #[test]
+    pub fn test_chunk_type_errors_point_at_the_byte() {
+        assert_eq!(ChunkType::from_str("ru5t").unwrap_err().to_string(), "byte 3 ('5') is not an ASCII letter");
+        assert_eq!(ChunkType::from_str("ruSt!").unwrap_err(), ChunkTypeError::WrongLength { len: 5 });
+        assert_eq!(ChunkType::try_from([b'r', 0, b'S', b't']).unwrap_err().to_string(), "byte 2 (0x00) is not an ASCII letter");
+    }
+
+    #[test]
+    pub fn test_chunk_type_suggestion() {
+        assert_eq!(ChunkType::suggest("ru5t"), Some(ChunkType::from_str("ruSt").unwrap()));
+        assert_eq!(ChunkType::suggest("ruSt"), None);
+        assert_eq!(ChunkType::suggest("r-St"), None);
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -192,4 +550,31 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
+
+    #[test]
+    fn test_map_keys_and_comparisons() {
+        use std::collections::HashMap;
+        use crate::chunk::Chunk;
+
+        let chunks: Vec<Chunk> = ["IHDR", "tEXt", "ruSt", "tEXt", "IDAT", "IEND"].iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), Vec::new()))
+            .collect();
+        let mut by_type: HashMap<ChunkType, Vec<&Chunk>> = HashMap::new();
+        for chunk in &chunks {
+            by_type.entry(*chunk.chunk_type()).or_default().push(chunk);
+        }
+        assert_eq!(by_type[&ChunkType::TEXT].len(), 2);
+        assert_eq!(by_type.len(), 5);
+
+        let mut sorted: Vec<ChunkType> = by_type.into_keys().collect();
+        sorted.sort();
+        let names: Vec<&str> = sorted.iter().map(ChunkType::name).collect();
+        assert_eq!(names, ["IDAT", "IEND", "IHDR", "ruSt", "tEXt"]);
+
+        let ru_st = chunks[2].chunk_type();
+        assert!(ru_st == "ruSt" && *ru_st == "ruSt" && *ru_st == *b"ruSt");
+        assert!(*ru_st != "RUST");
+        assert_eq!(ru_st.as_ref(), b"ruSt");
+        assert_eq!(<[u8; 4]>::from(*ru_st), *b"ruSt");
+    }
 }
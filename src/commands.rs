@@ -1,65 +1,1125 @@
+use std::collections::BTreeSet;
+use std::fmt;
 use std::fs;
-use std::fs::File;
-use std::io::Read;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use crate::chunk::Chunk;
-use crate::chunk_type::ChunkType;
-use crate::png::Png;
+use pngme::ancillary::RENDERING_CHUNKS;
+use pngme::atomic::{backup_file, backup_path, replace_file};
+use pngme::batch::{collect_files, process_files, BatchOptions, Destination};
+use pngme::chunk::{Chunk, ChunkPosition, CrcMismatch};
+use pngme::chunk_type::ChunkType;
+use pngme::decode::{decode_message_detailed, open_chunk_data, DecodeOptions};
+use pngme::diff::ChunkDiff;
+use pngme::encode::{encode_message, encode_parsed, EncodeOptions};
+use pngme::error::PngMsgError;
+use pngme::finding::{aggregate, Finding};
+use pngme::limits::{LimitExceeded, Limits};
+use pngme::pipeline::Pipeline;
+use pngme::png::{Inconsistency, ParseOptions, Png, SerializePolicy};
+use pngme::provenance::{ProvenanceRecord, PROVENANCE_CHUNK_TYPE};
+use pngme::sign::{SigningKey, VerifyingKey};
+use pngme::report::SizeReport;
+use pngme::summary::{PngSummary, DEFAULT_INLINE_LIMIT};
+use pngme::text_codec::{base64_decode, base64_encode, hex_decode, hex_encode};
 
-pub fn print (path: &str) {
-    let buffer = get_bytes_from_path(path);
-    let png = Png::try_from(buffer.as_slice()).unwrap();
+use crate::args::{BatchArgs, PipelineOp, ReportFormat, TextFormat, STDIO};
+use crate::i18n::{Lang, Msg};
 
-    let chunk_types: Vec<String> = png.chunks().iter()
-        .map(|c| c.chunk_type().to_string())
+#[derive(Debug)]
+pub enum CliError {
+    Read { path: String, reason: String },
+    Write { path: String, reason: String },
+    InvalidPng { path: String, reason: String },
+    Crc { path: String, stored: u32, computed: u32, position: ChunkPosition },
+    ChunkNotFound(String),
+    NotUtf8(String),
+    MissingIend,
+    Provenance(String),
+    Limit(String),
+    CriticalChunk(String),
+    Encrypt(String),
+    // Encoding failed for a reason with no variant of its own.
+    Encode(String),
+    Decrypt { chunk_type: String, reason: String },
+    Lsb { path: String, reason: String },
+    Integrity { chunk_type: String, reason: String },
+    BinaryMessage(String),
+    StdoutIsTerminal,
+    InvalidInput(String),
+    Key { path: String, reason: String },
+    Clock(String),
+    UnsafeChunkType(ChunkType),
+    RandomType(String),
+    NotADirectory(String),
+    // Names the argument that only works with a single file.
+    NotForDirectories(&'static str),
+    BatchFailed { failed: usize, total: usize },
+    // `flagged` of `total` files had the findings.
+    Suspicious { findings: usize, flagged: usize, total: usize },
+    // Names the chunk type whose data has no message frame.
+    NotAMessage(String),
+    Frame { chunk_type: String, reason: String },
+    #[cfg(feature = "rules")]
+    InvalidRules { path: String, reason: String },
+}
+
+impl CliError {
+    pub fn msg(&self) -> Msg {
+        match self {
+            CliError::Read { .. } => Msg::ReadFailed,
+            CliError::Write { .. } => Msg::WriteFailed,
+            CliError::InvalidPng { .. } => Msg::InvalidPng,
+            CliError::Crc { .. } => Msg::CrcMismatch,
+            CliError::ChunkNotFound(_) => Msg::ChunkNotFound,
+            CliError::NotUtf8(_) => Msg::NotUtf8,
+            CliError::MissingIend => Msg::MissingIend,
+            CliError::Provenance(_) => Msg::ProvenanceFailed,
+            CliError::Limit(_) => Msg::LimitExceeded,
+            CliError::CriticalChunk(_) => Msg::CriticalChunk,
+            CliError::Encrypt(_) => Msg::EncryptFailed,
+            CliError::Encode(_) => Msg::EncodeFailed,
+            CliError::Decrypt { .. } => Msg::DecryptFailed,
+            CliError::Lsb { .. } => Msg::LsbFailed,
+            CliError::Integrity { .. } => Msg::IntegrityFailed,
+            CliError::BinaryMessage(_) => Msg::BinaryMessage,
+            CliError::StdoutIsTerminal => Msg::StdoutIsTerminal,
+            CliError::InvalidInput(_) => Msg::InvalidInput,
+            CliError::Key { .. } => Msg::InvalidKey,
+            CliError::Clock(_) => Msg::ClockFailed,
+            CliError::UnsafeChunkType(chunk_type) if chunk_type.is_critical() => Msg::UnsafeChunkType,
+            CliError::UnsafeChunkType(_) => Msg::StandardChunkType,
+            CliError::RandomType(_) => Msg::RandomTypeFailed,
+            CliError::NotADirectory(_) => Msg::NotADirectory,
+            CliError::NotForDirectories(_) => Msg::NotForDirectories,
+            CliError::BatchFailed { .. } => Msg::BatchFailed,
+            CliError::Suspicious { .. } => Msg::ScanFound,
+            CliError::NotAMessage(_) => Msg::NotAMessage,
+            CliError::Frame { .. } => Msg::InvalidFrame,
+            #[cfg(feature = "rules")]
+            CliError::InvalidRules { .. } => Msg::InvalidRules,
+        }
+    }
+
+    pub fn render(&self, lang: Lang) -> String {
+        let msg = self.msg();
+        let body = match self {
+            CliError::Read { path, reason }
+            | CliError::Write { path, reason }
+            | CliError::InvalidPng { path, reason }
+            | CliError::Lsb { path, reason }
+            | CliError::Key { path, reason } => lang.render(msg, &[path, reason]),
+            CliError::Crc { path, stored, computed, position } => {
+                let chunk_type = position.chunk_type.map_or("?".to_string(), |chunk_type| chunk_type.to_string());
+                lang.render(msg, &[
+                    path, &format!("{:08x}", stored), &format!("{:08x}", computed),
+                    &position.index, &chunk_type, &format!("{:#x}", position.offset),
+                ])
+            },
+            CliError::ChunkNotFound(chunk_type)
+            | CliError::NotUtf8(chunk_type)
+            | CliError::CriticalChunk(chunk_type)
+            | CliError::BinaryMessage(chunk_type)
+            | CliError::NotAMessage(chunk_type) =>
+                lang.render(msg, &[chunk_type]),
+            CliError::NotADirectory(path) => lang.render(msg, &[path]),
+            CliError::NotForDirectories(arg) => lang.render(msg, &[arg]),
+            CliError::BatchFailed { failed, total } => lang.render(msg, &[failed, total]),
+            CliError::Suspicious { findings, flagged, total } => lang.render(msg, &[findings, flagged, total]),
+            CliError::MissingIend | CliError::StdoutIsTerminal => lang.render(msg, &[]),
+            CliError::Provenance(reason)
+            | CliError::Limit(reason)
+            | CliError::Encrypt(reason)
+            | CliError::Encode(reason)
+            | CliError::InvalidInput(reason)
+            | CliError::Clock(reason)
+            | CliError::RandomType(reason) =>
+                lang.render(msg, &[reason]),
+            CliError::UnsafeChunkType(chunk_type) => lang.render(msg, &[chunk_type, &chunk_type.to_private_ancillary()]),
+            CliError::Decrypt { chunk_type, reason }
+            | CliError::Integrity { chunk_type, reason }
+            | CliError::Frame { chunk_type, reason } =>
+                lang.render(msg, &[chunk_type, reason]),
+            #[cfg(feature = "rules")]
+            CliError::InvalidRules { path, reason } => lang.render(msg, &[path, reason]),
+        };
+        format!("{}: {}", lang.render(Msg::ErrorPrefix, &[]), body)
+    }
+}
+
+pub type CliResult<T> = std::result::Result<T, CliError>;
+
+// `hex` is the number of data bytes to dump under each chunk, or None for no dump.
+pub fn print(path: &str, provenance: bool, hex: Option<usize>, limits: Limits, lang: Lang) -> CliResult<()> {
+    let png = read_png(path, limits)?;
+
+    if provenance {
+        let records = png.provenance();
+        if records.is_empty() {
+            println!("{}", lang.render(Msg::NoProvenance, &[]));
+        }
+        for record in records {
+            println!("{}", record);
+        }
+        return Ok(());
+    }
+
+    // An unreadable IHDR is shown in place of the header rather than failing the listing.
+    match png.ihdr() {
+        Ok(ihdr) => println!("{}", lang.render(Msg::ImageHeader, &[&ihdr])),
+        Err(e) => println!("{}", lang.render(Msg::ImageHeader, &[&e])),
+    }
+    println!("{}", lang.render(Msg::DecodableChunks, &[]));
+    for chunk in png.chunks() {
+        println!("{} {:>10} {:08x}", chunk.chunk_type(), chunk.length(), chunk.crc());
+        if let Some(value) = describe(chunk) {
+            println!("  {}: {}", chunk.chunk_type(), value);
+        }
+        if let Some(limit) = hex {
+            print!("{}", chunk.hex_dump(limit));
+        }
+    }
+    match png.validate() {
+        Ok(()) => println!("{}", lang.render(Msg::StructureValid, &[])),
+        Err(errors) => {
+            println!("{}", lang.render(Msg::StructureInvalid, &[&errors.len()]));
+            for error in errors {
+                println!("  {}", error);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Readable values for the standard ancillary chunks that have them; a bad one shows the error.
+fn describe(chunk: &Chunk) -> Option<String> {
+    let value = match &chunk.chunk_type().bytes() {
+        b"pHYs" => chunk.as_phys().map(|dims| dims.to_string()),
+        b"gAMA" => chunk.as_gama().map(|gamma| format!("{:.5}", gamma)),
+        b"sRGB" => chunk.as_srgb().map(|intent| intent.to_string()),
+        _ => return None,
+    };
+    Some(value.unwrap_or_else(|e| e.to_string()))
+}
+
+pub fn stats(path: &str, json: bool, limits: Limits, lang: Lang) -> CliResult<()> {
+    let stats = read_png(path, limits)?.stats();
+    if json {
+        print!("{}", stats.to_json());
+        return Ok(());
+    }
+    let percent = |bytes: u64| format!("{:.1}", stats.percent(bytes));
+    println!("{}", lang.render(Msg::StatsFileSize, &[&stats.file_size, &stats.chunk_count]));
+    for (msg, bytes) in [
+        (Msg::StatsIdat, stats.idat_bytes),
+        (Msg::StatsAncillary, stats.ancillary_bytes),
+        (Msg::StatsCritical, stats.other_critical_bytes),
+        (Msg::StatsOverhead, stats.overhead_bytes),
+    ] {
+        println!("{}", lang.render(msg, &[&bytes, &percent(bytes)]));
+    }
+    println!("{}", lang.render(Msg::StatsCounts, &[
+        &stats.critical_count, &stats.ancillary_count, &stats.safe_to_copy_count, &stats.unsafe_to_copy_count,
+    ]));
+    if let Some(largest) = stats.largest {
+        println!("{}", lang.render(Msg::StatsLargest, &[&largest.index, &largest.chunk_type, &largest.length]));
+    }
+    let header = [Msg::SizeType, Msg::StatsCount, Msg::StatsBytes, Msg::StatsShare].map(|m| lang.render(m, &[]));
+    println!("{:<6} {:>6} {:>10} {:>7}", header[0], header[1], header[2], header[3]);
+    for row in &stats.by_type {
+        println!("{:<6} {:>6} {:>10} {:>6}%", row.chunk_type, row.count, row.bytes, percent(row.bytes.get()));
+    }
+    Ok(())
+}
+
+// Read leniently, keeping bad CRCs, so a damaged file can still be compared.
+pub fn diff(path: &str, other: &str, json: bool, color: bool, limits: Limits, lang: Lang) -> CliResult<()> {
+    let options = ParseOptions { lenient: true, keep_bad_crc: true, limits, ..Default::default() };
+    let parse = |path: &str| Png::parse_with(&read_bytes(path)?, &options).map(|(png, _)| png)
+        .map_err(|e| CliError::InvalidPng { path: path.to_string(), reason: e.to_string() });
+    let diffs = parse(path)?.diff(&parse(other)?);
+    if json {
+        print!("{}", pngme::diff::to_json(&diffs));
+        return Ok(());
+    }
+    if diffs.is_empty() {
+        println!("{}", lang.render(Msg::DiffIdentical, &[]));
+    }
+    for diff in diffs {
+        let ansi = match diff {
+            ChunkDiff::Added { .. } => "32",
+            ChunkDiff::Removed { .. } => "31",
+            ChunkDiff::Modified { .. } | ChunkDiff::Moved { .. } => "33",
+        };
+        match color {
+            true => println!("\x1b[{}m{}\x1b[0m", ansi, diff),
+            false => println!("{}", diff),
+        }
+    }
+    Ok(())
+}
+
+pub fn print_json(path: &str, limits: Limits) -> CliResult<()> {
+    let png = read_png(path, limits)?;
+    print!("{}", PngSummary::of(&png, DEFAULT_INLINE_LIMIT).to_json());
+    Ok(())
+}
+
+// The CLI-only parts of `encode`; the library side is in `EncodeOptions`.
+pub struct EncodeFlags<'a> {
+    // The input file is rewritten when this is None.
+    pub output: Option<&'a str>,
+    pub manifest: Option<&'a str>,
+    pub report_size: Option<ReportFormat>,
+    // Write even when the carrier fails `Png::validate`.
+    pub force: bool,
+    // The chunk type was picked at random, so it has to be shown to decode the message later.
+    pub random_type: bool,
+}
+
+// With --input-file there is no MESSAGE, so the positional that would hold it is the output path.
+pub fn encode_payload<'a>(
+    message: &'a Option<String>,
+    input_file: &Option<String>,
+    output: &'a Option<String>,
+    format: TextFormat,
+) -> CliResult<(Vec<u8>, Option<&'a str>)> {
+    let (payload, output) = match input_file {
+        Some(input_file) => (read_bytes(input_file)?, message.as_deref()),
+        None => (message.as_deref().unwrap_or_default().as_bytes().to_vec(), output.as_deref()),
+    };
+    let payload = match format {
+        TextFormat::Plain => return Ok((payload, output)),
+        TextFormat::Base64 => base64_decode(&String::from_utf8_lossy(&payload)),
+        TextFormat::Hex => hex_decode(&String::from_utf8_lossy(&payload)),
+    };
+    Ok((payload.map_err(|e| CliError::InvalidInput(e.to_string()))?, output))
+}
+
+pub fn encode(path: &str, message: &[u8], opts: &EncodeOptions, flags: &EncodeFlags, lang: Lang) -> CliResult<()> {
+    let carrier = read_bytes(path)?;
+    let mut png = parse_png(path, &carrier, opts.limits)?;
+    if png.chunk_by_type("IEND").is_none() {
+        return Err(CliError::MissingIend);
+    }
+    // Encoding only adds ancillary chunks before IEND, so a valid carrier gives a valid output.
+    if let (Err(errors), false) = (png.validate(), flags.force) {
+        let reasons: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        return Err(CliError::InvalidPng { path: path.to_string(), reason: reasons.join("; ") });
+    }
+    let report_size = flags.report_size;
+    let output_path = flags.output.unwrap_or(path);
+
+    let before = report_size.map(|_| png.clone());
+    let (output, report) = encode_parsed(&mut png, &carrier, message, opts)
+        .map_err(encode_error)?;
+    if let (Some(format), Some(before)) = (report_size, before) {
+        print_size_report(&SizeReport::diff(&before, &png), format, output_path, lang);
+    }
+
+    write_bytes(output_path, &output)?;
+    if let Some(manifest) = flags.manifest {
+        write_bytes(manifest, report.to_json().as_bytes())?;
+    }
+    say(output_path, lang.render(Msg::MessageEncoded, &[]));
+    if flags.random_type {
+        say(output_path, lang.render(Msg::RandomTypeChosen, &[&opts.chunk_type, &output_path]));
+    }
+    Ok(())
+}
+
+// By the concrete error, so that a failure is only reported as encryption or provenance when
+// that step is what failed.
+fn encode_error(error: pngme::Error) -> CliError {
+    if let Some(limit) = error.downcast_ref::<LimitExceeded>() {
+        return CliError::Limit(limit.to_string());
+    }
+    match error.downcast_ref::<PngMsgError>() {
+        Some(PngMsgError::LimitExceeded(limit)) => CliError::Limit(limit.to_string()),
+        Some(PngMsgError::UnsafeChunkType(chunk_type)) => CliError::UnsafeChunkType(*chunk_type),
+        Some(PngMsgError::ProvenanceFailed(reason)) => CliError::Provenance(reason.clone()),
+        Some(PngMsgError::InvalidEnvelope(_) | PngMsgError::EncryptionUnavailable | PngMsgError::RandomUnavailable) =>
+            CliError::Encrypt(error.to_string()),
+        _ => CliError::Encode(error.to_string()),
+    }
+}
+
+// For --random-type; an empty prefix, from the bare flag, leaves every letter to chance.
+pub fn random_chunk_type(prefix: &str) -> CliResult<ChunkType> {
+    let mut letters = prefix.chars();
+    let chunk_type = match (letters.next(), letters.next()) {
+        (None, _) => ChunkType::random_private(),
+        (Some(letter), None) => ChunkType::random_private_with_prefix(letter),
+        _ => return Err(CliError::RandomType(format!("the prefix is a single letter, not {:?}", prefix))),
+    };
+    chunk_type.map_err(|e| CliError::RandomType(e.to_string()))
+}
+
+pub fn encode_lsb(path: &str, message: &[u8], password: Option<&str>, output: Option<&str>, lang: Lang) -> CliResult<()> {
+    let carrier = read_bytes(path)?;
+    let output_bytes = hide_in_pixels(&carrier, message, password)
+        .map_err(|e| CliError::Lsb { path: path.to_string(), reason: e.to_string() })?;
+    let output = output.unwrap_or(path);
+    write_bytes(output, &output_bytes)?;
+    say(output, lang.render(Msg::MessageEncoded, &[]));
+    Ok(())
+}
+
+pub struct DecodeFlags<'a> {
+    pub password: Option<&'a str>,
+    pub hmac_key: Option<&'a str>,
+    pub verify_key: Option<&'a VerifyingKey>,
+    // Write the message here as is instead of printing it.
+    pub output_file: Option<&'a str>,
+    // Print the message bytes as they are, even to a terminal.
+    pub raw: bool,
+    pub format: TextFormat,
+    // Read chunk data without expecting the message frame, see `framing`.
+    pub unframed: bool,
+}
+
+impl DecodeFlags<'_> {
+    pub fn options(&self, limits: Limits) -> DecodeOptions {
+        DecodeOptions {
+            limits,
+            item_budget: None,
+            unframed: self.unframed,
+            password: self.password.map(str::to_string),
+            hmac_key: self.hmac_key.map(str::to_string),
+            verify_key: self.verify_key.copied(),
+        }
+    }
+}
+
+fn format_body(body: Vec<u8>, format: TextFormat) -> Vec<u8> {
+    match format {
+        TextFormat::Plain => body,
+        TextFormat::Base64 => base64_encode(&body).into_bytes(),
+        TextFormat::Hex => hex_encode(&body).into_bytes(),
+    }
+}
+
+pub fn decode_lsb(path: &str, flags: &DecodeFlags, lang: Lang) -> CliResult<()> {
+    let bytes = read_bytes(path)?;
+    let body = read_from_pixels(&bytes, flags.password)
+        .map_err(|e| CliError::Lsb { path: path.to_string(), reason: e.to_string() })?;
+    write_message(body, "LSB", flags, lang)
+}
+
+pub fn decode(
+    path: &str,
+    chunk_type: &ChunkType,
+    json: bool,
+    flags: &DecodeFlags,
+    limits: Limits,
+    lang: Lang,
+) -> CliResult<()> {
+    let png = read_png(path, limits)?;
+
+    let message = decode_message_detailed(&png, chunk_type, &flags.options(limits))
+        .map_err(|e| message_error(e, chunk_type))?
+        .ok_or_else(|| CliError::ChunkNotFound(chunk_type.to_string()))?;
+    if json {
+        print!("{}", message.to_json());
+        return Ok(());
+    }
+    for warning in &message.warnings {
+        eprintln!("{}", warning);
+    }
+    write_message(message.body, &chunk_type.to_string(), flags, lang)
+}
+
+// Text is printed as before; other bytes go out unchanged to a file or pipe, but not to a
+// terminal, which would only show garbage (and could act on escape sequences) unless --raw.
+fn write_message(body: Vec<u8>, label: &str, flags: &DecodeFlags, lang: Lang) -> CliResult<()> {
+    let body = format_body(body, flags.format);
+    if let Some(output_file) = flags.output_file {
+        write_bytes(output_file, &body)?;
+        say(output_file, lang.render(Msg::MessageWritten, &[&body.len(), &output_file]));
+        return Ok(());
+    }
+    if flags.raw {
+        return write_stdout(&body);
+    }
+    match String::from_utf8(body) {
+        Ok(text) => {
+            println!("{}", lang.render(Msg::MessageIs, &[&text]));
+            Ok(())
+        },
+        Err(_) if io::stdout().is_terminal() => Err(CliError::BinaryMessage(label.to_string())),
+        Err(e) => write_stdout(e.as_bytes()),
+    }
+}
+
+fn write_stdout(bytes: &[u8]) -> CliResult<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(bytes).and_then(|_| stdout.flush())
+        .map_err(|e| CliError::Write { path: "<stdout>".to_string(), reason: e.to_string() })
+}
+
+pub fn decode_all(
+    path: &str,
+    chunk_type: &ChunkType,
+    flags: &DecodeFlags,
+    limits: Limits,
+    lang: Lang,
+) -> CliResult<()> {
+    let png = read_png(path, limits)?;
+
+    let messages = pngme::decode::decode_all(&png, chunk_type);
+    if messages.is_empty() {
+        return Err(CliError::ChunkNotFound(chunk_type.to_string()));
+    }
+    let opts = flags.options(limits);
+    for (index, data) in messages {
+        let body = open_chunk_data(data, chunk_type, &opts).map_err(|e| message_error(e, chunk_type))?;
+        let text = String::from_utf8(format_body(body, flags.format)).map_err(|_| CliError::NotUtf8(chunk_type.to_string()))?;
+        println!("{}", lang.render(Msg::MessageAt, &[&index, &text]));
+    }
+    Ok(())
+}
+
+// Sorts a failure to read the message by the layer it came from.
+fn message_error(error: PngMsgError, chunk_type: &ChunkType) -> CliError {
+    let chunk_type = chunk_type.to_string();
+    match error {
+        PngMsgError::NotAPngMsgPayload => CliError::NotAMessage(chunk_type),
+        PngMsgError::InvalidIntegrityFrame(_)
+        | PngMsgError::IntegrityCheckFailed
+        | PngMsgError::IntegrityKeyRequired
+        | PngMsgError::InvalidSignatureFrame(_)
+        | PngMsgError::SignatureInvalid
+        | PngMsgError::Unsigned
+        | PngMsgError::SigningUnavailable => CliError::Integrity { chunk_type, reason: error.to_string() },
+        PngMsgError::PasswordRequired
+        | PngMsgError::InvalidEnvelope(_)
+        | PngMsgError::DecryptionFailed
+        | PngMsgError::EncryptionUnavailable => CliError::Decrypt { chunk_type, reason: error.to_string() },
+        e => CliError::Frame { chunk_type, reason: e.to_string() },
+    }
+}
+
+// Also derives the public key, so a build without the `sign` feature fails before any work.
+pub fn load_signing_key(path: &str) -> CliResult<SigningKey> {
+    let invalid = |e: PngMsgError| CliError::Key { path: path.to_string(), reason: e.to_string() };
+    let key = SigningKey::from_pem(&String::from_utf8_lossy(&read_bytes(path)?)).map_err(invalid)?;
+    key.verifying_key().map_err(invalid)?;
+    Ok(key)
+}
+
+pub fn load_verifying_key(path: &str) -> CliResult<VerifyingKey> {
+    VerifyingKey::from_pem(&String::from_utf8_lossy(&read_bytes(path)?))
+        .map_err(|e| CliError::Key { path: path.to_string(), reason: e.to_string() })
+}
+
+pub fn keygen(private_key: &str, public_key: &str, lang: Lang) -> CliResult<()> {
+    let invalid = |e: PngMsgError| CliError::Key { path: private_key.to_string(), reason: e.to_string() };
+    let signing = pngme::sign::generate().map_err(invalid)?;
+    let verifying = signing.verifying_key().map_err(invalid)?;
+    write_new_file(private_key, signing.to_pem().as_bytes(), true)?;
+    write_new_file(public_key, verifying.to_pem().as_bytes(), false)?;
+    println!("{}", lang.render(Msg::KeysWritten, &[&private_key, &public_key, &hex_encode(&verifying.fingerprint())]));
+    Ok(())
+}
+
+pub fn remove(
+    path: &str,
+    chunk_type: &ChunkType,
+    all: bool,
+    record_provenance: bool,
+    report_size: Option<ReportFormat>,
+    limits: Limits,
+    lang: Lang,
+) -> CliResult<()> {
+    let mut png = read_png(path, limits)?;
+    let before = report_size.map(|_| png.clone());
+    let chunk_type = chunk_type.to_string();
+
+    let removed = if all {
+        png.remove_chunks_by_type(&chunk_type)
+            .map_err(|_| CliError::CriticalChunk(chunk_type.clone()))?
+            .len()
+    } else {
+        png.remove_chunk(&chunk_type)
+            .map_err(|_| CliError::ChunkNotFound(chunk_type.clone()))?;
+        1
+    };
+    if record_provenance {
+        png.record_provenance(ProvenanceRecord::new("remove", &[&chunk_type]))
+            .map_err(|e| CliError::Provenance(e.to_string()))?;
+    }
+    if let (Some(format), Some(before)) = (report_size, before) {
+        print_size_report(&SizeReport::diff(&before, &png), format, path, lang);
+    }
+
+    write_png(path, &png)?;
+    if all {
+        say(path, lang.render(Msg::ChunksRemoved, &[&removed]));
+    } else {
+        say(path, lang.render(Msg::ChunkRemoved, &[]));
+    }
+    Ok(())
+}
+
+// --record-provenance and --report-size, for the commands that change a file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChangeFlags {
+    pub record_provenance: bool,
+    pub report_size: Option<ReportFormat>,
+}
+
+impl ChangeFlags {
+    fn record(&self, png: &mut Png, operation: &str, params: &[&str]) -> CliResult<()> {
+        if self.record_provenance {
+            png.record_provenance(ProvenanceRecord::new(operation, params))
+                .map_err(|e| CliError::Provenance(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn strip(
+    path: &str,
+    output_path: Option<&str>,
+    keep: &[ChunkType],
+    strip_trns: bool,
+    flags: ChangeFlags,
+    limits: Limits,
+    lang: Lang,
+) -> CliResult<()> {
+    let mut png = read_png(path, limits)?;
+    let before = flags.report_size.map(|_| png.clone());
+    let keep = kept_by_strip(keep, strip_trns, flags.record_provenance);
+
+    let output_path = output_path.unwrap_or(path);
+    let removed = png.strip_ancillary(&keep);
+    for chunk in &removed {
+        say(output_path, format!("{} {:>10}", chunk.chunk_type(), chunk.length()));
+    }
+    flags.record(&mut png, "strip", &[&strip_params(&keep)])?;
+    if let (Some(format), Some(before)) = (flags.report_size, before) {
+        print_size_report(&SizeReport::diff(&before, &png), format, output_path, lang);
+    }
+    write_png(output_path, &png)?;
+    say(output_path, lang.render(Msg::ChunksStripped, &[&removed.len()]));
+    Ok(())
+}
+
+// Recording provenance keeps the history it adds to, which strip would otherwise remove.
+fn kept_by_strip(keep: &[ChunkType], strip_trns: bool, record_provenance: bool) -> Vec<ChunkType> {
+    let mut keep = keep.to_vec();
+    if !strip_trns {
+        keep.extend(RENDERING_CHUNKS.iter().map(|t| ChunkType::from_str(t).expect("valid chunk type")));
+    }
+    if record_provenance {
+        keep.push(ChunkType::from_str(PROVENANCE_CHUNK_TYPE).expect("valid chunk type"));
+    }
+    keep
+}
+
+fn strip_params(keep: &[ChunkType]) -> String {
+    keep.iter().map(ToString::to_string).collect::<Vec<_>>().join(",")
+}
+
+// A directory PATH for encode, remove or strip, with the flags that say which of its files to
+// change and where the results go.
+pub struct BatchTarget<'a> {
+    pub dir: &'a str,
+    pub args: &'a BatchArgs,
+    pub backup: bool,
+    pub limits: Limits,
+}
+
+impl<'a> BatchTarget<'a> {
+    // None for a file PATH, which must come without the batch flags.
+    pub fn of(path: &'a str, args: &'a BatchArgs, backup: bool, limits: Limits) -> CliResult<Option<BatchTarget<'a>>> {
+        match (Path::new(path).is_dir(), args.is_set()) {
+            (true, _) => Ok(Some(BatchTarget { dir: path, args, backup, limits })),
+            (false, true) => Err(CliError::NotADirectory(path.to_string())),
+            (false, false) => Ok(None),
+        }
+    }
+
+    // Each file is reported as it fails; the summary line follows, and any failure makes the
+    // run fail.
+    fn run<F>(&self, done: Msg, lang: Lang, op: F) -> CliResult<()>
+    where
+        F: FnMut(&mut Png) -> pngme::Result<()>,
+    {
+        let files = collect_files(Path::new(self.dir), self.args.recursive, self.args.glob.as_deref())
+            .map_err(|e| CliError::Read { path: self.dir.to_string(), reason: e.to_string() })?;
+        let opts = BatchOptions {
+            destination: match &self.args.output_dir {
+                Some(dir) => Destination::Mirror { root: PathBuf::from(self.dir), dir: PathBuf::from(dir) },
+                None => Destination::InPlace,
+            },
+            limits: self.limits,
+            backup: self.backup,
+        };
+        let report = process_files(&files, &opts, op);
+        for failure in &report.failed {
+            eprintln!("{}: {}: {}", lang.render(Msg::ErrorPrefix, &[]), failure.path.display(), failure.error);
+        }
+        println!("{}", lang.render(done, &[&report.written.len(), &report.failed.len()]));
+        match report.failed.len() {
+            0 => Ok(()),
+            failed => Err(CliError::BatchFailed { failed, total: report.total() }),
+        }
+    }
+}
+
+// Arguments that only make sense for one file; the first one given is named in the error.
+pub fn refuse_for_directory(args: &[(&'static str, bool)]) -> CliResult<()> {
+    match args.iter().find(|(_, given)| *given) {
+        Some((arg, _)) => Err(CliError::NotForDirectories(arg)),
+        None => Ok(()),
+    }
+}
+
+pub fn encode_dir(target: &BatchTarget, message: &[u8], opts: &EncodeOptions, force: bool, lang: Lang) -> CliResult<()> {
+    target.run(Msg::BatchEncoded, lang, |png| {
+        if let (Err(errors), false) = (png.validate(), force) {
+            let reasons: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            return Err(reasons.join("; ").into());
+        }
+        encode_message(png, message, opts)
+    })
+}
+
+pub fn remove_dir(target: &BatchTarget, chunk_type: &ChunkType, all: bool, record_provenance: bool, lang: Lang) -> CliResult<()> {
+    let chunk_type = chunk_type.to_string();
+    target.run(Msg::BatchRemoved, lang, |png| {
+        match all {
+            true => png.remove_chunks_by_type(&chunk_type).map(|_| ())?,
+            false => png.remove_chunk(&chunk_type).map(|_| ())?,
+        }
+        if record_provenance {
+            png.record_provenance(ProvenanceRecord::new("remove", &[&chunk_type]))?;
+        }
+        Ok(())
+    })
+}
+
+pub fn strip_dir(target: &BatchTarget, keep: &[ChunkType], strip_trns: bool, record_provenance: bool, lang: Lang) -> CliResult<()> {
+    let keep = kept_by_strip(keep, strip_trns, record_provenance);
+    let params = strip_params(&keep);
+    target.run(Msg::BatchStripped, lang, |png| {
+        png.strip_ancillary(&keep);
+        if record_provenance {
+            png.record_provenance(ProvenanceRecord::new("strip", &[&params]))?;
+        }
+        Ok(())
+    })
+}
+
+pub fn repair(path: &str, output_path: Option<&str>, flags: ChangeFlags, limits: Limits, lang: Lang) -> CliResult<()> {
+    let output_path = output_path.unwrap_or(path);
+    let bytes = read_bytes(path)?;
+    let options = ParseOptions { lenient: true, keep_bad_crc: true, limits, ..Default::default() };
+    let (mut png, _) = Png::parse_with(&bytes, &options)
+        .map_err(|e| CliError::InvalidPng { path: path.to_string(), reason: e.to_string() })?;
+    let before = flags.report_size.map(|_| png.clone());
+
+    for inconsistency in png.verify_integrity() {
+        if let Inconsistency::Crc { index, stored, computed } = inconsistency {
+            let chunk_type = png.chunks()[index].chunk_type().to_string();
+            let (stored, computed) = (format!("{:08x}", stored), format!("{:08x}", computed));
+            say(output_path, lang.render(Msg::ChunkRepaired, &[&index, &chunk_type, &stored, &computed]));
+        }
+    }
+    let repaired = png.repair();
+    flags.record(&mut png, "repair", &[])?;
+    if let (Some(format), Some(before)) = (flags.report_size, before) {
+        print_size_report(&SizeReport::diff(&before, &png), format, output_path, lang);
+    }
+    // Written as is: repair only fixes CRCs and lengths, not the structure.
+    write_bytes(output_path, &png.as_bytes())?;
+    say(output_path, lang.render(Msg::ChunksRepaired, &[&repaired]));
+    Ok(())
+}
+
+// The operations in order, with the flags each one takes.
+pub fn pipeline_of(ops: &[PipelineOp], encode: Option<(EncodeOptions, &[u8])>, keep: &[ChunkType], strip_trns: bool) -> Pipeline {
+    ops.iter().fold(Pipeline::new(), |pipeline, op| match (op, &encode) {
+        (PipelineOp::Strip, _) => pipeline.strip(&kept_by_strip(keep, strip_trns, false)),
+        (PipelineOp::Encode, Some((opts, message))) => pipeline.encode(opts.clone(), message),
+        (PipelineOp::Encode, None) => unreachable!("clap requires --chunk-type and --message with encode"),
+        (PipelineOp::Repair, _) => pipeline.repair(),
+    })
+}
+
+// Parses once and writes once, however many operations there are.
+pub fn pipeline(
+    path: &str,
+    output_path: Option<&str>,
+    pipeline: &Pipeline,
+    applied: usize,
+    report_size: Option<ReportFormat>,
+    limits: Limits,
+    lang: Lang,
+) -> CliResult<()> {
+    let output_path = output_path.unwrap_or(path);
+    let before = parse_png_with(path, &read_bytes(path)?, &ParseOptions { limits, ..pipeline.parse_options() })?;
+    let mut png = before.clone();
+    pipeline.apply(&mut png).map_err(encode_error)?;
+    if let Some(format) = report_size {
+        print_size_report(&SizeReport::diff(&before, &png), format, output_path, lang);
+    }
+    write_png(output_path, &png)?;
+    say(output_path, lang.render(Msg::PipelineApplied, &[&applied]));
+    Ok(())
+}
+
+pub fn capacity(path: &str, max_size: Option<u64>, limits: Limits, lang: Lang) -> CliResult<()> {
+    let report = read_png(path, limits)?.capacity_report();
+    println!("{}", lang.render(Msg::CapacityFileSize, &[&report.file_size]));
+    println!("{}", lang.render(Msg::CapacityAncillary, &[&report.ancillary_count, &report.ancillary_bytes]));
+    println!("{}", lang.render(Msg::CapacityMaxChunk, &[&report.max_chunk_payload, &report.max_piece_payload]));
+    if let Some(max_size) = max_size {
+        println!("{}", lang.render(Msg::CapacityRoom, &[&max_size, &report.room_for(max_size)]));
+    }
+    Ok(())
+}
+
+// One line per finding, each led by its file. Any finding fails the run, so CI can gate on it; in
+// a directory, unreadable files are reported and counted as failures as well.
+pub fn scan(path: &str, recursive: bool, glob: Option<&str>, rules_path: Option<&str>, limits: Limits, lang: Lang) -> CliResult<()> {
+    let rules = load_rules(rules_path)?;
+    if !Path::new(path).is_dir() {
+        if recursive || glob.is_some() {
+            return Err(CliError::NotADirectory(path.to_string()));
+        }
+        let findings = scan_file(path, &rules, limits)?;
+        return scan_result(&findings.iter().map(|f| (path, f)).collect::<Vec<_>>(), 1, lang);
+    }
+
+    let files = collect_files(Path::new(path), recursive, glob)
+        .map_err(|e| CliError::Read { path: path.to_string(), reason: e.to_string() })?;
+    let mut findings = Vec::new();
+    let mut failed = 0;
+    for file in &files {
+        let file = file.to_string_lossy();
+        match scan_file(&file, &rules, limits) {
+            Ok(found) => findings.push((file, found)),
+            Err(e) => {
+                eprintln!("{}", e.render(lang));
+                failed += 1;
+            },
+        }
+    }
+    let flat: Vec<(&str, &Finding)> = findings.iter()
+        .flat_map(|(file, found)| found.iter().map(move |f| (file.as_ref(), f)))
         .collect();
-    println!("The following chunks can be decoded:");
-    for chunk_type in chunk_types {
-        println!("{}", chunk_type);
+    match (scan_result(&flat, files.len(), lang), failed) {
+        (result, 0) => result,
+        (result, failed) => {
+            if let Err(e) = result {
+                eprintln!("{}", e.render(lang));
+            }
+            Err(CliError::BatchFailed { failed, total: files.len() })
+        },
+    }
+}
+
+// Lenient, so chunks after IEND are kept for the scan to flag.
+fn scan_file(path: &str, rules: &Rules, limits: Limits) -> CliResult<Vec<Finding>> {
+    let options = ParseOptions { lenient: true, limits, ..Default::default() };
+    let (png, _) = Png::parse_with(&read_bytes(path)?, &options)
+        .map_err(|e| CliError::InvalidPng { path: path.to_string(), reason: e.to_string() })?;
+    Ok(aggregate(png.suspicious_chunks().into_iter().chain(rule_findings(rules, &png))))
+}
+
+fn scan_result(findings: &[(&str, &Finding)], files: usize, lang: Lang) -> CliResult<()> {
+    for (file, finding) in findings {
+        println!("{}: {}", file, finding);
+    }
+    let flagged = findings.iter().map(|(file, _)| file).collect::<BTreeSet<_>>().len();
+    match findings.len() {
+        0 => {
+            println!("{}", lang.render(Msg::ScanClean, &[&files]));
+            Ok(())
+        },
+        count => Err(CliError::Suspicious { findings: count, flagged, total: files }),
+    }
+}
+
+#[cfg(feature = "rules")]
+type Rules = Option<pngme::rules::RuleSet>;
+
+#[cfg(not(feature = "rules"))]
+struct Rules;
+
+#[cfg(feature = "rules")]
+fn load_rules(path: Option<&str>) -> CliResult<Rules> {
+    path.map(|path| {
+        String::from_utf8_lossy(&read_bytes(path)?).parse()
+            .map_err(|e: pngme::Error| CliError::InvalidRules { path: path.to_string(), reason: e.to_string() })
+    }).transpose()
+}
+
+#[cfg(not(feature = "rules"))]
+fn load_rules(_: Option<&str>) -> CliResult<Rules> {
+    Ok(Rules)
+}
+
+#[cfg(feature = "rules")]
+fn rule_findings(rules: &Rules, png: &Png) -> Vec<Finding> {
+    rules.as_ref().map_or_else(Vec::new, |rules| rules.scan(png).iter().map(Finding::from).collect())
+}
+
+#[cfg(not(feature = "rules"))]
+fn rule_findings(_: &Rules, _: &Png) -> Vec<Finding> {
+    Vec::new()
+}
+
+#[cfg(feature = "stego")]
+fn hide_in_pixels(carrier: &[u8], message: &[u8], password: Option<&str>) -> pngme::Result<Vec<u8>> {
+    pngme::stego::encode_lsb(carrier, message, password)
+}
+
+#[cfg(not(feature = "stego"))]
+fn hide_in_pixels(_: &[u8], _: &[u8], _: Option<&str>) -> pngme::Result<Vec<u8>> {
+    Err(PngMsgError::StegoUnavailable.into())
+}
+
+#[cfg(feature = "stego")]
+fn read_from_pixels(bytes: &[u8], password: Option<&str>) -> pngme::Result<Vec<u8>> {
+    pngme::stego::decode_lsb(bytes, password)
+}
+
+#[cfg(not(feature = "stego"))]
+fn read_from_pixels(_: &[u8], _: Option<&str>) -> pngme::Result<Vec<u8>> {
+    Err(PngMsgError::StegoUnavailable.into())
+}
+
+fn print_size_report(report: &SizeReport, format: ReportFormat, output: &str, lang: Lang) {
+    if format == ReportFormat::Json {
+        say(output, report.to_json().trim_end());
+        return;
     }
+
+    let header = [Msg::SizeType, Msg::SizeBefore, Msg::SizeAfter, Msg::SizeDelta]
+        .map(|m| lang.render(m, &[]));
+    say(output, format!("{:<6} {:>10} {:>10} {:>10}", header[0], header[1], header[2], header[3]));
+    for row in report.changed() {
+        say(output, format!("{:<6} {:>10} {:>10} {:>+10}", row.chunk_type, row.before.bytes, row.after.bytes, row.delta()));
+    }
+    say(output, lang.render(Msg::SizeTotal, &[
+        &report.before, &report.after, &format!("{:+}", report.delta()), &format!("{:+.2}", report.percent()),
+    ]));
 }
 
-pub fn encode(path: &str, chunk_type: &str, message: &str) -> std::io::Result<bool> {
-    let buffer = get_bytes_from_path(path);
-    let mut png = Png::try_from(buffer.as_slice()).unwrap();
+// Status lines go to stderr when stdout is carrying the written file.
+fn say(output: &str, line: impl fmt::Display) {
+    if output == STDIO {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
 
-    let i_end = png.remove_chunk("IEND").expect("Unable to remove end chunk");
-    png.append_chunk(
-        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), message.as_bytes().into())
-    );
-    png.append_chunk(i_end);
+// A secret given as is or in a file. Only the first line of the file counts, so the newline an
+// editor adds is not part of it.
+pub fn read_secret(value: &Option<String>, path: &Option<String>) -> CliResult<Option<String>> {
+    let Some(path) = path else {
+        return Ok(value.clone());
+    };
+    let text = String::from_utf8(read_bytes(path)?)
+        .map_err(|e| CliError::Read { path: path.to_string(), reason: e.to_string() })?;
+    Ok(Some(text.lines().next().unwrap_or_default().to_string()))
+}
 
-    let write_path = std::path::Path::new(path);
-    fs::write(write_path, png.as_bytes())?;
-    println!("Message encoded!");
-    Ok(true)
+fn read_bytes(path: &str) -> CliResult<Vec<u8>> {
+    let read = || match path {
+        STDIO => {
+            let mut bytes = Vec::new();
+            io::stdin().lock().read_to_end(&mut bytes).map(|_| bytes)
+        },
+        _ => fs::read(path),
+    };
+    read().map_err(|e| CliError::Read { path: path.to_string(), reason: e.to_string() })
 }
 
-pub fn decode(path: &str, chunk_type: &str) -> std::io::Result<bool> {
-    let buffer = get_bytes_from_path(path);
-    let png = Png::try_from(buffer.as_slice()).unwrap();
+fn parse_png(path: &str, bytes: &[u8], limits: Limits) -> CliResult<Png> {
+    parse_png_with(path, bytes, &ParseOptions { limits, ..Default::default() })
+}
 
-    let target = png.chunk_by_type(chunk_type).expect("Unable to get chunk");
+fn parse_png_with(path: &str, bytes: &[u8], options: &ParseOptions) -> CliResult<Png> {
+    Png::parse_with(bytes, options).map(|(png, _)| png).map_err(|e| {
+        if let Some(PngMsgError::CrcMismatch(CrcMismatch { stored, computed, position: Some(position) })) = e.downcast_ref() {
+            return CliError::Crc { path: path.to_string(), stored: *stored, computed: *computed, position: *position };
+        }
+        if let Some(limit) = e.downcast_ref::<LimitExceeded>() {
+            return CliError::Limit(limit.to_string());
+        }
+        CliError::InvalidPng { path: path.to_string(), reason: e.to_string() }
+    })
+}
 
-    println!("Message is: {}", target.data_as_string().unwrap());
+fn read_png(path: &str, limits: Limits) -> CliResult<Png> {
+    parse_png(path, &read_bytes(path)?, limits)
+}
 
-    Ok(true)
+// Files are replaced atomically, so an interrupted write never destroys the original.
+fn write_bytes(path: &str, bytes: &[u8]) -> CliResult<()> {
+    match path {
+        STDIO => write_stdout(bytes),
+        _ => replace_file(Path::new(path), |out| out.write_all(bytes))
+            .map_err(|e| CliError::Write { path: path.to_string(), reason: e.to_string() }),
+    }
 }
 
-pub fn remove(path: &str, chunk_type: &str) -> std::io::Result<bool> {
-    let buffer = get_bytes_from_path(path);
-    let mut png = Png::try_from(buffer.as_slice()).unwrap();
+// Nothing to keep when the output file does not exist yet.
+pub fn backup(path: &str) -> CliResult<()> {
+    let path = Path::new(path);
+    if !path.exists() {
+        return Ok(());
+    }
+    backup_file(path).map(|_| ()).map_err(|e| {
+        CliError::Write { path: backup_path(path).display().to_string(), reason: e.to_string() }
+    })
+}
 
-    png.remove_chunk(chunk_type).expect("Unable to remove chunk");
-    let write_path = std::path::Path::new(path);
-    fs::write(write_path, png.as_bytes())?;
-    println!("Chunk removed!");
+// Key files are created, never replaced; a private key is readable by its owner only.
+fn write_new_file(path: &str, bytes: &[u8], private: bool) -> CliResult<()> {
+    let mut options = fs::File::options();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    options.open(path).and_then(|mut file| file.write_all(bytes))
+        .map_err(|e| CliError::Write { path: path.to_string(), reason: e.to_string() })
+}
 
-    Ok(true)
+fn write_png(path: &str, png: &Png) -> CliResult<()> {
+    let bytes = png.serialize(SerializePolicy::Strict)
+        .map_err(|e| CliError::InvalidPng { path: path.to_string(), reason: e.to_string() })?;
+    write_bytes(path, &bytes)
 }
-fn get_bytes_from_path(path: &str) -> Vec<u8> {
-    let mut f = File::open(path).expect("no file found");
-    let mut buffer = Vec::new();
-    f.read_to_end(&mut buffer).expect("buffer overflow");
-    buffer
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_error_renders_in_every_language() {
+        let errors = [
+            CliError::Read { path: "a.png".into(), reason: "gone".into() },
+            CliError::Write { path: "a.png".into(), reason: "read-only".into() },
+            CliError::InvalidPng { path: "a.png".into(), reason: "Not a PNG file: the input is empty".into() },
+            CliError::Crc { path: "a.png".into(), stored: 1, computed: 2, position: ChunkPosition { offset: 8, index: 0, chunk_type: None } },
+            CliError::ChunkNotFound("ruSt".into()),
+            CliError::NotUtf8("ruSt".into()),
+            CliError::MissingIend,
+            CliError::CriticalChunk("IDAT".into()),
+            CliError::Provenance("full".into()),
+            CliError::Limit("3 is over the max_chunks limit of 2".into()),
+            CliError::Encrypt("no randomness".into()),
+            CliError::Encode("Conflicting chunk options: a signature cannot be stored under a tEXt keyword".into()),
+            CliError::Decrypt { chunk_type: "ruSt".into(), reason: "wrong passphrase".into() },
+            CliError::Lsb { path: "a.png".into(), reason: "palette image".into() },
+            CliError::Integrity { chunk_type: "ruSt".into(), reason: "bad tag".into() },
+            CliError::BinaryMessage("ruSt".into()),
+            CliError::StdoutIsTerminal,
+            CliError::InvalidInput("Invalid hex: odd number of hex digits".into()),
+            CliError::Key { path: "id.pem".into(), reason: "Invalid key file: not an Ed25519 key".into() },
+            CliError::Clock("tIME year 1969 is out of range".into()),
+            CliError::UnsafeChunkType(ChunkType::IDAT),
+            CliError::UnsafeChunkType(ChunkType::TEXT),
+            CliError::RandomType("Random chunk types need the `random-type` feature".into()),
+            CliError::NotADirectory("a.png".into()),
+            CliError::NotForDirectories("--manifest"),
+            CliError::BatchFailed { failed: 2, total: 145 },
+            CliError::Suspicious { findings: 3, flagged: 2, total: 145 },
+            CliError::NotAMessage("ruSt".into()),
+            CliError::Frame { chunk_type: "ruSt".into(), reason: "Invalid message frame: unknown version 2".into() },
+        ];
+        for lang in [Lang::En, Lang::De] {
+            for error in &errors {
+                let rendered = error.render(lang);
+                assert!(!rendered.contains('{'), "unfilled placeholder in {}", rendered);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_errors_keep_their_kind() {
+        let kind = |error: PngMsgError| encode_error(error.into()).msg();
+        assert_eq!(kind(PngMsgError::ProvenanceFailed("full".into())), Msg::ProvenanceFailed);
+        assert_eq!(kind(PngMsgError::EncryptionUnavailable), Msg::EncryptFailed);
+        assert_eq!(kind(PngMsgError::UnsafeChunkType(ChunkType::IDAT)), Msg::UnsafeChunkType);
+        assert_eq!(kind(PngMsgError::Io(io::Error::other("no randomness"))), Msg::EncodeFailed);
+
+        let mut opts = EncodeOptions::new(ChunkType::TEXT);
+        opts.keyword = Some("Comment".to_string());
+        opts.password = Some("secret".to_string());
+        let mut png = Png::from_chunks(vec![Chunk::new(ChunkType::IHDR, vec![0; 13]), Chunk::new(ChunkType::IEND, Vec::new())]).unwrap();
+        let error = encode_error(encode_message(&mut png, b"x", &opts).unwrap_err());
+        assert!(matches!(&error, CliError::Encode(reason) if reason.contains("tEXt keyword")), "{:?}", error);
+    }
+
+    #[test]
+    fn test_provenance_through_encode_strip_repair() {
+        let path = std::env::temp_dir().join(format!("pngme-provenance-{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/pixels.png"), path).unwrap();
+        let mut opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        opts.record_provenance = true;
+        let encode_flags = EncodeFlags { output: None, manifest: None, report_size: None, force: false, random_type: false };
+        let flags = ChangeFlags { record_provenance: true, report_size: None };
+
+        encode(path, b"hidden", &opts, &encode_flags, Lang::En).unwrap();
+        strip(path, None, &[], false, flags, Limits::default(), Lang::En).unwrap();
+        repair(path, None, flags, Limits::default(), Lang::En).unwrap();
+
+        let png = Png::try_from(fs::read(path).unwrap().as_slice()).unwrap();
+        let operations: Vec<String> = png.provenance().into_iter().map(|record| record.operation).collect();
+        assert_eq!(operations, ["encode", "strip", "repair"]);
+        assert!(png.chunk_by_type("ruSt").is_none());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_repair_records_provenance() {
+        let path = std::env::temp_dir().join(format!("pngme-repair-{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/pixels.png"), path).unwrap();
+        let flags = ChangeFlags { record_provenance: true, report_size: Some(ReportFormat::Json) };
+
+        repair(path, None, flags, Limits::default(), Lang::En).unwrap();
+        let png = Png::try_from(fs::read(path).unwrap().as_slice()).unwrap();
+        assert_eq!(png.provenance().iter().map(|record| record.operation.as_str()).collect::<Vec<_>>(), ["repair"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_secret() {
+        let path = std::env::temp_dir().join(format!("pngme-secret-{}", std::process::id()));
+        fs::write(&path, "correct horse\nsecond line\n").unwrap();
+        let file = Some(path.to_str().unwrap().to_string());
+        assert_eq!(read_secret(&None, &file).unwrap().as_deref(), Some("correct horse"));
+        assert_eq!(read_secret(&Some("given".to_string()), &None).unwrap().as_deref(), Some("given"));
+        assert_eq!(read_secret(&None, &None).unwrap(), None);
+        fs::remove_file(path).unwrap();
+        assert!(matches!(read_secret(&None, &Some("/nonexistent/pngme-secret".to_string())), Err(CliError::Read { .. })));
+    }
+
+    #[test]
+    fn test_crc_error_is_translated() {
+        let position = ChunkPosition { offset: 0x1a2b3c, index: 7, chunk_type: Some(ChunkType::TEXT) };
+        let error = CliError::Crc { path: "dice.png".into(), stored: 0x0badc0de, computed: 0xabcd1234, position };
+        assert_eq!(
+            error.render(Lang::En),
+            "error: dice.png: chunk #7 (tEXt) at offset 0x1a2b3c has a bad CRC (stored 0badc0de, computed abcd1234)"
+        );
+        assert_eq!(
+            error.render(Lang::De),
+            "Fehler: dice.png: Chunk #7 (tEXt) bei Offset 0x1a2b3c hat eine falsche Prüfsumme (gespeichert 0badc0de, berechnet abcd1234)"
+        );
+    }
 }
@@ -0,0 +1,145 @@
+use crc::{Crc, Digest, CRC_32_ISO_HDLC};
+
+pub trait Crc32Backend: Default {
+    fn update(&mut self, bytes: &[u8]);
+    fn finalize(self) -> u32;
+
+    fn checksum(bytes: &[u8]) -> u32 {
+        let mut hasher = Self::default();
+        hasher.update(bytes);
+        hasher.finalize()
+    }
+}
+
+static CRC_CRATE: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+pub struct CrcCrateBackend {
+    digest: Digest<'static, u32>,
+}
+
+impl Default for CrcCrateBackend {
+    fn default() -> Self {
+        CrcCrateBackend { digest: CRC_CRATE.digest() }
+    }
+}
+
+impl Crc32Backend for CrcCrateBackend {
+    fn update(&mut self, bytes: &[u8]) {
+        self.digest.update(bytes);
+    }
+
+    fn finalize(self) -> u32 {
+        self.digest.finalize()
+    }
+}
+
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+// The byte-at-a-time table algorithm from the PNG specification's sample code.
+pub struct TableBackend {
+    state: u32,
+}
+
+impl Default for TableBackend {
+    fn default() -> Self {
+        TableBackend { state: 0xffff_ffff }
+    }
+}
+
+impl Crc32Backend for TableBackend {
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state = TABLE[((self.state ^ byte as u32) & 0xff) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    fn finalize(self) -> u32 {
+        self.state ^ 0xffff_ffff
+    }
+}
+
+#[cfg(not(feature = "table-crc"))]
+pub type Crc32 = CrcCrateBackend;
+#[cfg(feature = "table-crc")]
+pub type Crc32 = TableBackend;
+
+pub fn crc_of(parts: &[&[u8]]) -> u32 {
+    let mut hasher = Crc32::default();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vectors() -> Vec<(Vec<u8>, u32)> {
+        vec![
+            (Vec::new(), 0),
+            (b"IEND".to_vec(), 0xae42_6082),
+            (b"123456789".to_vec(), 0xcbf4_3926),
+            (b"RuStThis is where your secret message will be!".to_vec(), 2882656334),
+        ]
+    }
+
+    fn check_vectors<B: Crc32Backend>() {
+        for (data, expected) in vectors() {
+            assert_eq!(B::checksum(&data), expected, "vector {:?}", String::from_utf8_lossy(&data));
+        }
+    }
+
+    fn streamed<B: Crc32Backend>(data: &[u8], step: usize) -> u32 {
+        let mut hasher = B::default();
+        for piece in data.chunks(step) {
+            hasher.update(piece);
+        }
+        hasher.finalize()
+    }
+
+    #[test]
+    fn test_crc_crate_backend_vectors() {
+        check_vectors::<CrcCrateBackend>();
+    }
+
+    #[test]
+    fn test_table_backend_vectors() {
+        check_vectors::<TableBackend>();
+    }
+
+    #[test]
+    fn test_selected_backend_vectors() {
+        check_vectors::<Crc32>();
+        assert_eq!(crc_of(&[b"RuSt", b"This is where your secret message will be!"]), 2882656334);
+    }
+
+    #[test]
+    fn test_backends_agree_on_streamed_megabytes() {
+        let data: Vec<u8> = (0..3 * 1024 * 1024u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let expected = CrcCrateBackend::checksum(&data);
+
+        assert_eq!(TableBackend::checksum(&data), expected);
+        for step in [1, 7, 4093, 65537] {
+            assert_eq!(streamed::<CrcCrateBackend>(&data, step), expected, "crc crate, step {}", step);
+            assert_eq!(streamed::<TableBackend>(&data, step), expected, "table, step {}", step);
+        }
+    }
+}
@@ -0,0 +1,646 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::Result;
+use crate::atomic::replace_file;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMsgError;
+use crate::limits::Limits;
+use crate::framing;
+use crate::integrity;
+use crate::finding::{json_escape, render, Finding, FindingCode, FindingFormat, Severity};
+use crate::png::{ParseOptions, Png, PngFileError};
+use crate::sha256::to_hex;
+use crate::sign::{self, VerifyingKey, FINGERPRINT_LEN};
+use crate::split::{join_piece_data, join_pieces, PieceHeader};
+
+// Limits apply to each input on its own, so one oversized or slow file only fails itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+    pub limits: Limits,
+    pub item_budget: Option<Duration>,
+    // Takes the chunk data as is, for messages written before framing or by other tools.
+    pub unframed: bool,
+    // Opens a message written with `EncodeOptions::password`; needs the `encrypt` feature.
+    pub password: Option<String>,
+    // Checks an HMAC integrity frame. When set, a message without one is refused.
+    pub hmac_key: Option<String>,
+    // Checks the signature. When set, an unsigned message is refused.
+    pub verify_key: Option<VerifyingKey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub body: Vec<u8>,
+    // Every chunk of the requested type, in file order; the body comes from the first. For a
+    // split message, only its pieces.
+    pub chunk_indices: Vec<usize>,
+    // The layers taken off to get the body.
+    pub codec: CodecId,
+    // None when the message had neither an integrity frame nor a signature.
+    pub verified: Option<VerificationStatus>,
+    pub metadata: MessageMetadata,
+    pub warnings: Vec<Finding>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecId {
+    Plain,
+    Zlib,
+    Encrypted,
+    ZlibEncrypted,
+}
+
+impl CodecId {
+    pub fn from_flags(flags: u8) -> Self {
+        match (flags & framing::COMPRESSED != 0, flags & framing::ENCRYPTED != 0) {
+            (false, false) => CodecId::Plain,
+            (true, false) => CodecId::Zlib,
+            (false, true) => CodecId::Encrypted,
+            (true, true) => CodecId::ZlibEncrypted,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CodecId::Plain => "plain",
+            CodecId::Zlib => "zlib",
+            CodecId::Encrypted => "encrypted",
+            CodecId::ZlibEncrypted => "zlib+encrypted",
+        }
+    }
+}
+
+// What was checked on the way to the body. A digest or HMAC frame that is reported here passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerificationStatus {
+    pub integrity: Option<integrity::Mode>,
+    pub signature: Option<SignatureCheck>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureCheck {
+    // The fingerprint the signature names.
+    pub signer: [u8; FINGERPRINT_LEN],
+    // False when no `DecodeOptions::verify_key` was given, so the signature was only stripped.
+    pub verified: bool,
+}
+
+// The envelope, as stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageMetadata {
+    // None for a message read unframed.
+    pub frame_version: Option<u8>,
+    // From the message frame, see `framing`; 0 for a message read unframed.
+    pub flags: u8,
+    // Chunks the body was joined from: 1 unless the message was split.
+    pub pieces: usize,
+    // Size of the frame bodies before any layer was taken off.
+    pub stored_size: usize,
+}
+
+impl Message {
+    pub fn to_json(&self) -> String {
+        let indices: Vec<String> = self.chunk_indices.iter().map(usize::to_string).collect();
+        let body = match std::str::from_utf8(&self.body) {
+            Ok(text) => format!("\"{}\"", json_escape(text)),
+            Err(_) => "null".to_string(),
+        };
+        let verified = match &self.verified {
+            None => "null".to_string(),
+            Some(status) => {
+                let integrity = status.integrity.map_or("null".to_string(), |mode| format!("\"{}\"", mode.as_str()));
+                let signature = status.signature.map_or("null".to_string(), |check| {
+                    format!("{{\"signer\": \"{}\", \"verified\": {}}}", to_hex(&check.signer), check.verified)
+                });
+                format!("{{\"integrity\": {}, \"signature\": {}}}", integrity, signature)
+            },
+        };
+        let metadata = format!(
+            "{{\"frame_version\": {}, \"flags\": {}, \"pieces\": {}, \"stored_size\": {}}}",
+            self.metadata.frame_version.map_or("null".to_string(), |version| version.to_string()),
+            self.metadata.flags, self.metadata.pieces, self.metadata.stored_size
+        );
+        let warnings = render(&self.warnings, FindingFormat::Json).replace('\n', "\n  ");
+        format!(
+            "{{\n  \"body\": {},\n  \"body_size\": {},\n  \"chunk_indices\": [{}],\n  \"codec\": \"{}\",\n  \"verified\": {},\n  \"metadata\": {},\n  \"warnings\": {}\n}}\n",
+            body, self.body.len(), indices.join(", "), self.codec.as_str(), verified, metadata, warnings.trim_end()
+        )
+    }
+}
+
+// Checks the frame, joins the pieces of a split message and opens every layer the frame names,
+// so the body is the plaintext. A layer that needs a key the options lack is an error.
+pub fn decode_message_detailed(png: &Png, chunk_type: &ChunkType, opts: &DecodeOptions) -> std::result::Result<Option<Message>, PngMsgError> {
+    let Some(mut message) = decode_unframed(png, chunk_type) else {
+        return Ok(None);
+    };
+    if opts.unframed {
+        (message.body, message.verified) = open_layers(&message.body, unframed_flags(opts), chunk_type, opts)?;
+        return Ok(Some(message));
+    }
+    let frame = framing::open(&message.body)?;
+    let body = if frame.has(framing::SPLIT) {
+        // Other chunks of the type can hold other messages, split or not, so only the pieces
+        // with the first one's message id are joined.
+        let message_id = PieceHeader::parse(frame.body).ok_or(PngMsgError::InvalidPieceHeader { piece: 0 })?.message_id;
+        let pieces: Vec<(usize, &[u8])> = message.chunk_indices.iter()
+            .filter_map(|&index| {
+                let piece = framing::open(png.chunks()[index].data()).ok().filter(|piece| piece.has(framing::SPLIT))?;
+                (PieceHeader::parse(piece.body)?.message_id == message_id).then_some((index, piece.body))
+            })
+            .collect();
+        // Pieces differ by design; they are not copies that disagree.
+        message.warnings.clear();
+        message.chunk_indices = pieces.iter().map(|(index, _)| *index).collect();
+        message.metadata.pieces = pieces.len();
+        message.metadata.stored_size = pieces.iter().map(|(_, piece)| piece.len()).sum();
+        join_piece_data(pieces.into_iter().map(|(_, piece)| piece))?
+    } else {
+        message.metadata.stored_size = frame.body.len();
+        frame.body.to_vec()
+    };
+    message.metadata.frame_version = Some(frame.version);
+    message.metadata.flags = frame.flags;
+    message.codec = CodecId::from_flags(frame.flags);
+    (message.body, message.verified) = open_layers(&body, frame.flags, chunk_type, opts)?;
+    Ok(Some(message))
+}
+
+// One chunk's data as a message of its own, for reading every copy; a piece of a split message
+// is refused, since it only holds part of one.
+pub fn open_chunk_data(data: &[u8], chunk_type: &ChunkType, opts: &DecodeOptions) -> std::result::Result<Vec<u8>, PngMsgError> {
+    if opts.unframed {
+        return Ok(open_layers(data, unframed_flags(opts), chunk_type, opts)?.0);
+    }
+    let frame = framing::open(data)?;
+    if frame.has(framing::SPLIT) {
+        return Err(PngMsgError::InvalidMessageFrame("a piece of a split message".to_string()));
+    }
+    Ok(open_layers(frame.body, frame.flags, chunk_type, opts)?.0)
+}
+
+// Undoes what encode did, in reverse: the integrity frame and signature, then encryption, then
+// compression. A password decrypts even without the flag, see `unframed_flags`.
+pub fn open_layers(body: &[u8], flags: u8, chunk_type: &ChunkType, opts: &DecodeOptions) -> std::result::Result<(Vec<u8>, Option<VerificationStatus>), PngMsgError> {
+    let (body, verified) = open_frame(body, flags, chunk_type, opts)?;
+    let body = match (&opts.password, flags & framing::ENCRYPTED != 0) {
+        (Some(password), _) => decrypt(body, password)?,
+        (None, true) => return Err(PngMsgError::PasswordRequired),
+        (None, false) => body.to_vec(),
+    };
+    if flags & framing::COMPRESSED == 0 {
+        return Ok((body, verified));
+    }
+    Ok((decompress_body(&body, &opts.limits)?, verified))
+}
+
+// Unframed data has no flags, so the keys given are the only hint of which layers it has.
+fn unframed_flags(opts: &DecodeOptions) -> u8 {
+    let mut flags = 0;
+    if opts.hmac_key.is_some() {
+        flags |= framing::INTEGRITY;
+    }
+    if opts.verify_key.is_some() {
+        flags |= framing::SIGNED;
+    }
+    flags
+}
+
+// Checks and strips the integrity frame, then the signature, when the flags name them; runs
+// before decryption since both cover the stored bytes. A key for a layer the flags do not name
+// is refused, so clearing the flag does not get around the check.
+pub fn open_frame<'a>(body: &'a [u8], flags: u8, chunk_type: &ChunkType, opts: &DecodeOptions) -> std::result::Result<(&'a [u8], Option<VerificationStatus>), PngMsgError> {
+    let (body, integrity) = match (flags & framing::INTEGRITY != 0, &opts.hmac_key) {
+        (true, key) => (integrity::open(body, key.as_deref())?, integrity::sealed_mode(body)),
+        (false, Some(_)) => return Err(PngMsgError::IntegrityCheckFailed),
+        (false, None) => (body, None),
+    };
+    let (body, signature) = match (flags & framing::SIGNED != 0, &opts.verify_key) {
+        (true, key) => {
+            let signature = sign::signer(body).map(|signer| SignatureCheck { signer, verified: key.is_some() });
+            (sign::open(body, chunk_type, key.as_ref())?, signature)
+        },
+        (false, Some(_)) => return Err(PngMsgError::Unsigned),
+        (false, None) => (body, None),
+    };
+    let verified = (integrity.is_some() || signature.is_some()).then_some(VerificationStatus { integrity, signature });
+    Ok((body, verified))
+}
+
+#[cfg(feature = "encrypt")]
+fn decrypt(body: &[u8], password: &str) -> std::result::Result<Vec<u8>, PngMsgError> {
+    crate::encrypt::decrypt_payload(body, password)
+}
+
+#[cfg(not(feature = "encrypt"))]
+fn decrypt(_: &[u8], _: &str) -> std::result::Result<Vec<u8>, PngMsgError> {
+    Err(PngMsgError::EncryptionUnavailable)
+}
+
+// The first chunk's data as stored, for messages written before framing or by other tools.
+pub fn decode_unframed(png: &Png, chunk_type: &ChunkType) -> Option<Message> {
+    let wanted = chunk_type.bytes();
+    let copies: Vec<(usize, &[u8])> = png.chunks().iter()
+        .enumerate()
+        .filter(|(_, c)| c.chunk_type().bytes() == wanted)
+        .map(|(index, c)| (index, c.data()))
+        .collect();
+    let (_, body) = *copies.first()?;
+
+    let warnings = copies.iter()
+        .filter(|(_, data)| *data != body)
+        .map(|(index, data)| Finding {
+            chunk_index: Some(*index),
+            chunk_type: Some(*chunk_type),
+            length: Some(data.len() as u32),
+            offset: None,
+            code: FindingCode::DisagreeingCopies,
+            severity: Severity::Warning,
+            message: format!("differs from the first copy at chunk {}", copies[0].0),
+        })
+        .collect();
+
+    Some(Message {
+        body: body.to_vec(),
+        chunk_indices: copies.iter().map(|(index, _)| *index).collect(),
+        codec: CodecId::Plain,
+        verified: None,
+        metadata: MessageMetadata { pieces: 1, stored_size: body.len(), ..Default::default() },
+        warnings,
+    })
+}
+
+pub fn decode_message(png: &Png, chunk_type: &ChunkType, opts: &DecodeOptions) -> std::result::Result<Option<Vec<u8>>, PngMsgError> {
+    Ok(decode_message_detailed(png, chunk_type, opts)?.map(|message| message.body))
+}
+
+// Writes the plaintext with no UTF-8 check, and returns its size.
+pub fn decode_to_file(png: &Png, chunk_type: &ChunkType, path: &Path, opts: &DecodeOptions) -> Result<usize> {
+    let body = decode_message(png, chunk_type, opts)?.ok_or_else(|| PngMsgError::ChunkNotFound(chunk_type.to_string()))?;
+    replace_file(path, |out| out.write_all(&body)).map_err(|source| PngFileError::Io { path: path.to_path_buf(), source })?;
+    Ok(body.len())
+}
+
+// Every chunk of the type as its own message, with its chunk index, in file order. The data is
+// as stored, frame included; `open_chunk_data` opens each.
+pub fn decode_all<'a>(png: &'a Png, chunk_type: &ChunkType) -> Vec<(usize, &'a [u8])> {
+    let wanted = chunk_type.bytes();
+    png.chunks().iter()
+        .enumerate()
+        .filter(|(_, c)| c.chunk_type().bytes() == wanted)
+        .map(|(index, c)| (index, c.data()))
+        .collect()
+}
+
+// Joins every chunk of the type as unframed pieces, the way `EncodeOptions::max_chunk_size`
+// wrote them before framing; `decode_message` joins framed pieces by itself.
+pub fn decode_split_message(png: &Png, chunk_type: &ChunkType) -> std::result::Result<Vec<u8>, PngMsgError> {
+    let wanted = chunk_type.bytes();
+    let mut pieces = png.chunks().iter().filter(|c| c.chunk_type().bytes() == wanted).peekable();
+    if pieces.peek().is_none() {
+        return Err(PngMsgError::ChunkNotFound(chunk_type.to_string()));
+    }
+    join_pieces(pieces)
+}
+
+// Undoes `framing::COMPRESSED`, the last layer of a message, holding the result to the
+// message limit.
+#[cfg(feature = "compression")]
+pub fn decompress_body(body: &[u8], limits: &Limits) -> std::result::Result<Vec<u8>, PngMsgError> {
+    crate::zlib::decompress(body, limits.max_message_bytes.unwrap_or(usize::MAX))
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn decompress_body(_: &[u8], _: &Limits) -> std::result::Result<Vec<u8>, PngMsgError> {
+    Err(PngMsgError::CompressionUnavailable)
+}
+
+pub fn decode_one(input: &[u8], chunk_type: &ChunkType, opts: &DecodeOptions) -> Result<Option<Vec<u8>>> {
+    let options = ParseOptions {
+        limits: opts.limits,
+        deadline: opts.item_budget.map(|budget| Instant::now() + budget),
+        ..Default::default()
+    };
+    let (png, _) = Png::parse_with(input, &options)?;
+    Ok(decode_message(&png, chunk_type, opts)?)
+}
+
+// Results line up with `inputs`; `Ok(None)` means the input parsed but has no such chunk.
+pub fn decode_many<'a, I>(inputs: I, chunk_type: &ChunkType, opts: &DecodeOptions) -> Vec<Result<Option<Vec<u8>>>>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    inputs.into_iter().map(|input| decode_one(input, chunk_type, opts)).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+
+    fn png_with(message: Option<&str>) -> Vec<u8> {
+        let mut chunks = vec![Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13])];
+        if let Some(message) = message {
+            chunks.push(Chunk::new(ChunkType::from_str("ruSt").unwrap(), framing::seal(0, message.as_bytes())));
+        }
+        chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+        Png::from_chunks(chunks).unwrap().as_bytes()
+    }
+
+    #[test]
+    fn test_decode_to_file_keeps_binary_bytes() {
+        let payload = vec![0x00, 0xff, 0xfe, 0x80, 0x0a];
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), framing::seal(0, &payload)),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap();
+        let path = std::env::temp_dir().join(format!("pngme-decode-file-{}", std::process::id()));
+
+        assert_eq!(decode_to_file(&png, &ChunkType::from_str("ruSt").unwrap(), &path, &DecodeOptions::default()).unwrap(), payload.len());
+        assert_eq!(fs::read(&path).unwrap(), payload);
+        assert!(decode_to_file(&png, &ChunkType::from_str("abCd").unwrap(), &path, &DecodeOptions::default()).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_mixed_batch_keeps_order_and_independent_results() {
+        let first = png_with(Some("first"));
+        let empty = png_with(None);
+        let mut corrupt = png_with(Some("broken"));
+        let last = corrupt.len() - 13;
+        corrupt[last] ^= 0xff;
+        let second = png_with(Some("second"));
+
+        let inputs = [&first[..], &empty[..], &corrupt[..], b"not a png at all", &second[..]];
+        let results = decode_many(inputs, &ChunkType::from_str("ruSt").unwrap(), &DecodeOptions::default());
+
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].as_ref().unwrap().as_deref(), Some(&b"first"[..]));
+        assert_eq!(results[1].as_ref().unwrap(), &None);
+        assert!(results[2].is_err());
+        assert!(results[3].is_err());
+        assert_eq!(results[4].as_ref().unwrap().as_deref(), Some(&b"second"[..]));
+    }
+
+    #[test]
+    fn test_size_limit_applies_per_item() {
+        let small = png_with(Some("ok"));
+        let large = png_with(Some(&"x".repeat(1000)));
+        let limits = Limits { max_input_bytes: Some(small.len() as u64), ..Limits::default() };
+        let opts = DecodeOptions { limits, ..Default::default() };
+
+        let results = decode_many([&large[..], &small[..]], &ChunkType::from_str("ruSt").unwrap(), &opts);
+        assert!(results[0].is_err());
+        assert_eq!(results[1].as_ref().unwrap().as_deref(), Some(&b"ok"[..]));
+    }
+
+    #[test]
+    fn test_detailed_message_reports_copies() {
+        let mut png = Png::try_from(png_with(Some("same")).as_slice()).unwrap();
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        png.insert_chunk(2, Chunk::new(ru_st, framing::seal(0, b"same")));
+        png.insert_chunk(3, Chunk::new(ru_st, framing::seal(0, b"other")));
+
+        let message = decode_message_detailed(&png, &ru_st, &DecodeOptions::default()).unwrap().unwrap();
+        assert_eq!(message.body, b"same");
+        assert_eq!(message.chunk_indices, [1, 2, 3]);
+        assert_eq!((message.codec, message.verified), (CodecId::Plain, None));
+        assert_eq!(message.metadata, MessageMetadata { frame_version: Some(1), flags: 0, pieces: 1, stored_size: 4 });
+        assert_eq!(message.warnings.len(), 1);
+        assert_eq!(message.warnings[0].code, FindingCode::DisagreeingCopies);
+        assert_eq!(message.warnings[0].chunk_index, Some(3));
+        assert_eq!(decode_message(&png, &ru_st, &DecodeOptions::default()).unwrap(), Some(b"same".to_vec()));
+        assert_eq!(decode_message(&png, &ChunkType::from_str("zzZz").unwrap(), &DecodeOptions::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_unframed_chunks() {
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ru_st, b"written before framing".to_vec()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap();
+        assert!(matches!(decode_message(&png, &ru_st, &DecodeOptions::default()), Err(PngMsgError::NotAPngMsgPayload)));
+        assert_eq!(decode_unframed(&png, &ru_st).unwrap().body, b"written before framing");
+
+        let bytes = png.as_bytes();
+        assert!(decode_one(&bytes, &ru_st, &DecodeOptions::default()).is_err());
+        let opts = DecodeOptions { unframed: true, ..Default::default() };
+        assert_eq!(decode_one(&bytes, &ru_st, &opts).unwrap().as_deref(), Some(&b"written before framing"[..]));
+    }
+
+    #[test]
+    fn test_framed_pieces_are_joined() {
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        let mut png = Png::try_from(png_with(None).as_slice()).unwrap();
+        let pieces = crate::split::split_payload(&ru_st, &[7; 100], 30).unwrap();
+        for piece in pieces.iter().rev() {
+            png.insert_chunk(1, Chunk::new(ru_st, framing::seal(framing::SPLIT, piece.data())));
+        }
+        let message = decode_message_detailed(&png, &ru_st, &DecodeOptions::default()).unwrap().unwrap();
+        assert_eq!(message.body, [7; 100]);
+        assert_eq!(message.metadata.flags, framing::SPLIT);
+        assert_eq!((message.metadata.pieces, message.metadata.stored_size), (6, pieces.iter().map(|piece| piece.data().len()).sum()));
+        assert_eq!(message.chunk_indices.len(), 6);
+        assert!(message.warnings.is_empty());
+
+        png.insert_chunk(1, Chunk::new(ru_st, framing::seal(framing::SPLIT, b"too short")));
+        assert!(matches!(decode_message(&png, &ru_st, &DecodeOptions::default()), Err(PngMsgError::InvalidPieceHeader { piece: 0 })));
+    }
+
+    #[test]
+    fn test_split_message_among_other_messages() {
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        let mut opts = crate::encode::EncodeOptions::new(ru_st);
+        opts.max_chunk_size = Some(24);
+        let mut png = Png::try_from(png_with(None).as_slice()).unwrap();
+        crate::encode::encode_message(&mut png, b"split in pieces", &opts).unwrap();
+        crate::encode::encode_message(&mut png, b"split in pieces", &opts).unwrap();
+        crate::encode::encode_message(&mut png, b"plain", &crate::encode::EncodeOptions::new(ru_st)).unwrap();
+
+        let message = decode_message_detailed(&png, &ru_st, &DecodeOptions::default()).unwrap().unwrap();
+        assert_eq!(message.body, b"split in pieces");
+        assert_eq!((message.metadata.pieces, message.chunk_indices), (3, vec![1, 2, 3]));
+    }
+
+    fn encoded(opts: &crate::encode::EncodeOptions) -> Png {
+        let mut png = Png::try_from(png_with(None).as_slice()).unwrap();
+        crate::encode::encode_message(&mut png, b"hello", opts).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_integrity_layers_are_opened() {
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        let mut opts = crate::encode::EncodeOptions::new(ru_st);
+        opts.integrity = Some(crate::integrity::Integrity::Digest);
+        let png = encoded(&opts);
+        assert_eq!(decode_message(&png, &ru_st, &DecodeOptions::default()).unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(decode_one(&png.as_bytes(), &ru_st, &DecodeOptions::default()).unwrap(), Some(b"hello".to_vec()));
+
+        opts.integrity = Some(crate::integrity::Integrity::Hmac("key".to_string()));
+        let png = encoded(&opts);
+        assert!(matches!(decode_message(&png, &ru_st, &DecodeOptions::default()), Err(PngMsgError::IntegrityKeyRequired)));
+        let keyed = |key: &str| DecodeOptions { hmac_key: Some(key.to_string()), ..Default::default() };
+        let message = decode_message_detailed(&png, &ru_st, &keyed("key")).unwrap().unwrap();
+        assert_eq!(message.body, b"hello");
+        assert_eq!(message.verified, Some(VerificationStatus { integrity: Some(integrity::Mode::Hmac), signature: None }));
+        assert!(matches!(decode_message(&png, &ru_st, &keyed("other")), Err(PngMsgError::IntegrityCheckFailed)));
+    }
+
+    #[test]
+    fn test_plain_message_that_starts_like_a_layer() {
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        for body in [&b"pmIG hel"[..], b"pmSGxxxx"] {
+            let mut png = Png::try_from(png_with(None).as_slice()).unwrap();
+            crate::encode::encode_message(&mut png, body, &crate::encode::EncodeOptions::new(ru_st)).unwrap();
+            let message = decode_message_detailed(&png, &ru_st, &DecodeOptions::default()).unwrap().unwrap();
+            assert_eq!((message.body.as_slice(), message.verified), (body, None));
+            assert_eq!(open_chunk_data(&framing::seal(0, body), &ru_st, &DecodeOptions::default()).unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn test_keys_for_layers_the_flags_do_not_name() {
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        let png = encoded(&crate::encode::EncodeOptions::new(ru_st));
+        let keyed = DecodeOptions { hmac_key: Some("key".to_string()), ..Default::default() };
+        assert!(matches!(decode_message(&png, &ru_st, &keyed), Err(PngMsgError::IntegrityCheckFailed)));
+        let keyed = DecodeOptions { verify_key: Some(VerifyingKey::from_bytes([7; 32])), ..Default::default() };
+        assert!(matches!(decode_message(&png, &ru_st, &keyed), Err(PngMsgError::Unsigned)));
+    }
+
+    #[test]
+    fn test_encrypted_message_needs_a_password() {
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        let data = framing::seal(framing::ENCRYPTED, b"envelope");
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ru_st, data.clone()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap();
+        assert!(matches!(decode_message(&png, &ru_st, &DecodeOptions::default()), Err(PngMsgError::PasswordRequired)));
+        assert!(matches!(open_chunk_data(&data, &ru_st, &DecodeOptions::default()), Err(PngMsgError::PasswordRequired)));
+    }
+
+    #[cfg(feature = "encrypt")]
+    #[test]
+    fn test_encrypted_message_is_decrypted() {
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        let mut opts = crate::encode::EncodeOptions::new(ru_st);
+        opts.password = Some("pw".to_string());
+        opts.integrity = Some(crate::integrity::Integrity::Digest);
+        let png = encoded(&opts);
+        let with = |password: &str| DecodeOptions { password: Some(password.to_string()), ..Default::default() };
+        assert_eq!(decode_one(&png.as_bytes(), &ru_st, &with("pw")).unwrap(), Some(b"hello".to_vec()));
+        let message = decode_message_detailed(&png, &ru_st, &with("pw")).unwrap().unwrap();
+        assert_eq!((message.body.as_slice(), message.codec), (&b"hello"[..], CodecId::Encrypted));
+        assert_eq!(message.verified.unwrap().integrity, Some(integrity::Mode::Digest));
+        assert_eq!(message.metadata.flags, framing::ENCRYPTED | framing::INTEGRITY);
+        assert!(message.metadata.stored_size > b"hello".len());
+        assert!(matches!(decode_message(&png, &ru_st, &with("wrong")), Err(PngMsgError::DecryptionFailed)));
+    }
+
+    #[test]
+    fn test_open_chunk_data_refuses_pieces() {
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(open_chunk_data(&framing::seal(0, b"hi"), &ru_st, &DecodeOptions::default()).unwrap(), b"hi");
+        assert!(matches!(
+            open_chunk_data(&framing::seal(framing::SPLIT, b"hi"), &ru_st, &DecodeOptions::default()),
+            Err(PngMsgError::InvalidMessageFrame(_))
+        ));
+        let unframed = DecodeOptions { unframed: true, ..Default::default() };
+        assert_eq!(open_chunk_data(b"raw", &ru_st, &unframed).unwrap(), b"raw");
+    }
+
+    #[test]
+    fn test_decode_all_keeps_file_order() {
+        let mut png = Png::try_from(png_with(Some("first")).as_slice()).unwrap();
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("abCd").unwrap(), b"other".to_vec()));
+        png.insert_chunk(3, Chunk::new(ru_st, b"second".to_vec()));
+
+        assert_eq!(decode_all(&png, &ru_st), [(2, &b"pmMG\x01\x00first"[..]), (3, &b"second"[..])]);
+        assert!(decode_all(&png, &ChunkType::from_str("zzZz").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_message_json() {
+        let png = Png::try_from(png_with(Some("say \"hi\"")).as_slice()).unwrap();
+        let message = decode_message_detailed(&png, &ChunkType::from_str("ruSt").unwrap(), &DecodeOptions::default()).unwrap().unwrap();
+        assert_eq!(
+            message.to_json(),
+            "{\n  \"body\": \"say \\\"hi\\\"\",\n  \"body_size\": 8,\n  \"chunk_indices\": [1],\n  \"codec\": \"plain\",\n  \"verified\": null,\n  \"metadata\": {\"frame_version\": 1, \"flags\": 0, \"pieces\": 1, \"stored_size\": 8},\n  \"warnings\": []\n}\n"
+        );
+
+        let mut message = message;
+        message.verified = Some(VerificationStatus {
+            integrity: Some(integrity::Mode::Digest),
+            signature: Some(SignatureCheck { signer: [0xab; FINGERPRINT_LEN], verified: false }),
+        });
+        let json = message.to_json();
+        assert!(json.contains(&format!("\"verified\": {{\"integrity\": \"digest\", \"signature\": {{\"signer\": \"{}\", \"verified\": false}}}}", "ab".repeat(32))), "{}", json);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compressed_message_fields() {
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        let stored = crate::zlib::compress(&[b'a'; 500]);
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ru_st, framing::seal(framing::COMPRESSED, &stored)),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap();
+        let message = decode_message_detailed(&png, &ru_st, &DecodeOptions::default()).unwrap().unwrap();
+        assert_eq!(message.body, [b'a'; 500]);
+        assert_eq!((message.codec, message.verified), (CodecId::Zlib, None));
+        assert_eq!(message.metadata, MessageMetadata { frame_version: Some(1), flags: framing::COMPRESSED, pieces: 1, stored_size: stored.len() });
+    }
+
+    #[test]
+    fn test_codec_from_flags() {
+        assert_eq!(CodecId::from_flags(framing::SPLIT | framing::SIGNED), CodecId::Plain);
+        assert_eq!(CodecId::from_flags(framing::COMPRESSED), CodecId::Zlib);
+        assert_eq!(CodecId::from_flags(framing::ENCRYPTED).as_str(), "encrypted");
+        assert_eq!(CodecId::from_flags(framing::COMPRESSED | framing::ENCRYPTED).as_str(), "zlib+encrypted");
+    }
+
+    #[test]
+    fn test_split_message_errors() {
+        let png = Png::try_from(png_with(None).as_slice()).unwrap();
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        assert!(matches!(decode_split_message(&png, &ru_st), Err(PngMsgError::ChunkNotFound(_))));
+
+        let mut png = png;
+        let pieces = crate::split::split_payload(&ru_st, &[7; 100], 22).unwrap();
+        for (i, piece) in pieces.into_iter().enumerate().filter(|(i, _)| *i != 4) {
+            png.insert_chunk(1 + i.min(4), piece);
+        }
+        assert!(matches!(decode_split_message(&png, &ru_st), Err(PngMsgError::MissingPieces { ref missing, .. }) if missing == &[4]));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_decompress_body() {
+        let body = crate::zlib::compress(&[b'a'; 500]);
+        assert_eq!(decompress_body(&body, &Limits::default()).unwrap(), [b'a'; 500]);
+        let limits = Limits { max_message_bytes: Some(100), ..Limits::default() };
+        assert!(decompress_body(&body, &limits).is_err());
+    }
+
+    #[test]
+    fn test_item_budget() {
+        let input = png_with(Some("late"));
+        let opts = DecodeOptions { item_budget: Some(Duration::ZERO), ..Default::default() };
+
+        let results = decode_many([&input[..]], &ChunkType::from_str("ruSt").unwrap(), &opts);
+        assert!(results[0].is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::finding::json_escape;
+use crate::png::Png;
+
+// How a chunk present in both files differs; the first that applies, so `Data` means same
+// length and `Crc` means same data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Length { before: u32, after: u32 },
+    Data,
+    Crc { before: u32, after: u32 },
+}
+
+impl Change {
+    fn between(before: &Chunk, after: &Chunk) -> Option<Change> {
+        let (before_len, after_len) = (before.length().get(), after.length().get());
+        if before_len != after_len {
+            Some(Change::Length { before: before_len, after: after_len })
+        } else if before.data() != after.data() {
+            Some(Change::Data)
+        } else if before.crc() != after.crc() {
+            Some(Change::Crc { before: before.crc(), after: after.crc() })
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Change::Length { .. } => "length",
+            Change::Data => "data",
+            Change::Crc { .. } => "crc",
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Length { before, after } => write!(f, "length {} -> {}", before, after),
+            Change::Data => write!(f, "data differs"),
+            Change::Crc { before, after } => write!(f, "only the CRC differs ({:08x} -> {:08x})", before, after),
+        }
+    }
+}
+
+// Indices are chunk positions, in the first file (`before`) and the second (`after`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkDiff {
+    Added { chunk_type: ChunkType, after: usize },
+    Removed { chunk_type: ChunkType, before: usize },
+    // Also used for a changed chunk that moved.
+    Modified { chunk_type: ChunkType, before: usize, after: usize, change: Change },
+    Moved { chunk_type: ChunkType, before: usize, after: usize },
+}
+
+impl ChunkDiff {
+    pub fn chunk_type(&self) -> ChunkType {
+        match *self {
+            ChunkDiff::Added { chunk_type, .. }
+            | ChunkDiff::Removed { chunk_type, .. }
+            | ChunkDiff::Modified { chunk_type, .. }
+            | ChunkDiff::Moved { chunk_type, .. } => chunk_type,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let index = |i: Option<usize>| i.map_or_else(|| "null".to_string(), |i| i.to_string());
+        let (kind, before, after, change) = match *self {
+            ChunkDiff::Added { after, .. } => ("added", None, Some(after), None),
+            ChunkDiff::Removed { before, .. } => ("removed", Some(before), None, None),
+            ChunkDiff::Modified { before, after, change, .. } => ("modified", Some(before), Some(after), Some(change)),
+            ChunkDiff::Moved { before, after, .. } => ("moved", Some(before), Some(after), None),
+        };
+        format!(
+            "{{\"kind\": \"{}\", \"chunk_type\": \"{}\", \"before\": {}, \"after\": {}, \"change\": {}}}",
+            kind, json_escape(self.chunk_type().name()), index(before), index(after),
+            change.map_or_else(|| "null".to_string(), |c| format!("\"{}\"", c.name()))
+        )
+    }
+}
+
+impl fmt::Display for ChunkDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChunkDiff::Added { chunk_type, after } => write!(f, "+ {} at #{}", chunk_type, after),
+            ChunkDiff::Removed { chunk_type, before } => write!(f, "- {} at #{}", chunk_type, before),
+            ChunkDiff::Modified { chunk_type, before, after, change } =>
+                write!(f, "~ {} #{} -> #{}: {}", chunk_type, before, after, change),
+            ChunkDiff::Moved { chunk_type, before, after } =>
+                write!(f, "~ {} moved #{} -> #{}", chunk_type, before, after),
+        }
+    }
+}
+
+pub fn to_json(diffs: &[ChunkDiff]) -> String {
+    let rows: Vec<String> = diffs.iter().map(|d| format!("    {}", d.to_json())).collect();
+    format!("{{\n  \"identical\": {},\n  \"chunks\": [\n{}\n  ]\n}}\n", diffs.is_empty(), rows.join(",\n"))
+}
+
+impl Png {
+    // Chunks pair up by type and occurrence, so the second tEXt of one file is compared with
+    // the second tEXt of the other. A pair counts as moved when it is out of order with the
+    // longest run of pairs that kept their order; chunks added or removed around it don't move
+    // it. Entries come in the order of a unified diff.
+    pub fn diff(&self, other: &Png) -> Vec<ChunkDiff> {
+        let (before, after) = (self.chunks(), other.chunks());
+        let after_index: HashMap<(ChunkType, usize), usize> = occurrences(after).into_iter()
+            .enumerate()
+            .map(|(index, key)| (key, index))
+            .collect();
+        let pair_of_before: Vec<Option<usize>> = occurrences(before).iter()
+            .map(|key| after_index.get(key).copied())
+            .collect();
+        let mut pair_of_after = vec![None; after.len()];
+        for (i, j) in pair_of_before.iter().enumerate() {
+            if let Some(j) = j {
+                pair_of_after[*j] = Some(i);
+            }
+        }
+        let anchored = in_order(&pair_of_before);
+
+        let changed = |i: usize, j: usize| -> ChunkDiff {
+            let chunk_type = *before[i].chunk_type();
+            match Change::between(&before[i], &after[j]) {
+                Some(change) => ChunkDiff::Modified { chunk_type, before: i, after: j, change },
+                None => ChunkDiff::Moved { chunk_type, before: i, after: j },
+            }
+        };
+        let mut diffs = Vec::new();
+        let mut next_after = 0;
+        let mut flush_after = |until: usize, diffs: &mut Vec<ChunkDiff>| {
+            for j in next_after..until {
+                match pair_of_after[j] {
+                    None => diffs.push(ChunkDiff::Added { chunk_type: *after[j].chunk_type(), after: j }),
+                    Some(i) if !anchored[i] => diffs.push(changed(i, j)),
+                    Some(_) => {},
+                }
+            }
+            next_after = next_after.max(until);
+        };
+        for (i, pair) in pair_of_before.iter().enumerate() {
+            match *pair {
+                None => diffs.push(ChunkDiff::Removed { chunk_type: *before[i].chunk_type(), before: i }),
+                Some(j) if anchored[i] => {
+                    flush_after(j + 1, &mut diffs);
+                    if let Some(change) = Change::between(&before[i], &after[j]) {
+                        diffs.push(ChunkDiff::Modified { chunk_type: *before[i].chunk_type(), before: i, after: j, change });
+                    }
+                },
+                Some(_) => {},
+            }
+        }
+        flush_after(after.len(), &mut diffs);
+        diffs
+    }
+}
+
+// Each chunk's type and how many of that type came before it.
+fn occurrences(chunks: &[Chunk]) -> Vec<(ChunkType, usize)> {
+    let mut counts: HashMap<ChunkType, usize> = HashMap::new();
+    chunks.iter().map(|chunk| {
+        let count = counts.entry(*chunk.chunk_type()).or_default();
+        *count += 1;
+        (*chunk.chunk_type(), *count - 1)
+    }).collect()
+}
+
+// Marks the pairs on a longest increasing run of `after` indices (patience sorting).
+fn in_order(pairs: &[Option<usize>]) -> Vec<bool> {
+    // tails[k]: the `before` index ending the best run of length k + 1 found so far.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; pairs.len()];
+    for (i, pair) in pairs.iter().enumerate() {
+        let Some(j) = *pair else { continue };
+        let k = tails.partition_point(|&t| pairs[t].expect("tails are paired") < j);
+        previous[i] = k.checked_sub(1).map(|k| tails[k]);
+        match tails.get_mut(k) {
+            Some(tail) => *tail = i,
+            None => tails.push(i),
+        }
+    }
+    let mut anchored = vec![false; pairs.len()];
+    let mut cursor = tails.last().copied();
+    while let Some(i) = cursor {
+        anchored[i] = true;
+        cursor = previous[i];
+    }
+    anchored
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn png(chunks: &[(&str, &[u8])]) -> Png {
+        Png::from_chunks_unchecked(chunks.iter().map(|(t, d)| chunk(t, d)).collect())
+    }
+
+    fn lines(diffs: &[ChunkDiff]) -> Vec<String> {
+        diffs.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_identical_files_have_no_diff() {
+        let a = png(&[("IHDR", &[0; 13]), ("tEXt", b"a\0b"), ("IEND", b"")]);
+        assert!(a.diff(&a.clone()).is_empty());
+        assert_eq!(to_json(&[]), "{\n  \"identical\": true,\n  \"chunks\": [\n\n  ]\n}\n");
+    }
+
+    #[test]
+    fn test_added_removed_and_modified() {
+        let a = png(&[("IHDR", &[0; 13]), ("tEXt", b"one"), ("tEXt", b"two"), ("gAMA", &[0, 0, 0, 1]), ("IDAT", b"x"), ("IEND", b"")]);
+        let b = png(&[("IHDR", &[0; 13]), ("tEXt", b"one"), ("tEXt", b"TWO"), ("IDAT", b"xy"), ("ruSt", b"hi"), ("IEND", b"")]);
+        assert_eq!(lines(&a.diff(&b)), [
+            "~ tEXt #2 -> #2: data differs",
+            "- gAMA at #3",
+            "~ IDAT #4 -> #3: length 1 -> 2",
+            "+ ruSt at #4",
+        ]);
+    }
+
+    #[test]
+    fn test_ordinals_line_up_repeated_types() {
+        let a = png(&[("IHDR", &[0; 13]), ("tEXt", b"one"), ("IEND", b"")]);
+        let b = png(&[("IHDR", &[0; 13]), ("tEXt", b"one"), ("tEXt", b"two"), ("IEND", b"")]);
+        assert_eq!(lines(&a.diff(&b)), ["+ tEXt at #2"]);
+        assert_eq!(lines(&b.diff(&a)), ["- tEXt at #2"]);
+    }
+
+    #[test]
+    fn test_moved_chunk() {
+        let a = png(&[("IHDR", &[0; 13]), ("gAMA", &[1]), ("pHYs", &[2]), ("sRGB", &[3]), ("IDAT", b"x"), ("IEND", b"")]);
+        let b = png(&[("IHDR", &[0; 13]), ("pHYs", &[2]), ("sRGB", &[3]), ("gAMA", &[1]), ("IDAT", b"x"), ("IEND", b"")]);
+        assert_eq!(lines(&a.diff(&b)), ["~ gAMA moved #1 -> #3"]);
+    }
+
+    #[test]
+    fn test_crc_only_change() {
+        let a = png(&[("IHDR", &[0; 13]), ("ruSt", b"hi"), ("IEND", b"")]);
+        let mut bytes = a.as_bytes();
+        let crc_at = 8 + 25 + 8 + 2;
+        bytes[crc_at] ^= 0xff;
+        let options = crate::png::ParseOptions { lenient: true, keep_bad_crc: true, ..Default::default() };
+        let (b, _) = Png::parse_with(&bytes, &options).unwrap();
+        let diffs = a.diff(&b);
+        assert!(matches!(diffs[..], [ChunkDiff::Modified { change: Change::Crc { .. }, before: 1, after: 1, .. }]), "{:?}", diffs);
+        assert!(diffs[0].to_string().starts_with("~ ruSt #1 -> #1: only the CRC differs"));
+    }
+
+    #[test]
+    fn test_json() {
+        let diff = ChunkDiff::Modified { chunk_type: ChunkType::TEXT, before: 2, after: 3, change: Change::Data };
+        assert_eq!(diff.to_json(), "{\"kind\": \"modified\", \"chunk_type\": \"tEXt\", \"before\": 2, \"after\": 3, \"change\": \"data\"}");
+        let diff = ChunkDiff::Added { chunk_type: ChunkType::TEXT, after: 1 };
+        assert_eq!(diff.to_json(), "{\"kind\": \"added\", \"chunk_type\": \"tEXt\", \"before\": null, \"after\": 1, \"change\": null}");
+    }
+}
@@ -0,0 +1,566 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use crate::Result;
+use crate::atomic::replace_file;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMsgError;
+use crate::framing;
+use crate::integrity::{self, Integrity};
+use crate::limits::Limits;
+use crate::png::{Png, PngFileError};
+use crate::provenance::ProvenanceRecord;
+use crate::sha256::{Sha256, to_hex};
+use crate::sign::{self, SigningKey};
+use crate::split::split_payload;
+use crate::time::Timestamp;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeOptions {
+    pub chunk_type: ChunkType,
+    pub record_provenance: bool,
+    // Stores the message as `keyword\0message` in Latin-1, the tEXt layout exiftool reads.
+    pub keyword: Option<String>,
+    // Wraps the message with `encrypt::encrypt_payload`; needs the `encrypt` feature.
+    pub password: Option<String>,
+    // Frames the stored payload (after any encryption) with a digest or HMAC, see `integrity`.
+    pub integrity: Option<Integrity>,
+    // Signs the payload (after any encryption, inside any integrity frame), see `sign`.
+    pub sign_key: Option<SigningKey>,
+    // Sets the tIME chunk, replacing any there already.
+    pub timestamp: Option<Timestamp>,
+    // Writes the payload as pieces of at most this many data bytes each, see `split`.
+    pub max_chunk_size: Option<usize>,
+    // Not part of the fingerprint: limits can reject a message but never change the output.
+    pub limits: Limits,
+    // Lets the message go into critical and standard chunk types, corrupting the image for
+    // viewers; like limits, it is left out of the fingerprint.
+    pub allow_unsafe: bool,
+}
+
+impl EncodeOptions {
+    pub fn new(chunk_type: ChunkType) -> EncodeOptions {
+        EncodeOptions { chunk_type, record_provenance: false, keyword: None, password: None, integrity: None, sign_key: None, timestamp: None, max_chunk_size: None, limits: Limits::default(), allow_unsafe: false }
+    }
+
+    // Fields in a fixed order, one per line, so the fingerprint only changes when an option does.
+    fn canonical(&self) -> String {
+        let mut canonical = format!("chunk_type={}\nrecord_provenance={}\n", self.chunk_type, self.record_provenance);
+        // Only present when set, so fingerprints from before keywords existed still match.
+        if let Some(keyword) = &self.keyword {
+            canonical.push_str(&format!("keyword={}\n", keyword));
+        }
+        // Whether the message was encrypted, never the password itself.
+        if self.password.is_some() {
+            canonical.push_str("encrypted=true\n");
+        }
+        // The mode only; like the password, an HMAC key stays out of the fingerprint.
+        match &self.integrity {
+            Some(Integrity::Plain) => canonical.push_str("integrity=plain\n"),
+            Some(Integrity::Digest) => canonical.push_str("integrity=digest\n"),
+            Some(Integrity::Hmac(_)) => canonical.push_str("integrity=hmac\n"),
+            None => {}
+        }
+        if self.sign_key.is_some() {
+            canonical.push_str("signed=true\n");
+        }
+        // Whether, not when: the time is an input like the message, not a setting.
+        if self.timestamp.is_some() {
+            canonical.push_str("timestamp=true\n");
+        }
+        if let Some(max_chunk_size) = self.max_chunk_size {
+            canonical.push_str(&format!("max_chunk_size={}\n", max_chunk_size));
+        }
+        canonical
+    }
+
+    pub fn fingerprint(&self) -> [u8; 32] {
+        Sha256::digest(self.canonical().as_bytes())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeReport {
+    pub chunk_type: ChunkType,
+    pub payload_size: u64,
+    pub payload_sha256: [u8; 32],
+    pub carrier_size: u64,
+    pub carrier_sha256: [u8; 32],
+    pub output_size: u64,
+    pub output_sha256: [u8; 32],
+    pub options_fingerprint: [u8; 32],
+}
+
+impl EncodeReport {
+    pub fn to_json(&self) -> String {
+        format!(
+            concat!(
+                "{{\n",
+                "  \"chunk_type\": \"{}\",\n",
+                "  \"payload_size\": {},\n",
+                "  \"payload_sha256\": \"{}\",\n",
+                "  \"carrier_size\": {},\n",
+                "  \"carrier_sha256\": \"{}\",\n",
+                "  \"output_size\": {},\n",
+                "  \"output_sha256\": \"{}\",\n",
+                "  \"options_fingerprint\": \"{}\"\n",
+                "}}\n",
+            ),
+            self.chunk_type,
+            self.payload_size,
+            to_hex(&self.payload_sha256),
+            self.carrier_size,
+            to_hex(&self.carrier_sha256),
+            self.output_size,
+            to_hex(&self.output_sha256),
+            to_hex(&self.options_fingerprint),
+        )
+    }
+}
+
+// The message goes directly before the first IEND, even if a lenient parse kept chunks after it,
+// or at the end of a file that has no IEND. Every chunk written starts with the frame from
+// `framing`, except a keyword message, which keeps the plain tEXt layout.
+pub fn encode_message(png: &mut Png, message: &[u8], opts: &EncodeOptions) -> Result<()> {
+    opts.limits.check_message(message.len())?;
+    check_chunk_type(opts)?;
+    if opts.keyword.is_some() && opts.integrity.is_some() {
+        return Err(PngMsgError::ConflictingChunkOptions("an integrity tag cannot be stored under a tEXt keyword".to_string()).into());
+    }
+    if opts.keyword.is_some() && opts.sign_key.is_some() {
+        return Err(PngMsgError::ConflictingChunkOptions("a signature cannot be stored under a tEXt keyword".to_string()).into());
+    }
+    let frame = |payload: Vec<u8>| -> std::result::Result<Vec<u8>, PngMsgError> {
+        let payload = match &opts.sign_key {
+            Some(key) => sign::seal(&payload, &opts.chunk_type, key)?,
+            None => payload,
+        };
+        Ok(match &opts.integrity {
+            Some(integrity) => integrity::seal(&payload, integrity),
+            None => payload,
+        })
+    };
+    let chunk = match (&opts.keyword, &opts.password) {
+        (Some(_), Some(_)) => return Err(PngMsgError::ConflictingChunkOptions("an encrypted message cannot be stored under a tEXt keyword".to_string()).into()),
+        (Some(keyword), None) => text_chunk(keyword, message, &opts.chunk_type)?,
+        (None, Some(password)) => Chunk::try_new(opts.chunk_type, frame(encrypt(message, password)?)?)?,
+        (None, None) => Chunk::try_new(opts.chunk_type, frame(message.to_vec())?)?,
+    };
+    // A digest of the plaintext would let anyone confirm a guess at an encrypted message.
+    let payload = opts.record_provenance.then(|| match opts.password {
+        Some(_) => Sha256::digest(chunk.data()),
+        None => Sha256::digest(message),
+    });
+    let flags = frame_flags(opts);
+    let chunks = match opts.max_chunk_size {
+        Some(_) if opts.keyword.is_some() => return Err(PngMsgError::ConflictingChunkOptions("a split message cannot be stored under a tEXt keyword".to_string()).into()),
+        Some(max_chunk_size) => split_payload(&opts.chunk_type, chunk.data(), max_chunk_size.saturating_sub(framing::HEADER_LEN))
+            .map_err(|e| match e {
+                PngMsgError::ChunkSizeTooSmall { .. } => PngMsgError::ChunkSizeTooSmall { max_chunk_size },
+                e => e,
+            })?
+            .iter()
+            .map(|piece| framed(piece, flags))
+            .collect::<std::result::Result<Vec<Chunk>, PngMsgError>>()?,
+        // Stays a plain tEXt chunk that any viewer can read; decode it unframed.
+        None if opts.keyword.is_some() => vec![chunk],
+        None => vec![framed(&chunk, flags)?],
+    };
+    for chunk in chunks {
+        png.insert_before_iend(chunk);
+    }
+    if let Some(timestamp) = opts.timestamp {
+        png.set_modification_time(timestamp)?;
+    }
+
+    if let Some(payload) = payload {
+        let chunk_type = opts.chunk_type.to_string();
+        png.record_provenance(ProvenanceRecord::new("encode", &[&chunk_type, &to_hex(&payload)]))
+            .map_err(|e| PngMsgError::ProvenanceFailed(e.to_string()))?;
+    }
+    Ok(())
+}
+
+fn frame_flags(opts: &EncodeOptions) -> u8 {
+    let mut flags = 0;
+    if opts.password.is_some() {
+        flags |= framing::ENCRYPTED;
+    }
+    if opts.sign_key.is_some() {
+        flags |= framing::SIGNED;
+    }
+    if opts.integrity.is_some() {
+        flags |= framing::INTEGRITY;
+    }
+    if opts.max_chunk_size.is_some() {
+        flags |= framing::SPLIT;
+    }
+    flags
+}
+
+fn framed(chunk: &Chunk, flags: u8) -> std::result::Result<Chunk, PngMsgError> {
+    Chunk::try_new(*chunk.chunk_type(), framing::seal(flags, chunk.data()))
+}
+
+#[cfg(feature = "encrypt")]
+pub(crate) fn encrypt(message: &[u8], password: &str) -> std::result::Result<Vec<u8>, PngMsgError> {
+    crate::encrypt::encrypt_payload(message, password, &crate::encrypt::DEFAULT_PARAMS)
+}
+
+#[cfg(not(feature = "encrypt"))]
+pub(crate) fn encrypt(_: &[u8], _: &str) -> std::result::Result<Vec<u8>, PngMsgError> {
+    Err(PngMsgError::EncryptionUnavailable)
+}
+
+// Critical and standard types mean something to every viewer; tEXt is the one exception, and
+// only for a message stored under a keyword in its own layout.
+fn check_chunk_type(opts: &EncodeOptions) -> std::result::Result<(), PngMsgError> {
+    let chunk_type = opts.chunk_type;
+    let text = opts.keyword.is_some() && chunk_type == ChunkType::TEXT;
+    if opts.allow_unsafe || text || !(chunk_type.is_critical() || chunk_type.is_standard()) {
+        return Ok(());
+    }
+    Err(PngMsgError::UnsafeChunkType(chunk_type))
+}
+
+fn text_chunk(keyword: &str, message: &[u8], chunk_type: &ChunkType) -> Result<Chunk> {
+    if &chunk_type.bytes() != b"tEXt" {
+        return Err(format!("A keyword needs the tEXt chunk type, not {}", chunk_type).into());
+    }
+    let message = std::str::from_utf8(message).map_err(|_| "A tEXt message must be text")?;
+    Ok(Chunk::new_text(keyword, message)?)
+}
+
+pub fn encode_bytes(carrier: &[u8], message: &[u8], opts: &EncodeOptions) -> Result<(Vec<u8>, EncodeReport)> {
+    let mut png = Png::try_from(carrier)?;
+    encode_parsed(&mut png, carrier, message, opts)
+}
+
+// Any file works as the payload; nothing about it needs to be text.
+pub fn encode_file(carrier: &Path, payload: &Path, output: &Path, opts: &EncodeOptions) -> Result<EncodeReport> {
+    let read = |path: &Path| fs::read(path).map_err(|source| PngFileError::Io { path: path.to_path_buf(), source });
+    let (output_bytes, report) = encode_bytes(&read(carrier)?, &read(payload)?, opts)?;
+    replace_file(output, |out| out.write_all(&output_bytes)).map_err(|source| PngFileError::Io { path: output.to_path_buf(), source })?;
+    Ok(report)
+}
+
+// For callers that already parsed `carrier` into `png` and want to avoid a second parse.
+pub fn encode_parsed(png: &mut Png, carrier: &[u8], message: &[u8], opts: &EncodeOptions) -> Result<(Vec<u8>, EncodeReport)> {
+    encode_message(png, message, opts)?;
+    let output = png.as_bytes();
+
+    let report = EncodeReport {
+        chunk_type: opts.chunk_type,
+        payload_size: message.len() as u64,
+        payload_sha256: Sha256::digest(message),
+        carrier_size: carrier.len() as u64,
+        carrier_sha256: Sha256::digest(carrier),
+        output_size: output.len() as u64,
+        output_sha256: Sha256::digest(&output),
+        options_fingerprint: opts.fingerprint(),
+    };
+    Ok((output, report))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::decode::{decode_message, decode_message_detailed, CodecId, DecodeOptions};
+    use crate::png::ParseOptions;
+
+    // The stored message with only the message frame taken off, inner layers still on.
+    fn body_of(png: &Png) -> Vec<u8> {
+        framing::open(png.chunk_by_type("ruSt").unwrap().data()).unwrap().body.to_vec()
+    }
+
+    fn carrier() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ]).unwrap().as_bytes()
+    }
+
+    #[test]
+    fn test_critical_and_standard_types_are_refused() {
+        for (chunk_type, message) in [
+            ("IDAT", "Refusing to store a message in critical IDAT chunks, which would corrupt the image; use a private ancillary type such as idAT"),
+            ("IHDR", "Refusing to store a message in critical IHDR chunks, which would corrupt the image; use a private ancillary type such as ihDR"),
+            ("sRGB", "Refusing to store a message in standard sRGB chunks, which viewers interpret by their specification; use a private ancillary type such as srGB"),
+            ("tEXt", "Refusing to store a message in standard tEXt chunks, which viewers interpret by their specification; use a private ancillary type such as teXt"),
+        ] {
+            let mut opts = EncodeOptions::new(ChunkType::from_str(chunk_type).unwrap());
+            let err = encode_bytes(&carrier(), b"hello", &opts).unwrap_err();
+            assert_eq!(err.to_string(), message);
+            assert!(matches!(err.downcast_ref::<PngMsgError>(), Some(PngMsgError::UnsafeChunkType(_))));
+
+            opts.allow_unsafe = true;
+            assert!(encode_bytes(&carrier(), b"hello", &opts).is_ok(), "{}", chunk_type);
+        }
+
+        let (output, _) = encode_bytes(&carrier(), b"hello", &EncodeOptions::new(ChunkType::from_str("ruSt").unwrap())).unwrap();
+        assert_eq!(body_of(&Png::try_from(output.as_slice()).unwrap()), b"hello");
+        let mut opts = EncodeOptions::new(ChunkType::TEXT);
+        opts.keyword = Some("Comment".to_string());
+        assert!(encode_bytes(&carrier(), b"hello", &opts).is_ok());
+    }
+
+    #[test]
+    fn test_encode_bytes_places_message_before_iend() {
+        let opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        let (output, _) = encode_bytes(&carrier(), b"hello", &opts).unwrap();
+        let png = Png::try_from(output.as_slice()).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "ruSt", "IEND"]);
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"pmMG\x01\x00hello");
+        assert_eq!(body_of(&png), b"hello");
+    }
+
+    #[test]
+    fn test_report_hashes_match_independent_digests() {
+        let carrier = carrier();
+        let opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        let (output, report) = encode_bytes(&carrier, b"hello", &opts).unwrap();
+
+        assert_eq!(
+            to_hex(&report.payload_sha256),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(report.carrier_sha256, Sha256::digest(&carrier));
+        assert_eq!(report.output_sha256, Sha256::digest(&output));
+        assert_eq!(report.payload_size, 5);
+        assert_eq!(report.carrier_size, carrier.len() as u64);
+        assert_eq!(report.output_size, carrier.len() as u64 + 12 + framing::HEADER_LEN as u64 + 5);
+    }
+
+    #[test]
+    fn test_fingerprint_is_stable_per_options() {
+        let a = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        let b = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        let mut c = a.clone();
+        c.record_provenance = true;
+
+        let (_, report_a) = encode_bytes(&carrier(), b"one", &a).unwrap();
+        let (_, report_b) = encode_bytes(&carrier(), b"two", &b).unwrap();
+        assert_eq!(report_a.options_fingerprint, report_b.options_fingerprint);
+        assert_ne!(a.fingerprint(), c.fingerprint());
+        assert_ne!(a.fingerprint(), EncodeOptions::new(ChunkType::from_str("abCd").unwrap()).fingerprint());
+    }
+
+    #[test]
+    fn test_encode_before_first_iend_after_lenient_parse() {
+        let bytes: Vec<u8> = Png::from_chunks_unchecked(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::from_str("trAl").unwrap(), b"trailing".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ]).as_bytes();
+        let (mut png, warnings) = Png::parse_with(&bytes, &ParseOptions { lenient: true, ..Default::default() }).unwrap();
+        assert_eq!(warnings.len(), 2);
+
+        let opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        encode_message(&mut png, b"hello", &opts).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "ruSt", "IEND", "trAl", "IEND"]);
+    }
+
+    #[test]
+    fn test_encode_without_iend_appends() {
+        let mut png = Png::from_chunks_unchecked(vec![Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13])]);
+        let opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        encode_message(&mut png, b"hello", &opts).unwrap();
+
+        assert_eq!(body_of(&png), b"hello");
+        assert_eq!(png.incomplete_parts(), ["IEND must be the last chunk"]);
+    }
+
+    #[test]
+    fn test_keyword_writes_readable_text() {
+        let mut opts = EncodeOptions::new(ChunkType::from_str("tEXt").unwrap());
+        opts.keyword = Some("Comment".to_string());
+        let (output, _) = encode_bytes(&carrier(), "na\u{ef}ve".as_bytes(), &opts).unwrap();
+        let png = Png::try_from(output.as_slice()).unwrap();
+
+        assert_eq!(png.chunk_by_type("tEXt").unwrap().data(), b"Comment\0na\xefve");
+        assert_eq!(png.well_known_text().comment.unwrap().value, "na\u{ef}ve");
+        assert_ne!(opts.fingerprint(), EncodeOptions::new(ChunkType::from_str("tEXt").unwrap()).fingerprint());
+
+        opts.chunk_type = ChunkType::from_str("ruSt").unwrap();
+        assert!(encode_bytes(&carrier(), b"x", &opts).is_err());
+    }
+
+    #[test]
+    fn test_password_is_fingerprinted_but_not_combined_with_keyword() {
+        let mut opts = EncodeOptions::new(ChunkType::from_str("tEXt").unwrap());
+        opts.password = Some("secret".to_string());
+        let mut other = opts.clone();
+        other.password = Some("other".to_string());
+        assert_eq!(opts.fingerprint(), other.fingerprint());
+        assert_ne!(opts.fingerprint(), EncodeOptions::new(ChunkType::from_str("tEXt").unwrap()).fingerprint());
+
+        opts.keyword = Some("Comment".to_string());
+        assert!(encode_bytes(&carrier(), b"x", &opts).is_err());
+    }
+
+    #[test]
+    fn test_encode_file_with_binary_payload() {
+        let dir = std::env::temp_dir().join(format!("pngme-encode-file-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // A zip local file header: not UTF-8, with NULs in it.
+        let payload = [0x50, 0x4b, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0xff, 0xfe];
+        fs::write(dir.join("carrier.png"), carrier()).unwrap();
+        fs::write(dir.join("payload.zip"), payload).unwrap();
+        // A link to the old output keeps its contents: the new file is swapped in, not written over it.
+        fs::write(dir.join("out.png"), b"old").unwrap();
+        fs::hard_link(dir.join("out.png"), dir.join("linked.png")).unwrap();
+
+        let opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        let report = encode_file(&dir.join("carrier.png"), &dir.join("payload.zip"), &dir.join("out.png"), &opts).unwrap();
+        assert_eq!(report.payload_size, payload.len() as u64);
+        let png = Png::from_file(dir.join("out.png")).unwrap();
+        assert_eq!(body_of(&png), payload);
+        assert_eq!(fs::read(dir.join("linked.png")).unwrap(), b"old");
+
+        let missing = encode_file(&dir.join("carrier.png"), &dir.join("missing"), &dir.join("out.png"), &opts).unwrap_err();
+        assert!(missing.to_string().contains("missing"), "{}", missing);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_integrity_frame_round_trip() {
+        let mut opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        opts.integrity = Some(Integrity::Hmac("key".to_string()));
+        let (output, _) = encode_bytes(&carrier(), b"tagged", &opts).unwrap();
+        let png = Png::try_from(output.as_slice()).unwrap();
+        assert_eq!(integrity::open(&body_of(&png), Some("key")).unwrap(), b"tagged");
+        let keyed = DecodeOptions { hmac_key: Some("key".to_string()), ..Default::default() };
+        assert_eq!(decode_message(&png, &opts.chunk_type, &keyed).unwrap().unwrap(), b"tagged");
+
+        let mut other = opts.clone();
+        other.integrity = Some(Integrity::Hmac("other".to_string()));
+        assert_eq!(opts.fingerprint(), other.fingerprint());
+        other.integrity = Some(Integrity::Digest);
+        assert_ne!(opts.fingerprint(), other.fingerprint());
+
+        opts.keyword = Some("Comment".to_string());
+        opts.chunk_type = ChunkType::TEXT;
+        assert!(encode_bytes(&carrier(), b"x", &opts).is_err());
+    }
+
+    #[test]
+    fn test_timestamp_sets_time_chunk() {
+        let mut opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        opts.timestamp = Some(Timestamp::new(2024, 2, 29, 13, 5, 9).unwrap());
+        let (output, _) = encode_bytes(&carrier(), b"stamped", &opts).unwrap();
+        let png = Png::try_from(output.as_slice()).unwrap();
+        assert_eq!(png.modification_time().unwrap().unwrap(), opts.timestamp.unwrap());
+        assert_eq!(body_of(&png), b"stamped");
+    }
+
+    #[cfg(feature = "sign")]
+    #[test]
+    fn test_signed_message_round_trip() {
+        let key = SigningKey::from_seed([3; 32]);
+        let mut opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        opts.sign_key = Some(key.clone());
+        opts.integrity = Some(Integrity::Digest);
+        let (output, _) = encode_bytes(&carrier(), b"signed", &opts).unwrap();
+        let png = Png::try_from(output.as_slice()).unwrap();
+        let body = body_of(&png);
+        let data = integrity::open(&body, None).unwrap();
+        assert_eq!(sign::open(data, &opts.chunk_type, Some(&key.verifying_key().unwrap())).unwrap(), b"signed");
+        let verified = DecodeOptions { verify_key: Some(key.verifying_key().unwrap()), ..Default::default() };
+        let message = decode_message_detailed(&png, &opts.chunk_type, &verified).unwrap().unwrap();
+        assert_eq!(message.body, b"signed");
+        use crate::decode::{SignatureCheck, VerificationStatus};
+        let signature = SignatureCheck { signer: key.verifying_key().unwrap().fingerprint(), verified: true };
+        assert_eq!(message.verified, Some(VerificationStatus { integrity: Some(integrity::Mode::Digest), signature: Some(signature) }));
+        let unchecked = decode_message_detailed(&png, &opts.chunk_type, &DecodeOptions::default()).unwrap().unwrap();
+        assert_eq!(unchecked.verified.unwrap().signature.map(|check| check.verified), Some(false));
+        assert_ne!(opts.fingerprint(), EncodeOptions::new(opts.chunk_type).fingerprint());
+    }
+
+    #[cfg(not(feature = "sign"))]
+    #[test]
+    fn test_signing_needs_feature() {
+        let mut opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        opts.sign_key = Some(SigningKey::from_seed([3; 32]));
+        let error = encode_bytes(&carrier(), b"x", &opts).unwrap_err();
+        assert!(matches!(error.downcast_ref::<PngMsgError>(), Some(PngMsgError::SigningUnavailable)), "{}", error);
+    }
+
+    #[cfg(feature = "encrypt")]
+    #[test]
+    fn test_password_encrypts_message() {
+        let mut opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        opts.password = Some("correct horse".to_string());
+        let (output, _) = encode_bytes(&carrier(), b"hello", &opts).unwrap();
+        let png = Png::try_from(output.as_slice()).unwrap();
+        let body = body_of(&png);
+        assert_ne!(body, b"hello");
+        assert_eq!(crate::encrypt::decrypt_payload(&body, "correct horse").unwrap(), b"hello");
+        assert!(matches!(crate::encrypt::decrypt_payload(&body, "wrong"), Err(PngMsgError::DecryptionFailed)));
+
+        let with_password = DecodeOptions { password: Some("correct horse".to_string()), ..Default::default() };
+        let message = decode_message_detailed(&png, &opts.chunk_type, &with_password).unwrap().unwrap();
+        assert_eq!((message.body.as_slice(), message.metadata.flags), (&b"hello"[..], framing::ENCRYPTED));
+        assert_eq!(message.codec, CodecId::Encrypted);
+        assert_eq!(message.verified, None);
+        assert_eq!(message.metadata.stored_size, body.len());
+        let without = decode_message(&png, &opts.chunk_type, &DecodeOptions::default());
+        assert!(matches!(without, Err(PngMsgError::PasswordRequired)));
+    }
+
+    #[cfg(not(feature = "encrypt"))]
+    #[test]
+    fn test_password_needs_feature() {
+        let mut opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        opts.password = Some("secret".to_string());
+        let error = encode_bytes(&carrier(), b"x", &opts).unwrap_err();
+        assert!(matches!(error.downcast_ref::<PngMsgError>(), Some(PngMsgError::EncryptionUnavailable)));
+    }
+
+    #[test]
+    fn test_split_message_round_trip() {
+        let mut opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        opts.max_chunk_size = Some(27);
+        let (output, _) = encode_bytes(&carrier(), b"a message in three pieces", &opts).unwrap();
+        let png = Png::try_from(output.as_slice()).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "ruSt", "ruSt", "ruSt", "IEND"]);
+        assert!(png.chunks_by_type("ruSt").iter().all(|c| c.data().len() <= 27));
+        let message = decode_message_detailed(&png, &opts.chunk_type, &DecodeOptions::default()).unwrap().unwrap();
+        assert_eq!((message.body.as_slice(), message.metadata.flags), (&b"a message in three pieces"[..], framing::SPLIT));
+        assert_eq!((message.metadata.pieces, message.codec), (3, CodecId::Plain));
+        assert_ne!(opts.fingerprint(), EncodeOptions::new(opts.chunk_type).fingerprint());
+
+        opts.max_chunk_size = Some(18);
+        let error = encode_bytes(&carrier(), b"x", &opts).unwrap_err();
+        assert!(matches!(error.downcast_ref::<PngMsgError>(), Some(PngMsgError::ChunkSizeTooSmall { max_chunk_size: 18 })));
+    }
+
+    #[test]
+    fn test_keyword_message_is_not_framed() {
+        let mut opts = EncodeOptions::new(ChunkType::TEXT);
+        opts.keyword = Some("Comment".to_string());
+        let (output, _) = encode_bytes(&carrier(), b"plain", &opts).unwrap();
+        let png = Png::try_from(output.as_slice()).unwrap();
+        assert!(!framing::is_framed(png.chunk_by_type("tEXt").unwrap().data()));
+        assert!(matches!(decode_message(&png, &ChunkType::TEXT, &DecodeOptions::default()), Err(PngMsgError::NotAPngMsgPayload)));
+    }
+
+    #[test]
+    fn test_manifest_json() {
+        let opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        let (_, report) = encode_bytes(&carrier(), b"hello", &opts).unwrap();
+        let json = report.to_json();
+
+        assert!(json.starts_with("{\n  \"chunk_type\": \"ruSt\",\n  \"payload_size\": 5,\n"));
+        assert!(json.contains(&format!("\"output_sha256\": \"{}\"", to_hex(&report.output_sha256))));
+    }
+}
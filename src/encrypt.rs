@@ -0,0 +1,194 @@
+use argon2::{Algorithm, Argon2, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMsgError;
+use crate::random::random_bytes;
+
+// Envelope layout, all of it authenticated as associated data:
+//   version (1) | kdf (1 = Argon2id) | memory KiB (u32 BE) | passes (u32 BE) | lanes (1)
+//   | salt (16) | nonce (12) | ciphertext and tag
+// A new version can change everything after the version byte.
+pub const VERSION: u8 = 1;
+const KDF_ARGON2ID: u8 = 1;
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = 11 + SALT_LEN + NONCE_LEN;
+
+// Argon2id (RFC 9106, version 0x13) costs, as stored in the envelope header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    // Memory in KiB; rounded down to a multiple of 4 * lanes, and at least 8 * lanes.
+    pub memory_kib: u32,
+    pub passes: u32,
+    pub lanes: u32,
+}
+
+impl Params {
+    pub fn is_valid(&self) -> bool {
+        self.passes >= 1 && (1..=0xff_ffff).contains(&self.lanes) && self.memory_kib >= 8 * self.lanes
+    }
+}
+
+// OWASP's minimum for Argon2id: 19 MiB, two passes, one lane.
+pub const DEFAULT_PARAMS: Params = Params { memory_kib: 19 * 1024, passes: 2, lanes: 1 };
+
+// What a header may ask for before decrypting, so a crafted chunk cannot make the reader
+// allocate gigabytes or spin for minutes.
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_PASSES: u32 = 64;
+
+fn derive_key(passphrase: &str, salt: &[u8], params: &Params) -> Result<[u8; KEY_LEN], PngMsgError> {
+    let unusable = |e: argon2::Error| PngMsgError::InvalidEnvelope(format!("unusable Argon2 parameters {:?}: {}", params, e));
+    let costs = argon2::Params::new(params.memory_kib, params.passes, params.lanes, Some(KEY_LEN)).map_err(unusable)?;
+    let mut key = [0u8; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, costs)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(unusable)?;
+    Ok(key)
+}
+
+fn cipher(key: &[u8; KEY_LEN]) -> ChaCha20Poly1305 {
+    ChaCha20Poly1305::new(Key::from_slice(key))
+}
+
+pub fn encrypt_payload(plaintext: &[u8], passphrase: &str, params: &Params) -> Result<Vec<u8>, PngMsgError> {
+    if !params.is_valid() || params.lanes > u8::MAX as u32 {
+        return Err(PngMsgError::InvalidEnvelope(format!("unusable Argon2 parameters {:?}", params)));
+    }
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    random_bytes(&mut salt)?;
+    random_bytes(&mut nonce)?;
+
+    let mut envelope = Vec::with_capacity(HEADER_LEN + plaintext.len() + TAG_LEN);
+    envelope.extend_from_slice(&[VERSION, KDF_ARGON2ID]);
+    envelope.extend_from_slice(&params.memory_kib.to_be_bytes());
+    envelope.extend_from_slice(&params.passes.to_be_bytes());
+    envelope.push(params.lanes as u8);
+    envelope.extend_from_slice(&salt);
+    envelope.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, params)?;
+    let sealed = cipher(&key).encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &envelope })
+        .map_err(|_| PngMsgError::InvalidEnvelope("the message is too long to encrypt".to_string()))?;
+    envelope.extend_from_slice(&sealed);
+    Ok(envelope)
+}
+
+pub fn decrypt_payload(envelope: &[u8], passphrase: &str) -> Result<Vec<u8>, PngMsgError> {
+    let invalid = |reason: String| Err(PngMsgError::InvalidEnvelope(reason));
+    match envelope.first() {
+        None => return invalid("the data is empty".to_string()),
+        Some(&VERSION) => {}
+        Some(version) => return invalid(format!("unknown version {}", version)),
+    }
+    if envelope.len() < HEADER_LEN + TAG_LEN {
+        return invalid(format!("{} bytes is too short for a version 1 envelope", envelope.len()));
+    }
+    let (header, sealed) = envelope.split_at(HEADER_LEN);
+    if header[1] != KDF_ARGON2ID {
+        return invalid(format!("unknown key derivation {}", header[1]));
+    }
+    let word = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().unwrap());
+    let params = Params { memory_kib: word(2), passes: word(6), lanes: header[10] as u32 };
+    if !params.is_valid() || params.memory_kib > MAX_MEMORY_KIB || params.passes > MAX_PASSES {
+        return invalid(format!("unsupported Argon2 parameters {:?}", params));
+    }
+
+    let salt = &header[11..11 + SALT_LEN];
+    let nonce = Nonce::from_slice(&header[11 + SALT_LEN..]);
+    let key = derive_key(passphrase, salt, &params)?;
+    cipher(&key).decrypt(nonce, Payload { msg: sealed, aad: header }).map_err(|_| PngMsgError::DecryptionFailed)
+}
+
+impl Chunk {
+    pub fn new_encrypted(chunk_type: ChunkType, data: &[u8], passphrase: &str) -> Result<Chunk, PngMsgError> {
+        Chunk::new_encrypted_with(chunk_type, data, passphrase, &DEFAULT_PARAMS)
+    }
+
+    pub fn new_encrypted_with(chunk_type: ChunkType, data: &[u8], passphrase: &str, params: &Params)
+        -> Result<Chunk, PngMsgError> {
+        Chunk::try_new(chunk_type, encrypt_payload(data, passphrase, params)?)
+    }
+
+    // A wrong passphrase and tampered data look the same: `PngMsgError::DecryptionFailed`.
+    pub fn decrypt_data(&self, passphrase: &str) -> Result<Vec<u8>, PngMsgError> {
+        decrypt_payload(self.data(), passphrase)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // Small enough to keep debug-build tests quick.
+    const FAST: Params = Params { memory_kib: 64, passes: 1, lanes: 1 };
+
+    fn encrypted(data: &[u8], passphrase: &str) -> Chunk {
+        Chunk::new_encrypted_with(ChunkType::from_str("ruSt").unwrap(), data, passphrase, &FAST).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for data in [&b""[..], b"hello", &[0xab; 1000]] {
+            let chunk = encrypted(data, "correct horse");
+            assert_eq!(chunk.data().len(), HEADER_LEN + data.len() + TAG_LEN);
+            assert_eq!(chunk.data()[0], VERSION);
+            assert_eq!(chunk.decrypt_data("correct horse").unwrap(), data);
+        }
+    }
+
+    // Written before the envelope moved to the RustCrypto crates, with FAST parameters.
+    #[test]
+    fn test_reads_existing_envelopes() {
+        let envelope = crate::text_codec::hex_decode(concat!(
+            "0101000000400000000101aaa513a7770e55d8db646a98b9524504714c12558f773713cdde6ccf2646861d70",
+            "0576771486a83da6e297158cf01bd1078e1e27f5eb77d7cfacb823b3c20de494c6752a5b",
+        )).unwrap();
+        assert_eq!(decrypt_payload(&envelope, "correct horse").unwrap(), b"written before the switch");
+    }
+
+    #[test]
+    fn test_salt_and_nonce_are_fresh() {
+        let (a, b) = (encrypted(b"same", "pw"), encrypted(b"same", "pw"));
+        assert_ne!(a.data()[11..HEADER_LEN], b.data()[11..HEADER_LEN]);
+        assert_ne!(a.data()[HEADER_LEN..], b.data()[HEADER_LEN..]);
+    }
+
+    #[test]
+    fn test_wrong_passphrase_and_tampering_fail_cleanly() {
+        let chunk = encrypted(b"secret", "right");
+        assert!(matches!(chunk.decrypt_data("wrong"), Err(PngMsgError::DecryptionFailed)));
+
+        // Every byte is covered, header included; a changed parameter changes the key.
+        for i in 0..chunk.data().len() {
+            let mut data = chunk.data().to_vec();
+            data[i] ^= 1;
+            let tampered = Chunk::new(*chunk.chunk_type(), data);
+            assert!(tampered.decrypt_data("right").is_err(), "flip at {}", i);
+        }
+    }
+
+    #[test]
+    fn test_malformed_envelopes() {
+        let chunk = encrypted(b"secret", "pw");
+        let with = |edit: &dyn Fn(&mut Vec<u8>)| {
+            let mut data = chunk.data().to_vec();
+            edit(&mut data);
+            decrypt_payload(&data, "pw")
+        };
+        assert!(matches!(with(&|d| d.clear()), Err(PngMsgError::InvalidEnvelope(_))));
+        assert!(matches!(with(&|d| d[0] = 2), Err(PngMsgError::InvalidEnvelope(r)) if r.contains("version 2")));
+        assert!(matches!(with(&|d| d[1] = 9), Err(PngMsgError::InvalidEnvelope(_))));
+        assert!(matches!(with(&|d| d.truncate(HEADER_LEN + 3)), Err(PngMsgError::InvalidEnvelope(_))));
+        assert!(matches!(with(&|d| d[2] = 0xff), Err(PngMsgError::InvalidEnvelope(_))));
+        assert!(matches!(with(&|d| d[10] = 0), Err(PngMsgError::InvalidEnvelope(_))));
+    }
+}
@@ -0,0 +1,305 @@
+use std::fmt;
+use std::io;
+use std::string::FromUtf8Error;
+
+use crate::chunk::{ChunkParseError, ChunkPosition, CrcMismatch, MAX_CHUNK_LENGTH};
+use crate::chunk_type::{ChunkType, ChunkTypeError};
+use crate::ihdr::{ColorType, IhdrData};
+use crate::limits::LimitExceeded;
+
+// What chunk-level operations can fail with; `Png` and the layers above still box errors, and
+// box this one unchanged so callers can downcast to it.
+#[derive(Debug)]
+pub enum PngMsgError {
+    InvalidChunkType(ChunkTypeError),
+    CrcMismatch(CrcMismatch),
+    TruncatedChunk(ChunkParseError),
+    // Any other chunk error, with where in the file the chunk starts.
+    MalformedChunk { position: ChunkPosition, source: Box<PngMsgError> },
+    // The input does not start with `Png::SIGNATURE`; holds the first 8 bytes, or fewer if
+    // that is all there was.
+    InvalidSignature { found: Vec<u8> },
+    ChunkNotFound(String),
+    SplitPastEnd { offset: usize, len: usize },
+    ChunkTypesDiffer { first: ChunkType, second: ChunkType },
+    DataTooLarge { len: usize },
+    WrongChunkType { expected: &'static str, found: ChunkType },
+    InvalidKeyword(String),
+    InvalidTextChar(char),
+    MissingKeywordSeparator,
+    Utf8(FromUtf8Error),
+    // None when the byte itself is missing.
+    UnknownCompressionMethod(Option<u8>),
+    InvalidCompressedData(String),
+    // Compressed text in a build without the `compression` feature.
+    CompressionUnavailable,
+    InvalidCompressionFlag(u8),
+    InvalidLanguageTag(String),
+    // Names the field whose NUL terminator is missing.
+    MissingTextField(&'static str),
+    // Everything `ChunkBuilder::build` found wrong, in the order it was found.
+    ChunkBuild(Vec<PngMsgError>),
+    ConflictingChunkOptions(String),
+    ChunkOverCap { len: usize, max: usize },
+    ShortRead { expected: usize, read: usize },
+    // An encrypted payload whose header cannot be used, as opposed to one that fails to open.
+    InvalidEnvelope(String),
+    // Wrong passphrase or tampered data; the two cannot be told apart.
+    DecryptionFailed,
+    // A passphrase in a build without the `encrypt` feature.
+    EncryptionUnavailable,
+    // IHDR, PLTE, IDAT or IEND, which the image cannot do without.
+    CriticalChunk(ChunkType),
+    // Encoding into a critical chunk type, which would corrupt the image, or a standard ancillary
+    // one, which viewers interpret by its specification.
+    UnsafeChunkType(ChunkType),
+    // Pieces of a split message, see `split`; `piece` counts from 0 in the order given.
+    ChunkSizeTooSmall { max_chunk_size: usize },
+    NoPieces,
+    InvalidPieceHeader { piece: usize },
+    PieceCountsDiffer { message_id: u32, first: u32, other: u32 },
+    DuplicatePiece { message_id: u32, sequence: u32 },
+    MissingPieces { message_id: u32, missing: Vec<u32>, count: u32 },
+    // IHDR fields, see `ihdr`.
+    InvalidIhdrLength(usize),
+    InvalidImageSize { width: u32, height: u32 },
+    InvalidColorType(u8),
+    InvalidBitDepth { bit_depth: u8, color_type: ColorType },
+    UnknownIhdrMethod { field: &'static str, value: u8 },
+    // tIME fields, see `time`.
+    InvalidTimeLength(usize),
+    InvalidTimeField { field: &'static str, value: u64 },
+    // The fixed-size ancillary chunks from `ancillary`; `layout` says what the bytes should be.
+    InvalidChunkLayout { chunk_type: &'static str, layout: &'static str, len: usize },
+    UnknownFieldValue { field: &'static str, value: u8 },
+    // Messages hidden in the image samples, see `stego`.
+    LsbPalette,
+    LsbUnsupported(IhdrData),
+    MessageTooLarge { len: usize, capacity: u64 },
+    NoLsbMessage,
+    PasswordRequired,
+    // `--lsb` in a build without the `stego` feature.
+    StegoUnavailable,
+    // Framing from `integrity`; a failed check does not say whether the payload or tag changed.
+    InvalidIntegrityFrame(String),
+    IntegrityCheckFailed,
+    IntegrityKeyRequired,
+    // Text input from `text_codec`, with where and why it stopped.
+    InvalidBase64(String),
+    InvalidHex(String),
+    // Signatures from `sign`. Not to be confused with `InvalidSignature`, the PNG file header.
+    InvalidSignatureFrame(String),
+    SignatureInvalid,
+    // A verifying key was given but the message carries no signature.
+    Unsigned,
+    InvalidKeyFile(String),
+    // Signing or verifying in a build without the `sign` feature.
+    SigningUnavailable,
+    // A target with no OS random source known to `random`.
+    RandomUnavailable,
+    // A provenance record could not be written, see `provenance`.
+    ProvenanceFailed(String),
+    // `ChunkType::random_private` in a build without the `random-type` feature.
+    RandomTypeUnavailable,
+    // The message frame from `framing`; data without its magic was not written by this crate,
+    // or was written before frames existed.
+    NotAPngMsgPayload,
+    InvalidMessageFrame(String),
+    // A size or count guard from `Limits`, hit while streaming or parsing a single chunk.
+    LimitExceeded(LimitExceeded),
+    Io(io::Error),
+}
+
+impl fmt::Display for PngMsgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PngMsgError::InvalidChunkType(e) => write!(f, "Invalid chunk type: {}", e),
+            PngMsgError::CrcMismatch(e) => e.fmt(f),
+            PngMsgError::TruncatedChunk(e) => write!(f, "Malformed chunk: {}", e),
+            PngMsgError::MalformedChunk { position, source } => write!(f, "{}: {}", position, source),
+            PngMsgError::InvalidSignature { found } if found.is_empty() => write!(f, "Not a PNG file: the input is empty"),
+            PngMsgError::InvalidSignature { found } => {
+                write!(f, "Not a PNG file: ")?;
+                match found.len() {
+                    8 => write!(f, "starts with {}", hex_bytes(found))?,
+                    len => write!(f, "only {} bytes long ({})", len, hex_bytes(found))?,
+                }
+                match looks_like(found) {
+                    Some(kind) => write!(f, "; looks like {}", kind),
+                    None => Ok(()),
+                }
+            },
+            PngMsgError::ChunkNotFound(chunk_type) => write!(f, "No {} chunk in PNG", chunk_type),
+            PngMsgError::SplitPastEnd { offset, len } =>
+                write!(f, "Split offset {} is past the end of {} bytes of chunk data", offset, len),
+            PngMsgError::ChunkTypesDiffer { first, second } =>
+                write!(f, "Cannot concatenate a {} chunk with a {} chunk", first, second),
+            PngMsgError::DataTooLarge { len } =>
+                write!(f, "Chunk data of {} bytes is over the PNG limit of {} bytes", len, MAX_CHUNK_LENGTH),
+            PngMsgError::WrongChunkType { expected, found } => write!(f, "Expected a {} chunk, got {}", expected, found),
+            PngMsgError::InvalidKeyword(keyword) => write!(f, "{:?} is not a valid tEXt keyword", keyword),
+            PngMsgError::InvalidTextChar(c) => write!(f, "{:?} cannot be stored in a Latin-1 tEXt chunk", c),
+            PngMsgError::MissingKeywordSeparator => write!(f, "Text chunk has no NUL after its keyword"),
+            PngMsgError::Utf8(e) => e.fmt(f),
+            PngMsgError::UnknownCompressionMethod(Some(method)) => write!(f, "Unknown compression method {}", method),
+            PngMsgError::UnknownCompressionMethod(None) => write!(f, "Compression method byte is missing"),
+            PngMsgError::InvalidCompressedData(reason) => write!(f, "Invalid compressed data: {}", reason),
+            PngMsgError::CompressionUnavailable => write!(f, "Compressed text needs the `compression` feature"),
+            PngMsgError::InvalidCompressionFlag(flag) => write!(f, "Compression flag must be 0 or 1, not {}", flag),
+            PngMsgError::InvalidLanguageTag(tag) => write!(f, "{:?} is not a valid language tag", tag),
+            PngMsgError::MissingTextField(field) => write!(f, "iTXt chunk has no NUL after its {}", field),
+            PngMsgError::ChunkBuild(problems) => {
+                let problems: Vec<String> = problems.iter().map(ToString::to_string).collect();
+                write!(f, "Cannot build the chunk: {}", problems.join("; "))
+            }
+            PngMsgError::ConflictingChunkOptions(reason) => write!(f, "Conflicting chunk options: {}", reason),
+            PngMsgError::ChunkOverCap { len, max } => write!(f, "Chunk data of {} bytes is over the cap of {} bytes", len, max),
+            PngMsgError::ShortRead { expected, read } => write!(f, "Reader ended after {} of {} bytes", read, expected),
+            PngMsgError::InvalidEnvelope(reason) => write!(f, "Invalid encrypted payload: {}", reason),
+            PngMsgError::DecryptionFailed => write!(f, "Decryption failed: wrong passphrase or damaged data"),
+            PngMsgError::EncryptionUnavailable => write!(f, "Encryption needs the `encrypt` feature"),
+            PngMsgError::CriticalChunk(chunk_type) => write!(f, "Refusing to remove critical {} chunks", chunk_type),
+            PngMsgError::UnsafeChunkType(chunk_type) if chunk_type.is_critical() => write!(
+                f, "Refusing to store a message in critical {} chunks, which would corrupt the image; use a private ancillary type such as {}",
+                chunk_type, chunk_type.to_private_ancillary()
+            ),
+            PngMsgError::UnsafeChunkType(chunk_type) => write!(
+                f, "Refusing to store a message in standard {} chunks, which viewers interpret by their specification; use a private ancillary type such as {}",
+                chunk_type, chunk_type.to_private_ancillary()
+            ),
+            PngMsgError::ChunkSizeTooSmall { max_chunk_size } =>
+                write!(f, "A chunk size of {} bytes leaves no room after the headers", max_chunk_size),
+            PngMsgError::NoPieces => write!(f, "No pieces of a split message"),
+            PngMsgError::InvalidPieceHeader { piece } => write!(f, "Piece {} has no valid piece header", piece),
+            PngMsgError::PieceCountsDiffer { message_id, first, other } =>
+                write!(f, "Split message {:08x} claims both {} and {} pieces", message_id, first, other),
+            PngMsgError::DuplicatePiece { message_id, sequence } =>
+                write!(f, "Split message {:08x} has piece {} more than once", message_id, sequence),
+            PngMsgError::MissingPieces { message_id, missing, count } => {
+                let missing: Vec<String> = missing.iter().map(u32::to_string).collect();
+                write!(f, "Split message {:08x} is missing pieces {} of {}", message_id, missing.join(", "), count)
+            }
+            PngMsgError::InvalidIhdrLength(len) => write!(f, "IHDR chunk must be 13 bytes, got {}", len),
+            PngMsgError::InvalidImageSize { width, height } =>
+                write!(f, "Image size {}x{} is out of range; both must be 1 to {}", width, height, MAX_CHUNK_LENGTH),
+            PngMsgError::InvalidColorType(color_type) => write!(f, "Unknown color type {}", color_type),
+            PngMsgError::InvalidBitDepth { bit_depth, color_type } =>
+                write!(f, "Bit depth {} is not allowed for {} images", bit_depth, color_type),
+            PngMsgError::UnknownIhdrMethod { field, value } => write!(f, "Unknown {} method {}", field, value),
+            PngMsgError::InvalidTimeLength(len) => write!(f, "tIME chunk must be 7 bytes, got {}", len),
+            PngMsgError::InvalidTimeField { field, value } => write!(f, "tIME {} {} is out of range", field, value),
+            PngMsgError::InvalidChunkLayout { chunk_type, layout, len } =>
+                write!(f, "{} chunk of {} bytes does not match its layout: {}", chunk_type, len, layout),
+            PngMsgError::UnknownFieldValue { field, value } => write!(f, "Unknown {} {}", field, value),
+            PngMsgError::LsbPalette =>
+                write!(f, "LSB embedding would visibly change the colors of a palette image; convert it to RGB first"),
+            PngMsgError::LsbUnsupported(ihdr) =>
+                write!(f, "LSB embedding needs a non-interlaced image with 8- or 16-bit samples, not {}", ihdr),
+            PngMsgError::MessageTooLarge { len, capacity } =>
+                write!(f, "A message of {} bytes does not fit; the image holds {} bytes", len, capacity),
+            PngMsgError::NoLsbMessage => write!(f, "No LSB message in the image data"),
+            PngMsgError::PasswordRequired => write!(f, "The message is encrypted; a passphrase is needed"),
+            PngMsgError::StegoUnavailable => write!(f, "LSB embedding needs the `stego` feature"),
+            PngMsgError::InvalidIntegrityFrame(reason) => write!(f, "Invalid integrity frame: {}", reason),
+            PngMsgError::IntegrityCheckFailed => write!(f, "Integrity check failed: the message was changed or cut short"),
+            PngMsgError::IntegrityKeyRequired => write!(f, "The message is tagged with an HMAC; a key is needed to check it"),
+            PngMsgError::InvalidBase64(reason) => write!(f, "Invalid base64: {}", reason),
+            PngMsgError::InvalidHex(reason) => write!(f, "Invalid hex: {}", reason),
+            PngMsgError::InvalidSignatureFrame(reason) => write!(f, "Invalid signature frame: {}", reason),
+            PngMsgError::SignatureInvalid => write!(f, "Signature check failed: the message was changed or signed with another key"),
+            PngMsgError::Unsigned => write!(f, "The message is not signed"),
+            PngMsgError::InvalidKeyFile(reason) => write!(f, "Invalid key file: {}", reason),
+            PngMsgError::SigningUnavailable => write!(f, "Signatures need the `sign` feature"),
+            PngMsgError::RandomUnavailable => write!(f, "No random source on this platform; encryption, key generation and random chunk types need one"),
+            PngMsgError::ProvenanceFailed(reason) => write!(f, "Could not record provenance: {}", reason),
+            PngMsgError::RandomTypeUnavailable => write!(f, "Random chunk types need the `random-type` feature"),
+            PngMsgError::NotAPngMsgPayload => write!(f, "The chunk does not hold a message written by pngme"),
+            PngMsgError::InvalidMessageFrame(reason) => write!(f, "Invalid message frame: {}", reason),
+            PngMsgError::LimitExceeded(e) => e.fmt(f),
+            PngMsgError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+// A guess at what was passed instead of a PNG, from its magic bytes.
+fn looks_like(bytes: &[u8]) -> Option<&'static str> {
+    const GUESSES: [(&[u8], &str); 9] = [
+        (b"\xFF\xD8\xFF", "JPEG"),
+        (b"GIF8", "GIF"),
+        (b"BM", "BMP"),
+        (b"RIFF", "a RIFF file such as WebP"),
+        (b"II*\0", "TIFF"),
+        (b"MM\0*", "TIFF"),
+        (b"%PDF", "PDF"),
+        (b"PK\x03\x04", "a ZIP archive"),
+        // Line endings converted by a text-mode transfer, which is what the CR LF is there to catch.
+        (b"\x89PNG\n", "a PNG damaged by a text-mode transfer"),
+    ];
+    GUESSES.iter().find(|(magic, _)| bytes.starts_with(magic)).map(|(_, kind)| *kind)
+}
+
+impl PngMsgError {
+    // Says where in the file the failing chunk starts. A CRC mismatch carries the position itself,
+    // so callers matching on it still can.
+    pub(crate) fn at(self, position: ChunkPosition) -> PngMsgError {
+        match self {
+            PngMsgError::CrcMismatch(crc) => PngMsgError::CrcMismatch(CrcMismatch { position: Some(position), ..crc }),
+            e => PngMsgError::MalformedChunk { position, source: Box::new(e) },
+        }
+    }
+}
+
+impl std::error::Error for PngMsgError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PngMsgError::InvalidChunkType(e) => Some(e),
+            PngMsgError::CrcMismatch(e) => Some(e),
+            PngMsgError::TruncatedChunk(e) => Some(e),
+            PngMsgError::MalformedChunk { source, .. } => Some(source.as_ref()),
+            PngMsgError::Utf8(e) => Some(e),
+            PngMsgError::LimitExceeded(e) => Some(e),
+            PngMsgError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ChunkTypeError> for PngMsgError {
+    fn from(e: ChunkTypeError) -> PngMsgError {
+        PngMsgError::InvalidChunkType(e)
+    }
+}
+
+impl From<CrcMismatch> for PngMsgError {
+    fn from(e: CrcMismatch) -> PngMsgError {
+        PngMsgError::CrcMismatch(e)
+    }
+}
+
+impl From<ChunkParseError> for PngMsgError {
+    fn from(e: ChunkParseError) -> PngMsgError {
+        PngMsgError::TruncatedChunk(e)
+    }
+}
+
+impl From<FromUtf8Error> for PngMsgError {
+    fn from(e: FromUtf8Error) -> PngMsgError {
+        PngMsgError::Utf8(e)
+    }
+}
+
+impl From<LimitExceeded> for PngMsgError {
+    fn from(e: LimitExceeded) -> PngMsgError {
+        PngMsgError::LimitExceeded(e)
+    }
+}
+
+impl From<io::Error> for PngMsgError {
+    fn from(e: io::Error) -> PngMsgError {
+        PngMsgError::Io(e)
+    }
+}
@@ -0,0 +1,324 @@
+// A C API over `encode_bytes` and `decode_one`, declared in include/pngme.h. Build the library
+// with `cargo rustc --lib --release --features ffi --crate-type cdylib` (or `staticlib`).
+//
+// Every entry point returns one of the PME_* codes and never unwinds into C: a panic is caught
+// and reported as PME_PANIC. Output buffers come from malloc, so pme_free and free both work.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::str::FromStr;
+
+use crate::chunk_type::ChunkType;
+use crate::decode::{decode_one, DecodeOptions};
+use crate::encode::{encode_bytes, EncodeOptions};
+use crate::error::PngMsgError;
+use crate::sign::VerifyingKey;
+
+pub const PME_OK: i32 = 0;
+pub const PME_NULL_ARGUMENT: i32 = -1;
+pub const PME_INVALID_ARGUMENT: i32 = -2;
+pub const PME_FAILED: i32 = -3;
+pub const PME_NOT_FOUND: i32 = -4;
+pub const PME_OUT_OF_MEMORY: i32 = -5;
+pub const PME_PANIC: i32 = -6;
+pub const PME_KEY_REQUIRED: i32 = -7;
+
+extern "C" {
+    fn malloc(size: usize) -> *mut c_void;
+    fn free(ptr: *mut c_void);
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+struct FfiError {
+    code: i32,
+    message: String,
+}
+
+impl FfiError {
+    fn new(code: i32, message: impl Into<String>) -> FfiError {
+        FfiError { code, message: message.into() }
+    }
+}
+
+// Runs `body` with the panic caught, storing the message of any failure for
+// `pme_last_error_message`.
+fn guarded(body: impl FnOnce() -> Result<(), FfiError>) -> i32 {
+    let result = catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|panic| {
+        let reason = panic.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(FfiError::new(PME_PANIC, format!("internal error: {}", reason)))
+    });
+    let (code, message) = match result {
+        Ok(()) => (PME_OK, None),
+        // A message with a NUL in it cannot be a C string; keep the part before it.
+        Err(e) => (e.code, Some(CString::new(e.message).unwrap_or_else(|e| {
+            let nul = e.nul_position();
+            CString::new(&e.into_vec()[..nul]).expect("cut at the first NUL")
+        }))),
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
+
+unsafe fn input<'a>(data: *const u8, len: usize, name: &str) -> Result<&'a [u8], FfiError> {
+    match (data.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(FfiError::new(PME_NULL_ARGUMENT, format!("{} is NULL", name))),
+        (false, len) => Ok(slice::from_raw_parts(data, len)),
+    }
+}
+
+unsafe fn chunk_type(name: *const c_char) -> Result<ChunkType, FfiError> {
+    if name.is_null() {
+        return Err(FfiError::new(PME_NULL_ARGUMENT, "chunk_type is NULL"));
+    }
+    let name = CStr::from_ptr(name).to_str()
+        .map_err(|_| FfiError::new(PME_INVALID_ARGUMENT, "chunk_type is not UTF-8"))?;
+    ChunkType::from_str(name)
+        .map_err(|e| FfiError::new(PME_INVALID_ARGUMENT, format!("invalid chunk type {:?}: {}", name, e)))
+}
+
+// Copies `bytes` into a malloc'd buffer; a zero-length result still gets a valid pointer.
+unsafe fn output(bytes: &[u8], out: *mut *mut u8, out_len: *mut usize) -> Result<(), FfiError> {
+    let buffer = malloc(bytes.len().max(1)) as *mut u8;
+    if buffer.is_null() {
+        return Err(FfiError::new(PME_OUT_OF_MEMORY, format!("cannot allocate {} bytes", bytes.len())));
+    }
+    ptr::copy_nonoverlapping(bytes.as_ptr(), buffer, bytes.len());
+    *out = buffer;
+    *out_len = bytes.len();
+    Ok(())
+}
+
+// NULL is no key.
+unsafe fn optional_string(value: *const c_char, name: &str) -> Result<Option<String>, FfiError> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    let value = CStr::from_ptr(value).to_str()
+        .map_err(|_| FfiError::new(PME_INVALID_ARGUMENT, format!("{} is not UTF-8", name)))?;
+    Ok(Some(value.to_string()))
+}
+
+fn decode_error(error: crate::Error) -> FfiError {
+    let code = match error.downcast_ref::<PngMsgError>() {
+        Some(PngMsgError::PasswordRequired | PngMsgError::IntegrityKeyRequired) => PME_KEY_REQUIRED,
+        _ => PME_FAILED,
+    };
+    FfiError::new(code, error.to_string())
+}
+
+fn outputs_present(out: *mut *mut u8, out_len: *mut usize) -> Result<(), FfiError> {
+    match out.is_null() || out_len.is_null() {
+        true => Err(FfiError::new(PME_NULL_ARGUMENT, "out and out_len must not be NULL")),
+        false => Ok(()),
+    }
+}
+
+/// Hides `msg` in a `chunk_type` chunk of the PNG in `png` and returns the new file in `*out`.
+///
+/// # Safety
+///
+/// `png` and `msg` must point to `len` and `msg_len` readable bytes (or be NULL when the length
+/// is 0), `chunk_type` must be a NUL-terminated string, and `out` and `out_len` must be writable.
+/// `*out` is only written on success and must be released with `pme_free`.
+#[no_mangle]
+pub unsafe extern "C" fn pme_encode(
+    png: *const u8,
+    len: usize,
+    chunk_type: *const c_char,
+    msg: *const u8,
+    msg_len: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    guarded(|| {
+        outputs_present(out, out_len)?;
+        let (png, chunk_type, msg) = (input(png, len, "png")?, self::chunk_type(chunk_type)?, input(msg, msg_len, "msg")?);
+        let (bytes, _) = encode_bytes(png, msg, &EncodeOptions::new(chunk_type))
+            .map_err(|e| FfiError::new(PME_FAILED, e.to_string()))?;
+        output(&bytes, out, out_len)
+    })
+}
+
+/// Reads the message in the first `chunk_type` chunk of the PNG in `png` into `*out`.
+/// Returns `PME_NOT_FOUND` when the file has no such chunk, and `PME_KEY_REQUIRED` when the
+/// message needs a password or HMAC key; `pme_decode_with_keys` takes those.
+///
+/// # Safety
+///
+/// As for `pme_encode`: `png` must point to `len` readable bytes, `chunk_type` must be a
+/// NUL-terminated string, and `out` and `out_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn pme_decode(
+    png: *const u8,
+    len: usize,
+    chunk_type: *const c_char,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    pme_decode_with_keys(png, len, chunk_type, ptr::null(), ptr::null(), ptr::null(), out, out_len)
+}
+
+/// As `pme_decode`, opening the layers the keys are for: `password` decrypts, `hmac_key` checks
+/// an HMAC integrity frame and `verify_key`, a public key in PEM form, checks the signature.
+/// NULL means no key; with `hmac_key` or `verify_key` given, a message without that layer is
+/// refused.
+///
+/// # Safety
+///
+/// As for `pme_decode`, and each key must be NULL or a NUL-terminated string.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn pme_decode_with_keys(
+    png: *const u8,
+    len: usize,
+    chunk_type: *const c_char,
+    password: *const c_char,
+    hmac_key: *const c_char,
+    verify_key: *const c_char,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    guarded(|| {
+        outputs_present(out, out_len)?;
+        let (png, chunk_type) = (input(png, len, "png")?, self::chunk_type(chunk_type)?);
+        let verify_key = optional_string(verify_key, "verify_key")?
+            .map(|pem| VerifyingKey::from_pem(&pem))
+            .transpose()
+            .map_err(|e| FfiError::new(PME_INVALID_ARGUMENT, format!("invalid verify_key: {}", e)))?;
+        let opts = DecodeOptions {
+            password: optional_string(password, "password")?,
+            hmac_key: optional_string(hmac_key, "hmac_key")?,
+            verify_key,
+            ..Default::default()
+        };
+        let message = decode_one(png, &chunk_type, &opts)
+            .map_err(decode_error)?
+            .ok_or_else(|| FfiError::new(PME_NOT_FOUND, format!("no {} chunk in the PNG", chunk_type)))?;
+        output(&message, out, out_len)
+    })
+}
+
+/// Releases a buffer returned by `pme_encode` or one of the decode functions; NULL is ignored.
+///
+/// # Safety
+///
+/// `ptr` must come from one of those functions and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn pme_free(ptr: *mut u8) {
+    if !ptr.is_null() {
+        free(ptr as *mut c_void);
+    }
+}
+
+/// The error of the last failed call on this thread, or NULL after a success. The string stays
+/// valid until the next `pme_*` call on the same thread.
+#[no_mangle]
+pub extern "C" fn pme_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::png::Png;
+
+    fn carrier() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap().as_bytes()
+    }
+
+    fn last_error() -> String {
+        let message = pme_last_error_message();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_encode_then_decode() {
+        let png = carrier();
+        let (mut encoded, mut encoded_len) = (ptr::null_mut(), 0);
+        let code = unsafe {
+            pme_encode(png.as_ptr(), png.len(), c"ruSt".as_ptr(), b"hello".as_ptr(), 5, &mut encoded, &mut encoded_len)
+        };
+        assert_eq!(code, PME_OK);
+        assert!(pme_last_error_message().is_null());
+
+        let (mut message, mut message_len) = (ptr::null_mut(), 0);
+        let code = unsafe { pme_decode(encoded, encoded_len, c"ruSt".as_ptr(), &mut message, &mut message_len) };
+        assert_eq!(code, PME_OK);
+        assert_eq!(unsafe { slice::from_raw_parts(message, message_len) }, b"hello");
+        unsafe {
+            pme_free(encoded);
+            pme_free(message);
+            pme_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_errors_are_codes_with_messages() {
+        let png = carrier();
+        let (mut out, mut out_len) = (ptr::null_mut(), 0);
+
+        let code = unsafe { pme_decode(png.as_ptr(), png.len(), c"ruSt".as_ptr(), &mut out, &mut out_len) };
+        assert_eq!((code, last_error().as_str()), (PME_NOT_FOUND, "no ruSt chunk in the PNG"));
+        assert!(out.is_null());
+
+        let code = unsafe { pme_decode(png.as_ptr(), png.len(), c"ru5t".as_ptr(), &mut out, &mut out_len) };
+        assert_eq!(code, PME_INVALID_ARGUMENT);
+        assert!(last_error().starts_with("invalid chunk type \"ru5t\""));
+
+        let code = unsafe { pme_decode(b"GIF89a".as_ptr(), 6, c"ruSt".as_ptr(), &mut out, &mut out_len) };
+        assert_eq!(code, PME_FAILED);
+        assert!(last_error().starts_with("Not a PNG file"));
+
+        let code = unsafe { pme_encode(ptr::null(), 10, c"ruSt".as_ptr(), ptr::null(), 0, &mut out, &mut out_len) };
+        assert_eq!((code, last_error().as_str()), (PME_NULL_ARGUMENT, "png is NULL"));
+        let code = unsafe { pme_decode(png.as_ptr(), png.len(), ptr::null(), ptr::null_mut(), &mut out_len) };
+        assert_eq!(code, PME_NULL_ARGUMENT);
+    }
+
+    #[test]
+    fn test_decode_with_keys() {
+        let mut opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        opts.integrity = Some(crate::integrity::Integrity::Hmac("key".to_string()));
+        let (png, _) = encode_bytes(&carrier(), b"hello", &opts).unwrap();
+        let (mut out, mut out_len) = (ptr::null_mut(), 0);
+
+        let code = unsafe { pme_decode(png.as_ptr(), png.len(), c"ruSt".as_ptr(), &mut out, &mut out_len) };
+        assert_eq!(code, PME_KEY_REQUIRED);
+        assert!(out.is_null());
+
+        let decode = |key: &CStr, out: &mut *mut u8, out_len: &mut usize| unsafe {
+            pme_decode_with_keys(png.as_ptr(), png.len(), c"ruSt".as_ptr(), ptr::null(), key.as_ptr(), ptr::null(), out, out_len)
+        };
+        assert_eq!(decode(c"other", &mut out, &mut out_len), PME_FAILED);
+        assert_eq!(decode(c"key", &mut out, &mut out_len), PME_OK);
+        assert_eq!(unsafe { slice::from_raw_parts(out, out_len) }, b"hello");
+        unsafe { pme_free(out) };
+
+        let code = unsafe {
+            pme_decode_with_keys(png.as_ptr(), png.len(), c"ruSt".as_ptr(), ptr::null(), ptr::null(), c"not a key".as_ptr(), &mut out, &mut out_len)
+        };
+        assert_eq!(code, PME_INVALID_ARGUMENT);
+        assert!(last_error().starts_with("invalid verify_key"));
+    }
+
+    #[test]
+    fn test_panics_do_not_unwind() {
+        let code = guarded(|| panic!("boom"));
+        assert_eq!((code, last_error().as_str()), (PME_PANIC, "internal error: boom"));
+    }
+}
@@ -0,0 +1,368 @@
+//! One result shape for every analysis: lenient-parse warnings, integrity checks, rule matches
+//! and the suspicious-chunk heuristics all produce `Finding`s, which share a renderer for text,
+//! JSON and CSV.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chunk_type::ChunkType;
+use crate::png::{Inconsistency, StructureError};
+#[cfg(feature = "rules")]
+use crate::rules::RuleMatch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FindingCode {
+    DuplicateIhdr,
+    ChunkAfterIend,
+    LengthMismatch,
+    CrcMismatch,
+    RuleMatch,
+    DisagreeingCopies,
+    NonStandardType,
+    LargeText,
+    RepeatedPrivateType,
+    HighEntropy,
+}
+
+impl FindingCode {
+    // Codes are part of the JSON and CSV output; never rename one.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FindingCode::DuplicateIhdr => "structure.duplicate-ihdr",
+            FindingCode::ChunkAfterIend => "structure.chunk-after-iend",
+            FindingCode::LengthMismatch => "integrity.length",
+            FindingCode::CrcMismatch => "integrity.crc",
+            FindingCode::RuleMatch => "scan.rule-match",
+            FindingCode::DisagreeingCopies => "decode.disagreeing-copies",
+            FindingCode::NonStandardType => "scan.non-standard-type",
+            FindingCode::LargeText => "scan.large-text",
+            FindingCode::RepeatedPrivateType => "scan.repeated-private-type",
+            FindingCode::HighEntropy => "scan.high-entropy",
+        }
+    }
+}
+
+impl fmt::Display for FindingCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Finding {
+    pub chunk_index: Option<usize>,
+    pub chunk_type: Option<ChunkType>,
+    // The chunk's data length.
+    pub length: Option<u32>,
+    pub offset: Option<u64>,
+    pub code: FindingCode,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl From<&StructureError> for Finding {
+    fn from(error: &StructureError) -> Finding {
+        match error {
+            StructureError::DuplicateIhdr { index } => Finding {
+                chunk_index: Some(*index),
+                chunk_type: Some(ChunkType::IHDR),
+                length: None,
+                offset: None,
+                code: FindingCode::DuplicateIhdr,
+                severity: Severity::Error,
+                message: "second IHDR chunk".to_string(),
+            },
+            StructureError::ChunkAfterIend { index, chunk_type } => Finding {
+                chunk_index: Some(*index),
+                chunk_type: ChunkType::from_str(chunk_type).ok(),
+                length: None,
+                offset: None,
+                code: FindingCode::ChunkAfterIend,
+                severity: Severity::Warning,
+                message: "chunk after IEND".to_string(),
+            },
+        }
+    }
+}
+
+impl From<&Inconsistency> for Finding {
+    fn from(issue: &Inconsistency) -> Finding {
+        match issue {
+            Inconsistency::Length { index, declared, actual } => Finding {
+                chunk_index: Some(*index),
+                chunk_type: None,
+                length: None,
+                offset: None,
+                code: FindingCode::LengthMismatch,
+                severity: Severity::Error,
+                message: format!("length field is {} but data is {} bytes", declared, actual),
+            },
+            Inconsistency::Crc { index, stored, computed } => Finding {
+                chunk_index: Some(*index),
+                chunk_type: None,
+                length: None,
+                offset: None,
+                code: FindingCode::CrcMismatch,
+                severity: Severity::Error,
+                message: format!("stored CRC {:08x} but data hashes to {:08x}", stored, computed),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "rules")]
+impl From<&RuleMatch> for Finding {
+    fn from(m: &RuleMatch) -> Finding {
+        Finding {
+            chunk_index: Some(m.chunk_index),
+            chunk_type: None,
+            length: None,
+            offset: Some(m.offset as u64),
+            code: FindingCode::RuleMatch,
+            severity: Severity::Warning,
+            message: format!("rule {} matched", m.rule),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.severity, self.code)?;
+        if let Some(index) = self.chunk_index {
+            write!(f, " chunk {}", index)?;
+        }
+        match (self.chunk_type, self.length) {
+            (Some(chunk_type), Some(length)) => write!(f, " ({}, {} bytes)", chunk_type, length)?,
+            (Some(chunk_type), None) => write!(f, " ({})", chunk_type)?,
+            (None, Some(length)) => write!(f, " ({} bytes)", length)?,
+            (None, None) => {}
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " offset {}", offset)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+// Merges findings from several analyses into chunk order, dropping exact duplicates. The
+// ordering covers every field, so the result never depends on the order of the input.
+pub fn aggregate<I: IntoIterator<Item = Finding>>(findings: I) -> Vec<Finding> {
+    let mut all: Vec<Finding> = findings.into_iter().collect();
+    all.sort();
+    all.dedup();
+    all
+}
+
+pub fn at_least(findings: &[Finding], severity: Severity) -> impl Iterator<Item = &Finding> {
+    findings.iter().filter(move |f| f.severity >= severity)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+pub fn render(findings: &[Finding], format: FindingFormat) -> String {
+    match format {
+        FindingFormat::Text => findings.iter().map(|f| format!("{}\n", f)).collect(),
+        FindingFormat::Json => {
+            let rows: Vec<String> = findings.iter()
+                .map(|f| format!(
+                    "  {{\"severity\": \"{}\", \"code\": \"{}\", \"chunk_index\": {}, \"chunk_type\": {}, \"length\": {}, \"offset\": {}, \"message\": \"{}\"}}",
+                    f.severity, f.code, json_option(f.chunk_index),
+                    json_option(f.chunk_type.map(|t| format!("\"{}\"", json_escape(t.name())))),
+                    json_option(f.length), json_option(f.offset), json_escape(&f.message)
+                ))
+                .collect();
+            if rows.is_empty() {
+                return "[]\n".to_string();
+            }
+            format!("[\n{}\n]\n", rows.join(",\n"))
+        }
+        FindingFormat::Csv => {
+            let mut out = String::from("severity,code,chunk_index,chunk_type,length,offset,message\n");
+            for f in findings {
+                out.push_str(&format!(
+                    "{},{},{},{},{},{},\"{}\"\n",
+                    f.severity, f.code,
+                    f.chunk_index.map(|i| i.to_string()).unwrap_or_default(),
+                    f.chunk_type.map(|t| t.to_string()).unwrap_or_default(),
+                    f.length.map(|l| l.to_string()).unwrap_or_default(),
+                    f.offset.map(|o| o.to_string()).unwrap_or_default(),
+                    f.message.replace('"', "\"\""),
+                ));
+            }
+            out
+        }
+    }
+}
+
+fn json_option<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "null".to_string(), |v| v.to_string())
+}
+
+pub(crate) fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::png::{ParseOptions, Png};
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn malformed() -> Vec<u8> {
+        Png::from_chunks_unchecked(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("IHDR", &[0; 13]),
+            chunk("IEND", &[]),
+            chunk("trAl", b"tail"),
+        ]).as_bytes()
+    }
+
+    #[test]
+    fn test_lenient_parse_warnings_become_findings() {
+        let options = ParseOptions { lenient: true, ..Default::default() };
+        let (_, warnings) = Png::parse_with(&malformed(), &options).unwrap();
+        let findings: Vec<Finding> = warnings.iter().map(Finding::from).collect();
+
+        let codes: Vec<&str> = findings.iter().map(|f| f.code.as_str()).collect();
+        assert_eq!(codes, ["structure.duplicate-ihdr", "structure.chunk-after-iend"]);
+        assert_eq!(findings[1].chunk_index, Some(3));
+        assert_eq!(at_least(&findings, Severity::Error).count(), 1);
+    }
+
+    #[test]
+    fn test_integrity_findings() {
+        let mut bad = chunk("ruSt", b"data");
+        bad.set_raw_fields_for_test(9, 0);
+        let png = Png::from_chunks_unchecked(vec![bad]);
+        let findings: Vec<Finding> = png.verify_integrity().iter().map(Finding::from).collect();
+
+        let codes: Vec<&str> = findings.iter().map(|f| f.code.as_str()).collect();
+        assert_eq!(codes, ["integrity.length", "integrity.crc"]);
+    }
+
+    #[cfg(feature = "rules")]
+    #[test]
+    fn test_rule_matches_become_findings() {
+        let rules = crate::rules::RuleSet::from_str("[[rule]]\nname = \"tail\"\ntarget = \"trailing\"\nstring = \"ai\"\n").unwrap();
+        let options = ParseOptions { lenient: true, ..Default::default() };
+        let (png, _) = Png::parse_with(&malformed(), &options).unwrap();
+        let findings: Vec<Finding> = rules.scan(&png).iter().map(Finding::from).collect();
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].code.as_str(), "scan.rule-match");
+        assert_eq!((findings[0].chunk_index, findings[0].offset), (Some(3), Some(1)));
+    }
+
+    #[test]
+    fn test_aggregate_orders_and_deduplicates() {
+        let options = ParseOptions { lenient: true, ..Default::default() };
+        let (_, warnings) = Png::parse_with(&malformed(), &options).unwrap();
+        let once = warnings.iter().map(Finding::from);
+        let twice = warnings.iter().rev().map(Finding::from);
+
+        let all = aggregate(once.chain(twice));
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].chunk_index, Some(1));
+    }
+
+    #[test]
+    fn test_aggregate_ignores_input_order() {
+        let finding = |chunk_index: usize, offset: u64, code: FindingCode| Finding {
+            chunk_index: Some(chunk_index),
+            chunk_type: None,
+            length: None,
+            offset: Some(offset),
+            code,
+            severity: Severity::Warning,
+            message: String::new(),
+        };
+        let findings = vec![
+            finding(1, 4, FindingCode::RuleMatch),
+            finding(1, 4, FindingCode::CrcMismatch),
+            finding(0, 9, FindingCode::RuleMatch),
+            finding(1, 2, FindingCode::RuleMatch),
+        ];
+        let expected = render(&aggregate(findings.clone()), FindingFormat::Json);
+        for rotation in 1..findings.len() {
+            let mut shuffled = findings.clone();
+            shuffled.rotate_left(rotation);
+            shuffled.swap(0, rotation);
+            assert_eq!(render(&aggregate(shuffled), FindingFormat::Json), expected);
+        }
+    }
+
+    #[test]
+    fn test_render_formats() {
+        let finding = Finding {
+            chunk_index: Some(2),
+            chunk_type: Some(ChunkType::from_str("trAl").unwrap()),
+            length: None,
+            offset: None,
+            code: FindingCode::ChunkAfterIend,
+            severity: Severity::Warning,
+            message: "say \"hi\"".to_string(),
+        };
+        let findings = [finding];
+
+        assert_eq!(
+            render(&findings, FindingFormat::Text),
+            "warning [structure.chunk-after-iend] chunk 2 (trAl): say \"hi\"\n"
+        );
+        assert_eq!(
+            render(&findings, FindingFormat::Json),
+            "[\n  {\"severity\": \"warning\", \"code\": \"structure.chunk-after-iend\", \"chunk_index\": 2, \"chunk_type\": \"trAl\", \"length\": null, \"offset\": null, \"message\": \"say \\\"hi\\\"\"}\n]\n"
+        );
+        assert_eq!(
+            render(&findings, FindingFormat::Csv),
+            "severity,code,chunk_index,chunk_type,length,offset,message\nwarning,structure.chunk-after-iend,2,trAl,,,\"say \"\"hi\"\"\"\n"
+        );
+
+        let sized = Finding { length: Some(512), ..findings[0].clone() };
+        assert_eq!(sized.to_string(), "warning [structure.chunk-after-iend] chunk 2 (trAl, 512 bytes): say \"hi\"");
+        assert_eq!(render(&[], FindingFormat::Json), "[]\n");
+    }
+}
@@ -0,0 +1,395 @@
+//! Deterministic test PNGs built from a declarative spec.
+//!
+//! A spec uses the same TOML subset as rules files:
+//!
+//! ```text
+//! [image]
+//! width = 16
+//! height = 16
+//! color_type = 2      # 0, 2, 3, 4 or 6
+//! bit_depth = 8
+//! idat_count = 3      # split the image data across this many IDAT chunks
+//!
+//! [[chunk]]
+//! type = "tEXt"
+//! text = "Comment\0hello"   # or `hex = "DE AD"`, or `size = 51200` for filler bytes
+//! position = "before-idat"  # after-ihdr, before-idat (default), after-idat or after-iend
+//!
+//! [corrupt]
+//! bad_crc = 2           # flip the CRC of the chunk at this index
+//! duplicate_ihdr = true # repeat IHDR right after the first one
+//! truncate = 100        # cut the file to this many bytes
+//! ```
+//!
+//! Pixel data is stored uncompressed, so the same spec always produces the same bytes.
+
+use std::str::FromStr;
+
+use crate::{Error, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::spec::{self, Table};
+
+// Largest payload of a stored deflate block.
+const STORED_BLOCK_MAX: usize = 65535;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    AfterIhdr,
+    BeforeIdat,
+    AfterIdat,
+    AfterIend,
+}
+
+impl FromStr for Position {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "after-ihdr" => Ok(Position::AfterIhdr),
+            "before-idat" => Ok(Position::BeforeIdat),
+            "after-idat" => Ok(Position::AfterIdat),
+            "after-iend" => Ok(Position::AfterIend),
+            other => Err(format!("Unknown chunk position: {}", other).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSpec {
+    pub width: u32,
+    pub height: u32,
+    pub color_type: u8,
+    pub bit_depth: u8,
+    pub idat_count: usize,
+}
+
+impl Default for ImageSpec {
+    fn default() -> Self {
+        ImageSpec { width: 1, height: 1, color_type: 2, bit_depth: 8, idat_count: 1 }
+    }
+}
+
+impl ImageSpec {
+    fn channels(&self) -> Result<u64> {
+        let (channels, depths): (u64, &[u8]) = match self.color_type {
+            0 => (1, &[1, 2, 4, 8, 16]),
+            2 => (3, &[8, 16]),
+            3 => (1, &[1, 2, 4, 8]),
+            4 => (2, &[8, 16]),
+            6 => (4, &[8, 16]),
+            other => return Err(format!("Unknown color type: {}", other).into()),
+        };
+        if !depths.contains(&self.bit_depth) {
+            return Err(format!("Bit depth {} is not allowed for color type {}", self.bit_depth, self.color_type).into());
+        }
+        Ok(channels)
+    }
+
+    fn ihdr(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(13);
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.extend_from_slice(&[self.bit_depth, self.color_type, 0, 0, 0]);
+        data
+    }
+
+    // Every palette index a pixel byte can produce gets an entry, so any pattern is valid.
+    fn palette(&self) -> Vec<u8> {
+        (0..1usize << self.bit_depth).flat_map(|i| [i as u8, (i * 3) as u8, (i * 7) as u8]).collect()
+    }
+
+    // Unfiltered scanlines with a repeatable gradient.
+    fn raw_pixels(&self) -> Result<Vec<u8>> {
+        let row_bytes = (self.width as u64 * self.channels()? * self.bit_depth as u64).div_ceil(8) as usize;
+        let mut raw = Vec::with_capacity((row_bytes + 1) * self.height as usize);
+        for y in 0..self.height as usize {
+            raw.push(0);
+            raw.extend((0..row_bytes).map(|x| (x + y * 3) as u8));
+        }
+        Ok(raw)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSpec {
+    pub chunk_type: ChunkType,
+    pub data: Vec<u8>,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Corruption {
+    pub bad_crc: Option<usize>,
+    pub truncate: Option<usize>,
+    pub duplicate_ihdr: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FixtureSpec {
+    pub image: ImageSpec,
+    pub chunks: Vec<ChunkSpec>,
+    pub corrupt: Corruption,
+}
+
+impl FixtureSpec {
+    pub fn is_corrupt(&self) -> bool {
+        self.corrupt != Corruption::default()
+    }
+
+    pub fn build(&self) -> Result<Vec<u8>> {
+        let stream = zlib_stored(&self.image.raw_pixels()?);
+        let count = self.image.idat_count;
+        if count == 0 || count > stream.len() {
+            return Err(format!("Cannot split {} bytes of image data into {} IDAT chunks", stream.len(), count).into());
+        }
+
+        let placed = |position: Position| {
+            self.chunks.iter()
+                .filter(move |c| c.position == position)
+                .map(|c| Chunk::new(c.chunk_type, c.data.clone()))
+        };
+        let ihdr = chunk("IHDR", self.image.ihdr());
+
+        let mut chunks = vec![ihdr.clone()];
+        if self.corrupt.duplicate_ihdr {
+            chunks.push(ihdr);
+        }
+        chunks.extend(placed(Position::AfterIhdr));
+        if self.image.color_type == 3 {
+            chunks.push(chunk("PLTE", self.image.palette()));
+        }
+        chunks.extend(placed(Position::BeforeIdat));
+        let split = stream.len().div_ceil(count);
+        chunks.extend(stream.chunks(split).map(|part| chunk("IDAT", part.to_vec())));
+        chunks.extend(placed(Position::AfterIdat));
+        chunks.push(chunk("IEND", Vec::new()));
+        chunks.extend(placed(Position::AfterIend));
+
+        let mut bytes = Png::from_chunks_unchecked(chunks.clone()).as_bytes();
+        if let Some(index) = self.corrupt.bad_crc {
+            if index >= chunks.len() {
+                return Err(format!("bad_crc index {} is past the last chunk ({})", index, chunks.len() - 1).into());
+            }
+            let end = Png::from_chunks_unchecked(chunks[..=index].to_vec()).total_size().get() as usize;
+            bytes[end - 4..end].iter_mut().for_each(|b| *b = !*b);
+        }
+        if let Some(len) = self.corrupt.truncate {
+            bytes.truncate(len);
+        }
+        Ok(bytes)
+    }
+}
+
+impl FromStr for FixtureSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut spec = FixtureSpec::default();
+        for table in spec::parse(s)? {
+            match table.name.as_str() {
+                "image" => spec.image = parse_image(&table)?,
+                "chunk" => spec.chunks.push(parse_chunk(&table)?),
+                "corrupt" => spec.corrupt = parse_corruption(&table)?,
+                other => return Err(format!("line {}: unknown table [{}]", table.line, other).into()),
+            }
+        }
+        spec.image.channels()?;
+        Ok(spec)
+    }
+}
+
+fn parse_image(table: &Table) -> Result<ImageSpec> {
+    table.reject_unknown(&["width", "height", "color_type", "bit_depth", "idat_count"])?;
+    let defaults = ImageSpec::default();
+    let small = |key: &str, default: u64, max: u64| -> Result<u64> {
+        match table.int(key)?.unwrap_or(default) {
+            n if n <= max => Ok(n),
+            n => Err(format!("line {}: `{}` = {} is out of range", table.line, key, n).into()),
+        }
+    };
+    Ok(ImageSpec {
+        width: small("width", defaults.width as u64, u32::MAX as u64)? as u32,
+        height: small("height", defaults.height as u64, u32::MAX as u64)? as u32,
+        color_type: small("color_type", defaults.color_type as u64, u8::MAX as u64)? as u8,
+        bit_depth: small("bit_depth", defaults.bit_depth as u64, u8::MAX as u64)? as u8,
+        idat_count: small("idat_count", defaults.idat_count as u64, usize::MAX as u64)? as usize,
+    })
+}
+
+fn parse_chunk(table: &Table) -> Result<ChunkSpec> {
+    table.reject_unknown(&["type", "text", "hex", "size", "position"])?;
+
+    let chunk_type = table.str("type")?
+        .ok_or_else(|| format!("line {}: chunk is missing `type`", table.line))?;
+    let chunk_type = ChunkType::from_str(chunk_type)
+        .map_err(|e| format!("line {}: `type` = \"{}\": {}", table.get("type").map_or(table.line, |entry| entry.line), chunk_type, e))?;
+    let data = match (table.str("text")?, table.str("hex")?, table.int("size")?) {
+        (Some(text), None, None) => text.as_bytes().to_vec(),
+        (None, Some(hex), None) => hex.split_whitespace()
+            .map(|token| u8::from_str_radix(token, 16)
+                .map_err(|_| format!("line {}: `{}` is not a hex byte", table.line, token).into()))
+            .collect::<Result<Vec<u8>>>()?,
+        (None, None, Some(size)) => (0..size).map(|i| (i % 251) as u8).collect(),
+        (None, None, None) => Vec::new(),
+        _ => return Err(format!("line {}: chunk takes at most one of `text`, `hex` or `size`", table.line).into()),
+    };
+    let position = match table.str("position")? {
+        Some(position) => position.parse()?,
+        None => Position::BeforeIdat,
+    };
+    Ok(ChunkSpec { chunk_type, data, position })
+}
+
+fn parse_corruption(table: &Table) -> Result<Corruption> {
+    table.reject_unknown(&["bad_crc", "truncate", "duplicate_ihdr"])?;
+    Ok(Corruption {
+        bad_crc: table.int("bad_crc")?.map(|n| n as usize),
+        truncate: table.int("truncate")?.map(|n| n as usize),
+        duplicate_ihdr: table.bool("duplicate_ihdr")?.unwrap_or(false),
+    })
+}
+
+fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).expect("built-in chunk types are valid"), data)
+}
+
+// A zlib stream of stored (uncompressed) deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK_MAX).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        out.push(blocks.peek().is_none() as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::error::PngMsgError;
+    use crate::png::StructureError;
+
+    const SPEC_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/specs");
+
+    fn types(bytes: &[u8]) -> Vec<String> {
+        Png::try_from(bytes).unwrap().chunks().iter().map(|c| c.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_checked_in_specs_round_trip_through_strict_parser() {
+        let mut seen = 0;
+        for entry in fs::read_dir(SPEC_DIR).unwrap() {
+            let path = entry.unwrap().path();
+            let spec = FixtureSpec::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            let bytes = spec.build().unwrap();
+            let parsed = Png::try_from(bytes.as_slice());
+            assert_eq!(parsed.is_err(), spec.is_corrupt(), "{}", path.display());
+            assert_eq!(spec.build().unwrap(), bytes, "{} is not deterministic", path.display());
+            seen += 1;
+        }
+        assert!(seen > 0);
+    }
+
+    #[test]
+    fn test_three_idats() {
+        let spec = FixtureSpec::from_str("[image]\nwidth = 8\nheight = 8\nidat_count = 3\n").unwrap();
+        assert_eq!(types(&spec.build().unwrap()), ["IHDR", "IDAT", "IDAT", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_chunk_positions_and_palette() {
+        let spec = FixtureSpec::from_str(r#"
+[image]
+color_type = 3
+bit_depth = 4
+
+[[chunk]]
+type = "tEXt"
+text = "Comment\0hi"
+position = "after-ihdr"
+
+[[chunk]]
+type = "ruSt"
+size = 50000
+
+[[chunk]]
+type = "tIME"
+hex = "07 E8 01 02 03 04 05"
+position = "after-idat"
+"#).unwrap();
+        let bytes = spec.build().unwrap();
+        assert_eq!(types(&bytes), ["IHDR", "tEXt", "PLTE", "ruSt", "IDAT", "tIME", "IEND"]);
+
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.chunk_by_type("PLTE").unwrap().data().len(), 16 * 3);
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data().len(), 50000);
+    }
+
+    #[test]
+    fn test_idat_is_valid_zlib() {
+        let spec = FixtureSpec::from_str("[image]\nwidth = 2\nheight = 2\n").unwrap();
+        let png = Png::try_from(spec.build().unwrap().as_slice()).unwrap();
+        let idat = png.chunk_by_type("IDAT").unwrap().data();
+
+        let raw = [0, 0, 1, 2, 3, 4, 5, 0, 3, 4, 5, 6, 7, 8];
+        assert_eq!(&idat[..2], &[0x78, 0x01]);
+        assert_eq!(&idat[2..7], &[1, 14, 0, !14, 0xff]);
+        assert_eq!(&idat[7..21], &raw);
+        assert_eq!(&idat[21..], &adler32(&raw).to_be_bytes());
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn test_corruptions() {
+        let bad_crc = FixtureSpec::from_str("[corrupt]\nbad_crc = 1\n").unwrap().build().unwrap();
+        let err = Png::try_from(bad_crc.as_slice()).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PngMsgError>(), Some(PngMsgError::CrcMismatch(_))));
+
+        let duplicate = FixtureSpec::from_str("[corrupt]\nduplicate_ihdr = true\n").unwrap().build().unwrap();
+        let err = Png::try_from(duplicate.as_slice()).unwrap_err();
+        assert_eq!(err.downcast_ref::<StructureError>(), Some(&StructureError::DuplicateIhdr { index: 1 }));
+
+        let truncated = FixtureSpec::from_str("[corrupt]\ntruncate = 20\n").unwrap().build().unwrap();
+        assert_eq!(truncated.len(), 20);
+        assert!(Png::try_from(truncated.as_slice()).is_err());
+
+        // Not a corruption as far as the spec goes, but strict parsing still rejects it.
+        let trailing = FixtureSpec::from_str("[[chunk]]\ntype = \"ruSt\"\nposition = \"after-iend\"\n").unwrap();
+        assert!(!trailing.is_corrupt());
+        assert!(Png::try_from(trailing.build().unwrap().as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_spec_errors() {
+        assert!(FixtureSpec::from_str("[image]\ncolor_type = 5\n").is_err());
+        assert!(FixtureSpec::from_str("[image]\ncolor_type = 2\nbit_depth = 4\n").is_err());
+        assert_eq!(
+            FixtureSpec::from_str("[image]\n\n[[chunk]]\ntype = \"ru5t\"\n").unwrap_err().to_string(),
+            "line 4: `type` = \"ru5t\": byte 3 ('5') is not an ASCII letter"
+        );
+        assert!(FixtureSpec::from_str("[[chunk]]\ntype = \"ruSt\"\ntext = \"a\"\nsize = 3\n").is_err());
+        assert!(FixtureSpec::from_str("[[chunk]]\ntype = \"ruSt\"\nposition = \"middle\"\n").is_err());
+        assert!(FixtureSpec::from_str("[pixels]\n").is_err());
+        assert!(FixtureSpec::from_str("[corrupt]\nbad_crc = 9\n").unwrap().build().is_err());
+    }
+}
@@ -0,0 +1,100 @@
+// The frame every message this crate writes starts with, so decode can tell its own chunks
+// from ancillary data that only happens to share the type.
+//
+// Layout: magic "pmMG" | version (u8) | flags (u8) | body.
+// The flags say which layers the body carries. Encode applies them in this order and decode
+// undoes them in reverse: compression, encryption, the signature (with any integrity frame
+// around it), then splitting into pieces, each of which gets its own frame.
+// Data without the magic was written by an older version or by someone else; it is only read
+// through the unframed path.
+
+use crate::error::PngMsgError;
+
+const MAGIC: [u8; 4] = *b"pmMG";
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 6;
+
+// zlib-deflated with `zlib::compress`; needs the `compression` feature to read.
+pub const COMPRESSED: u8 = 0x01;
+// An envelope from `encrypt::encrypt_payload`.
+pub const ENCRYPTED: u8 = 0x02;
+// A signature frame from `sign`.
+pub const SIGNED: u8 = 0x04;
+// One piece of a split message, piece header first, see `split`.
+pub const SPLIT: u8 = 0x08;
+// An integrity frame from `integrity`, around the signature if there is one.
+pub const INTEGRITY: u8 = 0x10;
+// Any other bit is from a newer version, whose layers this one cannot undo.
+const KNOWN_FLAGS: u8 = COMPRESSED | ENCRYPTED | SIGNED | SPLIT | INTEGRITY;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub version: u8,
+    pub flags: u8,
+    pub body: &'a [u8],
+}
+
+impl Frame<'_> {
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+pub fn seal(flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(HEADER_LEN + body.len());
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&[VERSION, flags]);
+    data.extend_from_slice(body);
+    data
+}
+
+pub fn is_framed(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+pub fn open(data: &[u8]) -> Result<Frame<'_>, PngMsgError> {
+    if !is_framed(data) {
+        return Err(PngMsgError::NotAPngMsgPayload);
+    }
+    let (version, flags) = match data.get(4..HEADER_LEN) {
+        Some(&[version, flags]) => (version, flags),
+        _ => return Err(PngMsgError::InvalidMessageFrame("header is cut off".to_string())),
+    };
+    if version != VERSION {
+        return Err(PngMsgError::InvalidMessageFrame(format!("unknown version {}", version)));
+    }
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(PngMsgError::InvalidMessageFrame(format!("unknown flags {:#04x}", flags & !KNOWN_FLAGS)));
+    }
+    Ok(Frame { version, flags, body: &data[HEADER_LEN..] })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let sealed = seal(ENCRYPTED | SIGNED, b"body");
+        assert_eq!(&sealed[..HEADER_LEN], b"pmMG\x01\x06");
+        let frame = open(&sealed).unwrap();
+        assert_eq!(frame, Frame { version: VERSION, flags: ENCRYPTED | SIGNED, body: b"body" });
+        assert!(frame.has(SIGNED) && !frame.has(SPLIT));
+        assert_eq!(open(&seal(0, b"")).unwrap().body, b"");
+    }
+
+    #[test]
+    fn test_unframed_and_damaged_data() {
+        assert!(matches!(open(b"hello"), Err(PngMsgError::NotAPngMsgPayload)));
+        assert!(matches!(open(b""), Err(PngMsgError::NotAPngMsgPayload)));
+        assert!(matches!(open(b"pmMG\x01"), Err(PngMsgError::InvalidMessageFrame(_))));
+
+        let mut sealed = seal(0, b"body");
+        sealed[4] = 2;
+        assert_eq!(open(&sealed).unwrap_err().to_string(), "Invalid message frame: unknown version 2");
+        sealed[4] = VERSION;
+        sealed[5] = 0x80 | SPLIT | INTEGRITY;
+        assert_eq!(open(&sealed).unwrap_err().to_string(), "Invalid message frame: unknown flags 0x80");
+    }
+}
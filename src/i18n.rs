@@ -0,0 +1,494 @@
+use std::fmt::Display;
+
+pub const LANG_ENV: &str = "PNG_MSG_LANG";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Msg {
+    MessageEncoded,
+    RandomTypeChosen,
+    ChunkRemoved,
+    ChunksRemoved,
+    ChunksStripped,
+    ChunkRepaired,
+    ChunksRepaired,
+    PipelineApplied,
+    BatchEncoded,
+    BatchRemoved,
+    BatchStripped,
+    MessageIs,
+    MessageWritten,
+    MessageAt,
+    ImageHeader,
+    DecodableChunks,
+    CapacityFileSize,
+    CapacityAncillary,
+    CapacityMaxChunk,
+    CapacityRoom,
+    StatsFileSize,
+    StatsIdat,
+    StatsAncillary,
+    StatsCritical,
+    StatsOverhead,
+    StatsCounts,
+    StatsLargest,
+    StatsCount,
+    StatsBytes,
+    StatsShare,
+    KeysWritten,
+    StructureValid,
+    StructureInvalid,
+    DiffIdentical,
+    NoProvenance,
+    ErrorPrefix,
+    ReadFailed,
+    WriteFailed,
+    InvalidPng,
+    CrcMismatch,
+    ChunkNotFound,
+    NotUtf8,
+    MissingIend,
+    ProvenanceFailed,
+    LimitExceeded,
+    CriticalChunk,
+    EncryptFailed,
+    EncodeFailed,
+    DecryptFailed,
+    LsbFailed,
+    IntegrityFailed,
+    BinaryMessage,
+    StdoutIsTerminal,
+    InvalidInput,
+    InvalidKey,
+    ClockFailed,
+    UnsafeChunkType,
+    StandardChunkType,
+    RandomTypeFailed,
+    NotADirectory,
+    NotForDirectories,
+    BatchFailed,
+    NotAMessage,
+    InvalidFrame,
+    UnknownLang,
+    SizeType,
+    SizeBefore,
+    SizeAfter,
+    SizeDelta,
+    SizeTotal,
+    ScanClean,
+    ScanFound,
+    #[cfg_attr(not(feature = "rules"), allow(dead_code))]
+    InvalidRules,
+}
+
+impl Msg {
+    #[cfg(test)]
+    pub const ALL: [Msg; 73] = [
+        Msg::MessageEncoded,
+        Msg::RandomTypeChosen,
+        Msg::ChunkRemoved,
+        Msg::ChunksRemoved,
+        Msg::ChunksStripped,
+        Msg::ChunkRepaired,
+        Msg::ChunksRepaired,
+        Msg::PipelineApplied,
+        Msg::BatchEncoded,
+        Msg::BatchRemoved,
+        Msg::BatchStripped,
+        Msg::MessageIs,
+        Msg::MessageWritten,
+        Msg::MessageAt,
+        Msg::ImageHeader,
+        Msg::DecodableChunks,
+        Msg::CapacityFileSize,
+        Msg::CapacityAncillary,
+        Msg::CapacityMaxChunk,
+        Msg::CapacityRoom,
+        Msg::StatsFileSize,
+        Msg::StatsIdat,
+        Msg::StatsAncillary,
+        Msg::StatsCritical,
+        Msg::StatsOverhead,
+        Msg::StatsCounts,
+        Msg::StatsLargest,
+        Msg::StatsCount,
+        Msg::StatsBytes,
+        Msg::StatsShare,
+        Msg::KeysWritten,
+        Msg::StructureValid,
+        Msg::StructureInvalid,
+        Msg::DiffIdentical,
+        Msg::NoProvenance,
+        Msg::ErrorPrefix,
+        Msg::ReadFailed,
+        Msg::WriteFailed,
+        Msg::InvalidPng,
+        Msg::CrcMismatch,
+        Msg::ChunkNotFound,
+        Msg::NotUtf8,
+        Msg::MissingIend,
+        Msg::ProvenanceFailed,
+        Msg::LimitExceeded,
+        Msg::CriticalChunk,
+        Msg::EncryptFailed,
+        Msg::EncodeFailed,
+        Msg::DecryptFailed,
+        Msg::LsbFailed,
+        Msg::IntegrityFailed,
+        Msg::BinaryMessage,
+        Msg::StdoutIsTerminal,
+        Msg::InvalidInput,
+        Msg::InvalidKey,
+        Msg::ClockFailed,
+        Msg::UnsafeChunkType,
+        Msg::StandardChunkType,
+        Msg::RandomTypeFailed,
+        Msg::NotADirectory,
+        Msg::NotForDirectories,
+        Msg::BatchFailed,
+        Msg::NotAMessage,
+        Msg::InvalidFrame,
+        Msg::UnknownLang,
+        Msg::SizeType,
+        Msg::SizeBefore,
+        Msg::SizeAfter,
+        Msg::SizeDelta,
+        Msg::SizeTotal,
+        Msg::ScanClean,
+        Msg::ScanFound,
+        Msg::InvalidRules,
+    ];
+
+    // Keys are part of the catalog format; never rename one without updating every catalog.
+    pub fn key(self) -> &'static str {
+        match self {
+            Msg::MessageEncoded => "encode.done",
+            Msg::RandomTypeChosen => "encode.random-type",
+            Msg::ChunkRemoved => "remove.done",
+            Msg::ChunksRemoved => "remove.done-all",
+            Msg::ChunksStripped => "strip.done",
+            Msg::ChunkRepaired => "repair.chunk",
+            Msg::ChunksRepaired => "repair.done",
+            Msg::PipelineApplied => "pipeline.done",
+            Msg::BatchEncoded => "batch.encoded",
+            Msg::BatchRemoved => "batch.removed",
+            Msg::BatchStripped => "batch.stripped",
+            Msg::MessageIs => "decode.message",
+            Msg::MessageWritten => "decode.written",
+            Msg::MessageAt => "decode.message-at",
+            Msg::ImageHeader => "print.image",
+            Msg::DecodableChunks => "print.header",
+            Msg::CapacityFileSize => "capacity.file-size",
+            Msg::CapacityAncillary => "capacity.ancillary",
+            Msg::CapacityMaxChunk => "capacity.max-chunk",
+            Msg::CapacityRoom => "capacity.room",
+            Msg::StatsFileSize => "stats.file-size",
+            Msg::StatsIdat => "stats.idat",
+            Msg::StatsAncillary => "stats.ancillary",
+            Msg::StatsCritical => "stats.critical",
+            Msg::StatsOverhead => "stats.overhead",
+            Msg::StatsCounts => "stats.counts",
+            Msg::StatsLargest => "stats.largest",
+            Msg::StatsCount => "stats.count",
+            Msg::StatsBytes => "stats.bytes",
+            Msg::StatsShare => "stats.share",
+            Msg::KeysWritten => "keygen.done",
+            Msg::StructureValid => "print.valid",
+            Msg::StructureInvalid => "print.invalid",
+            Msg::DiffIdentical => "diff.identical",
+            Msg::NoProvenance => "print.no-provenance",
+            Msg::ErrorPrefix => "error.prefix",
+            Msg::ReadFailed => "error.read",
+            Msg::WriteFailed => "error.write",
+            Msg::InvalidPng => "error.invalid-png",
+            Msg::CrcMismatch => "error.crc",
+            Msg::ChunkNotFound => "error.chunk-not-found",
+            Msg::NotUtf8 => "error.not-utf8",
+            Msg::MissingIend => "error.missing-iend",
+            Msg::ProvenanceFailed => "error.provenance",
+            Msg::LimitExceeded => "error.limit",
+            Msg::CriticalChunk => "error.critical-chunk",
+            Msg::EncryptFailed => "error.encrypt",
+            Msg::EncodeFailed => "error.encode",
+            Msg::DecryptFailed => "error.decrypt",
+            Msg::LsbFailed => "error.lsb",
+            Msg::IntegrityFailed => "error.integrity",
+            Msg::BinaryMessage => "error.binary-message",
+            Msg::StdoutIsTerminal => "error.stdout-terminal",
+            Msg::InvalidInput => "error.invalid-input",
+            Msg::InvalidKey => "error.key",
+            Msg::ClockFailed => "error.clock",
+            Msg::UnsafeChunkType => "error.unsafe-chunk-type",
+            Msg::StandardChunkType => "error.standard-chunk-type",
+            Msg::RandomTypeFailed => "error.random-type",
+            Msg::NotADirectory => "error.not-a-directory",
+            Msg::NotForDirectories => "error.not-for-directories",
+            Msg::BatchFailed => "error.batch",
+            Msg::NotAMessage => "error.not-a-message",
+            Msg::InvalidFrame => "error.frame",
+            Msg::UnknownLang => "warning.unknown-lang",
+            Msg::SizeType => "size.type",
+            Msg::SizeBefore => "size.before",
+            Msg::SizeAfter => "size.after",
+            Msg::SizeDelta => "size.delta",
+            Msg::SizeTotal => "size.total",
+            Msg::ScanClean => "scan.clean",
+            Msg::ScanFound => "error.scan-found",
+            Msg::InvalidRules => "error.invalid-rules",
+        }
+    }
+}
+
+const EN: &[(&str, &str)] = &[
+    ("encode.done", "Message encoded!"),
+    ("encode.random-type", "The message is in chunk type {0}; read it back with: pngme decode {1} {0}"),
+    ("remove.done", "Chunk removed!"),
+    ("remove.done-all", "{0} chunks removed!"),
+    ("strip.done", "{0} chunks stripped!"),
+    ("repair.chunk", "chunk {0} ({1}): CRC {2} -> {3}"),
+    ("repair.done", "{0} chunks repaired!"),
+    ("pipeline.done", "{0} operations applied in one pass!"),
+    ("batch.encoded", "encoded {0} files, {1} failed"),
+    ("batch.removed", "removed the chunk from {0} files, {1} failed"),
+    ("batch.stripped", "stripped {0} files, {1} failed"),
+    ("decode.message", "Message is: {0}"),
+    ("decode.written", "{0} bytes written to {1}"),
+    ("decode.message-at", "chunk {0}: {1}"),
+    ("print.image", "Image: {0}"),
+    ("print.header", "The following chunks can be decoded:"),
+    ("capacity.file-size", "File size: {0} bytes"),
+    ("capacity.ancillary", "Ancillary chunks: {0} ({1} bytes)"),
+    ("capacity.max-chunk", "Largest message per chunk: {0} bytes, {1} per piece when split"),
+    ("capacity.room", "Room for a message under {0} bytes: {1} bytes"),
+    ("stats.file-size", "File size: {0} bytes in {1} chunks"),
+    ("stats.idat", "IDAT data: {0} bytes ({1}%)"),
+    ("stats.ancillary", "Ancillary data: {0} bytes ({1}%)"),
+    ("stats.critical", "Other critical data: {0} bytes ({1}%)"),
+    ("stats.overhead", "Overhead (signature, length, type and CRC fields): {0} bytes ({1}%)"),
+    ("stats.counts", "Chunks: {0} critical, {1} ancillary; {2} safe to copy, {3} not"),
+    ("stats.largest", "Largest chunk: #{0} {1}, {2} bytes"),
+    ("stats.count", "count"),
+    ("stats.bytes", "bytes"),
+    ("stats.share", "share"),
+    ("keygen.done", "Private key written to {0}, public key to {1} (fingerprint {2})"),
+    ("print.valid", "Structure: valid PNG"),
+    ("print.invalid", "Structure: {0} problem(s)"),
+    ("diff.identical", "The files have the same chunks."),
+    ("print.no-provenance", "No provenance recorded."),
+    ("error.prefix", "error"),
+    ("error.read", "could not read {0}: {1}"),
+    ("error.write", "could not write {0}: {1}"),
+    ("error.invalid-png", "{0} is not a valid PNG: {1}"),
+    ("error.crc", "{0}: chunk #{3} ({4}) at offset {5} has a bad CRC (stored {1}, computed {2})"),
+    ("error.chunk-not-found", "no {0} chunk in the file"),
+    ("error.not-utf8", "the {0} chunk does not contain UTF-8 text"),
+    ("error.missing-iend", "the file has no IEND chunk"),
+    ("error.provenance", "could not record provenance: {0}"),
+    ("error.limit", "limit exceeded: {0}"),
+    ("error.critical-chunk", "refusing to remove critical {0} chunks"),
+    ("error.encrypt", "could not encrypt the message: {0}"),
+    ("error.encode", "could not encode the message: {0}"),
+    ("error.decrypt", "could not decrypt the {0} chunk: {1}"),
+    ("error.lsb", "LSB mode failed for {0}: {1}"),
+    ("error.integrity", "the {0} chunk cannot be trusted: {1}"),
+    ("error.binary-message", "the {0} message is binary; save it with --output-file PATH, or pass --raw to print it anyway"),
+    ("error.stdout-terminal", "not writing binary output to a terminal; redirect stdout or pass --force-stdout"),
+    ("error.invalid-input", "cannot read the message: {0}"),
+    ("error.key", "cannot use the key in {0}: {1}"),
+    ("error.clock", "cannot read the system clock: {0}"),
+    ("error.unsafe-chunk-type", "refusing to store a message in critical {0} chunks, which would corrupt the image; use a private type such as {1}, or pass --force"),
+    ("error.standard-chunk-type", "refusing to store a message in standard {0} chunks, which viewers interpret by their specification; use a private type such as {1}, or pass --force"),
+    ("error.random-type", "cannot pick a random chunk type: {0}"),
+    ("error.not-a-directory", "--recursive, --glob and --output-dir need PATH to be a directory, and {0} is not"),
+    ("error.not-for-directories", "{0} cannot be used when PATH is a directory"),
+    ("error.batch", "{0} of {1} files failed"),
+    ("error.not-a-message", "the {0} chunk does not hold a message written by pngme; pass --unframed to read its data as stored"),
+    ("error.frame", "cannot read the message in the {0} chunk: {1}"),
+    ("warning.unknown-lang", "warning: unknown language {0}, falling back to English"),
+    ("size.type", "type"),
+    ("size.before", "before"),
+    ("size.after", "after"),
+    ("size.delta", "delta"),
+    ("size.total", "total: {0} -> {1} bytes ({2}, {3}%)"),
+    ("scan.clean", "Nothing suspicious in {0} files."),
+    ("error.scan-found", "{0} findings in {1} of {2} files"),
+    ("error.invalid-rules", "invalid rules file {0}: {1}"),
+];
+
+const DE: &[(&str, &str)] = &[
+    ("encode.done", "Nachricht eingebettet!"),
+    ("encode.random-type", "Die Nachricht steht im Chunk-Typ {0}; auslesen mit: pngme decode {1} {0}"),
+    ("remove.done", "Chunk entfernt!"),
+    ("remove.done-all", "{0} Chunks entfernt!"),
+    ("strip.done", "{0} Chunks entfernt!"),
+    ("repair.chunk", "Chunk {0} ({1}): Prüfsumme {2} -> {3}"),
+    ("repair.done", "{0} Chunks repariert!"),
+    ("pipeline.done", "{0} Operationen in einem Durchgang angewendet!"),
+    ("batch.encoded", "{0} Dateien eingebettet, {1} fehlgeschlagen"),
+    ("batch.removed", "Chunk aus {0} Dateien entfernt, {1} fehlgeschlagen"),
+    ("batch.stripped", "{0} Dateien bereinigt, {1} fehlgeschlagen"),
+    ("decode.message", "Nachricht: {0}"),
+    ("decode.written", "{0} Bytes nach {1} geschrieben"),
+    ("decode.message-at", "Chunk {0}: {1}"),
+    ("print.image", "Bild: {0}"),
+    ("print.header", "Die folgenden Chunks können dekodiert werden:"),
+    ("capacity.file-size", "Dateigröße: {0} Bytes"),
+    ("capacity.ancillary", "Zusatz-Chunks: {0} ({1} Bytes)"),
+    ("capacity.max-chunk", "Größte Nachricht pro Chunk: {0} Bytes, {1} pro Teil beim Aufteilen"),
+    ("capacity.room", "Platz für eine Nachricht unter {0} Bytes: {1} Bytes"),
+    ("stats.file-size", "Dateigröße: {0} Bytes in {1} Chunks"),
+    ("stats.idat", "IDAT-Daten: {0} Bytes ({1}%)"),
+    ("stats.ancillary", "Zusatzdaten: {0} Bytes ({1}%)"),
+    ("stats.critical", "Andere kritische Daten: {0} Bytes ({1}%)"),
+    ("stats.overhead", "Verwaltungsdaten (Signatur, Längen-, Typ- und Prüfsummenfelder): {0} Bytes ({1}%)"),
+    ("stats.counts", "Chunks: {0} kritisch, {1} zusätzlich; {2} kopiersicher, {3} nicht"),
+    ("stats.largest", "Größter Chunk: #{0} {1}, {2} Bytes"),
+    ("stats.count", "Anzahl"),
+    ("stats.bytes", "Bytes"),
+    ("stats.share", "Anteil"),
+    ("keygen.done", "Privater Schlüssel nach {0}, öffentlicher nach {1} geschrieben (Fingerabdruck {2})"),
+    ("print.valid", "Struktur: gültige PNG-Datei"),
+    ("print.invalid", "Struktur: {0} Problem(e)"),
+    ("diff.identical", "Die Dateien haben dieselben Chunks."),
+    ("print.no-provenance", "Keine Herkunftsdaten vorhanden."),
+    ("error.prefix", "Fehler"),
+    ("error.read", "{0} konnte nicht gelesen werden: {1}"),
+    ("error.write", "{0} konnte nicht geschrieben werden: {1}"),
+    ("error.invalid-png", "{0} ist keine gültige PNG-Datei: {1}"),
+    ("error.crc", "{0}: Chunk #{3} ({4}) bei Offset {5} hat eine falsche Prüfsumme (gespeichert {1}, berechnet {2})"),
+    ("error.chunk-not-found", "kein {0}-Chunk in der Datei"),
+    ("error.not-utf8", "der {0}-Chunk enthält keinen UTF-8-Text"),
+    ("error.missing-iend", "die Datei hat keinen IEND-Chunk"),
+    ("error.provenance", "Herkunftsdaten konnten nicht gespeichert werden: {0}"),
+    ("error.limit", "Grenzwert überschritten: {0}"),
+    ("error.critical-chunk", "kritische {0}-Chunks werden nicht entfernt"),
+    ("error.encrypt", "Nachricht konnte nicht verschlüsselt werden: {0}"),
+    ("error.encode", "Nachricht konnte nicht eingebettet werden: {0}"),
+    ("error.decrypt", "der {0}-Chunk konnte nicht entschlüsselt werden: {1}"),
+    ("error.lsb", "LSB-Modus für {0} fehlgeschlagen: {1}"),
+    ("error.integrity", "dem {0}-Chunk kann nicht vertraut werden: {1}"),
+    ("error.binary-message", "die {0}-Nachricht ist binär; mit --output-file PATH speichern oder mit --raw trotzdem ausgeben"),
+    ("error.stdout-terminal", "Binärdaten werden nicht auf ein Terminal geschrieben; stdout umleiten oder --force-stdout angeben"),
+    ("error.invalid-input", "die Nachricht kann nicht gelesen werden: {0}"),
+    ("error.key", "der Schlüssel in {0} ist nicht verwendbar: {1}"),
+    ("error.clock", "die Systemuhr kann nicht gelesen werden: {0}"),
+    ("error.unsafe-chunk-type", "Nachrichten werden nicht in kritischen {0}-Chunks gespeichert, das würde das Bild beschädigen; einen privaten Typ wie {1} verwenden oder --force angeben"),
+    ("error.standard-chunk-type", "Nachrichten werden nicht in Standard-{0}-Chunks gespeichert, die Betrachter nach der Spezifikation auswerten; einen privaten Typ wie {1} verwenden oder --force angeben"),
+    ("error.random-type", "es kann kein zufälliger Chunk-Typ gewählt werden: {0}"),
+    ("error.not-a-directory", "--recursive, --glob und --output-dir setzen ein Verzeichnis als PATH voraus, {0} ist keins"),
+    ("error.not-for-directories", "{0} kann nicht verwendet werden, wenn PATH ein Verzeichnis ist"),
+    ("error.batch", "{0} von {1} Dateien fehlgeschlagen"),
+    ("error.not-a-message", "der {0}-Chunk enthält keine von pngme geschriebene Nachricht; mit --unframed werden seine Daten so gelesen, wie sie gespeichert sind"),
+    ("error.frame", "die Nachricht im {0}-Chunk kann nicht gelesen werden: {1}"),
+    ("warning.unknown-lang", "Warnung: unbekannte Sprache {0}, es wird Englisch verwendet"),
+    ("size.type", "Typ"),
+    ("size.before", "vorher"),
+    ("size.after", "nachher"),
+    ("size.delta", "Differenz"),
+    ("size.total", "gesamt: {0} -> {1} Bytes ({2}, {3}%)"),
+    ("scan.clean", "Nichts Verdächtiges in {0} Dateien."),
+    ("error.scan-found", "{0} Funde in {1} von {2} Dateien"),
+    ("error.invalid-rules", "ungültige Regeldatei {0}: {1}"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+}
+
+impl Lang {
+    pub fn from_tag(tag: &str) -> Option<Lang> {
+        // Accept full locale strings such as "de_DE.UTF-8" as well as bare tags.
+        let primary = tag.split(['_', '-', '.']).next().unwrap_or("");
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Lang::En),
+            "de" => Some(Lang::De),
+            _ => None,
+        }
+    }
+
+    // The --lang flag wins over PNG_MSG_LANG; anything unrecognised falls back to English.
+    pub fn resolve(flag: Option<&str>) -> Lang {
+        let requested = flag.map(str::to_string).or_else(|| std::env::var(LANG_ENV).ok());
+        match requested {
+            Some(tag) => Lang::from_tag(&tag).unwrap_or_else(|| {
+                eprintln!("{}", Lang::En.render(Msg::UnknownLang, &[&tag]));
+                Lang::En
+            }),
+            None => Lang::En,
+        }
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Lang::En => EN,
+            Lang::De => DE,
+        }
+    }
+
+    fn template(self, msg: Msg) -> &'static str {
+        let lookup = |catalog: &'static [(&'static str, &'static str)]| {
+            catalog.iter().find(|(key, _)| *key == msg.key()).map(|(_, t)| *t)
+        };
+        lookup(self.catalog())
+            .or_else(|| lookup(EN))
+            .unwrap_or_else(|| msg.key())
+    }
+
+    pub fn render(self, msg: Msg, args: &[&dyn Display]) -> String {
+        let mut out = self.template(msg).to_string();
+        for (i, arg) in args.iter().enumerate() {
+            out = out.replace(&format!("{{{}}}", i), &arg.to_string());
+        }
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_key_has_an_english_entry() {
+        for msg in Msg::ALL {
+            assert!(EN.iter().any(|(key, _)| *key == msg.key()), "missing English entry for {}", msg.key());
+        }
+    }
+
+    #[test]
+    fn test_catalogs_have_no_unknown_keys() {
+        for catalog in [EN, DE] {
+            for (key, _) in catalog {
+                assert!(Msg::ALL.iter().any(|m| m.key() == *key), "catalog has unused key {}", key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_fills_placeholders() {
+        let out = Lang::En.render(Msg::CrcMismatch, &[&"dice.png", &"0badc0de", &"abcd1234", &2, &"ruSt", &"0x21"]);
+        assert_eq!(out, "dice.png: chunk #2 (ruSt) at offset 0x21 has a bad CRC (stored 0badc0de, computed abcd1234)");
+    }
+
+    #[test]
+    fn test_german_crc_error() {
+        let out = Lang::De.render(Msg::CrcMismatch, &[&"dice.png", &"0badc0de", &"abcd1234", &2, &"ruSt", &"0x21"]);
+        assert_eq!(out, "dice.png: Chunk #2 (ruSt) bei Offset 0x21 hat eine falsche Prüfsumme (gespeichert 0badc0de, berechnet abcd1234)");
+    }
+
+    #[test]
+    fn test_from_tag() {
+        assert_eq!(Lang::from_tag("de"), Some(Lang::De));
+        assert_eq!(Lang::from_tag("de_DE.UTF-8"), Some(Lang::De));
+        assert_eq!(Lang::from_tag("EN-us"), Some(Lang::En));
+        assert_eq!(Lang::from_tag("fr"), None);
+    }
+
+    #[test]
+    fn test_flag_overrides_env() {
+        assert_eq!(Lang::resolve(Some("de")), Lang::De);
+        assert_eq!(Lang::resolve(Some("en")), Lang::En);
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+
+use crate::chunk::MAX_CHUNK_LENGTH;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMsgError;
+use crate::png::Png;
+
+pub const IHDR_LENGTH: usize = 13;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    pub fn from_byte(byte: u8) -> Result<ColorType, PngMsgError> {
+        match byte {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            other => Err(PngMsgError::InvalidColorType(other)),
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    // Samples per pixel; a palette index counts as one.
+    pub fn channels(self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    // The bit depths the spec allows for this color type (table 11.1).
+    pub fn allowed_bit_depths(self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale+alpha",
+            ColorType::Rgba => "RGBA",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interlace {
+    None = 0,
+    Adam7 = 1,
+}
+
+// The 13 bytes of IHDR. Compression and filter method are always 0, but are kept so a header
+// reads back exactly as stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IhdrData {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlace: Interlace,
+}
+
+impl IhdrData {
+    pub fn parse(data: &[u8]) -> Result<IhdrData, PngMsgError> {
+        let data: &[u8; IHDR_LENGTH] = data.try_into().map_err(|_| PngMsgError::InvalidIhdrLength(data.len()))?;
+        let width = u32::from_be_bytes(data[0..4].try_into().unwrap());
+        let height = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        let [bit_depth, color_type, compression_method, filter_method, interlace] = data[8..].try_into().unwrap();
+
+        // Zero and anything over 2^31 - 1 are both out of range.
+        if !(1..=MAX_CHUNK_LENGTH).contains(&width) || !(1..=MAX_CHUNK_LENGTH).contains(&height) {
+            return Err(PngMsgError::InvalidImageSize { width, height });
+        }
+        let color_type = ColorType::from_byte(color_type)?;
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(PngMsgError::InvalidBitDepth { bit_depth, color_type });
+        }
+        for (field, value) in [("compression", compression_method), ("filter", filter_method)] {
+            if value != 0 {
+                return Err(PngMsgError::UnknownIhdrMethod { field, value });
+            }
+        }
+        let interlace = match interlace {
+            0 => Interlace::None,
+            1 => Interlace::Adam7,
+            value => return Err(PngMsgError::UnknownIhdrMethod { field: "interlace", value }),
+        };
+
+        Ok(IhdrData { width, height, bit_depth, color_type, compression_method, filter_method, interlace })
+    }
+
+    pub fn to_data(self) -> [u8; IHDR_LENGTH] {
+        let mut data = [0; IHDR_LENGTH];
+        data[0..4].copy_from_slice(&self.width.to_be_bytes());
+        data[4..8].copy_from_slice(&self.height.to_be_bytes());
+        data[8..].copy_from_slice(&[
+            self.bit_depth,
+            self.color_type.to_byte(),
+            self.compression_method,
+            self.filter_method,
+            self.interlace as u8,
+        ]);
+        data
+    }
+
+    pub fn bits_per_pixel(&self) -> u32 {
+        self.bit_depth as u32 * self.color_type.channels() as u32
+    }
+}
+
+// "1920x1080, 8-bit RGBA, non-interlaced"
+impl fmt::Display for IhdrData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let interlace = match self.interlace {
+            Interlace::None => "non-interlaced",
+            Interlace::Adam7 => "Adam7-interlaced",
+        };
+        write!(f, "{}x{}, {}-bit {}, {}", self.width, self.height, self.bit_depth, self.color_type, interlace)
+    }
+}
+
+impl Png {
+    // From the first IHDR, wherever it is; `validate` is what checks that it comes first. Not
+    // `header`, which is the signature.
+    pub fn ihdr(&self) -> Result<IhdrData, PngMsgError> {
+        let ihdr = self.chunk_by_type(ChunkType::IHDR)
+            .ok_or_else(|| PngMsgError::ChunkNotFound(ChunkType::IHDR.to_string()))?;
+        IhdrData::parse(ihdr.data())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    // IHDR payloads copied from real files: a 50x50 RGBA screenshot, a 2x2 RGB test image, a
+    // 640x480 8-bit grayscale photo, a 16x16 4-bit palette icon and a 1x1 interlaced 16-bit gray.
+    const RGBA: [u8; 13] = [0, 0, 0, 50, 0, 0, 0, 50, 8, 6, 0, 0, 0];
+    const RGB: [u8; 13] = [0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0];
+    const GRAY: [u8; 13] = [0, 0, 2, 128, 0, 0, 1, 224, 8, 0, 0, 0, 0];
+    const PALETTE: [u8; 13] = [0, 0, 0, 16, 0, 0, 0, 16, 4, 3, 0, 0, 0];
+    const GRAY16_ADAM7: [u8; 13] = [0, 0, 0, 1, 0, 0, 0, 1, 16, 0, 0, 0, 1];
+
+    #[test]
+    fn test_parse_real_headers() {
+        for (data, expected) in [
+            (RGBA, "50x50, 8-bit RGBA, non-interlaced"),
+            (RGB, "2x2, 8-bit RGB, non-interlaced"),
+            (GRAY, "640x480, 8-bit grayscale, non-interlaced"),
+            (PALETTE, "16x16, 4-bit indexed, non-interlaced"),
+            (GRAY16_ADAM7, "1x1, 16-bit grayscale, Adam7-interlaced"),
+        ] {
+            let header = IhdrData::parse(&data).unwrap();
+            assert_eq!(header.to_string(), expected);
+            assert_eq!(header.to_data(), data);
+        }
+        let palette = IhdrData::parse(&PALETTE).unwrap();
+        assert_eq!((palette.color_type, palette.bits_per_pixel()), (ColorType::Indexed, 4));
+        assert_eq!(IhdrData::parse(&RGBA).unwrap().bits_per_pixel(), 32);
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        let with = |at: usize, value: u8| {
+            let mut data = RGB;
+            data[at] = value;
+            IhdrData::parse(&data)
+        };
+        assert!(matches!(IhdrData::parse(&RGB[..12]), Err(PngMsgError::InvalidIhdrLength(12))));
+        assert!(matches!(IhdrData::parse(&[0; 14]), Err(PngMsgError::InvalidIhdrLength(14))));
+        assert!(matches!(with(3, 0), Err(PngMsgError::InvalidImageSize { width: 0, height: 2 })));
+        assert!(matches!(with(4, 0x80), Err(PngMsgError::InvalidImageSize { height: 0x8000_0002, .. })));
+        assert!(matches!(with(9, 5), Err(PngMsgError::InvalidColorType(5))));
+        assert!(matches!(with(8, 4), Err(PngMsgError::InvalidBitDepth { bit_depth: 4, color_type: ColorType::Rgb })));
+        assert!(matches!(with(10, 1), Err(PngMsgError::UnknownIhdrMethod { field: "compression", value: 1 })));
+        assert!(matches!(with(11, 1), Err(PngMsgError::UnknownIhdrMethod { field: "filter", value: 1 })));
+        assert!(matches!(with(12, 2), Err(PngMsgError::UnknownIhdrMethod { field: "interlace", value: 2 })));
+        assert_eq!(with(8, 16).unwrap().bit_depth, 16);
+        assert_eq!(with(8, 4).unwrap_err().to_string(), "Bit depth 4 is not allowed for RGB images");
+    }
+
+    #[test]
+    fn test_png_header() {
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, PALETTE.to_vec()),
+            Chunk::new(ChunkType::IDAT, Vec::new()),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap();
+        assert_eq!(png.ihdr().unwrap().width, 16);
+
+        let no_ihdr = Png::from_chunks_unchecked(vec![Chunk::new(ChunkType::IEND, Vec::new())]);
+        assert!(matches!(no_ihdr.ihdr(), Err(PngMsgError::ChunkNotFound(t)) if t == "IHDR"));
+    }
+}
@@ -0,0 +1,207 @@
+// An optional frame around a message payload so decode can tell whether it was cut short or
+// changed. The chunk CRC only catches accidents, since anyone can recompute it.
+//
+// Layout: magic "pmIG" | version (u8) | mode (u8) | payload | tag.
+// The tag covers everything in front of it, header included, so the mode cannot be swapped.
+// Whether a payload has one is up to the message frame's `framing::INTEGRITY` flag, not the
+// magic, which an ordinary message is free to start with.
+
+use crate::error::PngMsgError;
+use crate::sha256::{hmac, Sha256};
+
+const MAGIC: [u8; 4] = *b"pmIG";
+const VERSION: u8 = 1;
+pub const FRAME_HEADER_LEN: usize = 6;
+const TAG_LEN: usize = 32;
+
+const MODE_PLAIN: u8 = 0;
+const MODE_DIGEST: u8 = 1;
+const MODE_HMAC: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integrity {
+    // Framed, but with no tag.
+    Plain,
+    // SHA-256 of the frame; catches truncation and accidents, not a deliberate edit.
+    Digest,
+    // HMAC-SHA256 keyed by a passphrase, which an editor without the key cannot forge.
+    Hmac(String),
+}
+
+impl Integrity {
+    fn mode(&self) -> u8 {
+        match self {
+            Integrity::Plain => MODE_PLAIN,
+            Integrity::Digest => MODE_DIGEST,
+            Integrity::Hmac(_) => MODE_HMAC,
+        }
+    }
+
+    fn tag(&self, framed: &[u8]) -> Option<[u8; TAG_LEN]> {
+        match self {
+            Integrity::Plain => None,
+            Integrity::Digest => Some(Sha256::digest(framed)),
+            Integrity::Hmac(key) => Some(hmac(key.as_bytes(), framed)),
+        }
+    }
+}
+
+pub fn seal(payload: &[u8], integrity: &Integrity) -> Vec<u8> {
+    let mut data = Vec::with_capacity(FRAME_HEADER_LEN + payload.len() + TAG_LEN);
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&[VERSION, integrity.mode()]);
+    data.extend_from_slice(payload);
+    if let Some(tag) = integrity.tag(&data) {
+        data.extend_from_slice(&tag);
+    }
+    data
+}
+
+fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Plain,
+    Digest,
+    Hmac,
+}
+
+impl Mode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Plain => "plain",
+            Mode::Digest => "digest",
+            Mode::Hmac => "hmac",
+        }
+    }
+}
+
+// What the frame says it carries, unchecked; None without a frame or for an unknown mode.
+pub fn sealed_mode(data: &[u8]) -> Option<Mode> {
+    if !is_sealed(data) {
+        return None;
+    }
+    match *data.get(5)? {
+        MODE_PLAIN => Some(Mode::Plain),
+        MODE_DIGEST => Some(Mode::Digest),
+        MODE_HMAC => Some(Mode::Hmac),
+        _ => None,
+    }
+}
+
+// For data the flags say is sealed, so a missing magic is a damaged frame. With a key, only an
+// HMAC frame is accepted: otherwise stripping the tag and relabelling the frame as plain would
+// get an edited message past the check.
+pub fn open<'a>(data: &'a [u8], key: Option<&str>) -> Result<&'a [u8], PngMsgError> {
+    if !is_sealed(data) {
+        return Err(PngMsgError::InvalidIntegrityFrame("no integrity frame".to_string()));
+    }
+    let (version, mode) = match data.get(4..FRAME_HEADER_LEN) {
+        Some(&[version, mode]) => (version, mode),
+        _ => return Err(PngMsgError::InvalidIntegrityFrame("header is cut off".to_string())),
+    };
+    if version != VERSION {
+        return Err(PngMsgError::InvalidIntegrityFrame(format!("unknown version {}", version)));
+    }
+    let integrity = match (mode, key) {
+        (MODE_HMAC, Some(key)) => Integrity::Hmac(key.to_string()),
+        (MODE_HMAC, None) => return Err(PngMsgError::IntegrityKeyRequired),
+        (_, Some(_)) => return Err(PngMsgError::IntegrityCheckFailed),
+        (MODE_PLAIN, None) => Integrity::Plain,
+        (MODE_DIGEST, None) => Integrity::Digest,
+        (mode, None) => return Err(PngMsgError::InvalidIntegrityFrame(format!("unknown mode {}", mode))),
+    };
+
+    if integrity == Integrity::Plain {
+        return Ok(&data[FRAME_HEADER_LEN..]);
+    }
+    let split = data.len().checked_sub(TAG_LEN).filter(|&split| split >= FRAME_HEADER_LEN)
+        .ok_or(PngMsgError::IntegrityCheckFailed)?;
+    let (framed, expected) = data.split_at(split);
+    let actual = integrity.tag(framed).expect("only Plain has no tag");
+    // Compared without an early exit so the time taken says nothing about where they differ.
+    if actual.iter().zip(expected).fold(0, |acc, (a, b)| acc | (a ^ b)) != 0 {
+        return Err(PngMsgError::IntegrityCheckFailed);
+    }
+    Ok(&framed[FRAME_HEADER_LEN..])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modes() -> [Integrity; 3] {
+        [Integrity::Plain, Integrity::Digest, Integrity::Hmac("key".to_string())]
+    }
+
+    fn key(integrity: &Integrity) -> Option<&str> {
+        match integrity {
+            Integrity::Hmac(key) => Some(key),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for integrity in modes() {
+            for payload in [&b""[..], b"hello", &[0; 1000]] {
+                let sealed = seal(payload, &integrity);
+                assert!(is_sealed(&sealed));
+                let mode = match integrity {
+                    Integrity::Plain => Mode::Plain,
+                    Integrity::Digest => Mode::Digest,
+                    Integrity::Hmac(_) => Mode::Hmac,
+                };
+                assert_eq!(sealed_mode(&sealed), Some(mode));
+                assert_eq!(open(&sealed, key(&integrity)).unwrap(), payload, "{:?}", integrity);
+            }
+        }
+    }
+
+    #[test]
+    fn test_data_without_a_frame() {
+        assert_eq!(open(b"an old message", None).unwrap_err().to_string(), "Invalid integrity frame: no integrity frame");
+        assert_eq!(sealed_mode(b"an old message"), None);
+        assert!(matches!(open(b"an old message", Some("key")), Err(PngMsgError::InvalidIntegrityFrame(_))));
+    }
+
+    #[test]
+    fn test_flipped_payload_or_tag_byte() {
+        for integrity in [Integrity::Digest, Integrity::Hmac("key".to_string())] {
+            let sealed = seal(b"tamper with me", &integrity);
+            for at in [FRAME_HEADER_LEN + 3, sealed.len() - 1] {
+                let mut damaged = sealed.clone();
+                damaged[at] ^= 0x01;
+                assert!(matches!(open(&damaged, key(&integrity)), Err(PngMsgError::IntegrityCheckFailed)), "byte {}", at);
+            }
+            let truncated = &sealed[..sealed.len() - 5];
+            assert!(matches!(open(truncated, key(&integrity)), Err(PngMsgError::IntegrityCheckFailed)));
+        }
+    }
+
+    #[test]
+    fn test_keys() {
+        let sealed = seal(b"keyed", &Integrity::Hmac("right".to_string()));
+        assert!(matches!(open(&sealed, Some("wrong")), Err(PngMsgError::IntegrityCheckFailed)));
+        assert!(matches!(open(&sealed, None), Err(PngMsgError::IntegrityKeyRequired)));
+
+        // A forger without the key can only produce a digest or plain frame, which a keyed decode refuses.
+        for integrity in [Integrity::Plain, Integrity::Digest] {
+            assert!(matches!(open(&seal(b"forged", &integrity), Some("right")), Err(PngMsgError::IntegrityCheckFailed)));
+        }
+    }
+
+    #[test]
+    fn test_bad_frames() {
+        let mut sealed = seal(b"x", &Integrity::Digest);
+        sealed[4] = 2;
+        assert_eq!(open(&sealed, None).unwrap_err().to_string(), "Invalid integrity frame: unknown version 2");
+        sealed[4] = VERSION;
+        sealed[5] = 9;
+        assert!(matches!(open(&sealed, None), Err(PngMsgError::InvalidIntegrityFrame(_))));
+        assert!(matches!(open(b"pmIG\x01", None), Err(PngMsgError::InvalidIntegrityFrame(_))));
+    }
+}
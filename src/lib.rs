@@ -0,0 +1,50 @@
+pub mod ancillary;
+pub mod atomic;
+pub mod batch;
+pub mod capacity;
+pub mod chunk;
+pub mod chunk_builder;
+pub mod chunk_type;
+pub mod crc32;
+pub mod decode;
+pub mod diff;
+pub mod encode;
+#[cfg(feature = "encrypt")]
+pub mod encrypt;
+pub mod entropy;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod finding;
+pub mod framing;
+#[cfg(any(test, feature = "fixture"))]
+pub mod fixture;
+pub mod ihdr;
+pub mod integrity;
+pub mod limits;
+pub mod pipeline;
+pub mod png;
+pub mod provenance;
+mod random;
+pub mod reader;
+pub mod report;
+#[cfg(feature = "rules")]
+pub mod rules;
+pub mod sha256;
+pub mod sign;
+#[cfg(any(test, feature = "rules", feature = "fixture"))]
+mod spec;
+pub mod split;
+pub mod stats;
+#[cfg(feature = "stego")]
+pub mod stego;
+pub mod summary;
+pub mod suspicious;
+pub mod text_codec;
+pub mod time;
+pub mod writer;
+#[cfg(feature = "compression")]
+pub mod zlib;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
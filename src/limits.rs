@@ -0,0 +1,142 @@
+use std::fmt;
+
+// Every size and count guard in the crate reads its bound from here. `None` means unbounded.
+//
+// Defaults allow anything a sane PNG tool would write: 256 MiB inputs, 64 MiB chunks, 16k chunks
+// and 16 MiB messages. The chunk bounds are checked before a chunk is read, so a declared length
+// of 2 GiB or a file of millions of empty chunks fails early instead of exhausting memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_input_bytes: Option<u64>,
+    pub max_chunk_bytes: Option<u32>,
+    pub max_chunks: Option<usize>,
+    pub max_message_bytes: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_input_bytes: Some(256 * 1024 * 1024),
+            max_chunk_bytes: Some(64 * 1024 * 1024),
+            max_chunks: Some(16_384),
+            max_message_bytes: Some(16 * 1024 * 1024),
+        }
+    }
+}
+
+impl Limits {
+    // For untrusted input, e.g. uploads: 32 MiB files, 8 MiB chunks, 4k chunks, 1 MiB messages.
+    pub fn strict() -> Limits {
+        Limits {
+            max_input_bytes: Some(32 * 1024 * 1024),
+            max_chunk_bytes: Some(8 * 1024 * 1024),
+            max_chunks: Some(4_096),
+            max_message_bytes: Some(1024 * 1024),
+        }
+    }
+
+    // For trusted archival work where any valid file must go through, chunks up to the spec's
+    // 2^31 - 1 bytes included.
+    pub fn unlimited() -> Limits {
+        Limits { max_input_bytes: None, max_chunk_bytes: None, max_chunks: None, max_message_bytes: None }
+    }
+
+    pub fn check_input(&self, bytes: u64) -> Result<(), LimitExceeded> {
+        check("max_input_bytes", self.max_input_bytes, bytes)
+    }
+
+    pub fn check_chunk(&self, bytes: u32) -> Result<(), LimitExceeded> {
+        check("max_chunk_bytes", self.max_chunk_bytes.map(u64::from), bytes as u64)
+    }
+
+    pub fn check_chunk_count(&self, count: usize) -> Result<(), LimitExceeded> {
+        check("max_chunks", self.max_chunks.map(|n| n as u64), count as u64)
+    }
+
+    pub fn check_message(&self, bytes: usize) -> Result<(), LimitExceeded> {
+        check("max_message_bytes", self.max_message_bytes.map(|n| n as u64), bytes as u64)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub limit: &'static str,
+    pub max: u64,
+    pub actual: u64,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is over the {} limit of {}", self.actual, self.limit, self.max)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+fn check(limit: &'static str, max: Option<u64>, actual: u64) -> Result<(), LimitExceeded> {
+    match max {
+        Some(max) if actual > max => Err(LimitExceeded { limit, max, actual }),
+        _ => Ok(()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::Result;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::encode::{encode_message, EncodeOptions};
+    use crate::png::{ParseOptions, Png};
+
+    fn sample() -> Png {
+        let chunk = |t: &str, len: usize| Chunk::new(ChunkType::from_str(t).unwrap(), vec![0; len]);
+        Png::from_chunks(vec![chunk("IHDR", 13), chunk("ruSt", 100), chunk("IEND", 0)]).unwrap()
+    }
+
+    type Guarded = fn(Limits) -> Result<()>;
+
+    fn parse(limits: Limits) -> Result<()> {
+        Png::parse_with(&sample().as_bytes(), &ParseOptions { limits, ..Default::default() }).map(|_| ())
+    }
+
+    fn encode(limits: Limits) -> Result<()> {
+        let mut opts = EncodeOptions::new(ChunkType::from_str("ruSt").unwrap());
+        opts.limits = limits;
+        encode_message(&mut sample(), &[0; 10], &opts)
+    }
+
+    #[test]
+    fn test_every_limit_is_enforced() {
+        // Fails to compile when a field is added, so the table below has to grow with it.
+        let Limits { max_input_bytes: _, max_chunk_bytes: _, max_chunks: _, max_message_bytes: _ } = Limits::default();
+
+        let none = Limits::unlimited();
+        let cases: [(&str, Limits, Guarded); 4] = [
+            ("max_input_bytes", Limits { max_input_bytes: Some(100), ..none }, parse),
+            ("max_chunk_bytes", Limits { max_chunk_bytes: Some(50), ..none }, parse),
+            ("max_chunks", Limits { max_chunks: Some(2), ..none }, parse),
+            ("max_message_bytes", Limits { max_message_bytes: Some(5), ..none }, encode),
+        ];
+        for (name, limits, run) in cases {
+            assert!(run(none).is_ok(), "{} fails even without limits", name);
+            let err = run(limits).unwrap_err();
+            assert_eq!(err.downcast_ref::<LimitExceeded>().map(|e| e.limit), Some(name));
+        }
+    }
+
+    #[test]
+    fn test_presets() {
+        let (strict, default) = (Limits::strict(), Limits::default());
+        assert!(strict.max_input_bytes < default.max_input_bytes);
+        assert!(strict.max_chunk_bytes < default.max_chunk_bytes);
+        assert!(strict.max_chunks < default.max_chunks);
+        assert!(strict.max_message_bytes < default.max_message_bytes);
+        assert_eq!(default.max_chunk_bytes, Some(64 << 20));
+        assert_eq!(Limits::unlimited(), Limits {
+            max_input_bytes: None, max_chunk_bytes: None, max_chunks: None, max_message_bytes: None,
+        });
+    }
+}
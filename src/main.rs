@@ -1,34 +1,173 @@
-use args::Args;
-use clap::Parser;
-use crate::args::Commands::{Decode, Encode, Print, Remove};
-use crate::commands::{decode, encode, print, remove};
+use std::path::Path;
+use args::{Args, Commands, STDIO};
+use crate::args::Commands::{Capacity, Decode, Diff, Encode, Keygen, Pipeline, Print, Remove, Repair, Scan, Stats, Strip};
+use crate::commands::{backup, capacity, decode, decode_all, decode_lsb, diff, encode, encode_dir, encode_lsb, encode_payload, keygen, load_signing_key, load_verifying_key, pipeline, pipeline_of, print, random_chunk_type, print_json, read_secret, refuse_for_directory, remove, remove_dir, repair, scan, stats, strip, strip_dir, BatchTarget, ChangeFlags, CliError, CliResult, DecodeFlags, EncodeFlags};
+use crate::i18n::Lang;
+use pngme::encode::EncodeOptions;
+use pngme::integrity::Integrity;
+use pngme::limits::Limits;
+use pngme::time::Timestamp;
 
 mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
+mod i18n;
 
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+fn main() {
+    let args = Args::parse_resolved();
+    let lang = Lang::resolve(args.lang.as_deref());
+    let limits = args.limits();
+    let result = prepare_output(&args).and_then(|_| run(&args.command, limits, args.backup, lang));
+    if let Err(e) = result {
+        eprintln!("{}", e.render(lang));
+        std::process::exit(1);
+    }
+}
+
+// Runs before the command, so a refused or failed backup leaves every file as it was. Files in a
+// directory PATH are backed up one by one as they are rewritten.
+fn prepare_output(args: &Args) -> CliResult<()> {
+    if args.writes_to_terminal() {
+        return Err(CliError::StdoutIsTerminal);
+    }
+    match args.command.output_path() {
+        Some(path) if args.backup && path != STDIO && !Path::new(path).is_dir() => backup(path),
+        _ => Ok(()),
+    }
+}
 
-fn main() -> Result<()> {
-    {
-        let args = Args::parse();
-        match &args.command {
-            Encode {path, chunk_type, message} => {
-                encode(path, chunk_type, message)?;
-            },
-            Decode {path, chunk_type} => {
-                decode(path, chunk_type)?;
-            },
-            Remove {path, chunk_type} => {
-                remove(path, chunk_type)?;
-            },
-            Print {path} => {
-                print(path);
+fn run(command: &Commands, limits: Limits, backup: bool, lang: Lang) -> CliResult<()> {
+    match command {
+        Encode {path, message, input_file, input_format, output, password, password_file, batch, lsb: true, ..} => {
+            if BatchTarget::of(path, batch, backup, limits)?.is_some() {
+                return Err(CliError::NotForDirectories("--lsb"));
+            }
+            let password = read_secret(password, password_file)?;
+            encode_payload(message, input_file, output, *input_format).and_then(|(payload, output)| {
+                encode_lsb(path, &payload, password.as_deref(), output, lang)
+            })
+        },
+        Encode {path, chunk_type, message, input_file, input_format, output, record_provenance, keyword, password, password_file, digest, hmac_key, hmac_key_file, sign_key, timestamp, split_size, manifest, report_size, force, random_type, batch, lsb: false, ..} => {
+            let target = BatchTarget::of(path, batch, backup, limits)?;
+            if target.is_some() {
+                refuse_for_directory(&[
+                    ("--manifest", manifest.is_some()),
+                    ("--report-size", report_size.is_some()),
+                    ("--random-type", random_type.is_some()),
+                ])?;
+            }
+            let opts = EncodeOptions {
+                chunk_type: match chunk_type {
+                    Some(chunk_type) => *chunk_type,
+                    None => random_chunk_type(random_type.as_deref().unwrap_or_default())?,
+                },
+                record_provenance: *record_provenance,
+                keyword: keyword.clone(),
+                password: read_secret(password, password_file)?,
+                integrity: match (digest, read_secret(hmac_key, hmac_key_file)?) {
+                    (_, Some(key)) => Some(Integrity::Hmac(key)),
+                    (true, None) => Some(Integrity::Digest),
+                    (false, None) => None,
+                },
+                sign_key: sign_key.as_deref().map(load_signing_key).transpose()?,
+                timestamp: timestamp.then(Timestamp::now).transpose().map_err(|e| CliError::Clock(e.to_string()))?,
+                max_chunk_size: *split_size,
+                limits,
+                allow_unsafe: *force,
+            };
+            encode_payload(message, input_file, output, *input_format).and_then(|(payload, output)| {
+                if let Some(target) = &target {
+                    refuse_for_directory(&[("OUTPUT", output.is_some())])?;
+                    return encode_dir(target, &payload, &opts, *force, lang);
+                }
+                let flags = EncodeFlags {
+                    output,
+                    manifest: manifest.as_deref(),
+                    report_size: *report_size,
+                    force: *force,
+                    random_type: random_type.is_some(),
+                };
+                encode(path, &payload, &opts, &flags, lang)
+            })
+        },
+        Decode {path, chunk_type, json, all, password, password_file, hmac_key, hmac_key_file, verify_key, output_file, raw, format, lsb, unframed} => {
+            let verify_key = verify_key.as_deref().map(load_verifying_key).transpose()?;
+            let password = read_secret(password, password_file)?;
+            let hmac_key = read_secret(hmac_key, hmac_key_file)?;
+            let flags = DecodeFlags {
+                password: password.as_deref(),
+                hmac_key: hmac_key.as_deref(),
+                verify_key: verify_key.as_ref(),
+                output_file: output_file.as_deref(),
+                raw: *raw,
+                format: *format,
+                unframed: *unframed,
+            };
+            if *lsb {
+                decode_lsb(path, &flags, lang)
+            } else if *all {
+                decode_all(path, chunk_type, &flags, limits, lang)
+            } else {
+                decode(path, chunk_type, *json, &flags, limits, lang)
+            }
+        },
+        Remove {path, chunk_type, all, record_provenance, report_size, batch} => {
+            match BatchTarget::of(path, batch, backup, limits)? {
+                Some(target) => {
+                    refuse_for_directory(&[("--report-size", report_size.is_some())])?;
+                    remove_dir(&target, chunk_type, *all, *record_provenance, lang)
+                },
+                None => remove(path, chunk_type, *all, *record_provenance, *report_size, limits, lang),
+            }
+        },
+        Strip {path, output, keep, strip_trns, record_provenance, report_size, batch} => {
+            match BatchTarget::of(path, batch, backup, limits)? {
+                Some(target) => {
+                    refuse_for_directory(&[("OUTPUT", output.is_some()), ("--report-size", report_size.is_some())])?;
+                    strip_dir(&target, keep, *strip_trns, *record_provenance, lang)
+                },
+                None => {
+                    let flags = ChangeFlags { record_provenance: *record_provenance, report_size: *report_size };
+                    strip(path, output.as_deref(), keep, *strip_trns, flags, limits, lang)
+                },
+            }
+        },
+        Repair {path, output, record_provenance, report_size} => {
+            let flags = ChangeFlags { record_provenance: *record_provenance, report_size: *report_size };
+            repair(path, output.as_deref(), flags, limits, lang)
+        },
+        Pipeline {path, output, ops, chunk_type, message, keep, strip_trns, report_size} => {
+            let encode = chunk_type.zip(message.as_deref()).map(|(chunk_type, message)| {
+                (EncodeOptions { limits, ..EncodeOptions::new(chunk_type) }, message.as_bytes())
+            });
+            let steps = pipeline_of(ops, encode, keep, *strip_trns);
+            pipeline(path, output.as_deref(), &steps, ops.len(), *report_size, limits, lang)
+        },
+        Capacity {path, max_size} => {
+            capacity(path, *max_size, limits, lang)
+        },
+        Keygen {private_key, public_key} => {
+            keygen(private_key, public_key, lang)
+        },
+        Print {path, provenance, hex, limit, json} => {
+            if *json {
+                print_json(path, limits)
+            } else {
+                print(path, *provenance, hex.then_some(*limit), limits, lang)
             }
-        }
+        },
+        Stats {path, json} => {
+            stats(path, *json, limits, lang)
+        },
+        Diff {path, other, json, color} => {
+            diff(path, other, *json, color.enabled(), limits, lang)
+        },
+        #[cfg(feature = "rules")]
+        Scan {path, recursive, glob, rules} => {
+            scan(path, *recursive, glob.as_deref(), rules.as_deref(), limits, lang)
+        },
+        #[cfg(not(feature = "rules"))]
+        Scan {path, recursive, glob} => {
+            scan(path, *recursive, glob.as_deref(), None, limits, lang)
+        },
     }
-    Ok(())
 }
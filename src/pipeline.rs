@@ -0,0 +1,169 @@
+use crate::Result;
+use crate::chunk_type::ChunkType;
+use crate::encode::{encode_message, EncodeOptions};
+use crate::png::{ParseOptions, Png};
+use crate::report::SizeReport;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    Remove(ChunkType),
+    Strip { keep: Vec<ChunkType> },
+    Encode { opts: Box<EncodeOptions>, message: Vec<u8> },
+    Repair,
+}
+
+// Parses once, applies every operation in order to the same in-memory `Png`, then checks the
+// structure and serializes once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pipeline {
+    ops: Vec<Op>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineOutput {
+    pub bytes: Vec<u8>,
+    pub size: SizeReport,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    pub fn remove(mut self, chunk_type: ChunkType) -> Pipeline {
+        self.ops.push(Op::Remove(chunk_type));
+        self
+    }
+
+    // As `Png::strip_ancillary`: `keep` is all that is kept, so add tRNS and the like to it.
+    pub fn strip(mut self, keep: &[ChunkType]) -> Pipeline {
+        self.ops.push(Op::Strip { keep: keep.to_vec() });
+        self
+    }
+
+    pub fn encode(mut self, opts: EncodeOptions, message: &[u8]) -> Pipeline {
+        self.ops.push(Op::Encode { opts: Box::new(opts), message: message.to_vec() });
+        self
+    }
+
+    pub fn repair(mut self) -> Pipeline {
+        self.ops.push(Op::Repair);
+        self
+    }
+
+    pub fn apply(&self, png: &mut Png) -> Result<()> {
+        for op in &self.ops {
+            match op {
+                Op::Remove(chunk_type) => {
+                    png.remove_chunk(&chunk_type.to_string())?;
+                }
+                Op::Strip { keep } => {
+                    png.strip_ancillary(keep);
+                }
+                Op::Encode { opts, message } => encode_message(png, message, opts)?,
+                Op::Repair => {
+                    png.repair();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // How `run` parses its input: with a repair to come, chunks with a wrong CRC are kept.
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions { keep_bad_crc: self.ops.contains(&Op::Repair), ..Default::default() }
+    }
+
+    pub fn run(&self, input: &[u8]) -> Result<PipelineOutput> {
+        let (before, _) = Png::parse_with(input, &self.parse_options())?;
+        let mut png = before.clone();
+        self.apply(&mut png)?;
+        let png = Png::from_chunks(png.chunks().to_vec())?;
+
+        Ok(PipelineOutput { bytes: png.as_bytes(), size: SizeReport::diff(&before, &png) })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+
+    fn chunk_type(s: &str) -> ChunkType {
+        ChunkType::from_str(s).unwrap()
+    }
+
+    fn carrier() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::new(chunk_type("IHDR"), vec![0; 13]),
+            Chunk::new(chunk_type("tEXt"), b"Comment\0old".to_vec()),
+            Chunk::new(chunk_type("IDAT"), vec![1, 2, 3]),
+            Chunk::new(chunk_type("IEND"), Vec::new()),
+        ]).unwrap().as_bytes()
+    }
+
+    #[test]
+    fn test_matches_sequential_operations() {
+        let mut opts = EncodeOptions::new(chunk_type("ruSt"));
+        opts.record_provenance = true;
+        let output = Pipeline::new()
+            .remove(chunk_type("tEXt"))
+            .encode(opts.clone(), b"hidden")
+            .run(&carrier())
+            .unwrap();
+
+        let mut png = Png::try_from(carrier().as_slice()).unwrap();
+        png.remove_chunk("tEXt").unwrap();
+        let removed = png.as_bytes();
+        let mut png = Png::try_from(removed.as_slice()).unwrap();
+        encode_message(&mut png, b"hidden", &opts).unwrap();
+
+        assert_eq!(output.bytes, png.as_bytes());
+        assert_eq!(output.size.after.get(), output.bytes.len() as u64);
+        assert_eq!(output.size.changed().map(|c| c.chunk_type.as_str()).collect::<Vec<_>>(), ["prVn", "ruSt", "tEXt"]);
+    }
+
+    #[test]
+    fn test_failing_operation_fails_the_run() {
+        let result = Pipeline::new()
+            .remove(chunk_type("zTXt"))
+            .encode(EncodeOptions::new(chunk_type("ruSt")), b"never")
+            .run(&carrier());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_strip_encode_repair_matches_sequential_operations() {
+        let mut input = carrier();
+        // Breaks the CRC of IDAT, the chunk before IEND.
+        let idat_crc = input.len() - 12 - 4;
+        input[idat_crc] ^= 0xff;
+        let opts = EncodeOptions::new(chunk_type("ruSt"));
+        let output = Pipeline::new()
+            .strip(&[])
+            .encode(opts.clone(), b"hidden")
+            .repair()
+            .run(&input)
+            .unwrap();
+
+        let options = ParseOptions { keep_bad_crc: true, ..Default::default() };
+        let (mut png, _) = Png::parse_with(&input, &options).unwrap();
+        png.strip_ancillary(&[]);
+        encode_message(&mut png, b"hidden", &opts).unwrap();
+        assert_eq!(png.repair(), 1);
+
+        assert_eq!(output.bytes, png.as_bytes());
+        assert!(Png::try_from(output.bytes.as_slice()).is_ok());
+        assert_eq!(output.size.changed().map(|c| c.chunk_type.as_str()).collect::<Vec<_>>(), ["ruSt", "tEXt"]);
+        assert!(Pipeline::new().strip(&[]).run(&input).is_err());
+    }
+
+    #[test]
+    fn test_empty_pipeline_round_trips() {
+        let output = Pipeline::new().run(&carrier()).unwrap();
+        assert_eq!(output.bytes, carrier());
+        assert_eq!(output.size.delta(), 0);
+    }
+}
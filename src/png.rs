@@ -1,131 +1,782 @@
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 use std::fmt;
-use std::path::Path;
-use std::io::{Read, BufReader};
+use std::path::{Path, PathBuf};
+use std::io::{Read, BufReader, Write};
 use std::fs::File;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 use crate::{Error, Result};
-use crate::chunk::Chunk;
-use crate::chunk_type::ChunkType;
+use crate::atomic::replace_file;
+use crate::chunk::{Chunk, ChunkLength, ChunkMut, ChunkPosition, SerializedSize};
+use crate::chunk_type::{ChunkCategory, ChunkType};
+use crate::error::PngMsgError;
+use crate::limits::Limits;
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    Length { index: usize, declared: ChunkLength, actual: usize },
+    Crc { index: usize, stored: u32, computed: u32 },
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inconsistency::Length { index, declared, actual } =>
+                write!(f, "chunk {}: length field is {} but data is {} bytes", index, declared, actual),
+            Inconsistency::Crc { index, stored, computed } =>
+                write!(f, "chunk {}: stored CRC {:08x} but data hashes to {:08x}", index, stored, computed),
+        }
+    }
+}
+
+// Strict parsing rejects these; lenient parsing keeps the chunks and reports them as warnings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StructureError {
+    DuplicateIhdr { index: usize },
+    ChunkAfterIend { index: usize, chunk_type: String },
+}
+
+impl fmt::Display for StructureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StructureError::DuplicateIhdr { index } =>
+                write!(f, "chunk {}: second IHDR chunk", index),
+            StructureError::ChunkAfterIend { index, chunk_type } =>
+                write!(f, "chunk {}: {} chunk after IEND", index, chunk_type),
+        }
+    }
+}
+
+impl std::error::Error for StructureError {}
+
+// What `Png::validate` finds wrong with the chunk order; `index` is the offending chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    MissingIhdr,
+    IhdrNotFirst { index: usize },
+    DuplicateIhdr { index: usize },
+    MissingIend,
+    DuplicateIend { index: usize },
+    ChunkAfterIend { index: usize, chunk_type: String },
+    MissingIdat,
+    IdatNotConsecutive { index: usize },
+    PlteAfterIdat { index: usize },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::MissingIhdr => write!(f, "no IHDR chunk"),
+            ValidationError::IhdrNotFirst { index } => write!(f, "chunk {}: IHDR is not the first chunk", index),
+            ValidationError::DuplicateIhdr { index } => write!(f, "chunk {}: second IHDR chunk", index),
+            ValidationError::MissingIend => write!(f, "no IEND chunk"),
+            ValidationError::DuplicateIend { index } => write!(f, "chunk {}: second IEND chunk", index),
+            ValidationError::ChunkAfterIend { index, chunk_type } =>
+                write!(f, "chunk {}: {} chunk after IEND", index, chunk_type),
+            ValidationError::MissingIdat => write!(f, "no IDAT chunk"),
+            ValidationError::IdatNotConsecutive { index } =>
+                write!(f, "chunk {}: IDAT separated from the IDAT chunks before it", index),
+            ValidationError::PlteAfterIdat { index } => write!(f, "chunk {}: PLTE after IDAT", index),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SerializePolicy {
+    // Exactly one IHDR, first, and an IEND last.
+    #[default]
+    Strict,
+    // Write whatever is there; `Png::incomplete_parts` says what a decoder will object to.
+    Lenient,
+    // Like `Strict`, but appends IEND when the file has none.
+    AutoFinalize,
+}
+
+// I/O failures and parse failures of `from_file` stay apart, so a caller can tell a missing file
+// from a broken one. Both name the path.
+#[derive(Debug)]
+pub enum PngFileError {
+    Io { path: PathBuf, source: std::io::Error },
+    Invalid { path: PathBuf, source: Error },
+}
+
+impl fmt::Display for PngFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PngFileError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            PngFileError::Invalid { path, source } => write!(f, "{} is not a valid PNG: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for PngFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PngFileError::Io { source, .. } => Some(source),
+            PngFileError::Invalid { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncompletePng {
+    pub missing: Vec<&'static str>,
+}
+
+impl fmt::Display for IncompletePng {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PNG is incomplete: {}", self.missing.join(", "))
+    }
+}
+
+impl std::error::Error for IncompletePng {}
+
+// Tracks what serialization needs to know about the chunks written so far.
+#[derive(Debug, Default)]
+pub(crate) struct Completeness {
+    first: Option<[u8; 4]>,
+    last: Option<[u8; 4]>,
+    ihdr: usize,
+    iend: usize,
+}
+
+impl Completeness {
+    pub(crate) fn record(&mut self, chunk_type: [u8; 4]) {
+        self.first.get_or_insert(chunk_type);
+        self.last = Some(chunk_type);
+        self.ihdr += (&chunk_type == b"IHDR") as usize;
+        self.iend += (&chunk_type == b"IEND") as usize;
+    }
+
+    fn missing(&self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if self.first != Some(*b"IHDR") {
+            missing.push("IHDR must be the first chunk");
+        } else if self.ihdr > 1 {
+            missing.push("only one IHDR is allowed");
+        }
+        if self.last != Some(*b"IEND") {
+            missing.push("IEND must be the last chunk");
+        }
+        missing
+    }
+}
+
+impl SerializePolicy {
+    // Ok(true) means an IEND chunk has to be appended.
+    pub(crate) fn resolve(self, completeness: &Completeness) -> std::result::Result<bool, IncompletePng> {
+        let mut missing = completeness.missing();
+        let append_iend = self == SerializePolicy::AutoFinalize && completeness.iend == 0;
+        if append_iend {
+            missing.retain(|m| !m.starts_with("IEND"));
+        }
+        match self {
+            SerializePolicy::Lenient => Ok(false),
+            _ if missing.is_empty() => Ok(append_iend),
+            _ => Err(IncompletePng { missing }),
+        }
+    }
+}
+
+#[derive(Default)]
+struct StructureTracker {
+    seen_ihdr: bool,
+    seen_iend: bool,
+}
+
+impl StructureTracker {
+    fn check(&mut self, index: usize, chunk: &Chunk) -> Option<StructureError> {
+        let type_bytes = chunk.chunk_type().bytes();
+        let issue = if self.seen_iend {
+            Some(StructureError::ChunkAfterIend { index, chunk_type: chunk.chunk_type().to_string() })
+        } else if &type_bytes == b"IHDR" && self.seen_ihdr {
+            Some(StructureError::DuplicateIhdr { index })
+        } else {
+            None
+        };
+        self.seen_ihdr |= &type_bytes == b"IHDR";
+        self.seen_iend |= &type_bytes == b"IEND";
+        issue
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub lenient: bool,
+    // Keeps chunks whose stored CRC is wrong, CRC and all, for `Png::repair`.
+    pub keep_bad_crc: bool,
+    pub limits: Limits,
+    pub deadline: Option<Instant>,
+    pub cancel: Option<CancelToken>,
+}
+
+impl ParseOptions {
+    fn interruption(&self) -> Option<InterruptKind> {
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return Some(InterruptKind::Cancelled);
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Some(InterruptKind::DeadlineExceeded);
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptKind {
+    Cancelled,
+    DeadlineExceeded,
+}
+
+// Returned when a parse is stopped early; `partial` holds every chunk read before the stop.
 #[derive(Debug)]
+pub struct Interrupted {
+    pub kind: InterruptKind,
+    pub partial: Png,
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            InterruptKind::Cancelled => write!(f, "Parse cancelled after {} chunks", self.partial.chunks.len()),
+            InterruptKind::DeadlineExceeded => write!(f, "Parse deadline exceeded after {} chunks", self.partial.chunks.len()),
+        }
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+// When a lenient parse kept duplicates, these always refer to the first IHDR and first IEND.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Structure {
+    pub ihdr: Option<usize>,
+    pub iend: Option<usize>,
+}
+
+// `bytes` counts whole serialized chunks: length, type and CRC fields included.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkStats {
+    pub count: usize,
+    pub bytes: SerializedSize,
+}
+
+#[derive(Clone)]
 pub struct Png {
     header: [u8; 8],
     chunks: Vec<Chunk>,
+    // Where each type's chunks sit in `chunks`, in ascending order, so lookups by type skip the
+    // scan. Every method that adds, removes or reorders chunks keeps it in step.
+    index: HashMap<ChunkType, Vec<usize>>,
 }
 
 impl Png {
-    const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    // The 8 bytes every PNG file starts with: 89 50 4E 47 0D 0A 1A 0A.
+    pub const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
-    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
+    pub fn new() -> Png {
+        Png::from_chunks_unchecked(Vec::new())
+    }
+
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Result<Png> {
+        let mut tracker = StructureTracker::default();
+        for (index, chunk) in chunks.iter().enumerate() {
+            if let Some(issue) = tracker.check(index, chunk) {
+                return Err(issue.into());
+            }
+        }
+        Ok(Png::from_chunks_unchecked(chunks))
+    }
+
+    // Skips the IHDR/IEND checks, e.g. to reproduce a malformed file on purpose.
+    pub fn from_chunks_unchecked(chunks: Vec<Chunk>) -> Png {
+        let index = index_of(&chunks);
         Png {
-            header: Png::STANDARD_HEADER,
-            chunks
+            header: Png::SIGNATURE,
+            chunks,
+            index,
+        }
+    }
+
+    pub fn finalize(&mut self) {
+        if self.structure().iend.is_none() {
+            self.append_chunk(Chunk::new(ChunkType::IEND, Vec::new()));
         }
     }
 
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Png> {
-       let f = File::open(path)?;
+       let path = path.as_ref();
+       let io_error = |source| PngFileError::Io { path: path.to_path_buf(), source };
+       let f = File::open(path).map_err(io_error)?;
        let mut reader = BufReader::new(f);
        let mut byte_read = Vec::new();
-       reader.read_to_end(&mut byte_read)?;
+       reader.read_to_end(&mut byte_read).map_err(io_error)?;
 
        let png_bytes: &[u8] = &byte_read;
-       Png::try_from(png_bytes)   
+       Png::try_from(png_bytes)
+           .map_err(|source| PngFileError::Invalid { path: path.to_path_buf(), source }.into())
+    }
+
+    // For pipes and sockets: reads to the end without seeking, so stdin works as well as a file.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Png> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Png::try_from(bytes.as_slice())
+    }
+
+    // Writes the chunks exactly as `as_bytes` does; `save` checks the PNG is complete first.
+    // Either call replaces `path` atomically, so a failed write never leaves half a file.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        replace_file(path, |out| self.write_to(out).map(|_| ()))
+            .map_err(|source| PngFileError::Io { path: path.to_path_buf(), source }.into())
     }
 
     pub fn append_chunk(&mut self, chunk: Chunk) {
+        self.index.entry(*chunk.chunk_type()).or_default().push(self.chunks.len());
         self.chunks.push(chunk);
     }
 
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) {
+        let chunk_type = *chunk.chunk_type();
+        self.chunks.insert(index, chunk);
+        for positions in self.index.values_mut() {
+            let from = positions.partition_point(|&p| p < index);
+            positions[from..].iter_mut().for_each(|p| *p += 1);
+        }
+        let positions = self.index.entry(chunk_type).or_default();
+        positions.insert(positions.partition_point(|&p| p < index), index);
+        debug_assert!(self.index_is_consistent());
+    }
+
+    fn remove_at(&mut self, index: usize) -> Chunk {
+        let chunk = self.chunks.remove(index);
+        let chunk_type = *chunk.chunk_type();
+        if let Some(positions) = self.index.get_mut(&chunk_type) {
+            positions.retain(|&p| p != index);
+            if positions.is_empty() {
+                self.index.remove(&chunk_type);
+            }
+        }
+        for positions in self.index.values_mut() {
+            let from = positions.partition_point(|&p| p < index);
+            positions[from..].iter_mut().for_each(|p| *p -= 1);
+        }
+        debug_assert!(self.index_is_consistent());
+        chunk
+    }
+
+    fn reindex(&mut self) {
+        self.index = index_of(&self.chunks);
+    }
+
+    // Checks `index` against `chunks`: every position is in range, holds its type and is
+    // listed once, in order, with no empty lists left behind.
+    pub(crate) fn index_is_consistent(&self) -> bool {
+        self.index.values().map(Vec::len).sum::<usize>() == self.chunks.len()
+            && self.index.iter().all(|(chunk_type, positions)| {
+                !positions.is_empty()
+                    && positions.windows(2).all(|pair| pair[0] < pair[1])
+                    && positions.iter().all(|&p| self.chunks.get(p).is_some_and(|c| c.chunk_type() == chunk_type))
+            })
+    }
+
+    // Goes at the end when there is no IEND, e.g. in a truncated file; `incomplete_parts` still
+    // reports the missing IEND.
+    pub fn insert_before_iend(&mut self, chunk: Chunk) {
+        let index = self.structure().iend.unwrap_or(self.chunks.len());
+        self.insert_chunk(index, chunk);
+    }
+
+    // Goes first when there is no IHDR.
+    pub fn insert_after_ihdr(&mut self, chunk: Chunk) {
+        let index = self.structure().ihdr.map_or(0, |ihdr| ihdr + 1);
+        self.insert_chunk(index, chunk);
+    }
+
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
-        for (index, chunk) in self.chunks.iter().enumerate() {
-            if chunk.chunk_type().to_string() == chunk_type {
-                return Ok(self.chunks.remove(index));
+        let first = ChunkType::from_str(chunk_type).ok()
+            .and_then(|wanted| self.index.get(&wanted))
+            .map(|positions| positions[0]);
+        match first {
+            Some(index) => Ok(self.remove_at(index)),
+            None => Err(PngMsgError::ChunkNotFound(chunk_type.to_string()).into()),
+        }
+    }
+
+    // Unlike `remove_chunk`, a type with no chunks is not an error: the result is just empty.
+    // Critical types are refused so a scrub cannot break the image.
+    pub fn remove_chunks_by_type(&mut self, chunk_type: &str) -> Result<Vec<Chunk>> {
+        let wanted = ChunkType::from_str(chunk_type)?;
+        if wanted.category() == Some(ChunkCategory::Critical) {
+            return Err(PngMsgError::CriticalChunk(wanted).into());
+        }
+        let Some(positions) = self.index.remove(&wanted) else {
+            return Ok(Vec::new());
+        };
+        let mut removed = Vec::with_capacity(positions.len());
+        let mut kept = Vec::with_capacity(self.chunks.len() - positions.len());
+        for (index, chunk) in std::mem::take(&mut self.chunks).into_iter().enumerate() {
+            if positions.binary_search(&index).is_ok() {
+                removed.push(chunk);
+            } else {
+                kept.push(chunk);
             }
         }
-        Err("Chunk not in PNG".into())
+        self.chunks = kept;
+        // Every other chunk moves up by the number of removed ones before it.
+        for others in self.index.values_mut() {
+            others.iter_mut().for_each(|p| *p -= positions.partition_point(|&r| r < *p));
+        }
+        debug_assert!(self.index_is_consistent());
+        Ok(removed)
+    }
+
+    // Keeps the chunks `keep` returns true for. Critical chunks are not protected here;
+    // `serialize` with a strict policy still refuses a file that lost them.
+    pub fn retain<F: FnMut(&Chunk) -> bool>(&mut self, keep: F) {
+        self.chunks.retain(keep);
+        self.reindex();
+    }
+
+    // Swaps the chunks at `indices` for `new_chunks` in one step; the new run starts where the
+    // first old chunk was, whatever the counts. Returns the old chunks in file order.
+    pub fn replace_chunk_run(&mut self, indices: &[usize], new_chunks: Vec<Chunk>) -> Result<Vec<Chunk>> {
+        let mut sorted = indices.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let anchor = *sorted.first().ok_or("No chunks to replace")?;
+        if sorted.len() != indices.len() {
+            return Err("Chunk indices to replace must be distinct".into());
+        }
+        if let Some(&last) = sorted.last().filter(|&&last| last >= self.chunks.len()) {
+            return Err(format!("Chunk index {} is past the last chunk", last).into());
+        }
+
+        let mut kept = Vec::with_capacity(self.chunks.len() - sorted.len() + new_chunks.len());
+        let mut removed = Vec::with_capacity(sorted.len());
+        let mut new_chunks = Some(new_chunks);
+        for (index, chunk) in std::mem::take(&mut self.chunks).into_iter().enumerate() {
+            if index == anchor {
+                kept.extend(new_chunks.take().unwrap_or_default());
+            }
+            if sorted.binary_search(&index).is_ok() {
+                removed.push(chunk);
+            } else {
+                kept.push(chunk);
+            }
+        }
+        self.chunks = kept;
+        self.reindex();
+        Ok(removed)
     }
 
     pub fn header(&self) -> &[u8; 8] {
-        &Png::STANDARD_HEADER
+        &Png::SIGNATURE
     }
 
     pub fn chunks(&self) -> &[Chunk] {
         &self.chunks
     }
 
-    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        let chunk = ChunkType::from_str(chunk_type).ok()?;
-        let chunk_type_bytes = chunk.bytes();
+    pub fn iter(&self) -> std::slice::Iter<'_, Chunk> {
+        self.chunks.iter()
+    }
 
-        for (index, c) in self.chunks().iter().enumerate() {
-            if (*c).chunk_type().bytes() == chunk_type_bytes {
-                return Some(c);
-            }
+    // Only ancillary chunks are handed out: rewriting IHDR, IDAT and the like in place could
+    // leave an image that no longer decodes, so critical chunks are skipped.
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        IterMut { chunks: self.chunks.iter_mut() }
+    }
+
+    pub fn chunk_by_type<T: TryInto<ChunkType>>(&self, chunk_type: T) -> Option<&Chunk> {
+        self.chunk_by_type_nth(chunk_type, 0)
+    }
+
+    // Counts from 0 in file order, so `n = 2` is the third chunk of the type.
+    pub fn chunk_by_type_nth<T: TryInto<ChunkType>>(&self, chunk_type: T, n: usize) -> Option<&Chunk> {
+        self.iter_by_type(chunk_type).nth(n)
+    }
+
+    // In file order; empty for a type that is absent or not a valid chunk type.
+    pub fn chunks_by_type<T: TryInto<ChunkType>>(&self, chunk_type: T) -> Vec<&Chunk> {
+        self.iter_by_type(chunk_type).collect()
+    }
+
+    fn iter_by_type<T: TryInto<ChunkType>>(&self, chunk_type: T) -> impl Iterator<Item = &Chunk> {
+        let positions = chunk_type.try_into().ok()
+            .and_then(|wanted| self.index.get(&wanted))
+            .map_or(&[][..], Vec::as_slice);
+        positions.iter().map(|&p| &self.chunks[p])
+    }
+
+    pub fn structure(&self) -> Structure {
+        let first = |chunk_type: ChunkType| self.index.get(&chunk_type).map(|positions| positions[0]);
+        Structure { ihdr: first(ChunkType::IHDR), iend: first(ChunkType::IEND) }
+    }
+
+    // Keyed in chunk-type byte order, so iteration and every report built on it is the same on
+    // every run, whatever order the chunks appear in.
+    pub fn chunk_stats(&self) -> BTreeMap<String, ChunkStats> {
+        let mut stats: BTreeMap<String, ChunkStats> = BTreeMap::new();
+        for c in self.chunks() {
+            let entry = stats.entry(c.chunk_type().to_string()).or_default();
+            entry.count += 1;
+            entry.bytes += c.serialized_size();
         }
-        None
+        stats
+    }
+
+    pub fn total_size(&self) -> SerializedSize {
+        SerializedSize(self.header().len() as u64) + self.chunks().iter().map(Chunk::serialized_size).sum()
     }
 
     pub fn find_pattern_index(bytes: &[u8], c_type: &[u8; 4]) -> Option<usize> {
         bytes.windows(4).position(|window| window == c_type)
     }
     
+    // Recomputes the length and CRC of every chunk whose stored ones are wrong; returns how many.
+    pub fn repair(&mut self) -> usize {
+        self.chunks.iter_mut().filter_map(|c| c.repair_crc().then_some(())).count()
+    }
+
+    pub fn verify_integrity(&self) -> Vec<Inconsistency> {
+        let mut found = Vec::new();
+        for (index, c) in self.chunks().iter().enumerate() {
+            if c.length().get() as usize != c.data().len() {
+                found.push(Inconsistency::Length { index, declared: c.length(), actual: c.data().len() });
+            }
+            if c.crc() != c.computed_crc() {
+                found.push(Inconsistency::Crc { index, stored: c.crc(), computed: c.computed_crc() });
+            }
+        }
+        found
+    }
+
+    // Lists every problem rather than stopping at the first: missing chunks, then misplaced ones
+    // in file order.
+    pub fn validate(&self) -> std::result::Result<(), Vec<ValidationError>> {
+        let mut misplaced = Vec::new();
+        let (mut ihdr, mut iend, mut last_idat) = (None, None, None);
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            match &chunk.chunk_type().bytes() {
+                b"IEND" if iend.is_some() => misplaced.push(ValidationError::DuplicateIend { index }),
+                _ if iend.is_some() => misplaced.push(ValidationError::ChunkAfterIend {
+                    index,
+                    chunk_type: chunk.chunk_type().to_string(),
+                }),
+                b"IEND" => iend = Some(index),
+                b"IHDR" if ihdr.is_some() => misplaced.push(ValidationError::DuplicateIhdr { index }),
+                b"IHDR" => {
+                    if index != 0 {
+                        misplaced.push(ValidationError::IhdrNotFirst { index });
+                    }
+                    ihdr = Some(index);
+                }
+                b"IDAT" => {
+                    if last_idat.is_some_and(|last| last + 1 != index) {
+                        misplaced.push(ValidationError::IdatNotConsecutive { index });
+                    }
+                    last_idat = Some(index);
+                }
+                b"PLTE" if last_idat.is_some() => misplaced.push(ValidationError::PlteAfterIdat { index }),
+                _ => {}
+            }
+        }
+
+        let mut errors = Vec::new();
+        if ihdr.is_none() {
+            errors.push(ValidationError::MissingIhdr);
+        }
+        if last_idat.is_none() {
+            errors.push(ValidationError::MissingIdat);
+        }
+        if iend.is_none() {
+            errors.push(ValidationError::MissingIend);
+        }
+        errors.append(&mut misplaced);
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    pub fn incomplete_parts(&self) -> Vec<&'static str> {
+        self.completeness().missing()
+    }
+
+    fn completeness(&self) -> Completeness {
+        let mut completeness = Completeness::default();
+        self.chunks.iter().for_each(|c| completeness.record(c.chunk_type().bytes()));
+        completeness
+    }
+
+    pub fn serialize(&self, policy: SerializePolicy) -> Result<Vec<u8>> {
+        let append_iend = policy.resolve(&self.completeness())?;
+        let mut bytes = self.as_bytes();
+        if append_iend {
+            bytes.extend(Chunk::new(ChunkType::IEND, Vec::new()).as_bytes());
+        }
+        Ok(bytes)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, policy: SerializePolicy) -> Result<()> {
+        let path = path.as_ref();
+        let bytes = self.serialize(policy)?;
+        replace_file(path, |out| out.write_all(&bytes))
+            .map_err(|source| PngFileError::Io { path: path.to_path_buf(), source }.into())
+    }
+
+    // Writes the chunks exactly as they are; use `serialize` to check the result is a whole PNG.
     pub fn as_bytes(&self) -> Vec<u8> {
-       let mut bytes = Vec::new();
-       bytes.extend_from_slice(self.header());
-       
-       for c in self.chunks().iter() {
-           bytes.extend_from_slice(&(*c).as_bytes());
-       }
+        let mut bytes = Vec::with_capacity(self.total_size().get() as usize);
+        self.write_to(&mut bytes).expect("writing to a Vec cannot fail");
+        bytes
+    }
 
-       bytes
+    // Streams the same bytes as `as_bytes` without building them in memory first.
+    pub fn write_to<W: Write + ?Sized>(&self, w: &mut W) -> std::io::Result<usize> {
+        w.write_all(self.header())?;
+        let mut written = self.header().len();
+        for (index, c) in self.chunks().iter().enumerate() {
+            debug_assert!(c.length().get() as usize == c.data().len(), "chunk {} ({}) has a stale length", index, c.chunk_type());
+            written += c.write_to(w)?;
+        }
+        Ok(written)
     }
 }
 
-impl TryFrom<&[u8]> for Png {
-    type Error = Error;
+impl Png {
+    // Keeps up to 8 of the bytes found, for the error to say what the input looks like instead.
+    pub(crate) fn check_signature(bytes: &[u8]) -> std::result::Result<(), PngMsgError> {
+        match bytes.get(..8) {
+            Some(signature) if signature == Png::SIGNATURE => Ok(()),
+            _ => Err(PngMsgError::InvalidSignature { found: bytes[..bytes.len().min(8)].to_vec() }),
+        }
+    }
 
-    fn try_from(bytes: &[u8]) -> Result<Png> {
+    pub fn parse_with(bytes: &[u8], options: &ParseOptions) -> Result<(Png, Vec<StructureError>)> {
         let mut chunks: Vec<Chunk> = Vec::new();
-        let mut index = 8;
-
-        let head: &[u8; 8] = bytes[..8].try_into().unwrap();
-        if head != &Png::STANDARD_HEADER {
-            return Err("Invalid header".into());
-        }
-
-        while index + 8 <= bytes.len() {
-            let length_bits: [u8; 4] = bytes[index..index + 4].try_into().unwrap();
-            let length_as_u32 = u32::from_be_bytes(length_bits);
-            let type_bits: [u8; 4] = bytes[index + 4..index + 8].try_into().unwrap();
-            let chunk_type = ChunkType::try_from(type_bits).unwrap();
-            println!("Chunk type valid: {}", chunk_type.is_valid());
-            let chunk_data: &[u8] = bytes[index + 8..index + 8 + length_as_u32 as usize].try_into().unwrap();
-            let chunk_crc: [u8; 4] = bytes[index + length_as_u32 as usize + 8..index + length_as_u32 as usize + 12].try_into().unwrap();
-
-            let mut chunk_bytes = Vec::new();
-            chunk_bytes.extend_from_slice(&length_bits);
-            chunk_bytes.extend_from_slice(&type_bits);
-            chunk_bytes.extend_from_slice(&chunk_data);
-            chunk_bytes.extend_from_slice(&chunk_crc);
-
-            let chunk_bytes_ref: &[u8] = &chunk_bytes;
-
-            match Chunk::try_from(chunk_bytes_ref) {
-                Ok(chunk) => chunks.push(chunk),
-                Err(e) => {
-                    return Err(format!("Error while parsing chunk: {}", e).into());
-                },
+        let mut warnings = Vec::new();
+        let mut tracker = StructureTracker::default();
+        let mut offset = 8;
+
+        options.limits.check_input(bytes.len() as u64)?;
+        Png::check_signature(bytes)?;
+
+        while offset < bytes.len() {
+            if let Some(kind) = options.interruption() {
+                let partial = Png::from_chunks_unchecked(chunks);
+                return Err(Interrupted { kind, partial }.into());
             }
+            let rest = &bytes[offset..];
+            let declared = rest.get(..4).map(|bits| u32::from_be_bytes(bits.try_into().unwrap()));
+            if let Some(length) = declared {
+                options.limits.check_chunk(length)?;
+            }
+            options.limits.check_chunk_count(chunks.len() + 1)?;
+
+            let parsed = match options.keep_bad_crc {
+                true => Chunk::parse_prefix_lenient(rest),
+                false => Chunk::parse_prefix(rest),
+            };
+            let (chunk, consumed) = parsed.map_err(|e| e.at(ChunkPosition::of(offset as u64, chunks.len(), rest)))?;
 
-            index += 12 + length_as_u32 as usize;
+            if let Some(issue) = tracker.check(chunks.len(), &chunk) {
+                if !options.lenient {
+                    return Err(issue.into());
+                }
+                warnings.push(issue);
+            }
+            chunks.push(chunk);
+
+            offset += consumed;
         }
-        Ok(Png {
-            header: Png::STANDARD_HEADER,
-            chunks,
-        })
+        Ok((Png::from_chunks_unchecked(chunks), warnings))
+    }
+}
+
+fn index_of(chunks: &[Chunk]) -> HashMap<ChunkType, Vec<usize>> {
+    let mut index: HashMap<ChunkType, Vec<usize>> = HashMap::new();
+    for (position, chunk) in chunks.iter().enumerate() {
+        index.entry(*chunk.chunk_type()).or_default().push(position);
+    }
+    index
+}
+
+impl Default for Png {
+    fn default() -> Self {
+        Png::new()
+    }
+}
+
+impl FromIterator<Chunk> for Result<Png> {
+    fn from_iter<I: IntoIterator<Item = Chunk>>(iter: I) -> Self {
+        Png::from_chunks(iter.into_iter().collect())
+    }
+}
+
+impl<'a> IntoIterator for &'a Png {
+    type Item = &'a Chunk;
+    type IntoIter = std::slice::Iter<'a, Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.iter()
+    }
+}
+
+impl IntoIterator for Png {
+    type Item = Chunk;
+    type IntoIter = std::vec::IntoIter<Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.into_iter()
+    }
+}
+
+pub struct IterMut<'a> {
+    chunks: std::slice::IterMut<'a, Chunk>,
+}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = ChunkMut<'a>;
+
+    fn next(&mut self) -> Option<ChunkMut<'a>> {
+        self.chunks.find(|c| !c.chunk_type().is_critical()).map(ChunkMut::new)
+    }
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Png> {
+        Png::parse_with(bytes, &ParseOptions::default()).map(|(png, _)| png)
+    }
+}
+
+// The index is left out: it only restates `chunks`.
+impl fmt::Debug for Png {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Png").field("header", &self.header).field("chunks", &self.chunks).finish()
     }
 }
 
@@ -137,11 +788,10 @@ impl fmt::Display for Png {
 
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
     use crate::chunk::Chunk;
-    use std::str::FromStr;
     use std::convert::TryFrom;
 
     fn testing_chunks() -> Vec<Chunk> {
@@ -154,7 +804,7 @@ mod tests {
 
     fn testing_png() -> Png {
         let chunks = testing_chunks();
-        Png::from_chunks(chunks)
+        Png::from_chunks(chunks).unwrap()
     }
 
     fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk> {
@@ -169,11 +819,59 @@ mod tests {
     #[test]
     fn test_from_chunks() {
         let chunks = testing_chunks();
-        let png = Png::from_chunks(chunks);
+        let png = Png::from_chunks(chunks).unwrap();
 
         assert_eq!(png.chunks().len(), 3);
     }
 
+    #[test]
+    fn test_from_chunks_rejects_bad_structure() {
+        let chunks = vec![
+            chunk_from_strings("IHDR", "a").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+            chunk_from_strings("ruSt", "late").unwrap(),
+        ];
+        let err = Png::from_chunks(chunks.clone()).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StructureError>(),
+            Some(&StructureError::ChunkAfterIend { index: 2, chunk_type: "ruSt".to_string() })
+        );
+        assert_eq!(Png::from_chunks_unchecked(chunks).chunks().len(), 3);
+
+        let doubled: Result<Png> = ["IHDR", "IHDR"].iter()
+            .map(|t| chunk_from_strings(t, "").unwrap())
+            .collect();
+        assert!(doubled.is_err());
+    }
+
+    #[test]
+    fn test_new_and_finalize() {
+        let mut png = Png::new();
+        assert!(png.chunks().is_empty());
+        assert_eq!(png.as_bytes(), Png::SIGNATURE.to_vec());
+
+        png.finalize();
+        png.finalize();
+        assert_eq!(png.structure().iend, Some(0));
+        assert_eq!(png.chunks().len(), 1);
+    }
+
+    #[test]
+    fn test_build_minimal_png_from_scratch() {
+        // tests/fixtures/pixels.png is a 2x2 RGB image written by zlib/libpng-compatible tooling.
+        let fixture = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/pixels.png")).unwrap();
+        let idat = Png::try_from(fixture.as_slice()).unwrap().chunk_by_type("IDAT").unwrap().data().to_vec();
+
+        let ihdr = [0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0];
+        let mut png: Png = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr.to_vec()),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), idat),
+        ].into_iter().collect::<Result<Png>>().unwrap();
+        png.finalize();
+
+        assert_eq!(png.as_bytes(), fixture);
+    }
+
     #[test]
     fn test_valid_from_bytes() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -181,7 +879,7 @@ mod tests {
             .flat_map(|chunk| chunk.as_bytes())
             .collect();
 
-        let bytes: Vec<u8> = Png::STANDARD_HEADER
+        let bytes: Vec<u8> = Png::SIGNATURE
             .iter()
             .chain(chunk_bytes.iter())
             .copied()
@@ -205,9 +903,248 @@ mod tests {
             .copied()
             .collect();
 
-        let png = Png::try_from(bytes.as_ref());
+        let err = Png::try_from(bytes.as_ref()).unwrap_err();
 
-        assert!(png.is_err());
+        assert!(matches!(
+            err.downcast_ref::<PngMsgError>(),
+            Some(PngMsgError::InvalidSignature { found }) if found[..] == [13, 80, 78, 71, 13, 10, 26, 10]
+        ));
+    }
+
+    #[test]
+    fn test_signature_error_says_what_the_input_looks_like() {
+        let message = |bytes: &[u8]| Png::try_from(bytes).unwrap_err().to_string();
+        assert_eq!(
+            message(b"\xFF\xD8\xFF\xE0\x00\x10JFIF\x00"),
+            "Not a PNG file: starts with FF D8 FF E0 00 10 4A 46; looks like JPEG"
+        );
+        assert_eq!(message(b"GIF89a\x01\x00\x01\x00"), "Not a PNG file: starts with 47 49 46 38 39 61 01 00; looks like GIF");
+        assert_eq!(message(b""), "Not a PNG file: the input is empty");
+        assert_eq!(message(&Png::SIGNATURE[..7]), "Not a PNG file: only 7 bytes long (89 50 4E 47 0D 0A 1A)");
+        assert_eq!(
+            message(b"\x89PNG\n\x1A\nrest"),
+            "Not a PNG file: starts with 89 50 4E 47 0A 1A 0A 72; looks like a PNG damaged by a text-mode transfer"
+        );
+        assert_eq!(message(b"plain text"), "Not a PNG file: starts with 70 6C 61 69 6E 20 74 65");
+    }
+
+    fn incomplete(types: &[&str]) -> Png {
+        Png::from_chunks_unchecked(types.iter().map(|t| chunk_from_strings(t, "").unwrap()).collect())
+    }
+
+    #[test]
+    fn test_serialize_policies() {
+        let valid = incomplete(&["IHDR", "ruSt", "IEND"]);
+        let no_iend = incomplete(&["IHDR", "ruSt"]);
+        let no_ihdr = incomplete(&["ruSt", "IEND"]);
+
+        for policy in [SerializePolicy::Strict, SerializePolicy::Lenient, SerializePolicy::AutoFinalize] {
+            assert_eq!(valid.serialize(policy).unwrap(), valid.as_bytes());
+            assert_eq!(no_ihdr.serialize(policy).is_err(), policy != SerializePolicy::Lenient);
+        }
+
+        let err = no_iend.serialize(SerializePolicy::Strict).unwrap_err();
+        assert_eq!(err.downcast_ref::<IncompletePng>().unwrap().missing, ["IEND must be the last chunk"]);
+        assert_eq!(no_iend.serialize(SerializePolicy::Lenient).unwrap(), no_iend.as_bytes());
+        assert_eq!(no_iend.serialize(SerializePolicy::AutoFinalize).unwrap(), valid.as_bytes());
+
+        assert_eq!(no_ihdr.incomplete_parts(), ["IHDR must be the first chunk"]);
+        assert_eq!(incomplete(&["IHDR", "IHDR", "IEND"]).incomplete_parts(), ["only one IHDR is allowed"]);
+        assert!(incomplete(&["IHDR", "IEND", "trAl"]).serialize(SerializePolicy::AutoFinalize).is_err());
+    }
+
+    fn shards(count: usize, first: u8) -> Vec<Chunk> {
+        (0..count as u8).map(|n| Chunk::new(ChunkType::try_from(*b"shRd").unwrap(), vec![first + n])).collect()
+    }
+
+    // Positions and payloads of the shard chunks, in file order.
+    fn shard_run(png: &Png) -> Vec<(usize, u8)> {
+        png.chunks().iter().enumerate()
+            .filter(|(_, c)| &c.chunk_type().bytes() == b"shRd")
+            .map(|(index, c)| (index, c.data()[0]))
+            .collect()
+    }
+
+    fn with_shards(count: usize) -> Png {
+        let mut chunks = testing_chunks();
+        chunks.splice(1..1, shards(count, 0));
+        Png::from_chunks(chunks).unwrap()
+    }
+
+    #[test]
+    fn test_replace_chunk_run_counts() {
+        for (old, new) in [(3, 5), (5, 3), (3, 3), (1, 0)] {
+            let mut png = with_shards(old);
+            let removed = png.replace_chunk_run(&(1..1 + old).collect::<Vec<_>>(), shards(new, 10)).unwrap();
+
+            assert_eq!(removed.iter().map(|c| c.data()[0]).collect::<Vec<_>>(), (0..old as u8).collect::<Vec<_>>());
+            assert_eq!(shard_run(&png), (0..new).map(|n| (1 + n, 10 + n as u8)).collect::<Vec<_>>());
+            assert_eq!(png.chunks().len(), 3 + new);
+            assert_eq!(png.chunks()[0].chunk_type().to_string(), "FrSt");
+            assert_eq!(png.chunks()[png.chunks().len() - 1].chunk_type().to_string(), "LASt");
+        }
+    }
+
+    #[test]
+    fn test_replace_chunk_run_anchors_on_first_old_chunk() {
+        let mut png = with_shards(2);
+        png.insert_chunk(4, chunk_from_strings("laTe", "scattered shard").unwrap());
+        let removed = png.replace_chunk_run(&[4, 2, 1], shards(2, 7)).unwrap();
+
+        assert_eq!(removed.len(), 3);
+        assert_eq!(removed[2].chunk_type().to_string(), "laTe");
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["FrSt", "shRd", "shRd", "miDl", "LASt"]);
+
+        assert!(png.replace_chunk_run(&[], shards(1, 0)).is_err());
+        assert!(png.replace_chunk_run(&[1, 1], shards(1, 0)).is_err());
+        assert!(png.replace_chunk_run(&[1, 5], shards(1, 0)).is_err());
+        assert_eq!(png.chunks().len(), 5);
+    }
+
+    #[test]
+    fn test_random_replaces_keep_shards_contiguous() {
+        let mut seed: u32 = 0x2545_f491;
+        let mut next = |bound: u32| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed % bound
+        };
+        let mut png = with_shards(3);
+        for round in 0..200 {
+            let run = shard_run(&png);
+            let anchor = run[0].0;
+            let new = 1 + next(8) as usize;
+            let indices: Vec<usize> = run.iter().map(|(index, _)| *index).collect();
+            png.replace_chunk_run(&indices, shards(new, round as u8)).unwrap();
+
+            let expected: Vec<(usize, u8)> = (0..new).map(|n| (anchor + n, round as u8 + n as u8)).collect();
+            assert_eq!(shard_run(&png), expected, "round {}", round);
+            assert_eq!(png.chunks().len(), 3 + new);
+        }
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let fixture = format!("{}/tests/fixtures/pixels.png", env!("CARGO_MANIFEST_DIR"));
+        let dir = std::env::temp_dir().join(format!("pngme-png-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.png");
+
+        let mut png = Png::from_file(&fixture).unwrap();
+        let count = png.chunks().len();
+        png.insert_chunk(count - 1, chunk_from_strings("ruSt", "round trip").unwrap());
+        png.write_to_file(&output).unwrap();
+
+        let reread = Png::from_file(&output).unwrap();
+        assert_eq!(reread.chunks().len(), count + 1);
+        assert_eq!(reread.chunk_by_type("ruSt").unwrap().data(), b"round trip");
+        assert_eq!(reread.as_bytes(), png.as_bytes());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Hands out a few bytes per read, like a pipe, and cannot seek.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_from_reader_without_seeking() {
+        let bytes = testing_png().as_bytes();
+        let png = Png::from_reader(Trickle(&bytes)).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(png.write_to(&mut out).unwrap(), bytes.len());
+        assert_eq!(out, bytes);
+        assert!(Png::from_reader(Trickle(&bytes[..bytes.len() - 1])).is_err());
+    }
+
+    #[test]
+    fn test_file_errors_name_the_path() {
+        let dir = std::env::temp_dir().join(format!("pngme-png-errors-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let not_png = dir.join("notes.txt");
+        std::fs::write(&not_png, b"plain text").unwrap();
+
+        let err = Png::from_file(&not_png).unwrap_err();
+        let err = err.downcast_ref::<PngFileError>().unwrap();
+        assert!(matches!(err, PngFileError::Invalid { .. }));
+        assert!(err.to_string().starts_with(&format!("{} is not a valid PNG", not_png.display())));
+
+        let jpeg = dir.join("photo.jpg");
+        std::fs::write(&jpeg, b"\xFF\xD8\xFF\xE0\x00\x10JFIF\x00").unwrap();
+        assert!(Png::from_file(&jpeg).unwrap_err().to_string().ends_with("looks like JPEG"));
+
+        let missing = dir.join("missing.png");
+        let err = Png::from_file(&missing).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PngFileError>(), Some(PngFileError::Io { path, .. }) if *path == missing));
+
+        let err = testing_png().write_to_file(dir.join("no-such-dir").join("out.png")).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PngFileError>(), Some(PngFileError::Io { .. })));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_every_prefix_and_byte_flip_is_handled() {
+        // Cuts on a chunk boundary leave a shorter but well-formed chunk stream.
+        let mut boundaries = vec![8];
+        for chunk in Png::try_from(&PNG_FILE[..]).unwrap().chunks() {
+            boundaries.push(boundaries.last().unwrap() + chunk.serialized_size().get() as usize);
+        }
+        for len in 0..PNG_FILE.len() {
+            assert_eq!(Png::try_from(&PNG_FILE[..len]).is_ok(), boundaries.contains(&len), "prefix of {} bytes", len);
+        }
+        for index in 0..PNG_FILE.len() {
+            let mut bytes = PNG_FILE.to_vec();
+            bytes[index] ^= 0x5a;
+            let _ = Png::try_from(bytes.as_slice());
+            let _ = Png::parse_with(&bytes, &ParseOptions { lenient: true, ..Default::default() });
+        }
+    }
+
+    #[test]
+    fn test_truncated_input_is_an_error() {
+        assert!(Png::try_from(&PNG_FILE[..5]).is_err());
+        assert!(Png::try_from(&PNG_FILE[..PNG_FILE.len() - 20]).is_err());
+
+        let err = Png::try_from(&PNG_FILE[..PNG_FILE.len() - 2]).unwrap_err();
+        assert_eq!(err.to_string(), "chunk #6 (IEND) at offset 0x12b7: Malformed chunk: CRC cut off after 2 of 4 bytes");
+    }
+
+    // Signature (8) + FrSt (12 + 20) puts miDl at 0x28 and LASt at 0x46.
+    fn corrupt(at: usize) -> String {
+        let mut bytes = testing_png().as_bytes();
+        bytes[at] ^= 0xff;
+        Png::try_from(bytes.as_slice()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_parse_errors_carry_position() {
+        let mut bytes = testing_png().as_bytes();
+        let last_crc = 0x46 + 8 + 19;
+        bytes[last_crc] ^= 0xff;
+        let err = Png::try_from(bytes.as_slice()).unwrap_err();
+        match err.downcast_ref::<PngMsgError>() {
+            Some(PngMsgError::CrcMismatch(crc)) => {
+                let position = crc.position.unwrap();
+                assert_eq!((position.offset, position.index), (0x46, 2));
+                assert_eq!(position.chunk_type.unwrap().to_string(), "LASt");
+            },
+            other => panic!("expected a CRC mismatch, got {:?}", other),
+        }
+        assert!(err.to_string().starts_with("chunk #2 (LASt) at offset 0x46: CRC mismatch (expected "), "{}", err);
+
+        // An unreadable type leaves it out of the position.
+        assert_eq!(corrupt(0x28 + 4), "chunk #1 at offset 0x28: Invalid chunk type: byte 1 (0x92) is not an ASCII letter");
+        // A length past the end of the file reads as a cut-off chunk where the length is.
+        assert!(corrupt(0x28 + 3).starts_with("chunk #1 (miDl) at offset 0x28: Malformed chunk: data cut off"), "{}", corrupt(0x28 + 3));
     }
 
     #[test]
@@ -249,6 +1186,23 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        for (i, data) in ["one", "two", "three"].into_iter().enumerate() {
+            png.insert_chunk(1 + 2 * i, chunk_from_strings("ruSt", data).unwrap());
+        }
+        let texts = |chunks: Vec<&Chunk>| -> Vec<String> { chunks.iter().map(|c| c.data_as_string().unwrap()).collect() };
+
+        assert_eq!(texts(png.chunks_by_type("ruSt")), ["one", "two", "three"]);
+        assert_eq!(texts(png.chunks_by_type(ChunkType::from_str("ruSt").unwrap())), ["one", "two", "three"]);
+        let ru_st = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(png.chunk_by_type_nth(ru_st, 2).unwrap().data(), b"three");
+        assert!(png.chunk_by_type_nth("ruSt", 3).is_none());
+        assert!(png.chunks_by_type("NoNe").is_empty());
+        assert!(png.chunks_by_type("not a type").is_empty());
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -258,6 +1212,58 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_insert_before_iend_and_after_ihdr() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_before_iend(chunk_from_strings("ruSt", "late").unwrap());
+        png.insert_after_ihdr(chunk_from_strings("ruSt", "early").unwrap());
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types[..2], ["IHDR", "ruSt"]);
+        assert_eq!(types[types.len() - 2..], ["ruSt", "IEND"]);
+
+        let bytes = png.serialize(SerializePolicy::Strict).unwrap();
+        let reparsed = Png::try_from(bytes.as_slice()).unwrap();
+        assert!(reparsed.incomplete_parts().is_empty());
+        #[cfg(feature = "compression")]
+        {
+            // The image itself still decodes: 50 rows of a filter byte and 50 RGBA pixels.
+            let idat: Vec<u8> = reparsed.chunks_by_type("IDAT").iter().flat_map(|c| c.data().to_vec()).collect();
+            assert_eq!(crate::zlib::decompress(&idat, usize::MAX).unwrap().len(), 50 * (1 + 50 * 4));
+        }
+
+        let mut truncated = Png::from_chunks_unchecked(vec![chunk_from_strings("ruSt", "first").unwrap()]);
+        truncated.insert_before_iend(chunk_from_strings("ruSt", "end").unwrap());
+        truncated.insert_after_ihdr(chunk_from_strings("ruSt", "start").unwrap());
+        let texts: Vec<String> = truncated.chunks().iter().map(|c| c.data_as_string().unwrap()).collect();
+        assert_eq!(texts, ["start", "first", "end"]);
+        assert!(truncated.incomplete_parts().contains(&"IEND must be the last chunk"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Png::try_from(&PNG_FILE[..]).unwrap().validate(), Ok(()));
+
+        let png = |types: &[&str]| Png::from_chunks_unchecked(
+            types.iter().map(|t| chunk_from_strings(t, "").unwrap()).collect()
+        );
+        assert_eq!(png(&[]).validate().unwrap_err(), [
+            ValidationError::MissingIhdr, ValidationError::MissingIdat, ValidationError::MissingIend,
+        ]);
+        assert_eq!(png(&["IHDR", "PLTE", "IDAT", "IDAT", "ruSt", "IEND"]).validate(), Ok(()));
+        assert_eq!(png(&["ruSt", "IHDR", "IDAT", "IHDR", "IEND"]).validate().unwrap_err(), [
+            ValidationError::IhdrNotFirst { index: 1 }, ValidationError::DuplicateIhdr { index: 3 },
+        ]);
+        assert_eq!(png(&["IHDR", "IDAT", "ruSt", "IDAT", "IDAT", "PLTE", "IEND"]).validate().unwrap_err(), [
+            ValidationError::IdatNotConsecutive { index: 3 }, ValidationError::PlteAfterIdat { index: 5 },
+        ]);
+        let errors = png(&["IHDR", "IDAT", "IEND", "ruSt", "IEND"]).validate().unwrap_err();
+        assert_eq!(errors, [
+            ValidationError::ChunkAfterIend { index: 3, chunk_type: "ruSt".to_string() },
+            ValidationError::DuplicateIend { index: 4 },
+        ]);
+        assert_eq!(errors[0].to_string(), "chunk 3: ruSt chunk after IEND");
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -265,6 +1271,136 @@ mod tests {
         png.remove_chunk("TeSt").unwrap();
         let chunk = png.chunk_by_type("TeSt");
         assert!(chunk.is_none());
+
+        let err = png.remove_chunk("TeSt").unwrap_err();
+        assert!(matches!(err.downcast_ref::<PngMsgError>(), Some(PngMsgError::ChunkNotFound(t)) if t == "TeSt"));
+    }
+
+    #[test]
+    fn test_remove_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "one").unwrap());
+        png.insert_chunk(1, chunk_from_strings("TeSt", "two").unwrap());
+        let before = png.chunks().len();
+
+        let removed = png.remove_chunks_by_type("TeSt").unwrap();
+        let texts: Vec<String> = removed.iter().map(|c| c.data_as_string().unwrap()).collect();
+        assert_eq!(texts, ["two", "one"]);
+        assert_eq!(png.chunks().len(), before - 2);
+        assert!(png.remove_chunks_by_type("TeSt").unwrap().is_empty());
+
+        for critical in ["IHDR", "PLTE", "IDAT", "IEND"] {
+            let err = png.remove_chunks_by_type(critical).unwrap_err();
+            assert!(matches!(err.downcast_ref::<PngMsgError>(), Some(PngMsgError::CriticalChunk(_))), "{}", critical);
+        }
+        assert!(png.remove_chunks_by_type("too long").is_err());
+    }
+
+    #[test]
+    fn test_index_follows_every_mutation() {
+        let text = |data: &str| chunk_from_strings("tEXt", data).unwrap();
+        let mut png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IDAT, vec![1]),
+            Chunk::new(ChunkType::IDAT, vec![2]),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap();
+        let texts = |png: &Png| -> Vec<String> {
+            png.chunks_by_type("tEXt").iter().map(|c| c.data_as_string().unwrap()).collect()
+        };
+        assert!(png.index_is_consistent());
+
+        png.insert_after_ihdr(text("b"));
+        png.insert_chunk(1, text("a"));
+        png.insert_before_iend(text("d"));
+        png.append_chunk(text("e"));
+        assert!(png.index_is_consistent());
+        assert_eq!(texts(&png), ["a", "b", "d", "e"]);
+        assert_eq!(png.structure().iend, Some(6));
+
+        png.remove_chunk("tEXt").unwrap();
+        assert!(png.index_is_consistent());
+        assert_eq!(png.chunk_by_type_nth("IDAT", 1).unwrap().data(), [2]);
+
+        png.retain(|c| c.data() != b"d");
+        assert!(png.index_is_consistent());
+        assert_eq!(texts(&png), ["b", "e"]);
+
+        png.replace_chunk_run(&[2, 3], vec![Chunk::new(ChunkType::IDAT, vec![3]), text("c")]).unwrap();
+        assert!(png.index_is_consistent());
+        assert_eq!(png.chunks_by_type("IDAT").len(), 1);
+        assert_eq!(texts(&png), ["b", "c", "e"]);
+
+        assert_eq!(png.remove_chunks_by_type("tEXt").unwrap().len(), 3);
+        assert!(png.index_is_consistent());
+        assert!(png.chunk_by_type("tEXt").is_none());
+        assert_eq!(png.structure(), Structure { ihdr: Some(0), iend: Some(2) });
+
+        png.remove_chunk("IEND").unwrap();
+        png.finalize();
+        assert!(png.index_is_consistent());
+        assert_eq!(png.structure().iend, Some(2));
+
+        let (parsed, _) = Png::parse_with(&png.as_bytes(), &ParseOptions::default()).unwrap();
+        assert!(parsed.index_is_consistent());
+    }
+
+    #[test]
+    fn test_iterators() {
+        let png = testing_png();
+        let mut types = Vec::new();
+        for chunk in &png {
+            types.push(chunk.chunk_type().to_string());
+        }
+        assert_eq!(types, ["FrSt", "miDl", "LASt"]);
+        assert_eq!(png.iter().len(), 3);
+
+        let owned: Vec<Chunk> = png.clone().into_iter().collect();
+        assert_eq!(owned, png.chunks());
+    }
+
+    #[test]
+    fn test_iter_mut_recomputes_length_and_crc() {
+        let mut png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            chunk_from_strings("tEXt", "Comment\0old").unwrap(),
+            Chunk::new(ChunkType::IDAT, vec![1, 2, 3]),
+            chunk_from_strings("ruSt", "hi").unwrap(),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap();
+        let untouched = png.chunks()[3].crc();
+
+        let mut seen = Vec::new();
+        for mut chunk in png.iter_mut() {
+            seen.push(chunk.chunk_type().to_string());
+            if chunk.chunk_type() == "tEXt" {
+                let data = chunk.data_mut();
+                data.truncate(8);
+                data.extend_from_slice(b"a longer comment");
+            }
+        }
+        assert_eq!(seen, ["tEXt", "ruSt"]);
+
+        let text = &png.chunks()[1];
+        assert_eq!(text.data(), b"Comment\0a longer comment");
+        assert_eq!(text.length().get(), 24);
+        assert!(text.is_consistent());
+        assert_eq!(png.chunks()[3].crc(), untouched);
+
+        let reparsed = Png::try_from(&png.as_bytes()[..]).unwrap();
+        assert_eq!(reparsed.chunks()[1].crc(), text.crc());
+        assert_eq!(reparsed.chunks()[1].data(), b"Comment\0a longer comment");
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "one").unwrap());
+        png.retain(|c| c.chunk_type() != "miDl");
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["FrSt", "LASt", "TeSt"]);
+        png.retain(|_| false);
+        assert!(png.chunks().is_empty());
     }
 
     #[test]
@@ -281,6 +1417,197 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_write_to_streams_the_same_bytes() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut out = std::io::Cursor::new(Vec::new());
+        assert_eq!(png.write_to(&mut out).unwrap(), PNG_FILE.len());
+        assert_eq!(out.into_inner(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_verify_integrity_clean() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.verify_integrity().is_empty());
+    }
+
+    #[test]
+    fn test_verify_integrity_reports_all() {
+        let mut png = testing_png();
+        let crc = png.chunks[1].crc();
+        png.chunks[1].set_raw_fields_for_test(3, crc);
+        png.chunks[2].set_raw_fields_for_test(19, 7);
+
+        let found = png.verify_integrity();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], Inconsistency::Length { index: 1, declared: ChunkLength(3), actual: 18 });
+        assert!(matches!(found[1], Inconsistency::Crc { index: 2, stored: 7, .. }));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "chunk 2 (LASt) has a stale length")]
+    fn test_as_bytes_panics_with_chunk_index_in_debug() {
+        let mut png = testing_png();
+        png.chunks[2].set_raw_fields_for_test(3, 7);
+        png.as_bytes();
+    }
+
+    #[test]
+    fn test_keep_bad_crc_then_repair() {
+        let mut bytes = PNG_FILE.to_vec();
+        let (first_crc, last_crc) = (8 + 8 + 13, PNG_FILE.len() - 4);
+        bytes[first_crc] ^= 0xff;
+        bytes[last_crc] ^= 0xff;
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+
+        let options = ParseOptions { keep_bad_crc: true, ..Default::default() };
+        let (mut png, _) = Png::parse_with(&bytes, &options).unwrap();
+        let last = png.chunks().len() - 1;
+        let bad: Vec<usize> = png.verify_integrity().iter().map(|i| match i {
+            Inconsistency::Crc { index, .. } | Inconsistency::Length { index, .. } => *index,
+        }).collect();
+        assert_eq!(bad, [0, last]);
+        // Written back as read until repaired.
+        assert_eq!(png.as_bytes(), bytes);
+        let mut written = Vec::new();
+        assert_eq!(png.write_to(&mut written).unwrap(), bytes.len());
+        assert_eq!(written, bytes);
+
+        assert_eq!(png.repair(), 2);
+        assert!(png.verify_integrity().is_empty());
+        assert_eq!(png.as_bytes(), PNG_FILE);
+        assert_eq!(png.repair(), 0);
+
+        bytes[12] = b'1';
+        assert!(Png::parse_with(&bytes, &options).is_err());
+    }
+
+    #[test]
+    fn test_chunk_stats() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "more").unwrap());
+        let stats = png.chunk_stats();
+
+        assert_eq!(stats.keys().collect::<Vec<_>>(), vec!["FrSt", "LASt", "miDl"]);
+        assert_eq!(stats["miDl"], ChunkStats { count: 2, bytes: SerializedSize(12 + 18 + 12 + 4) });
+        assert_eq!(png.total_size().get(), png.as_bytes().len() as u64);
+    }
+
+    #[test]
+    fn test_total_size_is_header_plus_serialized_chunks() {
+        let png = testing_png();
+        let chunks: SerializedSize = png.chunks().iter().map(Chunk::serialized_size).sum();
+        let stats: SerializedSize = png.chunk_stats().values().map(|s| s.bytes).sum();
+
+        assert_eq!(png.total_size(), SerializedSize(8) + chunks);
+        assert_eq!(stats, chunks);
+    }
+
+    fn crafted(types: &[&str]) -> Vec<u8> {
+        let chunks: Vec<u8> = types.iter()
+            .enumerate()
+            .flat_map(|(i, t)| chunk_from_strings(t, &format!("chunk {}", i)).unwrap().as_bytes())
+            .collect();
+        Png::SIGNATURE.iter().chain(chunks.iter()).copied().collect()
+    }
+
+    #[test]
+    fn test_strict_rejects_duplicate_ihdr() {
+        let bytes = crafted(&["IHDR", "IDAT", "IHDR", "IEND"]);
+        let err = Png::try_from(bytes.as_slice()).unwrap_err();
+        assert_eq!(err.downcast_ref::<StructureError>(), Some(&StructureError::DuplicateIhdr { index: 2 }));
+    }
+
+    #[test]
+    fn test_strict_rejects_chunk_after_iend() {
+        let bytes = crafted(&["IHDR", "IEND", "ruSt", "IEND"]);
+        let err = Png::try_from(bytes.as_slice()).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StructureError>(),
+            Some(&StructureError::ChunkAfterIend { index: 2, chunk_type: "ruSt".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_lenient_keeps_duplicates_and_points_at_first() {
+        let bytes = crafted(&["IHDR", "IDAT", "IHDR", "IEND", "ruSt", "IEND"]);
+        let (png, warnings) = Png::parse_with(&bytes, &ParseOptions { lenient: true, ..Default::default() }).unwrap();
+
+        assert_eq!(png.chunks().len(), 6);
+        assert_eq!(warnings, vec![
+            StructureError::DuplicateIhdr { index: 2 },
+            StructureError::ChunkAfterIend { index: 4, chunk_type: "ruSt".to_string() },
+            StructureError::ChunkAfterIend { index: 5, chunk_type: "IEND".to_string() },
+        ]);
+        assert_eq!(png.structure(), Structure { ihdr: Some(0), iend: Some(3) });
+        assert_eq!(png.chunk_by_type("IHDR").unwrap().data_as_string().unwrap(), "chunk 0");
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data_as_string().unwrap(), "chunk 4");
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_cloned_png_is_independent() {
+        let template = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut copy = template.clone();
+        copy.chunks[3].map_data(|data| data[0] ^= 0xff);
+
+        assert_eq!(template.as_bytes(), PNG_FILE.to_vec());
+        assert_ne!(copy.as_bytes(), PNG_FILE.to_vec());
+        assert!(copy.verify_integrity().is_empty());
+    }
+
+    fn many_chunks(count: usize) -> Vec<u8> {
+        let chunk = chunk_from_strings("ruSt", "x").unwrap().as_bytes();
+        let mut bytes = Png::SIGNATURE.to_vec();
+        for _ in 0..count {
+            bytes.extend_from_slice(&chunk);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_expired_deadline_interrupts() {
+        let bytes = many_chunks(10);
+        let options = ParseOptions { deadline: Some(Instant::now()), ..Default::default() };
+
+        let err = Png::parse_with(&bytes, &options).unwrap_err();
+        let interrupted = err.downcast_ref::<Interrupted>().unwrap();
+        assert_eq!(interrupted.kind, InterruptKind::DeadlineExceeded);
+        assert!(interrupted.partial.chunks().is_empty());
+    }
+
+    #[test]
+    fn test_future_deadline_does_not_interrupt() {
+        let bytes = many_chunks(10);
+        let deadline = Instant::now() + std::time::Duration::from_secs(60);
+        let options = ParseOptions { deadline: Some(deadline), ..Default::default() };
+
+        assert_eq!(Png::parse_with(&bytes, &options).unwrap().0.chunks().len(), 10);
+    }
+
+    #[test]
+    fn test_cancel_from_another_thread() {
+        let total = 1_000_000;
+        let bytes = many_chunks(total);
+        let token = CancelToken::new();
+        let options = ParseOptions { cancel: Some(token.clone()), ..Default::default() };
+
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            token.cancel();
+        });
+        let started = Instant::now();
+        let result = Png::parse_with(&bytes, &options);
+        canceller.join().unwrap();
+
+        let err = result.unwrap_err();
+        let interrupted = err.downcast_ref::<Interrupted>().unwrap();
+        assert_eq!(interrupted.kind, InterruptKind::Cancelled);
+        assert!(interrupted.partial.chunks().len() < total);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -288,7 +1615,7 @@ mod tests {
             .flat_map(|chunk| chunk.as_bytes())
             .collect();
 
-        let bytes: Vec<u8> = Png::STANDARD_HEADER
+        let bytes: Vec<u8> = Png::SIGNATURE
             .iter()
             .chain(chunk_bytes.iter())
             .copied()
@@ -300,7 +1627,7 @@ mod tests {
     }
 
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
-    const PNG_FILE: [u8; 4803] = [
+    pub(crate) const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
         6, 0, 0, 0, 30, 63, 136, 177, 0, 0, 0, 1, 115, 82, 71, 66, 0, 174, 206, 28, 233, 0, 0, 0,
         4, 103, 65, 77, 65, 0, 0, 177, 143, 11, 252, 97, 5, 0, 0, 0, 9, 112, 72, 89, 115, 0, 0, 14,
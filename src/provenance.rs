@@ -0,0 +1,263 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Error, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crc32::crc_of;
+use crate::png::Png;
+
+pub const PROVENANCE_CHUNK_TYPE: &str = "prVn";
+pub const MAX_PROVENANCE_BYTES: usize = 4096;
+
+const TOOL_NAME: &str = env!("CARGO_PKG_NAME");
+const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvenanceRecord {
+    pub tool: String,
+    pub version: String,
+    pub operation: String,
+    pub timestamp: u64,
+    pub params_hash: u32,
+}
+
+impl ProvenanceRecord {
+    pub fn new(operation: &str, params: &[&str]) -> ProvenanceRecord {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        ProvenanceRecord {
+            tool: TOOL_NAME.to_string(),
+            version: TOOL_VERSION.to_string(),
+            operation: operation.to_string(),
+            timestamp,
+            params_hash: crc_of(&[params.join("\0").as_bytes()]),
+        }
+    }
+}
+
+impl fmt::Display for ProvenanceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} at {} (params {:08x})",
+               self.tool, self.version, self.operation, self.timestamp, self.params_hash)
+    }
+}
+
+impl Png {
+    pub fn provenance(&self) -> Vec<ProvenanceRecord> {
+        self.chunk_by_type(PROVENANCE_CHUNK_TYPE)
+            .and_then(|chunk| decode_records(chunk.data()).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn record_provenance(&mut self, record: ProvenanceRecord) -> Result<()> {
+        let mut records = self.provenance();
+        records.push(record);
+
+        let mut data = encode_records(&records);
+        while data.len() > MAX_PROVENANCE_BYTES && records.len() > 1 {
+            records.remove(0);
+            data = encode_records(&records);
+        }
+
+        while self.remove_chunk(PROVENANCE_CHUNK_TYPE).is_ok() {}
+        let i_end = self.remove_chunk("IEND").ok();
+        self.append_chunk(Chunk::new(ChunkType::from_str(PROVENANCE_CHUNK_TYPE)?, data));
+        if let Some(i_end) = i_end {
+            self.append_chunk(i_end);
+        }
+        Ok(())
+    }
+}
+
+// The chunk body is a CBOR array of maps. Only the handful of major types the
+// records need are supported: unsigned ints, text strings, arrays and maps.
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+
+fn encode_records(records: &[ProvenanceRecord]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_head(&mut out, MAJOR_ARRAY, records.len() as u64);
+    for record in records {
+        write_head(&mut out, MAJOR_MAP, 5);
+        write_text(&mut out, "tool");
+        write_text(&mut out, &record.tool);
+        write_text(&mut out, "version");
+        write_text(&mut out, &record.version);
+        write_text(&mut out, "operation");
+        write_text(&mut out, &record.operation);
+        write_text(&mut out, "timestamp");
+        write_head(&mut out, MAJOR_UNSIGNED, record.timestamp);
+        write_text(&mut out, "params_hash");
+        write_head(&mut out, MAJOR_UNSIGNED, record.params_hash as u64);
+    }
+    out
+}
+
+fn write_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..=23 => out.push(major | value as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, value as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(value as u16).to_be_bytes());
+        },
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        },
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&value.to_be_bytes());
+        },
+    }
+}
+
+fn write_text(out: &mut Vec<u8>, text: &str) {
+    write_head(out, MAJOR_TEXT, text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+fn decode_records(bytes: &[u8]) -> Result<Vec<ProvenanceRecord>> {
+    let mut reader = CborReader { bytes, pos: 0 };
+    let count = reader.expect_head(MAJOR_ARRAY)?;
+    let mut records = Vec::new();
+
+    for _ in 0..count {
+        let fields = reader.expect_head(MAJOR_MAP)?;
+        let mut record = ProvenanceRecord {
+            tool: String::new(),
+            version: String::new(),
+            operation: String::new(),
+            timestamp: 0,
+            params_hash: 0,
+        };
+        for _ in 0..fields {
+            match reader.read_text()?.as_str() {
+                "tool" => record.tool = reader.read_text()?,
+                "version" => record.version = reader.read_text()?,
+                "operation" => record.operation = reader.read_text()?,
+                "timestamp" => record.timestamp = reader.expect_head(MAJOR_UNSIGNED)?,
+                "params_hash" => record.params_hash = u32::try_from(reader.expect_head(MAJOR_UNSIGNED)?)?,
+                other => return Err(format!("Unknown provenance field: {}", other).into()),
+            }
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+struct CborReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl CborReader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| Error::from("Truncated provenance data"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn expect_head(&mut self, major: u8) -> Result<u64> {
+        let initial = self.take(1)?[0];
+        if initial >> 5 != major {
+            return Err(format!("Unexpected CBOR major type {}", initial >> 5).into());
+        }
+        let value = match initial & 0x1f {
+            n @ 0..=23 => n as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into()?) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into()?) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into()?),
+            _ => return Err("Unsupported CBOR length encoding".into()),
+        };
+        Ok(value)
+    }
+
+    fn read_text(&mut self) -> Result<String> {
+        let len = usize::try_from(self.expect_head(MAJOR_TEXT)?)?;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ]).unwrap()
+    }
+
+    #[test]
+    fn test_no_provenance() {
+        assert!(testing_png().provenance().is_empty());
+    }
+
+    #[test]
+    fn test_records_are_ordered() {
+        let mut png = testing_png();
+        png.record_provenance(ProvenanceRecord::new("encode", &["ruSt", "hello"])).unwrap();
+        png.record_provenance(ProvenanceRecord::new("remove", &["ruSt"])).unwrap();
+        png.record_provenance(ProvenanceRecord::new("encode", &["ruSt", "again"])).unwrap();
+
+        let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        let operations: Vec<String> = reparsed.provenance().into_iter()
+            .map(|r| r.operation)
+            .collect();
+
+        assert_eq!(operations, vec!["encode", "remove", "encode"]);
+        assert_eq!(reparsed.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+        assert_eq!(reparsed.chunks().iter()
+                       .filter(|c| *c.chunk_type() == PROVENANCE_CHUNK_TYPE)
+                       .count(), 1);
+    }
+
+    #[test]
+    fn test_record_fields_round_trip() {
+        let record = ProvenanceRecord {
+            tool: "pngme".to_string(),
+            version: "0.1.0".to_string(),
+            operation: "encode".to_string(),
+            timestamp: 1_700_000_000,
+            params_hash: 0xdead_beef,
+        };
+        let decoded = decode_records(&encode_records(std::slice::from_ref(&record))).unwrap();
+        assert_eq!(decoded, vec![record]);
+    }
+
+    #[test]
+    fn test_oldest_records_are_evicted() {
+        let mut png = testing_png();
+        for i in 0..200 {
+            png.record_provenance(ProvenanceRecord::new(&format!("op{}", i), &[])).unwrap();
+        }
+
+        let records = png.provenance();
+        let chunk = png.chunk_by_type(PROVENANCE_CHUNK_TYPE).unwrap();
+        assert!(chunk.data().len() <= MAX_PROVENANCE_BYTES);
+        assert!(records.len() < 200);
+        assert_eq!(records.last().unwrap().operation, "op199");
+    }
+
+    #[test]
+    fn test_params_hash_depends_on_params() {
+        let a = ProvenanceRecord::new("encode", &["ruSt", "a"]);
+        let b = ProvenanceRecord::new("encode", &["ruSt", "b"]);
+        assert_ne!(a.params_hash, b.params_hash);
+    }
+}
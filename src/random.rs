@@ -0,0 +1,57 @@
+// Random bytes from the OS, for encryption salts and nonces, signing keys, split message ids and
+// random chunk types. Other targets build, but these fail there with `PngMsgError::RandomUnavailable`.
+
+use crate::error::PngMsgError;
+
+#[cfg(unix)]
+pub fn random_bytes(buf: &mut [u8]) -> Result<(), PngMsgError> {
+    use std::fs::File;
+    use std::io::Read;
+
+    File::open("/dev/urandom")?.read_exact(buf)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn random_bytes(buf: &mut [u8]) -> Result<(), PngMsgError> {
+    use std::ffi::c_void;
+    use std::io;
+
+    // BCRYPT_USE_SYSTEM_PREFERRED_RNG, which needs no algorithm handle.
+    const SYSTEM_PREFERRED_RNG: u32 = 0x0000_0002;
+
+    #[link(name = "bcrypt")]
+    extern "system" {
+        fn BCryptGenRandom(algorithm: *mut c_void, buffer: *mut u8, len: u32, flags: u32) -> i32;
+    }
+
+    for part in buf.chunks_mut(u32::MAX as usize) {
+        // `part` is valid for writes of its length, which fits in a u32.
+        let status = unsafe { BCryptGenRandom(std::ptr::null_mut(), part.as_mut_ptr(), part.len() as u32, SYSTEM_PREFERRED_RNG) };
+        // An NTSTATUS below 0 is an error.
+        if status < 0 {
+            return Err(io::Error::other(format!("BCryptGenRandom failed with status {:#010x}", status)).into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn random_bytes(_: &mut [u8]) -> Result<(), PngMsgError> {
+    Err(PngMsgError::RandomUnavailable)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fills_the_buffer() {
+        let (mut a, mut b) = ([0u8; 32], [0u8; 32]);
+        random_bytes(&mut a).unwrap();
+        random_bytes(&mut b).unwrap();
+        assert_ne!(a, b);
+        random_bytes(&mut []).unwrap();
+    }
+}
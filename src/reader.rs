@@ -0,0 +1,294 @@
+use std::io::{self, Read};
+
+use crate::chunk::{checked_length, Chunk, ChunkParseError, ChunkPosition, CrcMismatch, CHUNK_OVERHEAD};
+use crate::chunk_type::ChunkType;
+use crate::error::PngMsgError;
+use crate::limits::Limits;
+use crate::png::Png;
+
+// Reads chunks one at a time, so only the chunk being returned is ever held in memory.
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    done: bool,
+    // Of the chunk being read, for errors; moves on once its CRC has been read.
+    position: ChunkPosition,
+    limits: Limits,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(reader: R) -> Result<ChunkReader<R>, PngMsgError> {
+        ChunkReader::with_limits(reader, Limits::default())
+    }
+
+    // The chunk size and count limits are checked from each header, before any data is read.
+    pub fn with_limits(mut reader: R, limits: Limits) -> Result<ChunkReader<R>, PngMsgError> {
+        let mut header = [0; 8];
+        let got = read_full(&mut reader, &mut header)?;
+        Png::check_signature(&header[..got])?;
+        let position = ChunkPosition { offset: 8, index: 0, chunk_type: None };
+        Ok(ChunkReader { reader, done: false, position, limits })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // Skips the data of every other chunk without buffering it; skipped CRCs are not checked.
+    pub fn find_chunk(&mut self, wanted: &ChunkType) -> Option<Result<Chunk, PngMsgError>> {
+        while !self.done {
+            let (length, chunk_type) = match self.read_header() {
+                Ok(Some(header)) => header,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(self.fail(e))),
+            };
+            if chunk_type == *wanted {
+                return Some(self.read_body(length, chunk_type).map_err(|e| self.fail(e)));
+            }
+            if let Err(e) = self.skip_body(length) {
+                return Some(Err(self.fail(e)));
+            }
+        }
+        None
+    }
+
+    // A CRC mismatch only spoils its own chunk and already says where it was; any other error
+    // leaves the stream position unknown, so nothing more is read.
+    fn fail(&mut self, e: PngMsgError) -> PngMsgError {
+        match e {
+            PngMsgError::CrcMismatch(_) => e,
+            e => {
+                self.done = true;
+                e.at(self.position)
+            }
+        }
+    }
+
+    // Called with the chunk's CRC read; returns where the chunk was.
+    fn advance(&mut self, length: u32) -> ChunkPosition {
+        let finished = self.position;
+        self.position = ChunkPosition {
+            offset: finished.offset + CHUNK_OVERHEAD + length as u64,
+            index: finished.index + 1,
+            chunk_type: None,
+        };
+        finished
+    }
+
+    // `None` at a clean end of input; the type is checked here so a bad one stops the reader.
+    fn read_header(&mut self) -> Result<Option<(u32, ChunkType)>, PngMsgError> {
+        let mut header = [0; 8];
+        let got = read_full(&mut self.reader, &mut header)?;
+        match got {
+            0 => {
+                self.done = true;
+                Ok(None)
+            }
+            1..=3 => Err(ChunkParseError::MissingLength { got }.into()),
+            4..=7 => Err(ChunkParseError::MissingChunkType { got: got - 4 }.into()),
+            _ => {
+                self.position = ChunkPosition::of(self.position.offset, self.position.index, &header);
+                let length = checked_length(u32::from_be_bytes(header[..4].try_into().unwrap()) as usize)?;
+                self.limits.check_chunk(length)?;
+                self.limits.check_chunk_count(self.position.index + 1)?;
+                let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&header[4..]).unwrap())?;
+                if &chunk_type.bytes() == b"IEND" {
+                    self.done = true;
+                }
+                Ok(Some((length, chunk_type)))
+            }
+        }
+    }
+
+    fn read_body(&mut self, length: u32, chunk_type: ChunkType) -> Result<Chunk, PngMsgError> {
+        let mut data = Vec::new();
+        let available = (&mut self.reader).take(length as u64).read_to_end(&mut data)?;
+        if available < length as usize {
+            return Err(ChunkParseError::TruncatedData { declared: length, available }.into());
+        }
+        let stored = self.read_crc()?;
+        let position = self.advance(length);
+        let chunk = Chunk::new(chunk_type, data);
+        if chunk.crc() != stored {
+            return Err(CrcMismatch { stored, computed: chunk.crc(), position: Some(position) }.into());
+        }
+        Ok(chunk)
+    }
+
+    fn skip_body(&mut self, length: u32) -> Result<(), PngMsgError> {
+        let skipped = io::copy(&mut (&mut self.reader).take(length as u64), &mut io::sink())?;
+        if skipped < length as u64 {
+            return Err(ChunkParseError::TruncatedData { declared: length, available: skipped as usize }.into());
+        }
+        self.read_crc()?;
+        self.advance(length);
+        Ok(())
+    }
+
+    fn read_crc(&mut self) -> Result<u32, PngMsgError> {
+        let mut crc = [0; 4];
+        let got = read_full(&mut self.reader, &mut crc)?;
+        if got < 4 {
+            return Err(ChunkParseError::MissingCrc { got }.into());
+        }
+        Ok(u32::from_be_bytes(crc))
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk, PngMsgError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_header() {
+            Ok(Some((length, chunk_type))) => Some(self.read_body(length, chunk_type).map_err(|e| self.fail(e))),
+            Ok(None) => None,
+            Err(e) => Some(Err(self.fail(e))),
+        }
+    }
+}
+
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn png_bytes(chunks: Vec<Chunk>) -> Vec<u8> {
+        Png::from_chunks_unchecked(chunks).as_bytes()
+    }
+
+    // Counts what the reader pulls, to show how far into the input it went.
+    struct Counting<'a> {
+        inner: &'a [u8],
+        read: usize,
+    }
+
+    impl Read for Counting<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_reads_same_chunks_as_png() {
+        let bytes = std::fs::read(format!("{}/tests/fixtures/pixels.png", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let streamed: Vec<Chunk> = ChunkReader::new(bytes.as_slice()).unwrap().map(Result::unwrap).collect();
+        assert_eq!(streamed, Png::try_from(bytes.as_slice()).unwrap().chunks());
+    }
+
+    #[test]
+    fn test_stops_after_iend_and_continues_past_bad_crc() {
+        let mut bytes = png_bytes(vec![chunk("IHDR", &[0; 13]), chunk("ruSt", b"bad"), chunk("ruSt", b"good"), chunk("IEND", &[])]);
+        // The last CRC byte of the first ruSt chunk.
+        bytes[8 + 25 + 14] ^= 0xff;
+        bytes.extend_from_slice(b"trailing junk");
+
+        let results: Vec<Result<Chunk, PngMsgError>> = ChunkReader::new(bytes.as_slice()).unwrap().collect();
+        assert_eq!(results.len(), 4);
+        // The ruSt chunk starts after the signature and the 25 bytes of IHDR.
+        let position = ChunkPosition { offset: 33, index: 1, chunk_type: Some(ChunkType::from_str("ruSt").unwrap()) };
+        assert!(matches!(results[1], Err(PngMsgError::CrcMismatch(CrcMismatch { position: Some(p), .. })) if p == position));
+        assert_eq!(results[2].as_ref().unwrap().data(), b"good");
+        assert_eq!(results[3].as_ref().unwrap().chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_truncation_ends_iteration() {
+        let bytes = png_bytes(vec![chunk("IHDR", &[0; 13]), chunk("ruSt", b"cut off")]);
+        let mut reader = ChunkReader::new(&bytes[..bytes.len() - 5]).unwrap();
+
+        assert!(reader.next().unwrap().is_ok());
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.to_string(), "chunk #1 (ruSt) at offset 0x21: Malformed chunk: data cut off after 6 of 7 bytes");
+        assert!(matches!(
+            error,
+            PngMsgError::MalformedChunk { source, .. }
+                if matches!(*source, PngMsgError::TruncatedChunk(ChunkParseError::TruncatedData { declared: 7, available: 6 }))
+        ));
+        assert!(reader.next().is_none());
+        assert!(matches!(
+            ChunkReader::new(&b"\x89PNG"[..]),
+            Err(PngMsgError::InvalidSignature { found }) if found == b"\x89PNG"
+        ));
+    }
+
+    #[test]
+    fn test_length_over_spec_limit_stops_reading() {
+        let mut bytes = png_bytes(vec![chunk("IHDR", &[0; 13]), chunk("ruSt", b"huge"), chunk("IEND", &[])]);
+        bytes[8 + 25..8 + 29].copy_from_slice(&u32::MAX.to_be_bytes());
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(PngMsgError::MalformedChunk { position: ChunkPosition { offset: 33, index: 1, .. }, source }))
+                if matches!(*source, PngMsgError::DataTooLarge { len: 0xFFFF_FFFF })
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_limits_are_checked_before_reading_data() {
+        let mut bytes = png_bytes(vec![chunk("IHDR", &[0; 13]), chunk("ruSt", b"huge"), chunk("IEND", &[])]);
+        bytes[8 + 25..8 + 29].copy_from_slice(&(100u32 << 20).to_be_bytes());
+        let mut reader = ChunkReader::new(Counting { inner: &bytes, read: 0 }).unwrap();
+
+        assert!(reader.next().unwrap().is_ok());
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.to_string(), "chunk #1 (ruSt) at offset 0x21: 104857600 is over the max_chunk_bytes limit of 67108864");
+        assert!(reader.next().is_none());
+        // Signature, IHDR and the ruSt header only.
+        assert_eq!(reader.into_inner().read, 8 + 25 + 8);
+
+        let bytes = png_bytes(vec![chunk("IHDR", &[0; 13]), chunk("ruSt", b"a"), chunk("IEND", &[])]);
+        let limits = Limits { max_chunks: Some(2), ..Limits::unlimited() };
+        let results: Vec<_> = ChunkReader::with_limits(bytes.as_slice(), limits).unwrap().collect();
+        assert_eq!(results.len(), 3);
+        assert!(matches!(
+            &results[2],
+            Err(PngMsgError::MalformedChunk { source, .. })
+                if matches!(source.as_ref(), PngMsgError::LimitExceeded(e) if e.limit == "max_chunks")
+        ));
+    }
+
+    #[test]
+    fn test_find_chunk_exits_early() {
+        let idat = vec![7; 1 << 20];
+        let bytes = png_bytes(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("IDAT", &idat),
+            chunk("ruSt", b"found"),
+            chunk("IDAT", &idat),
+            chunk("IEND", &[]),
+        ]);
+
+        let mut reader = ChunkReader::new(Counting { inner: &bytes, read: 0 }).unwrap();
+        let found = reader.find_chunk(&ChunkType::from_str("ruSt").unwrap()).unwrap().unwrap();
+        assert_eq!(found.data(), b"found");
+        assert!(reader.into_inner().read < bytes.len() / 2 + 100);
+
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        assert!(reader.find_chunk(&ChunkType::from_str("zzZz").unwrap()).is_none());
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::BTreeSet;
+
+use crate::chunk::SerializedSize;
+use crate::png::{ChunkStats, Png};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSizeDelta {
+    pub chunk_type: String,
+    pub before: ChunkStats,
+    pub after: ChunkStats,
+}
+
+impl ChunkSizeDelta {
+    pub fn delta(&self) -> i64 {
+        self.before.bytes.delta(self.after.bytes)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeReport {
+    pub before: SerializedSize,
+    pub after: SerializedSize,
+    pub chunks: Vec<ChunkSizeDelta>,
+}
+
+impl SizeReport {
+    // Rows are sorted by chunk type and include every type present on either side.
+    pub fn diff(before: &Png, after: &Png) -> SizeReport {
+        let before_stats = before.chunk_stats();
+        let after_stats = after.chunk_stats();
+        let types: BTreeSet<&String> = before_stats.keys().chain(after_stats.keys()).collect();
+
+        let chunks = types.into_iter()
+            .map(|chunk_type| ChunkSizeDelta {
+                chunk_type: chunk_type.clone(),
+                before: before_stats.get(chunk_type).copied().unwrap_or_default(),
+                after: after_stats.get(chunk_type).copied().unwrap_or_default(),
+            })
+            .collect();
+
+        SizeReport { before: before.total_size(), after: after.total_size(), chunks }
+    }
+
+    pub fn delta(&self) -> i64 {
+        self.before.delta(self.after)
+    }
+
+    pub fn percent(&self) -> f64 {
+        if self.before.get() == 0 {
+            return 0.0;
+        }
+        self.delta() as f64 * 100.0 / self.before.get() as f64
+    }
+
+    pub fn changed(&self) -> impl Iterator<Item = &ChunkSizeDelta> {
+        self.chunks.iter().filter(|c| c.before != c.after)
+    }
+
+    pub fn to_json(&self) -> String {
+        let rows: Vec<String> = self.chunks.iter()
+            .map(|c| format!(
+                "    {{\"chunk_type\": \"{}\", \"count_before\": {}, \"count_after\": {}, \"bytes_before\": {}, \"bytes_after\": {}, \"delta\": {}}}",
+                c.chunk_type, c.before.count, c.after.count, c.before.bytes, c.after.bytes, c.delta()
+            ))
+            .collect();
+        format!(
+            "{{\n  \"before\": {},\n  \"after\": {},\n  \"delta\": {},\n  \"percent\": {:.2},\n  \"chunks\": [\n{}\n  ]\n}}\n",
+            self.before, self.after, self.delta(), self.percent(), rows.join(",\n")
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, len: usize) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![b'x'; len])
+    }
+
+    fn metadata_heavy() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", 13),
+            chunk("tEXt", 100),
+            chunk("tEXt", 50),
+            chunk("zTXt", 30),
+            chunk("IDAT", 200),
+            chunk("IEND", 0),
+        ]).unwrap()
+    }
+
+    #[test]
+    fn test_diff_after_stripping_text() {
+        let before = metadata_heavy();
+        let mut after = metadata_heavy();
+        while after.remove_chunk("tEXt").is_ok() {}
+        after.remove_chunk("zTXt").unwrap();
+
+        let report = SizeReport::diff(&before, &after);
+        assert_eq!(report.before, SerializedSize(8 + 6 * 12 + 13 + 100 + 50 + 30 + 200));
+        assert_eq!(report.delta(), -(3 * 12 + 180));
+
+        let changed: Vec<(&str, i64)> = report.changed().map(|c| (c.chunk_type.as_str(), c.delta())).collect();
+        assert_eq!(changed, vec![("tEXt", -174), ("zTXt", -42)]);
+        assert_eq!(report.chunks.iter().map(|c| c.chunk_type.as_str()).collect::<Vec<_>>(),
+                   vec!["IDAT", "IEND", "IHDR", "tEXt", "zTXt"]);
+    }
+
+    #[test]
+    fn test_diff_includes_added_types() {
+        let before = metadata_heavy();
+        let mut after = metadata_heavy();
+        after.append_chunk(chunk("ruSt", 5));
+
+        let report = SizeReport::diff(&before, &after);
+        let added = report.changed().next().unwrap();
+        assert_eq!(added.chunk_type, "ruSt");
+        assert_eq!(added.before, ChunkStats::default());
+        assert_eq!(added.after, ChunkStats { count: 1, bytes: SerializedSize(17) });
+    }
+
+    #[test]
+    fn test_json_is_stable() {
+        let before = metadata_heavy();
+        let mut after = metadata_heavy();
+        after.remove_chunk("zTXt").unwrap();
+
+        let json = SizeReport::diff(&before, &after).to_json();
+        assert_eq!(json, SizeReport::diff(&before, &after).to_json());
+        assert!(json.starts_with("{\n  \"before\": 473,\n  \"after\": 431,\n  \"delta\": -42,\n  \"percent\": -8.88,\n"));
+        assert!(json.contains("{\"chunk_type\": \"zTXt\", \"count_before\": 1, \"count_after\": 0, \"bytes_before\": 42, \"bytes_after\": 0, \"delta\": -42}\n  ]"));
+    }
+
+    #[test]
+    fn test_json_survives_reparse_and_reordering() {
+        let png = metadata_heavy();
+        let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(SizeReport::diff(&png, &png).to_json(), SizeReport::diff(&reparsed, &reparsed).to_json());
+
+        let shuffled = Png::from_chunks(vec![
+            chunk("IHDR", 13),
+            chunk("zTXt", 30),
+            chunk("tEXt", 50),
+            chunk("IDAT", 200),
+            chunk("tEXt", 100),
+            chunk("IEND", 0),
+        ]).unwrap();
+        assert_eq!(SizeReport::diff(&png, &png).to_json(), SizeReport::diff(&shuffled, &shuffled).to_json());
+    }
+}
@@ -0,0 +1,228 @@
+//! Minimal signature rules for `scan`.
+//!
+//! A rules file is a list of `[[rule]]` tables, each with `name`, `target` and exactly one of
+//! `hex` or `string`:
+//!
+//! ```text
+//! # Known loader marker
+//! [[rule]]
+//! name = "loader"
+//! target = "chunk-data"
+//! hex = "DE AD ?? EF"
+//! ```
+//!
+//! `target` is one of `chunk-data` (every chunk before the first IEND), `trailing` (chunks
+//! after the first IEND) or `text` (tEXt, zTXt and iTXt chunks). In `hex` patterns `??` matches
+//! any byte. Regular expressions are not supported.
+
+use std::str::FromStr;
+
+use crate::{Error, Result};
+use crate::png::Png;
+use crate::spec::{self, Table};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleTarget {
+    ChunkData,
+    Trailing,
+    Text,
+}
+
+impl FromStr for RuleTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "chunk-data" => Ok(RuleTarget::ChunkData),
+            "trailing" => Ok(RuleTarget::Trailing),
+            "text" => Ok(RuleTarget::Text),
+            other => Err(format!("Unknown rule target: {}", other).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub name: String,
+    pub target: RuleTarget,
+    // `None` is a `??` wildcard.
+    pattern: Vec<Option<u8>>,
+}
+
+impl Rule {
+    fn find_all(&self, data: &[u8]) -> Vec<usize> {
+        if self.pattern.is_empty() || data.len() < self.pattern.len() {
+            return Vec::new();
+        }
+        data.windows(self.pattern.len())
+            .enumerate()
+            .filter(|(_, window)| {
+                window.iter().zip(&self.pattern).all(|(byte, p)| p.is_none_or(|p| p == *byte))
+            })
+            .map(|(offset, _)| offset)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleMatch {
+    pub rule: String,
+    pub chunk_index: usize,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    // Matches are ordered by chunk index, then rule order, then offset.
+    pub fn scan(&self, png: &Png) -> Vec<RuleMatch> {
+        let iend = png.structure().iend.unwrap_or(usize::MAX);
+        let mut matches = Vec::new();
+
+        for (chunk_index, chunk) in png.chunks().iter().enumerate() {
+            let is_text = matches!(&chunk.chunk_type().bytes(), b"tEXt" | b"zTXt" | b"iTXt");
+            for rule in &self.rules {
+                let applies = match rule.target {
+                    RuleTarget::ChunkData => chunk_index <= iend,
+                    RuleTarget::Trailing => chunk_index > iend,
+                    RuleTarget::Text => is_text,
+                };
+                if !applies {
+                    continue;
+                }
+                matches.extend(rule.find_all(chunk.data()).into_iter().map(|offset| RuleMatch {
+                    rule: rule.name.clone(),
+                    chunk_index,
+                    offset,
+                }));
+            }
+        }
+        matches
+    }
+}
+
+impl FromStr for RuleSet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rules = spec::parse(s)?
+            .iter()
+            .map(|table| match table.name.as_str() {
+                "rule" => parse_rule(table),
+                other => Err(format!("line {}: unknown table [{}]", table.line, other).into()),
+            })
+            .collect::<Result<Vec<Rule>>>()?;
+        Ok(RuleSet { rules })
+    }
+}
+
+fn parse_rule(table: &Table) -> Result<Rule> {
+    table.reject_unknown(&["name", "target", "hex", "string"])?;
+
+    let name = table.str("name")?
+        .ok_or_else(|| format!("line {}: rule is missing `name`", table.line))?
+        .to_string();
+    let target = table.str("target")?
+        .ok_or_else(|| format!("rule {} is missing `target`", name))?
+        .parse()?;
+    let pattern = match (table.str("hex")?, table.str("string")?) {
+        (Some(hex), None) => parse_hex(hex, table.get("hex").map_or(table.line, |e| e.line))?,
+        (None, Some(string)) => string.bytes().map(Some).collect(),
+        (Some(_), Some(_)) => return Err(format!("rule {} takes exactly one pattern", name).into()),
+        (None, None) => return Err(format!("rule {} needs a `hex` or `string` pattern", name).into()),
+    };
+    if pattern.is_empty() {
+        return Err(format!("rule {} has an empty pattern", name).into());
+    }
+    Ok(Rule { name, target, pattern })
+}
+
+fn parse_hex(value: &str, line: usize) -> Result<Vec<Option<u8>>> {
+    value.split_whitespace()
+        .map(|token| match token {
+            "??" => Ok(None),
+            _ => u8::from_str_radix(token, 16)
+                .map(Some)
+                .map_err(|_| format!("line {}: `{}` is not a hex byte or ??", line, token).into()),
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    const RULES: &str = r#"
+# Two signatures used by the scan tests.
+[[rule]]
+name = "dead-beef"
+target = "chunk-data"
+hex = "DE AD ?? EF"
+
+[[rule]]
+name = "eval"
+target = "text"
+string = "eval("
+"#;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn crafted() -> Png {
+        Png::from_chunks_unchecked(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("tEXt", b"Comment\0eval(payload)"),
+            chunk("ruSt", &[0x00, 0x11, 0xde, 0xad, 0x42, 0xef, 0x22, 0xde, 0xad]),
+            chunk("IEND", &[]),
+            chunk("trAl", &[0xde, 0xad, 0xbe, 0xef]),
+        ])
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = RuleSet::from_str(RULES).unwrap();
+        assert_eq!(rules.rules().len(), 2);
+        assert_eq!(rules.rules()[0].name, "dead-beef");
+        assert_eq!(rules.rules()[0].target, RuleTarget::ChunkData);
+        assert_eq!(rules.rules()[0].pattern, vec![Some(0xde), Some(0xad), None, Some(0xef)]);
+        assert_eq!(rules.rules()[1].target, RuleTarget::Text);
+    }
+
+    #[test]
+    fn test_scan_wildcard_in_chunk_middle() {
+        let matches = RuleSet::from_str(RULES).unwrap().scan(&crafted());
+        assert_eq!(matches, vec![
+            RuleMatch { rule: "eval".to_string(), chunk_index: 1, offset: 8 },
+            RuleMatch { rule: "dead-beef".to_string(), chunk_index: 2, offset: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_trailing_target() {
+        let rules = RuleSet::from_str("[[rule]]\nname = \"tail\"\ntarget = \"trailing\"\nhex = \"DE AD BE EF\"\n").unwrap();
+        assert_eq!(rules.scan(&crafted()), vec![
+            RuleMatch { rule: "tail".to_string(), chunk_index: 4, offset: 0 },
+        ]);
+    }
+
+    #[test]
+    fn test_rule_errors() {
+        assert!(RuleSet::from_str("name = \"x\"").is_err());
+        assert!(RuleSet::from_str("[[rules]]\nname = \"x\"\ntarget = \"text\"\nhex = \"00\"").is_err());
+        assert!(RuleSet::from_str("[[rule]]\nname = \"x\"\ntarget = \"pixels\"\nhex = \"00\"").is_err());
+        assert!(RuleSet::from_str("[[rule]]\nname = \"x\"\ntarget = \"text\"\nhex = \"0G\"").is_err());
+        assert!(RuleSet::from_str("[[rule]]\nname = \"x\"\ntarget = \"text\"").is_err());
+        assert!(RuleSet::from_str("[[rule]]\nname = \"x\"\ntarget = \"text\"\nhex = \"00\"\nstring = \"a\"").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+// A small TOML subset shared by the rules and fixture spec formats: `[table]` and
+// `[[array-of-tables]]` headers, `key = value` pairs with double-quoted strings (supporting
+// `\n`, `\t`, `\0`, `\"` and `\\` escapes), integers and booleans, and `#` comment lines.
+
+use crate::Result;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Str(String),
+    Int(u64),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub name: String,
+    pub line: usize,
+    pub entries: Vec<Entry>,
+}
+
+impl Table {
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.key == key)
+    }
+
+    pub fn str(&self, key: &str) -> Result<Option<&str>> {
+        match self.get(key) {
+            Some(Entry { value: Value::Str(s), .. }) => Ok(Some(s)),
+            Some(entry) => Err(format!("line {}: `{}` must be a string", entry.line, key).into()),
+            None => Ok(None),
+        }
+    }
+
+    #[cfg_attr(not(any(test, feature = "fixture")), allow(dead_code))]
+    pub fn int(&self, key: &str) -> Result<Option<u64>> {
+        match self.get(key) {
+            Some(Entry { value: Value::Int(n), .. }) => Ok(Some(*n)),
+            Some(entry) => Err(format!("line {}: `{}` must be an integer", entry.line, key).into()),
+            None => Ok(None),
+        }
+    }
+
+    #[cfg_attr(not(any(test, feature = "fixture")), allow(dead_code))]
+    pub fn bool(&self, key: &str) -> Result<Option<bool>> {
+        match self.get(key) {
+            Some(Entry { value: Value::Bool(b), .. }) => Ok(Some(*b)),
+            Some(entry) => Err(format!("line {}: `{}` must be true or false", entry.line, key).into()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn reject_unknown(&self, known: &[&str]) -> Result<()> {
+        match self.entries.iter().find(|e| !known.contains(&e.key.as_str())) {
+            Some(entry) => Err(format!("line {}: unknown key `{}` in [{}]", entry.line, entry.key, self.name).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Vec<Table>> {
+    let mut tables: Vec<Table> = Vec::new();
+
+    for (line, text) in input.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let header = text.strip_prefix("[[").and_then(|t| t.strip_suffix("]]"))
+            .or_else(|| text.strip_prefix('[').and_then(|t| t.strip_suffix(']')));
+        if let Some(name) = header {
+            tables.push(Table { name: name.trim().to_string(), line, entries: Vec::new() });
+            continue;
+        }
+
+        let (key, value) = text.split_once('=')
+            .ok_or_else(|| format!("line {}: expected `key = value`", line))?;
+        let value = parse_value(value.trim())
+            .ok_or_else(|| format!("line {}: `{}` is not a string, integer or boolean", line, value.trim()))?;
+        tables.last_mut()
+            .ok_or_else(|| format!("line {}: key outside of a table", line))?
+            .entries
+            .push(Entry { key: key.trim().to_string(), value, line });
+    }
+    Ok(tables)
+}
+
+fn parse_value(raw: &str) -> Option<Value> {
+    if let Some(quoted) = raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+        return unescape(quoted).map(Value::Str);
+    }
+    match raw {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => raw.replace('_', "").parse().ok().map(Value::Int),
+    }
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            '0' => '\0',
+            '"' => '"',
+            '\\' => '\\',
+            _ => return None,
+        });
+    }
+    Some(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tables() {
+        let tables = parse("# comment\n[image]\nwidth = 4\n\n[[chunk]]\ntype = \"tEXt\"\ntext = \"a\\0b\"\n[[chunk]]\nlast = true\n").unwrap();
+
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables[0].name, "image");
+        assert_eq!(tables[0].int("width").unwrap(), Some(4));
+        assert_eq!(tables[1].str("text").unwrap(), Some("a\0b"));
+        assert_eq!(tables[2].bool("last").unwrap(), Some(true));
+        assert_eq!(tables[2].line, 8);
+    }
+
+    #[test]
+    fn test_type_errors_name_the_line() {
+        let tables = parse("[image]\nwidth = \"four\"\n").unwrap();
+        let err = tables[0].int("width").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: `width` must be an integer");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("width = 4").is_err());
+        assert!(parse("[image]\nwidth").is_err());
+        assert!(parse("[image]\nwidth = four").is_err());
+        assert!(parse("[image]\nname = \"bad \\q escape\"").is_err());
+    }
+}
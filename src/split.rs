@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+
+use crate::chunk::{Chunk, MAX_CHUNK_LENGTH};
+use crate::chunk_type::ChunkType;
+use crate::error::PngMsgError;
+use crate::random::random_bytes;
+
+// Every piece starts with: message id (u32 BE) | sequence number (u32 BE) | piece count (u32 BE).
+// The id is random, so the same message split twice into one file makes two messages.
+pub const PIECE_HEADER_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PieceHeader {
+    pub message_id: u32,
+    pub sequence: u32,
+    pub count: u32,
+}
+
+impl PieceHeader {
+    pub fn parse(data: &[u8]) -> Option<PieceHeader> {
+        let word = |at: usize| Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().unwrap()));
+        let header = PieceHeader { message_id: word(0)?, sequence: word(4)?, count: word(8)? };
+        (header.sequence < header.count).then_some(header)
+    }
+
+    fn to_bytes(self) -> [u8; PIECE_HEADER_LEN] {
+        let mut bytes = [0; PIECE_HEADER_LEN];
+        bytes[..4].copy_from_slice(&self.message_id.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.sequence.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.count.to_be_bytes());
+        bytes
+    }
+}
+
+// `max_chunk_size` bounds the data of each chunk, header included. An empty payload still
+// makes one piece so the message can be found.
+pub fn split_payload(chunk_type: &ChunkType, payload: &[u8], max_chunk_size: usize) -> Result<Vec<Chunk>, PngMsgError> {
+    let body_len = max_chunk_size.min(MAX_CHUNK_LENGTH as usize).saturating_sub(PIECE_HEADER_LEN);
+    if body_len == 0 {
+        return Err(PngMsgError::ChunkSizeTooSmall { max_chunk_size });
+    }
+    let count = payload.len().div_ceil(body_len).max(1);
+    let count = u32::try_from(count).map_err(|_| PngMsgError::DataTooLarge { len: payload.len() })?;
+    let mut message_id = [0; 4];
+    random_bytes(&mut message_id)?;
+    let message_id = u32::from_be_bytes(message_id);
+
+    let bodies = payload.chunks(body_len).chain(payload.is_empty().then_some(&[][..]));
+    Ok(bodies.zip(0..).map(|(body, sequence)| {
+        let mut data = Vec::with_capacity(PIECE_HEADER_LEN + body.len());
+        data.extend_from_slice(&PieceHeader { message_id, sequence, count }.to_bytes());
+        data.extend_from_slice(body);
+        Chunk::new(*chunk_type, data)
+    }).collect())
+}
+
+// Pieces may come in any order. Only pieces sharing the first piece's message id are joined;
+// others belong to a different message.
+pub fn join_pieces<'a, I>(pieces: I) -> Result<Vec<u8>, PngMsgError>
+where
+    I: IntoIterator<Item = &'a Chunk>,
+{
+    join_piece_data(pieces.into_iter().map(Chunk::data))
+}
+
+// The same for piece data taken out of its chunks, e.g. from inside a message frame.
+pub fn join_piece_data<'a, I>(pieces: I) -> Result<Vec<u8>, PngMsgError>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut pieces = pieces.into_iter().enumerate().peekable();
+    let first = match pieces.peek() {
+        Some(&(_, data)) => PieceHeader::parse(data).ok_or(PngMsgError::InvalidPieceHeader { piece: 0 })?,
+        None => return Err(PngMsgError::NoPieces),
+    };
+
+    let mut bodies = BTreeMap::new();
+    for (piece, data) in pieces {
+        let header = PieceHeader::parse(data).ok_or(PngMsgError::InvalidPieceHeader { piece })?;
+        if header.message_id != first.message_id {
+            continue;
+        }
+        if header.count != first.count {
+            return Err(PngMsgError::PieceCountsDiffer { message_id: first.message_id, first: first.count, other: header.count });
+        }
+        if bodies.insert(header.sequence, &data[PIECE_HEADER_LEN..]).is_some() {
+            return Err(PngMsgError::DuplicatePiece { message_id: first.message_id, sequence: header.sequence });
+        }
+    }
+
+    let missing: Vec<u32> = (0..first.count).filter(|sequence| !bodies.contains_key(sequence)).collect();
+    if !missing.is_empty() {
+        return Err(PngMsgError::MissingPieces { message_id: first.message_id, missing, count: first.count });
+    }
+    Ok(bodies.into_values().flatten().copied().collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn ru_st() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_round_trip_in_1_2_and_100_pieces() {
+        for (len, max_chunk_size, expected) in [(5, 100, 1), (0, 13, 1), (20, 22, 2), (1000, 22, 100), (999, 22, 100)] {
+            let data = payload(len);
+            let pieces = split_payload(&ru_st(), &data, max_chunk_size).unwrap();
+            assert_eq!(pieces.len(), expected, "{} bytes in pieces of {}", len, max_chunk_size);
+            assert!(pieces.iter().all(|p| p.data().len() <= max_chunk_size));
+            assert_eq!(join_pieces(&pieces).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_pieces_are_reordered() {
+        let data = payload(300);
+        let mut pieces = split_payload(&ru_st(), &data, 62).unwrap();
+        pieces.reverse();
+        pieces.swap(1, 3);
+        assert_eq!(join_pieces(&pieces).unwrap(), data);
+    }
+
+    #[test]
+    fn test_missing_middle_piece() {
+        let mut pieces = split_payload(&ru_st(), &payload(1000), 22).unwrap();
+        let message_id = PieceHeader::parse(pieces[0].data()).unwrap().message_id;
+        pieces.remove(50);
+        pieces.remove(60);
+
+        let error = join_pieces(&pieces).unwrap_err();
+        assert!(matches!(&error, PngMsgError::MissingPieces { missing, .. } if missing == &[50, 61]));
+        assert_eq!(error.to_string(), format!("Split message {:08x} is missing pieces 50, 61 of 100", message_id));
+    }
+
+    #[test]
+    fn test_duplicate_and_bad_pieces() {
+        let mut pieces = split_payload(&ru_st(), &payload(100), 40).unwrap();
+        pieces.push(pieces[2].clone());
+        assert!(matches!(join_pieces(&pieces), Err(PngMsgError::DuplicatePiece { sequence: 2, .. })));
+
+        let short = Chunk::new(ru_st(), vec![0; 11]);
+        assert!(matches!(join_pieces([&pieces[0], &short]), Err(PngMsgError::InvalidPieceHeader { piece: 1 })));
+        let past_count = Chunk::new(ru_st(), [0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 2].to_vec());
+        assert!(matches!(join_pieces([&past_count]), Err(PngMsgError::InvalidPieceHeader { piece: 0 })));
+        assert!(matches!(join_pieces([]), Err(PngMsgError::NoPieces)));
+        assert!(matches!(split_payload(&ru_st(), b"x", 12), Err(PngMsgError::ChunkSizeTooSmall { max_chunk_size: 12 })));
+    }
+
+    #[test]
+    fn test_same_message_gets_a_new_id() {
+        let mut pieces = split_payload(&ru_st(), b"twice", 14).unwrap();
+        let again = split_payload(&ru_st(), b"twice", 14).unwrap();
+        assert_ne!(PieceHeader::parse(pieces[0].data()), PieceHeader::parse(again[0].data()));
+        pieces.extend(again);
+        assert_eq!(join_pieces(&pieces).unwrap(), b"twice");
+    }
+
+    #[test]
+    fn test_other_messages_are_skipped() {
+        let mut pieces = split_payload(&ru_st(), b"first message", 16).unwrap();
+        let other = split_payload(&ru_st(), b"second message", 16).unwrap();
+        pieces.insert(1, other[0].clone());
+        pieces.extend(other[2..].iter().cloned());
+        assert_eq!(join_pieces(&pieces).unwrap(), b"first message");
+
+        let mut count = pieces[0].data().to_vec();
+        count[11] = 9;
+        pieces[2] = Chunk::new(ru_st(), count);
+        assert!(matches!(join_pieces(&pieces), Err(PngMsgError::PieceCountsDiffer { first: 4, other: 9, .. })));
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use crate::chunk::{SerializedSize, CHUNK_OVERHEAD};
+use crate::chunk_type::ChunkType;
+use crate::finding::json_escape;
+use crate::png::Png;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeStats {
+    pub chunk_type: ChunkType,
+    pub count: usize,
+    // Whole chunks, length, type and CRC fields included.
+    pub bytes: SerializedSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LargestChunk {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub length: u32,
+}
+
+// Where a file's bytes go. The four byte counts split the file between them: chunk data of
+// IDAT, of ancillary chunks and of the other critical chunks, and overhead, which is the
+// signature plus each chunk's length, type and CRC fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PngStats {
+    pub file_size: SerializedSize,
+    pub chunk_count: usize,
+    pub idat_bytes: u64,
+    pub ancillary_bytes: u64,
+    pub other_critical_bytes: u64,
+    pub overhead_bytes: u64,
+    // Largest first; equal sizes in chunk-type byte order.
+    pub by_type: Vec<TypeStats>,
+    // The first one when several share the largest length; None without chunks.
+    pub largest: Option<LargestChunk>,
+    pub critical_count: usize,
+    pub ancillary_count: usize,
+    pub safe_to_copy_count: usize,
+    pub unsafe_to_copy_count: usize,
+}
+
+impl PngStats {
+    // Share of the file, 0 for an empty one.
+    pub fn percent(&self, bytes: u64) -> f64 {
+        match self.file_size.get() {
+            0 => 0.0,
+            size => bytes as f64 * 100.0 / size as f64,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let rows: Vec<String> = self.by_type.iter()
+            .map(|t| format!(
+                "    {{\"chunk_type\": \"{}\", \"count\": {}, \"bytes\": {}, \"percent\": {:.2}}}",
+                json_escape(t.chunk_type.name()), t.count, t.bytes, self.percent(t.bytes.get())
+            ))
+            .collect();
+        let largest = self.largest.map_or_else(|| "null".to_string(), |l| format!(
+            "{{\"index\": {}, \"chunk_type\": \"{}\", \"length\": {}}}", l.index, json_escape(l.chunk_type.name()), l.length
+        ));
+        format!(
+            "{{\n  \"file_size\": {},\n  \"chunk_count\": {},\n  \"idat_bytes\": {},\n  \"ancillary_bytes\": {},\n  \
+             \"other_critical_bytes\": {},\n  \"overhead_bytes\": {},\n  \"critical_count\": {},\n  \"ancillary_count\": {},\n  \
+             \"safe_to_copy_count\": {},\n  \"unsafe_to_copy_count\": {},\n  \"largest\": {},\n  \"by_type\": [\n{}\n  ]\n}}\n",
+            self.file_size, self.chunk_count, self.idat_bytes, self.ancillary_bytes, self.other_critical_bytes,
+            self.overhead_bytes, self.critical_count, self.ancillary_count, self.safe_to_copy_count,
+            self.unsafe_to_copy_count, largest, rows.join(",\n")
+        )
+    }
+}
+
+impl Png {
+    pub fn stats(&self) -> PngStats {
+        let chunks = self.chunks();
+        let data_of = |keep: &dyn Fn(&ChunkType) -> bool| -> u64 {
+            chunks.iter().filter(|c| keep(c.chunk_type())).map(|c| c.data().len() as u64).sum()
+        };
+        let count_of = |keep: &dyn Fn(&ChunkType) -> bool| chunks.iter().filter(|c| keep(c.chunk_type())).count();
+
+        let mut by_type: BTreeMap<ChunkType, TypeStats> = BTreeMap::new();
+        for chunk in chunks {
+            let chunk_type = *chunk.chunk_type();
+            let entry = by_type.entry(chunk_type).or_insert(TypeStats { chunk_type, count: 0, bytes: SerializedSize(0) });
+            entry.count += 1;
+            entry.bytes += chunk.serialized_size();
+        }
+        let mut by_type: Vec<TypeStats> = by_type.into_values().collect();
+        by_type.sort_by_key(|t| std::cmp::Reverse(t.bytes.get()));
+
+        let largest = chunks.iter().enumerate()
+            .rev()
+            .max_by_key(|(_, c)| c.data().len())
+            .map(|(index, c)| LargestChunk { index, chunk_type: *c.chunk_type(), length: c.length().get() });
+
+        PngStats {
+            file_size: self.total_size(),
+            chunk_count: chunks.len(),
+            idat_bytes: data_of(&|t| *t == ChunkType::IDAT),
+            ancillary_bytes: data_of(&|t| !t.is_critical()),
+            other_critical_bytes: data_of(&|t| t.is_critical() && *t != ChunkType::IDAT),
+            overhead_bytes: self.header().len() as u64 + chunks.len() as u64 * CHUNK_OVERHEAD,
+            by_type,
+            largest,
+            critical_count: count_of(&|t| t.is_critical()),
+            ancillary_count: count_of(&|t| !t.is_critical()),
+            safe_to_copy_count: count_of(&|t| t.is_safe_to_copy()),
+            unsafe_to_copy_count: count_of(&|t| !t.is_safe_to_copy()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+
+    fn chunk(chunk_type: &str, len: usize) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; len])
+    }
+
+    fn chunk_type(s: &str) -> ChunkType {
+        ChunkType::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_fixture_stats() {
+        let fixture = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/pixels.png")).unwrap();
+        let stats = Png::try_from(fixture.as_slice()).unwrap().stats();
+        assert_eq!(stats, PngStats {
+            file_size: SerializedSize(75),
+            chunk_count: 3,
+            idat_bytes: 18,
+            ancillary_bytes: 0,
+            other_critical_bytes: 13,
+            overhead_bytes: 44,
+            by_type: vec![
+                TypeStats { chunk_type: ChunkType::IDAT, count: 1, bytes: SerializedSize(30) },
+                TypeStats { chunk_type: ChunkType::IHDR, count: 1, bytes: SerializedSize(25) },
+                TypeStats { chunk_type: ChunkType::IEND, count: 1, bytes: SerializedSize(12) },
+            ],
+            largest: Some(LargestChunk { index: 1, chunk_type: ChunkType::IDAT, length: 18 }),
+            critical_count: 3,
+            ancillary_count: 0,
+            safe_to_copy_count: 0,
+            unsafe_to_copy_count: 3,
+        });
+        assert_eq!(format!("{:.1}", stats.percent(stats.idat_bytes)), "24.0");
+    }
+
+    #[test]
+    fn test_ancillary_heavy_file() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", 13),
+            chunk("tEXt", 100),
+            chunk("iTXt", 300),
+            chunk("tEXt", 200),
+            chunk("IDAT", 300),
+            chunk("IEND", 0),
+        ]).unwrap();
+        let stats = png.stats();
+        assert_eq!(stats.file_size.get(), 8 + 6 * 12 + 13 + 600 + 300);
+        assert_eq!((stats.idat_bytes, stats.ancillary_bytes, stats.other_critical_bytes, stats.overhead_bytes), (300, 600, 13, 80));
+        assert_eq!(stats.idat_bytes + stats.ancillary_bytes + stats.other_critical_bytes + stats.overhead_bytes, stats.file_size.get());
+        let order: Vec<(String, usize)> = stats.by_type.iter().map(|t| (t.chunk_type.to_string(), t.count)).collect();
+        assert_eq!(order, [("tEXt".to_string(), 2), ("IDAT".to_string(), 1), ("iTXt".to_string(), 1), ("IHDR".to_string(), 1), ("IEND".to_string(), 1)]);
+        assert_eq!(stats.largest, Some(LargestChunk { index: 2, chunk_type: chunk_type("iTXt"), length: 300 }));
+        assert_eq!((stats.critical_count, stats.ancillary_count), (3, 3));
+        assert_eq!((stats.safe_to_copy_count, stats.unsafe_to_copy_count), (3, 3));
+    }
+
+    #[test]
+    fn test_empty_png_and_json() {
+        let stats = Png::new().stats();
+        assert_eq!((stats.largest, stats.percent(0)), (None, 0.0));
+
+        let png = Png::from_chunks(vec![chunk("IHDR", 13), chunk("IEND", 0)]).unwrap();
+        let json = png.stats().to_json();
+        assert!(json.starts_with("{\n  \"file_size\": 45,\n  \"chunk_count\": 2,\n"), "{}", json);
+        assert!(json.contains("\"largest\": {\"index\": 0, \"chunk_type\": \"IHDR\", \"length\": 13}"), "{}", json);
+        assert!(json.ends_with("{\"chunk_type\": \"IEND\", \"count\": 1, \"bytes\": 12, \"percent\": 26.67}\n  ]\n}\n"), "{}", json);
+    }
+}
@@ -0,0 +1,300 @@
+// Hides a message in the least significant bit of every image sample instead of in a chunk of
+// its own. The image data is inflated, unfiltered, changed, refiltered with each row's original
+// filter type and deflated again with `zlib::compress`.
+//
+// The bit stream, one bit per sample and most significant bit first, is:
+// magic "pmLS" | flags (u8, bit 0 = encrypted) | payload length (u32 BE) | payload.
+// 16-bit samples carry their bit in the low byte.
+
+use crate::Result;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMsgError;
+use crate::ihdr::{ColorType, IhdrData, Interlace};
+use crate::png::Png;
+use crate::zlib;
+
+const MAGIC: [u8; 4] = *b"pmLS";
+const FLAG_ENCRYPTED: u8 = 1;
+pub const LSB_HEADER_LEN: usize = 9;
+
+// Message bytes the image can carry, after the bit stream header.
+pub fn lsb_capacity(ihdr: &IhdrData) -> std::result::Result<u64, PngMsgError> {
+    check_image(ihdr)?;
+    let samples = ihdr.width as u64 * ihdr.height as u64 * ihdr.color_type.channels() as u64;
+    Ok((samples / 8).saturating_sub(LSB_HEADER_LEN as u64))
+}
+
+pub fn encode_lsb(png_bytes: &[u8], message: &[u8], password: Option<&str>) -> Result<Vec<u8>> {
+    let png = Png::try_from(png_bytes)?;
+    let mut pixels = Pixels::read(&png)?;
+
+    let (flags, payload) = match password {
+        Some(password) => (FLAG_ENCRYPTED, crate::encode::encrypt(message, password)?),
+        None => (0, message.to_vec()),
+    };
+    let capacity = lsb_capacity(&pixels.ihdr)?;
+    let length = u32::try_from(payload.len()).ok().filter(|&len| len as u64 <= capacity)
+        .ok_or(PngMsgError::MessageTooLarge { len: payload.len(), capacity })?;
+
+    let mut stream = Vec::with_capacity(LSB_HEADER_LEN + payload.len());
+    stream.extend_from_slice(&MAGIC);
+    stream.push(flags);
+    stream.extend_from_slice(&length.to_be_bytes());
+    stream.extend_from_slice(&payload);
+
+    let bits = stream.iter().flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1));
+    for (sample, bit) in pixels.sample_indices().zip(bits) {
+        pixels.rows[sample] = pixels.rows[sample] & !1 | bit;
+    }
+    Ok(pixels.write(&png)?.as_bytes())
+}
+
+// `password` is only used when the message was stored encrypted.
+pub fn decode_lsb(png_bytes: &[u8], password: Option<&str>) -> Result<Vec<u8>> {
+    let png = Png::try_from(png_bytes)?;
+    let pixels = Pixels::read(&png)?;
+    let capacity = lsb_capacity(&pixels.ihdr)?;
+
+    let mut bits = pixels.sample_indices().map(|sample| pixels.rows[sample] & 1);
+    let mut next_byte = || (0..8).try_fold(0u8, |byte, _| Some(byte << 1 | bits.next()?));
+    let header: Vec<u8> = (0..LSB_HEADER_LEN).map_while(|_| next_byte()).collect();
+    if header.len() < LSB_HEADER_LEN || header[..4] != MAGIC {
+        return Err(PngMsgError::NoLsbMessage.into());
+    }
+    let flags = header[4];
+    let length = u32::from_be_bytes(header[5..9].try_into().unwrap()) as u64;
+    if length > capacity {
+        return Err(PngMsgError::NoLsbMessage.into());
+    }
+    let payload: Vec<u8> = (0..length).map_while(|_| next_byte()).collect();
+
+    match (flags & FLAG_ENCRYPTED != 0, password) {
+        (false, _) => Ok(payload),
+        (true, Some(password)) => Ok(decrypt(&payload, password)?),
+        (true, None) => Err(PngMsgError::PasswordRequired.into()),
+    }
+}
+
+// Palette indices are refused outright: flipping one picks a different, unrelated color.
+fn check_image(ihdr: &IhdrData) -> std::result::Result<(), PngMsgError> {
+    if ihdr.color_type == ColorType::Indexed {
+        return Err(PngMsgError::LsbPalette);
+    }
+    if ihdr.bit_depth < 8 || ihdr.interlace != Interlace::None {
+        return Err(PngMsgError::LsbUnsupported(*ihdr));
+    }
+    Ok(())
+}
+
+// The unfiltered scanlines of a non-interlaced image, without their filter type bytes.
+struct Pixels {
+    ihdr: IhdrData,
+    filters: Vec<u8>,
+    rows: Vec<u8>,
+    row_len: usize,
+    // Bytes per complete pixel, the distance the Sub, Average and Paeth filters look back.
+    pixel_len: usize,
+}
+
+impl Pixels {
+    fn read(png: &Png) -> std::result::Result<Pixels, PngMsgError> {
+        let ihdr = png.ihdr()?;
+        check_image(&ihdr)?;
+        let pixel_len = ihdr.color_type.channels() as usize * ihdr.bit_depth as usize / 8;
+        let too_large = || PngMsgError::InvalidCompressedData(format!("{} image is too large to load", ihdr));
+        let row_len = (ihdr.width as usize).checked_mul(pixel_len).ok_or_else(too_large)?;
+        let expected = (row_len + 1).checked_mul(ihdr.height as usize).ok_or_else(too_large)?;
+
+        let idat: Vec<u8> = png.chunks_by_type(ChunkType::IDAT).into_iter().flat_map(|c| c.data().iter().copied()).collect();
+        if idat.is_empty() {
+            return Err(PngMsgError::ChunkNotFound(ChunkType::IDAT.to_string()));
+        }
+        let raw = zlib::decompress(&idat, expected)?;
+        if raw.len() != expected {
+            return Err(PngMsgError::InvalidCompressedData(
+                format!("image data is {} bytes, {} needs {}", raw.len(), ihdr, expected)));
+        }
+
+        let mut pixels = Pixels { ihdr, filters: Vec::new(), rows: vec![0; row_len * ihdr.height as usize], row_len, pixel_len };
+        for (y, line) in raw.chunks(row_len + 1).enumerate() {
+            let filter = line[0];
+            if filter > 4 {
+                return Err(PngMsgError::InvalidCompressedData(format!("row {} has unknown filter type {}", y, filter)));
+            }
+            pixels.filters.push(filter);
+            for x in 0..row_len {
+                let predicted = pixels.predict(filter, y, x);
+                pixels.rows[y * row_len + x] = line[1 + x].wrapping_add(predicted);
+            }
+        }
+        Ok(pixels)
+    }
+
+    // Every chunk but IDAT is kept as it was; the new image data goes where the first IDAT was.
+    fn write(&self, png: &Png) -> std::result::Result<Png, PngMsgError> {
+        let mut raw = Vec::with_capacity((self.row_len + 1) * self.filters.len());
+        for (y, &filter) in self.filters.iter().enumerate() {
+            raw.push(filter);
+            raw.extend((0..self.row_len).map(|x| self.rows[y * self.row_len + x].wrapping_sub(self.predict(filter, y, x))));
+        }
+        let mut idat = Some(Chunk::try_new(ChunkType::IDAT, zlib::compress(&raw))?);
+        let chunks = png.chunks().iter()
+            .filter_map(|c| match c.chunk_type() == &ChunkType::IDAT {
+                true => idat.take(),
+                false => Some(c.clone()),
+            })
+            .collect();
+        Ok(Png::from_chunks_unchecked(chunks))
+    }
+
+    // The filter predictor for byte `x` of row `y`, from already unfiltered neighbours.
+    fn predict(&self, filter: u8, y: usize, x: usize) -> u8 {
+        let at = |y: usize, x: usize| self.rows[y * self.row_len + x];
+        let a = if x >= self.pixel_len { at(y, x - self.pixel_len) } else { 0 };
+        let b = if y > 0 { at(y - 1, x) } else { 0 };
+        let c = if y > 0 && x >= self.pixel_len { at(y - 1, x - self.pixel_len) } else { 0 };
+        match filter {
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            4 => paeth(a, b, c),
+            _ => 0,
+        }
+    }
+
+    // The byte of each sample that holds its least significant bit, in image order.
+    fn sample_indices(&self) -> impl Iterator<Item = usize> {
+        let sample_len = self.ihdr.bit_depth as usize / 8;
+        (sample_len - 1..self.rows.len()).step_by(sample_len)
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(feature = "encrypt")]
+fn decrypt(payload: &[u8], password: &str) -> std::result::Result<Vec<u8>, PngMsgError> {
+    crate::encrypt::decrypt_payload(payload, password)
+}
+
+#[cfg(not(feature = "encrypt"))]
+fn decrypt(_: &[u8], _: &str) -> std::result::Result<Vec<u8>, PngMsgError> {
+    Err(PngMsgError::EncryptionUnavailable)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{FixtureSpec, ImageSpec};
+
+    fn carrier(width: u32, height: u32, color_type: u8, bit_depth: u8) -> Vec<u8> {
+        let image = ImageSpec { width, height, color_type, bit_depth, idat_count: 2 };
+        FixtureSpec { image, ..Default::default() }.build().unwrap()
+    }
+
+    // The fixture only writes filter type 0, so this one cycles through all five.
+    fn filtered_carrier() -> Vec<u8> {
+        let bytes = carrier(9, 10, 6, 8);
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        let mut pixels = Pixels::read(&png).unwrap();
+        pixels.filters = (0..10).map(|y| y % 5).collect();
+        pixels.write(&png).unwrap().as_bytes()
+    }
+
+    fn error(result: Result<Vec<u8>>) -> PngMsgError {
+        *result.unwrap_err().downcast::<PngMsgError>().unwrap()
+    }
+
+    fn pixels(bytes: &[u8]) -> Pixels {
+        Pixels::read(&Png::try_from(bytes).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_per_color_type_and_depth() {
+        for (color_type, bit_depth) in [(2, 8), (6, 8), (0, 8), (4, 8), (0, 16), (6, 16)] {
+            let bytes = carrier(24, 20, color_type, bit_depth);
+            let encoded = encode_lsb(&bytes, b"hidden in plain sight", None).unwrap();
+            assert_eq!(decode_lsb(&encoded, None).unwrap(), b"hidden in plain sight", "color type {} at {} bits", color_type, bit_depth);
+            assert!(Png::try_from(encoded.as_slice()).unwrap().validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_only_least_significant_bits_change() {
+        for bytes in [carrier(16, 16, 2, 8), carrier(24, 24, 0, 16), filtered_carrier()] {
+            let encoded = encode_lsb(&bytes, b"a message of some length", None).unwrap();
+            let (before, after) = (pixels(&bytes), pixels(&encoded));
+            assert_eq!(before.filters, after.filters);
+            let sample_len = before.ihdr.bit_depth as usize / 8;
+            for (i, (old, new)) in before.rows.iter().zip(&after.rows).enumerate() {
+                let mask = if i % sample_len == sample_len - 1 { !1 } else { 0xff };
+                assert_eq!(old & mask, new & mask, "byte {}", i);
+            }
+            assert_eq!(decode_lsb(&encoded, None).unwrap(), b"a message of some length");
+        }
+    }
+
+    // A screenshot saved by a real encoder, with its own filter choices and compression.
+    #[test]
+    fn test_real_file() {
+        let encoded = encode_lsb(&crate::png::tests::PNG_FILE, b"from a real encoder", None).unwrap();
+        assert_eq!(decode_lsb(&encoded, None).unwrap(), b"from a real encoder");
+        let (before, after) = (pixels(&crate::png::tests::PNG_FILE), pixels(&encoded));
+        assert!(before.filters.iter().any(|&f| f != 0));
+        assert_eq!(before.filters, after.filters);
+        assert!(before.rows.iter().zip(&after.rows).all(|(old, new)| old & !1 == new & !1));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let bytes = carrier(10, 10, 2, 8);
+        let ihdr = Png::try_from(bytes.as_slice()).unwrap().ihdr().unwrap();
+        let capacity = lsb_capacity(&ihdr).unwrap();
+        assert_eq!(capacity, 10 * 10 * 3 / 8 - LSB_HEADER_LEN as u64);
+
+        let fits = vec![b'x'; capacity as usize];
+        assert_eq!(decode_lsb(&encode_lsb(&bytes, &fits, None).unwrap(), None).unwrap(), fits);
+        assert!(matches!(
+            error(encode_lsb(&bytes, &[0; 29], None)),
+            PngMsgError::MessageTooLarge { len: 29, capacity: 28 }
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_images() {
+        assert!(matches!(error(encode_lsb(&carrier(8, 8, 3, 8), b"x", None)), PngMsgError::LsbPalette));
+        assert!(matches!(error(decode_lsb(&carrier(8, 8, 3, 4), None)), PngMsgError::LsbPalette));
+        assert!(matches!(error(encode_lsb(&carrier(8, 8, 0, 4), b"x", None)), PngMsgError::LsbUnsupported(_)));
+        assert_eq!(
+            encode_lsb(&carrier(8, 8, 3, 8), b"x", None).unwrap_err().to_string(),
+            "LSB embedding would visibly change the colors of a palette image; convert it to RGB first"
+        );
+    }
+
+    #[test]
+    fn test_clean_image_has_no_message() {
+        assert!(matches!(error(decode_lsb(&carrier(16, 16, 2, 8), None)), PngMsgError::NoLsbMessage));
+        assert!(matches!(error(decode_lsb(&carrier(2, 2, 2, 8), None)), PngMsgError::NoLsbMessage));
+    }
+
+    #[cfg(feature = "encrypt")]
+    #[test]
+    fn test_encrypted_round_trip() {
+        let bytes = carrier(64, 64, 6, 8);
+        let encoded = encode_lsb(&bytes, b"secret", Some("hunter2")).unwrap();
+        assert_eq!(decode_lsb(&encoded, Some("hunter2")).unwrap(), b"secret");
+        assert!(matches!(error(decode_lsb(&encoded, None)), PngMsgError::PasswordRequired));
+        assert!(matches!(error(decode_lsb(&encoded, Some("wrong"))), PngMsgError::DecryptionFailed));
+    }
+}
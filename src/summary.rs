@@ -0,0 +1,116 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::finding::json_escape;
+use crate::png::Png;
+use crate::sha256::{to_hex, Sha256};
+use crate::text_codec::base64_encode;
+
+// Bumped whenever a field is renamed or removed; new fields may be added without a bump.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// Data up to this size is inlined as base64; bigger chunks (usually IDAT) only get a digest,
+// so a 50 MB image doesn't turn into 70 MB of JSON.
+pub const DEFAULT_INLINE_LIMIT: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSummary {
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    pub crc: u32,
+    pub critical: bool,
+    pub public: bool,
+    pub safe_to_copy: bool,
+    // Always present, so large and small chunks can be compared the same way.
+    pub sha256: [u8; 32],
+    // Base64 of the data, or None when it is over the inline limit.
+    pub data: Option<String>,
+}
+
+impl ChunkSummary {
+    pub fn of(chunk: &Chunk, inline_limit: usize) -> ChunkSummary {
+        let chunk_type = *chunk.chunk_type();
+        ChunkSummary {
+            chunk_type,
+            length: chunk.length().get(),
+            crc: chunk.crc(),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+            sha256: Sha256::digest(chunk.data()),
+            data: (chunk.data().len() <= inline_limit).then(|| base64_encode(chunk.data())),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let data = self.data.as_ref().map_or_else(|| "null".to_string(), |d| format!("\"{}\"", d));
+        format!(
+            "{{\"chunk_type\": \"{}\", \"length\": {}, \"crc\": \"{:08x}\", \"critical\": {}, \"public\": {}, \"safe_to_copy\": {}, \"sha256\": \"{}\", \"data\": {}}}",
+            json_escape(self.chunk_type.name()), self.length, self.crc, self.critical, self.public,
+            self.safe_to_copy, to_hex(&self.sha256), data
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PngSummary {
+    pub size: u64,
+    pub valid: bool,
+    pub chunks: Vec<ChunkSummary>,
+}
+
+impl PngSummary {
+    pub fn of(png: &Png, inline_limit: usize) -> PngSummary {
+        PngSummary {
+            size: png.total_size().get(),
+            valid: png.validate().is_ok(),
+            chunks: png.chunks().iter().map(|c| ChunkSummary::of(c, inline_limit)).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let rows: Vec<String> = self.chunks.iter().map(|c| format!("    {}", c.to_json())).collect();
+        format!(
+            "{{\n  \"schema\": {},\n  \"size\": {},\n  \"valid\": {},\n  \"chunks\": [\n{}\n  ]\n}}\n",
+            SCHEMA_VERSION, self.size, self.valid, rows.join(",\n")
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_chunk_summary_json() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hi".to_vec());
+        assert_eq!(ChunkSummary::of(&chunk, 16).to_json(), format!(
+            "{{\"chunk_type\": \"ruSt\", \"length\": 2, \"crc\": \"{:08x}\", \"critical\": false, \"public\": false, \"safe_to_copy\": true, \"sha256\": \"{}\", \"data\": \"aGk=\"}}",
+            chunk.crc(), to_hex(&Sha256::digest(b"hi"))
+        ));
+    }
+
+    #[test]
+    fn test_large_data_is_digested_not_inlined() {
+        let data = vec![7; 64 * 1024];
+        let summary = ChunkSummary::of(&Chunk::new(ChunkType::IDAT, data.clone()), DEFAULT_INLINE_LIMIT);
+        assert_eq!(summary.data, None);
+        assert_eq!(summary.sha256, Sha256::digest(&data));
+        assert!(summary.to_json().ends_with("\"data\": null}"));
+        assert!(summary.critical && summary.public && !summary.safe_to_copy);
+    }
+
+    #[test]
+    fn test_png_summary_json() {
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IDAT, vec![1; 2000]),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap();
+        let json = PngSummary::of(&png, DEFAULT_INLINE_LIMIT).to_json();
+        assert!(json.starts_with("{\n  \"schema\": 1,\n  \"size\": 2057,\n  \"valid\": true,\n  \"chunks\": [\n    {\"chunk_type\": \"IHDR\""));
+        assert_eq!(json.matches("\"data\": null").count(), 1);
+        assert!(json.ends_with("\"data\": \"\"}\n  ]\n}\n"));
+    }
+}
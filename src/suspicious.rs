@@ -0,0 +1,185 @@
+// Heuristics for triaging files that may hide data. Each one only says a chunk is worth a look:
+// plenty of ordinary tools write private chunks or large metadata.
+
+use std::collections::BTreeMap;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::{ChunkCategory, ChunkType};
+use crate::entropy::shannon_entropy;
+use crate::finding::{aggregate, Finding, FindingCode, Severity};
+use crate::png::Png;
+
+// Text chunks past this are rare outside of XMP packets, which seldom reach it.
+pub const LARGE_TEXT_BYTES: usize = 16 * 1024;
+// Bits per byte. Random data of MIN_ENTROPY_BYTES scores about 7.3, text rarely above 5.
+pub const HIGH_ENTROPY: f64 = 7.0;
+// Shorter data cannot score high: n bytes hold at most log2(n) bits of entropy per byte.
+pub const MIN_ENTROPY_BYTES: usize = 256;
+
+// Chunks whose standard contents are zlib streams, so high entropy is expected of them.
+const COMPRESSED_TYPES: [[u8; 4]; 4] = [*b"IDAT", *b"fdAT", *b"iCCP", *b"zTXt"];
+
+impl Png {
+    // In chunk order; a chunk can be flagged for more than one reason.
+    pub fn suspicious_chunks(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let mut private_types: BTreeMap<ChunkType, (usize, usize)> = BTreeMap::new();
+        let iend = self.structure().iend;
+
+        for (index, chunk) in self.iter().enumerate() {
+            let chunk_type = *chunk.chunk_type();
+            let finding = |code: FindingCode, severity: Severity, message: String| Finding {
+                chunk_index: Some(index),
+                chunk_type: Some(chunk_type),
+                length: Some(chunk.data().len() as u32),
+                offset: None,
+                code,
+                severity,
+                message,
+            };
+
+            if !chunk_type.is_standard() {
+                findings.push(finding(
+                    FindingCode::NonStandardType,
+                    Severity::Info,
+                    "chunk type is not registered in the PNG specification".to_string(),
+                ));
+            }
+            if chunk_type.category() == Some(ChunkCategory::Text) && chunk.data().len() > LARGE_TEXT_BYTES {
+                findings.push(finding(
+                    FindingCode::LargeText,
+                    Severity::Warning,
+                    format!("text chunk is larger than {} bytes", LARGE_TEXT_BYTES),
+                ));
+            }
+            if !chunk_type.is_public() {
+                private_types.entry(chunk_type).or_insert((index, 0)).1 += 1;
+            }
+            if iend.is_some_and(|iend| index > iend) {
+                findings.push(finding(FindingCode::ChunkAfterIend, Severity::Warning, "chunk after IEND".to_string()));
+            }
+            if chunk.data().len() >= MIN_ENTROPY_BYTES && !is_compressed_by_design(chunk) {
+                let entropy = shannon_entropy(chunk.data());
+                if entropy >= HIGH_ENTROPY {
+                    findings.push(finding(
+                        FindingCode::HighEntropy,
+                        Severity::Warning,
+                        format!("data has {:.2} bits of entropy per byte, like compressed or encrypted data", entropy),
+                    ));
+                }
+            }
+        }
+
+        for (chunk_type, (first, count)) in private_types {
+            if count > 1 {
+                findings.push(Finding {
+                    chunk_index: Some(first),
+                    chunk_type: Some(chunk_type),
+                    length: Some(self.chunks()[first].data().len() as u32),
+                    offset: None,
+                    code: FindingCode::RepeatedPrivateType,
+                    severity: Severity::Warning,
+                    message: format!("first of {} chunks of this private type", count),
+                });
+            }
+        }
+        aggregate(findings)
+    }
+}
+
+// iTXt says per chunk: its compression flag follows the keyword's NUL terminator.
+fn is_compressed_by_design(chunk: &Chunk) -> bool {
+    let chunk_type = chunk.chunk_type().bytes();
+    if chunk_type == *b"iTXt" {
+        let data = chunk.data();
+        return data.iter().position(|&b| b == 0).and_then(|nul| data.get(nul + 1)) == Some(&1);
+    }
+    COMPRESSED_TYPES.contains(&chunk_type)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x9e37_79b9_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn codes(png: &Png) -> Vec<(usize, &'static str)> {
+        png.suspicious_chunks().iter().map(|f| (f.chunk_index.unwrap(), f.code.as_str())).collect()
+    }
+
+    fn png_of(middle: Vec<Chunk>) -> Png {
+        let mut chunks = vec![chunk("IHDR", &[0; 13])];
+        chunks.extend(middle);
+        chunks.push(chunk("IEND", &[]));
+        Png::from_chunks_unchecked(chunks)
+    }
+
+    #[test]
+    fn test_ordinary_file_is_clean() {
+        let png = png_of(vec![
+            Chunk::new_text("Comment", "made with care").unwrap(),
+            chunk("IDAT", &noise(4096)),
+            chunk("zTXt", &noise(1024)),
+        ]);
+        assert!(png.suspicious_chunks().is_empty());
+    }
+
+    #[test]
+    fn test_non_standard_and_repeated_private_types() {
+        let png = png_of(vec![chunk("ruSt", b"one"), chunk("ruSt", b"two"), chunk("prVt", b"x"), chunk("ruSt", b"3")]);
+        assert_eq!(codes(&png), [
+            (1, "scan.non-standard-type"),
+            (1, "scan.repeated-private-type"),
+            (2, "scan.non-standard-type"),
+            (3, "scan.non-standard-type"),
+            (4, "scan.non-standard-type"),
+        ]);
+        let repeated = &png.suspicious_chunks()[1];
+        assert_eq!((repeated.chunk_type, repeated.length), (Some(ChunkType::from_str("ruSt").unwrap()), Some(3)));
+        assert_eq!(repeated.message, "first of 3 chunks of this private type");
+    }
+
+    #[test]
+    fn test_large_text() {
+        let big = "x".repeat(LARGE_TEXT_BYTES);
+        let png = png_of(vec![Chunk::new_text("Comment", &big).unwrap(), Chunk::new_text("Title", "small").unwrap()]);
+        assert_eq!(codes(&png), [(1, "scan.large-text")]);
+        assert_eq!(png.suspicious_chunks()[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_chunks_after_iend() {
+        let mut png = png_of(vec![]);
+        png.append_chunk(chunk("tIME", &[0; 7]));
+        assert_eq!(codes(&png), [(2, "structure.chunk-after-iend")]);
+    }
+
+    #[test]
+    fn test_high_entropy() {
+        let png = png_of(vec![
+            Chunk::new_text("Comment", &"abc".repeat(200)).unwrap(),
+            chunk("eXIf", &noise(MIN_ENTROPY_BYTES)),
+            chunk("eXIf", &noise(MIN_ENTROPY_BYTES - 1)),
+            chunk("iTXt", &[b"XML\0\x01\0\0\0".as_slice(), &noise(1024)].concat()),
+            chunk("iTXt", &[b"XML\0\0\0\0\0".as_slice(), &noise(1024)].concat()),
+        ]);
+        assert_eq!(codes(&png), [(2, "scan.high-entropy"), (5, "scan.high-entropy")]);
+        assert!(png.suspicious_chunks()[0].message.starts_with("data has 7."));
+    }
+}
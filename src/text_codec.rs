@@ -0,0 +1,148 @@
+// Base64 and hex for moving binary payloads through text: terminals, tickets, JSON.
+// Decoding skips ASCII whitespace, so wrapped or indented pastes work as they are.
+
+use crate::error::PngMsgError;
+pub use crate::sha256::to_hex as hex_encode;
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Standard alphabet with padding (RFC 4648).
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let n = group.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// Padding may be left off, but if present it must be right; leftover bits must be zero, so
+// each payload has exactly one encoding.
+pub fn base64_decode(text: &str) -> Result<Vec<u8>, PngMsgError> {
+    let invalid = |reason: String| PngMsgError::InvalidBase64(reason);
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut acc, mut bits, mut digits, mut padding) = (0u32, 0, 0usize, 0usize);
+
+    for (offset, c) in text.char_indices().filter(|(_, c)| !c.is_ascii_whitespace()) {
+        if c == '=' {
+            padding += 1;
+            continue;
+        }
+        if padding > 0 {
+            return Err(invalid(format!("{:?} at offset {} comes after the padding", c, offset)));
+        }
+        let value = BASE64_ALPHABET.iter().position(|&b| b as char == c)
+            .ok_or_else(|| invalid(format!("{:?} at offset {} is not a base64 character", c, offset)))?;
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        digits += 1;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    if digits % 4 == 1 {
+        return Err(invalid(format!("{} characters do not make a whole number of bytes", digits)));
+    }
+    if padding > 0 && (padding > 2 || (digits + padding) % 4 != 0) {
+        return Err(invalid(format!("{} padding characters after {} characters", padding, digits)));
+    }
+    if acc != 0 {
+        return Err(invalid("the last character has bits set past the end of the data".to_string()));
+    }
+    Ok(out)
+}
+
+// Either case; the digits of a byte may not be split by whitespace.
+pub fn hex_decode(text: &str) -> Result<Vec<u8>, PngMsgError> {
+    let invalid = |reason: String| PngMsgError::InvalidHex(reason);
+    let mut out = Vec::with_capacity(text.len() / 2);
+    let mut high: Option<u8> = None;
+
+    for (offset, c) in text.char_indices() {
+        if c.is_ascii_whitespace() {
+            if high.is_some() {
+                return Err(invalid(format!("whitespace at offset {} splits a byte", offset)));
+            }
+            continue;
+        }
+        let digit = c.to_digit(16)
+            .ok_or_else(|| invalid(format!("{:?} at offset {} is not a hex digit", c, offset)))? as u8;
+        match high.take() {
+            Some(high) => out.push(high << 4 | digit),
+            None => high = Some(digit),
+        }
+    }
+
+    if high.is_some() {
+        return Err(invalid("odd number of hex digits".to_string()));
+    }
+    Ok(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NULs, a lone continuation byte and an overlong encoding: nothing here is valid UTF-8.
+    const BINARY: [u8; 9] = [0x00, 0xff, 0x00, 0x80, 0xc0, 0xaf, 0xed, 0xa0, 0x80];
+
+    #[test]
+    fn test_base64_rfc_vectors() {
+        for (input, expected) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("foobar", "Zm9vYmFy")] {
+            assert_eq!(base64_encode(input.as_bytes()), expected);
+            assert_eq!(base64_decode(expected).unwrap(), input.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_round_trip_binary() {
+        for len in 0..=BINARY.len() {
+            let bytes = &BINARY[..len];
+            assert_eq!(base64_decode(&base64_encode(bytes)).unwrap(), bytes);
+            assert_eq!(hex_decode(&hex_encode(bytes)).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_whitespace_and_missing_padding() {
+        assert_eq!(base64_decode("Zm9v\n  YmE=\n").unwrap(), b"fooba");
+        assert_eq!(base64_decode("Zm9vYmE").unwrap(), b"fooba");
+        assert_eq!(hex_decode("00 FF\n0a").unwrap(), [0x00, 0xff, 0x0a]);
+    }
+
+    #[test]
+    fn test_invalid_base64() {
+        for (input, reason) in [
+            ("Zm9v!", "'!' at offset 4 is not a base64 character"),
+            ("Zm9vY", "5 characters do not make a whole number of bytes"),
+            ("Zg=a", "'a' at offset 3 comes after the padding"),
+            ("Zg===", "3 padding characters after 2 characters"),
+            ("Zm8==", "2 padding characters after 3 characters"),
+            ("Zh==", "the last character has bits set past the end of the data"),
+        ] {
+            assert_eq!(base64_decode(input).unwrap_err().to_string(), format!("Invalid base64: {}", reason), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_invalid_hex() {
+        for (input, reason) in [
+            ("0g", "'g' at offset 1 is not a hex digit"),
+            ("abc", "odd number of hex digits"),
+            ("a bc", "whitespace at offset 1 splits a byte"),
+            ("0x00", "'x' at offset 1 is not a hex digit"),
+        ] {
+            assert_eq!(hex_decode(input).unwrap_err().to_string(), format!("Invalid hex: {}", reason), "{}", input);
+        }
+    }
+}
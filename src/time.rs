@@ -0,0 +1,231 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::PngMsgError;
+use crate::png::Png;
+
+pub const TIME_LENGTH: usize = 7;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// The 7 bytes of tIME: the last modification time in UTC. Second 60 is allowed for a leap
+// second, as the spec says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Timestamp {
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Result<Timestamp, PngMsgError> {
+        let timestamp = Timestamp { year, month, day, hour, minute, second };
+        timestamp.check()?;
+        Ok(timestamp)
+    }
+
+    // Whole seconds since 1970-01-01 UTC; Unix time has no leap seconds, so second is never 60.
+    pub fn from_unix_seconds(seconds: u64) -> Result<Timestamp, PngMsgError> {
+        let (days, rest) = (seconds / SECONDS_PER_DAY, seconds % SECONDS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        let year = u16::try_from(year).map_err(|_| PngMsgError::InvalidTimeField { field: "year", value: year })?;
+        Ok(Timestamp {
+            year,
+            month,
+            day,
+            hour: (rest / 3600) as u8,
+            minute: (rest / 60 % 60) as u8,
+            second: (rest % 60) as u8,
+        })
+    }
+
+    pub fn now() -> Result<Timestamp, PngMsgError> {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map_err(|_| PngMsgError::InvalidTimeField { field: "year", value: 1969 })?;
+        Timestamp::from_unix_seconds(since_epoch.as_secs())
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Timestamp, PngMsgError> {
+        let data: &[u8; TIME_LENGTH] = data.try_into().map_err(|_| PngMsgError::InvalidTimeLength(data.len()))?;
+        let [_, _, month, day, hour, minute, second] = *data;
+        Timestamp::new(u16::from_be_bytes([data[0], data[1]]), month, day, hour, minute, second)
+    }
+
+    pub fn to_data(self) -> [u8; TIME_LENGTH] {
+        let [high, low] = self.year.to_be_bytes();
+        [high, low, self.month, self.day, self.hour, self.minute, self.second]
+    }
+
+    // The day is checked against the month, leap years included, so Feb 30 is refused.
+    fn check(&self) -> Result<(), PngMsgError> {
+        let days = match self.month {
+            1..=12 => days_in_month(self.year, self.month),
+            _ => 0,
+        };
+        for (field, value, max) in [
+            ("month", self.month, 12),
+            ("day", self.day, days.max(1)),
+            ("hour", self.hour, 23),
+            ("minute", self.minute, 59),
+            ("second", self.second, 60),
+        ] {
+            let min = if matches!(field, "month" | "day") { 1 } else { 0 };
+            if !(min..=max).contains(&value) {
+                return Err(PngMsgError::InvalidTimeField { field, value: value as u64 });
+            }
+        }
+        Ok(())
+    }
+}
+
+// "2024-02-29T13:05:09Z"
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Howard Hinnant's civil_from_days, for days since 1970-01-01 only: shifting the year to start
+// in March puts the leap day last, and 400-year eras repeat exactly.
+fn civil_from_days(days: u64) -> (u64, u8, u8) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u8;
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
+impl Chunk {
+    pub fn new_time(timestamp: Timestamp) -> Result<Chunk, PngMsgError> {
+        timestamp.check()?;
+        Chunk::try_new(ChunkType::TIME, timestamp.to_data().to_vec())
+    }
+
+    pub fn as_time(&self) -> Result<Timestamp, PngMsgError> {
+        if self.chunk_type() != &ChunkType::TIME {
+            return Err(PngMsgError::WrongChunkType { expected: "tIME", found: *self.chunk_type() });
+        }
+        Timestamp::parse(self.data())
+    }
+}
+
+impl Png {
+    // From the first tIME, if any.
+    pub fn modification_time(&self) -> Option<Result<Timestamp, PngMsgError>> {
+        self.chunk_by_type(ChunkType::TIME).map(Chunk::as_time)
+    }
+
+    // The spec allows one tIME, so this replaces every existing one, keeping the first one's
+    // place; a file without one gets it just before IEND.
+    pub fn set_modification_time(&mut self, timestamp: Timestamp) -> Result<(), PngMsgError> {
+        let chunk = Chunk::new_time(timestamp)?;
+        let existing: Vec<usize> = self.chunks().iter().enumerate()
+            .filter(|(_, c)| c.chunk_type() == &ChunkType::TIME)
+            .map(|(index, _)| index)
+            .collect();
+        if existing.is_empty() {
+            self.insert_before_iend(chunk);
+        } else {
+            self.replace_chunk_run(&existing, vec![chunk]).expect("indices come from the chunk list");
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IEND, Vec::new()),
+        ]).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let timestamp = Timestamp::new(2024, 2, 29, 13, 5, 60).unwrap();
+        let chunk = Chunk::new_time(timestamp).unwrap();
+        assert_eq!(chunk.data(), [0x07, 0xe8, 2, 29, 13, 5, 60]);
+        assert_eq!(chunk.as_time().unwrap(), timestamp);
+        assert_eq!(timestamp.to_string(), "2024-02-29T13:05:60Z");
+    }
+
+    #[test]
+    fn test_out_of_range_fields() {
+        for (data, message) in [
+            ([0x07, 0xe8, 13, 1, 0, 0, 0], "tIME month 13 is out of range"),
+            ([0x07, 0xe8, 0, 1, 0, 0, 0], "tIME month 0 is out of range"),
+            ([0x07, 0xe7, 2, 29, 0, 0, 0], "tIME day 29 is out of range"),
+            ([0x07, 0xe8, 4, 31, 0, 0, 0], "tIME day 31 is out of range"),
+            ([0x07, 0xe8, 1, 0, 0, 0, 0], "tIME day 0 is out of range"),
+            ([0x07, 0xe8, 1, 1, 24, 0, 0], "tIME hour 24 is out of range"),
+            ([0x07, 0xe8, 1, 1, 0, 60, 0], "tIME minute 60 is out of range"),
+            ([0x07, 0xe8, 1, 1, 0, 0, 61], "tIME second 61 is out of range"),
+        ] {
+            assert_eq!(Timestamp::parse(&data).unwrap_err().to_string(), message);
+        }
+        assert!(matches!(Timestamp::parse(&[0x07, 0xe8, 1, 1, 0, 0]), Err(PngMsgError::InvalidTimeLength(6))));
+        assert!(Chunk::new_time(Timestamp { year: 2024, month: 1, day: 1, hour: 99, minute: 0, second: 0 }).is_err());
+        let text = Chunk::new_text("Comment", "hi").unwrap();
+        assert!(matches!(text.as_time(), Err(PngMsgError::WrongChunkType { expected: "tIME", .. })));
+    }
+
+    #[test]
+    fn test_from_unix_seconds() {
+        for (seconds, expected) in [
+            (0, "1970-01-01T00:00:00Z"),
+            (951_782_400, "2000-02-29T00:00:00Z"),
+            (1_709_211_909, "2024-02-29T13:05:09Z"),
+            (4_102_444_799, "2099-12-31T23:59:59Z"),
+        ] {
+            assert_eq!(Timestamp::from_unix_seconds(seconds).unwrap().to_string(), expected);
+        }
+        assert!(Timestamp::from_unix_seconds(u64::MAX / 2).is_err());
+    }
+
+    #[test]
+    fn test_set_modification_time_replaces_existing() {
+        let mut png = testing_png();
+        assert!(png.modification_time().is_none());
+        let first = Timestamp::new(2001, 1, 1, 0, 0, 0).unwrap();
+        let second = Timestamp::new(2002, 2, 2, 2, 2, 2).unwrap();
+        png.set_modification_time(first).unwrap();
+        png.append_chunk(Chunk::new_time(first).unwrap());
+        png.set_modification_time(second).unwrap();
+
+        assert_eq!(png.chunks_by_type(ChunkType::TIME).len(), 1);
+        assert_eq!(png.modification_time().unwrap().unwrap(), second);
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types.last().map(String::as_str), Some("IEND"));
+        assert_eq!(types[types.len() - 2], "tIME");
+    }
+}
@@ -0,0 +1,297 @@
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::Result;
+use crate::chunk_type::ChunkType;
+use crate::crc32::{Crc32, Crc32Backend};
+use crate::png::{Completeness, SerializePolicy};
+
+const COPY_BUFFER: usize = 64 * 1024;
+const DEFAULT_SPILL_THRESHOLD: usize = 8 * 1024 * 1024;
+
+// Outputs a failed chunk write can be cut back to, so a half-written chunk never survives.
+pub trait Rollback {
+    fn rollback_to(&mut self, len: u64) -> io::Result<()>;
+}
+
+impl Rollback for Vec<u8> {
+    fn rollback_to(&mut self, len: u64) -> io::Result<()> {
+        self.truncate(len as usize);
+        Ok(())
+    }
+}
+
+impl Rollback for Cursor<Vec<u8>> {
+    fn rollback_to(&mut self, len: u64) -> io::Result<()> {
+        self.get_mut().truncate(len as usize);
+        self.set_position(len);
+        Ok(())
+    }
+}
+
+impl Rollback for File {
+    fn rollback_to(&mut self, len: u64) -> io::Result<()> {
+        self.set_len(len)?;
+        self.seek(SeekFrom::Start(len))?;
+        Ok(())
+    }
+}
+
+// Writes chunks straight to `W`, for chunk data that never needs to be held in memory as a whole.
+pub struct ChunkWriter<W: Write + Rollback> {
+    out: W,
+    written: u64,
+    spill_threshold: usize,
+    completeness: Completeness,
+}
+
+impl<W: Write + Rollback> ChunkWriter<W> {
+    // `written` is how many bytes `out` already holds, e.g. the PNG signature.
+    pub fn new(out: W, written: u64) -> ChunkWriter<W> {
+        ChunkWriter { out, written, spill_threshold: DEFAULT_SPILL_THRESHOLD, completeness: Completeness::default() }
+    }
+
+    pub fn with_spill_threshold(mut self, bytes: usize) -> ChunkWriter<W> {
+        self.spill_threshold = bytes;
+        self
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    // Applies the same completeness rules as `Png::serialize` to the chunks written so far.
+    pub fn finish(mut self, policy: SerializePolicy) -> Result<W> {
+        if policy.resolve(&self.completeness)? {
+            self.write_chunk_streamed(&ChunkType::IEND, 0, io::empty())?;
+        }
+        Ok(self.out)
+    }
+
+    // `reader` must yield exactly `expected_len` bytes; anything else rolls the output back.
+    pub fn write_chunk_streamed<R: Read>(&mut self, chunk_type: &ChunkType, expected_len: u32, reader: R) -> Result<()> {
+        let start = self.written;
+        match self.copy_chunk(chunk_type, expected_len, reader) {
+            Ok(()) => {
+                self.written += 12 + expected_len as u64;
+                self.completeness.record(chunk_type.bytes());
+                Ok(())
+            }
+            Err(e) => {
+                self.out.rollback_to(start)?;
+                Err(e)
+            }
+        }
+    }
+
+    // For data of unknown size: spools to memory, or to a temp file past the spill threshold.
+    pub fn write_chunk_buffering<R: Read>(&mut self, chunk_type: &ChunkType, reader: R) -> Result<()> {
+        let mut spool = Spool::new(self.spill_threshold);
+        let len = spool.fill(reader)?;
+        let len = u32::try_from(len)
+            .map_err(|_| format!("Chunk data of {} bytes does not fit the length field", len))?;
+        let reader = spool.into_reader()?;
+        self.write_chunk_streamed(chunk_type, len, reader)
+    }
+
+    fn copy_chunk<R: Read>(&mut self, chunk_type: &ChunkType, expected_len: u32, mut reader: R) -> Result<()> {
+        let type_bytes = chunk_type.bytes();
+        let mut crc = Crc32::default();
+        crc.update(&type_bytes);
+        self.out.write_all(&expected_len.to_be_bytes())?;
+        self.out.write_all(&type_bytes)?;
+
+        let mut remaining = expected_len as u64;
+        let mut buf = vec![0; COPY_BUFFER];
+        while remaining > 0 {
+            let want = remaining.min(buf.len() as u64) as usize;
+            let n = reader.read(&mut buf[..want])?;
+            if n == 0 {
+                return Err(format!(
+                    "{} chunk data ended after {} of {} bytes", chunk_type, expected_len as u64 - remaining, expected_len
+                ).into());
+            }
+            crc.update(&buf[..n]);
+            self.out.write_all(&buf[..n])?;
+            remaining -= n as u64;
+        }
+        if reader.read(&mut buf[..1])? != 0 {
+            return Err(format!("{} chunk data is longer than the declared {} bytes", chunk_type, expected_len).into());
+        }
+
+        self.out.write_all(&crc.finalize().to_be_bytes())?;
+        Ok(())
+    }
+}
+
+static SPOOL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+enum Spool {
+    Memory(Vec<u8>, usize),
+    File(SpoolFile),
+}
+
+impl Spool {
+    fn new(threshold: usize) -> Spool {
+        Spool::Memory(Vec::new(), threshold)
+    }
+
+    fn fill<R: Read>(&mut self, mut reader: R) -> Result<u64> {
+        let mut buf = vec![0; COPY_BUFFER];
+        let mut total = 0u64;
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                return Ok(total);
+            }
+            total += n as u64;
+            if let Spool::Memory(data, threshold) = self {
+                if data.len() + n > *threshold {
+                    let path = std::env::temp_dir().join(format!(
+                        "pngme-spool-{}-{}", std::process::id(), SPOOL_COUNTER.fetch_add(1, Ordering::Relaxed)
+                    ));
+                    let mut file = File::options().read(true).write(true).create_new(true).open(&path)?;
+                    file.write_all(data)?;
+                    *self = Spool::File(SpoolFile { file, path });
+                }
+            }
+            match self {
+                Spool::Memory(data, _) => data.extend_from_slice(&buf[..n]),
+                Spool::File(spool) => spool.file.write_all(&buf[..n])?,
+            }
+        }
+    }
+
+    fn into_reader(self) -> Result<SpoolReader> {
+        match self {
+            Spool::Memory(data, _) => Ok(SpoolReader::Memory(Cursor::new(data))),
+            Spool::File(mut spool) => {
+                spool.file.seek(SeekFrom::Start(0))?;
+                Ok(SpoolReader::File(spool))
+            }
+        }
+    }
+}
+
+enum SpoolReader {
+    Memory(Cursor<Vec<u8>>),
+    File(SpoolFile),
+}
+
+// Deletes the temp file when dropped, whether or not the chunk was written.
+struct SpoolFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Read for SpoolReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            SpoolReader::Memory(cursor) => cursor.read(buf),
+            SpoolReader::File(spool) => spool.file.read(buf),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+
+    fn ru_st() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    fn expected(data: &[u8]) -> Vec<u8> {
+        Chunk::new(ru_st(), data.to_vec()).as_bytes()
+    }
+
+    #[test]
+    fn test_streamed_exact() {
+        let mut writer = ChunkWriter::new(Vec::new(), 0);
+        writer.write_chunk_streamed(&ru_st(), 5, &b"hello"[..]).unwrap();
+        assert_eq!(writer.written(), 17);
+        assert_eq!(writer.into_inner(), expected(b"hello"));
+    }
+
+    #[test]
+    fn test_streamed_short_and_long_roll_back() {
+        let mut writer = ChunkWriter::new(b"prefix".to_vec(), 6);
+
+        assert!(writer.write_chunk_streamed(&ru_st(), 10, &b"short"[..]).is_err());
+        assert!(writer.write_chunk_streamed(&ru_st(), 3, &b"too long"[..]).is_err());
+        assert_eq!(writer.written(), 6);
+
+        writer.write_chunk_streamed(&ru_st(), 2, &b"ok"[..]).unwrap();
+        let mut want = b"prefix".to_vec();
+        want.extend(expected(b"ok"));
+        assert_eq!(writer.into_inner(), want);
+    }
+
+    #[test]
+    fn test_finish_policies() {
+        let ihdr = ChunkType::from_str("IHDR").unwrap();
+        let start = |writer: &mut ChunkWriter<Vec<u8>>| writer.write_chunk_streamed(&ihdr, 1, &b"x"[..]).unwrap();
+
+        let mut writer = ChunkWriter::new(Vec::new(), 0);
+        start(&mut writer);
+        assert!(writer.finish(SerializePolicy::Strict).is_err());
+
+        let mut writer = ChunkWriter::new(Vec::new(), 0);
+        start(&mut writer);
+        let lenient = writer.finish(SerializePolicy::Lenient).unwrap();
+        assert_eq!(lenient, Chunk::new(ihdr, b"x".to_vec()).as_bytes());
+
+        let mut writer = ChunkWriter::new(Vec::new(), 0);
+        start(&mut writer);
+        let finished = writer.finish(SerializePolicy::AutoFinalize).unwrap();
+        let mut want = lenient.clone();
+        want.extend(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()).as_bytes());
+        assert_eq!(finished, want);
+
+        let mut writer = ChunkWriter::new(Vec::new(), 0);
+        writer.write_chunk_streamed(&ru_st(), 0, io::empty()).unwrap();
+        assert!(writer.finish(SerializePolicy::AutoFinalize).is_err());
+    }
+
+    #[test]
+    fn test_buffering_in_memory_and_spilled() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+
+        let mut writer = ChunkWriter::new(Cursor::new(Vec::new()), 0);
+        writer.write_chunk_buffering(&ru_st(), data.as_slice()).unwrap();
+        assert_eq!(writer.into_inner().into_inner(), expected(&data));
+
+        let mut writer = ChunkWriter::new(Vec::new(), 0).with_spill_threshold(100);
+        writer.write_chunk_buffering(&ru_st(), data.as_slice()).unwrap();
+        assert_eq!(writer.into_inner(), expected(&data));
+    }
+
+    #[test]
+    fn test_file_output_is_truncated_on_failure() {
+        let path = std::env::temp_dir().join(format!("pngme-writer-test-{}", std::process::id()));
+        let mut file = File::options().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+        file.write_all(b"sig").unwrap();
+
+        let mut writer = ChunkWriter::new(file, 3);
+        assert!(writer.write_chunk_streamed(&ru_st(), 100, &b"not enough"[..]).is_err());
+        drop(writer);
+
+        assert_eq!(fs::read(&path).unwrap(), b"sig");
+        fs::remove_file(&path).unwrap();
+    }
+}
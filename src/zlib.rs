@@ -0,0 +1,108 @@
+// zlib (RFC 1950/1951) for zTXt, compressed iTXt, zXMP and re-encoded IDAT, from miniz_oxide.
+
+use miniz_oxide::inflate::{decompress_to_vec_zlib_with_limit, TINFLStatus};
+
+use crate::error::PngMsgError;
+
+// zlib's own default, between speed and size.
+const LEVEL: u8 = 6;
+
+// `max_len` caps the output, so a small chunk cannot inflate into gigabytes. The Adler-32
+// trailer is checked.
+pub fn decompress(stream: &[u8], max_len: usize) -> Result<Vec<u8>, PngMsgError> {
+    decompress_to_vec_zlib_with_limit(stream, max_len).map_err(|e| {
+        let reason = match e.status {
+            TINFLStatus::HasMoreOutput => "decompressed data is over the size limit",
+            TINFLStatus::FailedCannotMakeProgress => "deflate stream ends early",
+            TINFLStatus::Adler32Mismatch => "Adler-32 checksum does not match",
+            _ => "not a valid zlib deflate stream",
+        };
+        PngMsgError::InvalidCompressedData(reason.to_string())
+    })
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec_zlib(data, LEVEL)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        decompress(&compress(data), usize::MAX).unwrap()
+    }
+
+    #[test]
+    fn test_round_trips() {
+        assert_eq!(round_trip(b""), b"");
+        assert_eq!(round_trip(b"a"), b"a");
+        let repetitive = "the quick brown fox jumps over the lazy dog. ".repeat(500);
+        assert_eq!(round_trip(repetitive.as_bytes()), repetitive.as_bytes());
+        assert!(compress(repetitive.as_bytes()).len() < repetitive.len() / 10);
+
+        let mut seed: u32 = 12345;
+        let noise: Vec<u8> = (0..100_000).map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8
+        }).collect();
+        assert_eq!(round_trip(&noise), noise);
+        let runs: Vec<u8> = (0..70_000u32).map(|i| (i / 1000) as u8).collect();
+        assert_eq!(round_trip(&runs), runs);
+    }
+
+    #[test]
+    fn test_inflates_stored_and_dynamic_blocks() {
+        // CPython's `zlib.compress(TEXT * 3, 9)`, which picks a dynamic-Huffman block.
+        const TEXT: &[u8] = b"Dynamic Huffman blocks show up once the text is long enough to pay for a code table. ";
+        let dynamic = [
+            0x78, 0xda, 0xd5, 0xcc, 0xcb, 0x11, 0x80, 0x20, 0x0c, 0x45, 0xd1, 0x56, 0x5e, 0x05, 0x56, 0xe1,
+            0xc2, 0x36, 0x42, 0x0c, 0x9f, 0x11, 0x12, 0x46, 0x60, 0x94, 0xee, 0xa5, 0x0d, 0x17, 0x77, 0x77,
+            0xe6, 0xee, 0x53, 0xa9, 0x24, 0xc6, 0x31, 0xbc, 0x2f, 0xa4, 0x70, 0xd9, 0xf8, 0x6a, 0x68, 0xd1,
+            0x1e, 0x8c, 0x0a, 0x53, 0x16, 0xf4, 0xb8, 0x92, 0xb7, 0x23, 0x35, 0x64, 0xd3, 0x00, 0x51, 0x1b,
+            0x21, 0xa2, 0x1b, 0x2a, 0x4d, 0x78, 0xbb, 0x41, 0x60, 0x3b, 0x97, 0x22, 0x97, 0x65, 0xc3, 0xfe,
+            0x9b, 0xe9, 0x07, 0xa7, 0xc7, 0x5a, 0x94,
+        ];
+        assert_eq!(dynamic[2] >> 1 & 3, 2);
+        assert_eq!(decompress(&dynamic, 1000).unwrap(), TEXT.repeat(3));
+
+        let stored = [0x78, 0x01, 0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c', 0x02, 0x4d, 0x01, 0x27];
+        assert_eq!(decompress(&stored, 100).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_corrupt_streams_are_errors() {
+        let good = compress(&b"some text to compress, some text to compress".repeat(4));
+        assert!(decompress(&good[..good.len() - 1], usize::MAX).is_err());
+        assert!(decompress(&good[..5], usize::MAX).is_err());
+        assert!(decompress(&[0x78], usize::MAX).is_err());
+        assert!(decompress(b"not zlib at all", usize::MAX).is_err());
+        assert!(decompress(&good, 10).is_err());
+        for index in 2..good.len() {
+            let mut bad = good.clone();
+            bad[index] ^= 0x55;
+            assert!(decompress(&bad, 1 << 20).is_err(), "flip at {}", index);
+        }
+    }
+
+    #[test]
+    fn test_every_cut_is_an_error() {
+        let good = compress(&b"cut me anywhere ".repeat(20));
+        for end in 0..good.len() {
+            assert!(decompress(&good[..end], usize::MAX).is_err(), "cut at {}", end);
+        }
+    }
+
+    #[test]
+    fn test_error_reasons() {
+        let good = compress(&[b'a'; 500]);
+        let reason = |stream: &[u8], max_len: usize| decompress(stream, max_len).unwrap_err().to_string();
+        assert_eq!(reason(&good, 499), "Invalid compressed data: decompressed data is over the size limit");
+        assert_eq!(decompress(&good, 500).unwrap(), [b'a'; 500]);
+        let mut bad_sum = good.clone();
+        *bad_sum.last_mut().unwrap() ^= 1;
+        assert_eq!(reason(&bad_sum, usize::MAX), "Invalid compressed data: Adler-32 checksum does not match");
+        assert_eq!(reason(b"not zlib at all", usize::MAX), "Invalid compressed data: not a valid zlib deflate stream");
+    }
+}
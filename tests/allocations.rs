@@ -0,0 +1,58 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use pngme::chunk::Chunk;
+use pngme::chunk_type::ChunkType;
+
+// Counts bytes requested from the allocator, so the tests can tell a copy of the data from
+// bookkeeping.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+// Tests share the counter, so they take turns.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const SIXTEEN_MIB: usize = 16 << 20;
+
+fn allocated_by<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let value = f();
+    (value, ALLOCATED.load(Ordering::Relaxed) - before)
+}
+
+#[test]
+fn test_new_does_not_copy_the_data() {
+    let _guard = SERIAL.lock().unwrap();
+    let chunk_type = ChunkType::from_str("ruSt").unwrap();
+    let data = vec![0x5a; SIXTEEN_MIB];
+
+    let (chunk, allocated) = allocated_by(|| Chunk::new(chunk_type, data));
+    assert!(allocated < 1024, "Chunk::new allocated {} bytes", allocated);
+    assert_eq!(chunk.data().len(), SIXTEEN_MIB);
+}
+
+#[test]
+fn test_as_bytes_allocates_once() {
+    let _guard = SERIAL.lock().unwrap();
+    let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![0x5a; SIXTEEN_MIB]);
+
+    let (bytes, allocated) = allocated_by(|| chunk.as_bytes());
+    assert_eq!(bytes.len(), SIXTEEN_MIB + 12);
+    assert_eq!(allocated, SIXTEEN_MIB + 12);
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// Settings the CLI reads from the environment are tested on a child process, so no test changes
+// the environment the others run in.
+fn pngme(args: &[&str], env: &[(&str, &str)]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_pngme"));
+    command.args(args);
+    for var in ["PNG_MSG_CHUNK_TYPE", "PNG_MSG_FORMAT", "PNG_MSG_PASSWORD", "PNG_MSG_PASSWORD_FILE", "PNG_MSG_HMAC_KEY", "PNG_MSG_HMAC_KEY_FILE", "PNG_MSG_LANG"] {
+        command.env_remove(var);
+    }
+    command.envs(env.iter().copied()).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn carrier(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("pngme-cli-{}-{}.png", name, std::process::id()));
+    fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/pixels.png"), &path).unwrap();
+    path
+}
+
+#[test]
+fn test_format_from_environment() {
+    let path = carrier("format");
+    let path = path.to_str().unwrap();
+    stdout(&pngme(&["encode", path, "ruSt", "aGk="], &[("PNG_MSG_FORMAT", "base64")]));
+
+    assert_eq!(stdout(&pngme(&["decode", path, "ruSt"], &[])), "Message is: hi\n");
+    assert_eq!(stdout(&pngme(&["decode", path, "ruSt"], &[("PNG_MSG_FORMAT", "hex")])), "Message is: 6869\n");
+    // Gives way to --raw and --json, which an explicit --format does not.
+    assert_eq!(stdout(&pngme(&["decode", path, "ruSt", "--raw"], &[("PNG_MSG_FORMAT", "hex")])), "hi");
+    assert!(stdout(&pngme(&["decode", path, "ruSt", "--json"], &[("PNG_MSG_FORMAT", "hex")])).contains("\"body\": \"hi\""));
+    assert!(!pngme(&["decode", path, "ruSt", "--raw", "--format", "hex"], &[]).status.success());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_chunk_type_from_environment() {
+    let path = carrier("chunk-type");
+    let path = path.to_str().unwrap();
+    stdout(&pngme(&["encode", path, "ruSt", "hi"], &[]));
+    stdout(&pngme(&["encode", path, "enVt", "there"], &[]));
+
+    assert!(!pngme(&["remove", path], &[]).status.success());
+    stdout(&pngme(&["remove", path], &[("PNG_MSG_CHUNK_TYPE", "enVt")]));
+    assert!(!pngme(&["decode", path, "enVt"], &[]).status.success());
+    // An argument still wins over the environment.
+    assert_eq!(stdout(&pngme(&["decode", path, "ruSt"], &[("PNG_MSG_CHUNK_TYPE", "enVt")])), "Message is: hi\n");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_secrets_from_environment_and_files() {
+    let path = carrier("secrets");
+    let path = path.to_str().unwrap();
+    let key_file = std::env::temp_dir().join(format!("pngme-cli-key-{}", std::process::id()));
+    fs::write(&key_file, "hmac key\n").unwrap();
+    let key_file = key_file.to_str().unwrap();
+    stdout(&pngme(&["encode", path, "ruSt", "tagged"], &[("PNG_MSG_HMAC_KEY", "hmac key")]));
+
+    assert!(!pngme(&["decode", path, "ruSt"], &[]).status.success());
+    assert_eq!(stdout(&pngme(&["decode", path, "ruSt", "--hmac-key-file", key_file], &[])), "Message is: tagged\n");
+    assert_eq!(stdout(&pngme(&["decode", path, "ruSt"], &[("PNG_MSG_HMAC_KEY_FILE", key_file)])), "Message is: tagged\n");
+    assert!(!pngme(&["decode", path, "ruSt"], &[("PNG_MSG_HMAC_KEY", "other")]).status.success());
+    fs::remove_file(key_file).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_split_message() {
+    let path = carrier("split");
+    let path = path.to_str().unwrap();
+    stdout(&pngme(&["encode", path, "ruSt", "a message in several pieces", "--split-size", "20"], &[]));
+    assert_eq!(stdout(&pngme(&["decode", path, "ruSt"], &[])), "Message is: a message in several pieces\n");
+    assert!(!pngme(&["encode", path, "ruSt", "hi", "--split-size", "18"], &[]).status.success());
+    fs::remove_file(path).unwrap();
+}
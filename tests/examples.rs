@@ -0,0 +1,51 @@
+use std::fs;
+use std::path::PathBuf;
+
+#[allow(dead_code)]
+#[path = "../examples/embed_message.rs"]
+mod embed_message;
+
+#[allow(dead_code)]
+#[path = "../examples/extract_all.rs"]
+mod extract_all;
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pngme-examples-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_embed_then_extract() {
+    let dir = scratch_dir("embed");
+    let output = dir.join("out.png");
+
+    embed_message::run(&fixture("pixels.png"), output.to_str().unwrap(), "ruSt", "hidden").unwrap();
+    let written = extract_all::run(output.to_str().unwrap(), &dir.join("extracted")).unwrap();
+
+    assert_eq!(written.len(), 1);
+    assert_eq!(written[0].file_name().unwrap(), "2-ruSt.bin");
+    assert_eq!(fs::read(&written[0]).unwrap(), b"hidden");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_extract_from_clean_file() {
+    let dir = scratch_dir("clean");
+    let written = extract_all::run(&fixture("pixels.png"), &dir).unwrap();
+    assert!(written.is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_embed_rejects_bad_chunk_type() {
+    let dir = scratch_dir("bad-type");
+    let output = dir.join("out.png");
+    assert!(embed_message::run(&fixture("pixels.png"), output.to_str().unwrap(), "ru5t", "x").is_err());
+    fs::remove_dir_all(dir).unwrap();
+}